@@ -17,67 +17,56 @@ use std::f32::consts::PI;
 
 #[derive(Debug, Clone)]
 struct Object {
-    pub dynamics: FreeBody,
+    pub body: BodyId,
     pub geometry: Polyhedron,
     pub transform: Transform,
 }
 
 type Particle = Object;
 
-impl Distribution<Particle> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Particle {
-        let dynamics = FreeBody {
-                posn: glm::vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0),
-                vel: glm::Vec3::zeros(),
-                force: glm::Vec3::zeros(),
-                // accel: glm::vec2(0, 0),
-                mass: 1.0,
-                theta: rng.gen_range(0.0..2.*PI),
-                omega: 0.0,
-                // alpha: 
-            };
-        Particle {
-            dynamics,
-            geometry: Polyhedron::from(TriMesh::create_sphere(0.01, 20, 20)),
-            transform: Transform::default()
-        }
+impl Distribution<RigidBody> for Standard {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> RigidBody {
+        RigidBody::default()
+            .with_position(glm::vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0))
+            .with_orientation(glm::quat_angle_axis(rng.gen_range(0.0..2.*PI), &glm::Vec3::z()))
+            // tumbling
+            .with_velocity(
+                glm::Vec3::zeros(),
+                glm::vec3(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+            )
     }
 }
 
-impl Particle {
-    fn compute_gravity(&self) -> glm::Vec3 {
-        glm::vec3(0.0, 0.0, -9.8 * self.dynamics.mass)
-    }
+// the world integrates the particles under gravity, this only draws them
+pub struct ParticleSim {
+    world: PhysicsWorld,
+    particles: Vec<Particle>,
 }
 
-#[derive(Clone, Debug)]
-pub struct ParticleSim(Vec<Particle>);
-
 impl PhysicsProgram for ParticleSim {
     fn new() -> Self {
-        Self(Vec::new())
+        Self {
+            world: PhysicsWorld::default().with_gravity(glm::vec3(0.0, 0.0, -9.8)),
+            particles: Vec::new(),
+        }
     }
     fn setup(&mut self, scene: &str) {
         let mut rng = rand::thread_rng();
-        let ParticleSim(particles) = self;
         let n = 2;
         for _ in 0..n {
-            let p: Particle = rng.gen();
-            particles.push(p);
+            let body = self.world.add_body(rng.gen());
+            self.particles.push(Particle {
+                body,
+                geometry: Polyhedron::from(TriMesh::create_sphere(0.01, 20, 20)),
+                transform: Transform::default(),
+            });
         }
     }
     fn step(&mut self) {
-        let ParticleSim(particles) = self;
-        let dt = 1;
-
-        for p in particles.iter_mut() {
-            let fg = p.compute_gravity();
-            let a = fg / p.dynamics.mass;
-            // p.dynamics.vel += a * (dt as f32);
-            // p.dynamics.posn += p.dynamics.vel * (dt as f32);
-            p.transform = Transform::new(p.dynamics.posn, glm::Vec3::zeros());
-            // dbg!(p.transform);
-        } 
+        self.world.step(1.0 / 60.0);
+        for p in self.particles.iter_mut() {
+            p.transform = Transform { tmatrix: self.world.body(p.body).transform() };
+        }
     }
     fn apply_forces(&mut self) {}
     fn update_kinematics(&mut self) {}
@@ -124,10 +113,10 @@ fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
     let mut sim = ParticleSim::new();
     sim.setup("scene1");
 
-    let transform_buffers = program.create_transform_buffers(sim.0.iter().map(|p| p.transform));
+    let transform_buffers = program.create_transform_buffers(sim.particles.iter().map(|p| p.transform));
     let light_buffer = program.create_light_buffer();
     let camera_buffer = program.create_camera_buffer();
-    let mesh_buffers = program.create_mesh_buffers(sim.0.iter().map(|p|&p.geometry));
+    let mesh_buffers = program.create_mesh_buffers(sim.particles.iter().map(|p|&p.geometry));
     program.create_bindings(&light_buffer, &camera_buffer, &transform_buffers);
 
     let pipeline = program
@@ -164,7 +153,7 @@ fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
                 //UPDATE
                 program.update(&mut |p| {
                     sim.step();
-                    p.update_transforms(&transform_buffers, sim.0.iter().map(|p|&p.transform));
+                    p.update_transforms(&transform_buffers, sim.particles.iter().map(|p|&p.transform));
                 });
 
                 // RENDER
@@ -1,8 +1,10 @@
+use nalgebra_glm as glm;
 use std::f32::consts::PI;
 
 use physics_engine::bindings::*;
 use physics_engine::geometry::{BoxMesh, CylinderMesh, Polyhedron, TriMesh};
 use physics_engine::graphics::GraphicsProgram;
+use physics_engine::kinematics::{IkSolver, IkTarget, JointState};
 use physics_engine::shader::CreatePipeline;
 use physics_engine::urdf::*;
 use physics_engine::wgpu_program::{MeshBuffer, WGPUGraphics};
@@ -30,6 +32,12 @@ pub fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
         .create_render_pipeline(include_str!("../shaders/shader.wgsl"))
         .expect("failed to get render pipeline!");
 
+    // the solver poses a copy, the robot itself is moved to the solutions;
+    // the event loop never returns, so the copy lives as long as the program
+    let arm: &'static RobotDescriptor = Box::leak(Box::new(robot.clone()));
+    let solver = IkSolver::new(arm, "base_link", "virtual_grasp_link")
+        .expect("xarm is a chain from its base to the grasp point");
+    let mut joints = JointState::new(&robot);
     let mut increment = 0.0;
     program.preloop(&mut |_| {
         println!("Called one time before the loop!");
@@ -73,8 +81,14 @@ pub fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
                     p.update_camera(&camera_buffer);
                     p.update_light(&light_buffer);
                     increment = (increment + 0.02) % (2.0*PI);
-                    robot.set_joint_position(&[0.,0.,increment.cos(),-increment.cos(),-increment.cos(),0.,0.,0.,0.,0.,0.,0.,], false);
-                    robot.build();
+                    // trace a circle in front of the arm with the grasp point
+                    let target = IkTarget::position(glm::vec3(
+                        0.15,
+                        0.08 * increment.cos(),
+                        0.2 + 0.08 * increment.sin(),
+                    ));
+                    joints = solver.solve(&target, &joints).state;
+                    robot.build_with(&joints);
                     p.robot_assign_transform_buffers(&robot, &transform_buffers);
                 });
 
@@ -1,4 +1,5 @@
 use physics_engine::graphics::GraphicsProgram;
+use physics_engine::kinematics::JointState;
 use physics_engine::shader::CreatePipeline;
 use physics_engine::urdf::*;
 use physics_engine::wgpu_program::WGPUGraphics;
@@ -31,6 +32,7 @@ pub fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
         .create_render_pipeline(shader_string)
         .expect("unable to create render pipeline");
 
+    let mut joints = JointState::new(&robot);
     let mut increment = 0.0;
     program.preloop(&mut |_| {
         println!("Called one time before the loop!");
@@ -74,8 +76,10 @@ pub fn run_loop(mut program: WGPUGraphics, event_loop: EventLoop<()>) {
                     p.update_camera(&camera_buffer);
                     p.update_light(&light_buffer);
                     increment = (increment + 0.02) % (2.0*PI);
-                    robot.set_joint_position(&[0.,0.,increment.cos(),-increment.cos(),-increment.cos(),0.,0.,0.,0.,0.,0.,0.,], false);
-                    robot.build();
+                    joints.set_position("front_left_knee", increment.cos()).unwrap();
+                    joints.set_position("front_right_hip_roll", -increment.cos()).unwrap();
+                    joints.set_position("front_right_hip_pitch", -increment.cos()).unwrap();
+                    robot.build_with(&joints);
                     p.robot_assign_transform_buffers(&robot, &transform_buffers);
                 });
 
@@ -0,0 +1,185 @@
+// Rigid-body dynamics of robots over their kinematic tree, from the mass
+// properties in InertialBody. Joint velocities, accelerations and torques
+// follow the velocity vector of JointState (forces for prismatic joints);
+// a mimic joint moves with the joint it follows, which also takes its torque.
+//
+// Inverse dynamics works in world coordinates: per link the angular velocity
+// and acceleration and the acceleration of the link origin going out from
+// the root, then the force and moment each link needs coming back. The mass
+// matrix, forward dynamics and stepping are in dynamics/forward.rs.
+use crate::kinematics::{
+    forward_kinematics, motion_subspace, JointId, JointState, JointStateError, KinematicTree,
+};
+use crate::urdf::RobotDescriptor;
+use crate::world::DEFAULT_GRAVITY;
+
+mod forward;
+mod spatial;
+
+#[derive(Debug, Clone)]
+pub struct RobotDynamics<'a> {
+    robot: &'a RobotDescriptor,
+    tree: &'a KinematicTree,
+    gravity: glm::Vec3,
+    base: glm::Mat4,
+}
+
+// Motion of a link in world coordinates, `acceleration` being that of its origin
+#[derive(Debug, Clone, Copy, Default)]
+struct LinkMotion {
+    omega: glm::Vec3,
+    alpha: glm::Vec3,
+    acceleration: glm::Vec3,
+}
+
+impl<'a> RobotDynamics<'a> {
+    // gravity is DEFAULT_GRAVITY and the root link is held at the world origin
+    pub fn new(robot: &'a RobotDescriptor) -> Self {
+        Self {
+            robot,
+            tree: robot.kinematic_tree(),
+            gravity: DEFAULT_GRAVITY,
+            base: glm::Mat4::identity(),
+        }
+    }
+    pub fn with_gravity(mut self, gravity: glm::Vec3) -> Self {
+        self.gravity = gravity;
+        self
+    }
+    // world pose of the root link, which the robot is fixed to; a robot free
+    // in space has a floating joint above its body instead
+    pub fn with_base(mut self, base: glm::Mat4) -> Self {
+        self.base = base;
+        self
+    }
+    pub fn robot(&self) -> &RobotDescriptor {
+        self.robot
+    }
+    pub fn tree(&self) -> &KinematicTree {
+        self.tree
+    }
+    pub fn gravity(&self) -> glm::Vec3 {
+        self.gravity
+    }
+    pub fn base(&self) -> &glm::Mat4 {
+        &self.base
+    }
+
+    // Joint torques giving the joint `accelerations` at the positions and
+    // velocities of `state` under gravity, by recursive Newton-Euler
+    pub fn inverse_dynamics(
+        &self,
+        state: &JointState,
+        accelerations: &[f32],
+    ) -> Result<Vec<f32>, JointStateError> {
+        if accelerations.len() != state.nv() {
+            return Err(JointStateError::WrongLength {
+                joint: None,
+                expected: state.nv(),
+                got: accelerations.len(),
+            });
+        }
+        Ok(self.rnea(state, state.velocity_vector(), accelerations, &self.gravity))
+    }
+    // torques holding the robot still at the positions of `state`
+    pub fn gravity_compensation(&self, state: &JointState) -> Vec<f32> {
+        let zero = vec![0.0; state.nv()];
+        self.rnea(state, &zero, &zero, &self.gravity)
+    }
+    // Coriolis and centrifugal torques at the velocities of `state`, so that
+    // inverse_dynamics = M(q) qdd + coriolis + gravity_compensation
+    pub fn coriolis(&self, state: &JointState) -> Vec<f32> {
+        let zero = vec![0.0; state.nv()];
+        self.rnea(state, state.velocity_vector(), &zero, &glm::Vec3::zeros())
+    }
+    // gravity_compensation + coriolis in one pass
+    pub fn bias(&self, state: &JointState) -> Vec<f32> {
+        let zero = vec![0.0; state.nv()];
+        self.rnea(state, state.velocity_vector(), &zero, &self.gravity)
+    }
+
+    pub(crate) fn rnea(
+        &self,
+        state: &JointState,
+        velocities: &[f32],
+        accelerations: &[f32],
+        gravity: &glm::Vec3,
+    ) -> Vec<f32> {
+        let robot = self.robot;
+        let poses = forward_kinematics(robot, self.tree, state, Some(&self.base));
+        let origin = |link: usize| poses.link(link).column(3).xyz();
+        let order = self.tree.topological_order();
+        // gravity as an upward acceleration of the root moves every link with it
+        let mut motion = vec![LinkMotion::default(); robot.links.len()];
+        motion[self.tree.root()].acceleration = -gravity;
+        let mut axes = vec![Vec::new(); robot.links.len()];
+        for &link in &order[1..] {
+            let j = self
+                .tree
+                .parent_joint(link)
+                .expect("only the root has no parent");
+            let joint = &robot.joints[j];
+            let frame = poses.link(joint.parent()) * joint.origin().matrix();
+            axes[link] = motion_subspace(joint, &glm::mat4_to_mat3(&frame));
+            let (column, scale) = state
+                .velocity_column(JointId::from_index(j))
+                .unwrap_or((0, 0.0));
+            let (mut v_linear, mut v_angular) = (glm::Vec3::zeros(), glm::Vec3::zeros());
+            let (mut a_linear, mut a_angular) = (glm::Vec3::zeros(), glm::Vec3::zeros());
+            for (k, (linear, angular)) in axes[link].iter().enumerate() {
+                let (qd, qdd) = (
+                    scale * velocities[column + k],
+                    scale * accelerations[column + k],
+                );
+                v_linear += linear * qd;
+                v_angular += angular * qd;
+                a_linear += linear * qdd;
+                a_angular += angular * qdd;
+            }
+            // the joint axes are fixed in the parent and turn with it
+            let parent = motion[joint.parent()];
+            let r = origin(link) - origin(joint.parent());
+            motion[link] = LinkMotion {
+                omega: parent.omega + v_angular,
+                alpha: parent.alpha + a_angular + parent.omega.cross(&v_angular),
+                acceleration: parent.acceleration
+                    + parent.alpha.cross(&r)
+                    + parent.omega.cross(&parent.omega.cross(&r))
+                    + a_linear
+                    + 2.0 * parent.omega.cross(&v_linear),
+            };
+        }
+
+        // force on each link from its parent joint and moment about the link origin
+        let mut force = vec![glm::Vec3::zeros(); robot.links.len()];
+        let mut moment = vec![glm::Vec3::zeros(); robot.links.len()];
+        let mut torques = vec![0.0; state.nv()];
+        for &link in order.iter().rev() {
+            let inertial = &robot.links[link].inertial;
+            let m = motion[link];
+            let pose = poses[link].inertial;
+            let c = pose.column(3).xyz() - origin(link);
+            let rotation = glm::mat4_to_mat3(&pose);
+            let inertia = rotation * inertial.tensor() * rotation.transpose();
+            let a_com = m.acceleration + m.alpha.cross(&c) + m.omega.cross(&m.omega.cross(&c));
+            let f = a_com * inertial.mass;
+            force[link] += f;
+            moment[link] += inertia * m.alpha + m.omega.cross(&(inertia * m.omega)) + c.cross(&f);
+            let Some(j) = self.tree.parent_joint(link) else {
+                continue;
+            };
+            if let Some((column, scale)) = state.velocity_column(JointId::from_index(j)) {
+                for (k, (linear, angular)) in axes[link].iter().enumerate() {
+                    torques[column + k] +=
+                        scale * (linear.dot(&force[link]) + angular.dot(&moment[link]));
+                }
+            }
+            let parent = robot.joints[j].parent();
+            let r = origin(link) - origin(parent);
+            let (f, n) = (force[link], moment[link]);
+            force[parent] += f;
+            moment[parent] += n + r.cross(&f);
+        }
+        torques
+    }
+}
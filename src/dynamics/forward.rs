@@ -0,0 +1,379 @@
+// Forward dynamics: the joint-space mass matrix by the composite rigid body
+// algorithm, joint accelerations from torques by the articulated body
+// algorithm, and stepping a JointState through time with any of the
+// integrators in physics.
+//
+// Mimic joints couple joints across branches of the tree, which the
+// articulated body recursion cannot express, so robots with them get their
+// accelerations from the mass matrix instead. Joints that move no mass get a
+// tiny armature to keep both solvable.
+use super::spatial::{self, Spatial};
+use super::RobotDynamics;
+use crate::kinematics::{
+    forward_kinematics, motion_subspace, JointId, JointState, JointStateError, LimitPolicy,
+};
+use crate::physics::{Integrable, Integrator, SemiImplicitEuler};
+use crate::urdf::JointType;
+use nalgebra::{DMatrix, DVector, Matrix6};
+
+// rotor inertia added to every joint coordinate
+const ARMATURE: f32 = 1e-6;
+
+// The joint leading to a link, in spatial coordinates
+#[derive(Debug, Clone)]
+struct JointColumns {
+    // one per coordinate of the joint
+    axes: Vec<Spatial>,
+    // the joint's velocity column and how much it moves per unit of it
+    column: usize,
+    scale: f32,
+}
+
+// What the backward pass of ABA leaves at a joint for the forward one:
+// U = I^A S, D^-1 = (S^T U)^-1 and u = tau - S^T p^A
+#[derive(Debug, Clone)]
+struct Projection {
+    u: DMatrix<f32>,
+    d_inv: DMatrix<f32>,
+    remaining: DVector<f32>,
+}
+
+// A configuration of the robot in spatial coordinates, indexed by link
+struct Articulation {
+    joints: Vec<Option<JointColumns>>,
+    inertia: Vec<Matrix6<f32>>,
+}
+
+impl RobotDynamics<'_> {
+    fn articulation(&self, state: &JointState) -> Articulation {
+        let robot = self.robot;
+        let poses = forward_kinematics(robot, self.tree, state, Some(&self.base));
+        let mut joints = vec![None; robot.links.len()];
+        for &link in &self.tree.topological_order()[1..] {
+            let j = self
+                .tree
+                .parent_joint(link)
+                .expect("only the root has no parent");
+            let Some((column, scale)) = state.velocity_column(JointId::from_index(j)) else {
+                continue;
+            };
+            let joint = &robot.joints[j];
+            let frame = poses.link(joint.parent()) * joint.origin().matrix();
+            let origin = poses.link(link).column(3).xyz();
+            let axes = motion_subspace(joint, &glm::mat4_to_mat3(&frame))
+                .iter()
+                .map(|(linear, angular)| spatial::motion(linear, angular, &origin))
+                .collect();
+            joints[link] = Some(JointColumns {
+                axes,
+                column,
+                scale,
+            });
+        }
+        let inertia = std::iter::zip(&robot.links, poses.iter())
+            .map(|(l, pose)| {
+                let rotation = glm::mat4_to_mat3(&pose.inertial);
+                let tensor = rotation * l.inertial.tensor() * rotation.transpose();
+                spatial::inertia(l.inertial.mass, &pose.inertial.column(3).xyz(), &tensor)
+            })
+            .collect();
+        Articulation { joints, inertia }
+    }
+
+    // M(q), so that inverse_dynamics = M(q) qdd + bias (plus the armature on
+    // the diagonal)
+    pub fn mass_matrix(&self, state: &JointState) -> DMatrix<f32> {
+        let a = self.articulation(state);
+        let order = self.tree.topological_order();
+        // inertia of every subtree, all about the world origin so they just add
+        let mut composite = a.inertia.clone();
+        for &link in order.iter().rev() {
+            if let Some(parent) = self.tree.parent(link) {
+                let c = composite[link];
+                composite[parent] += c;
+            }
+        }
+        let mut m = DMatrix::zeros(state.nv(), state.nv());
+        for &link in order {
+            let Some(joint) = &a.joints[link] else {
+                continue;
+            };
+            for (k, s) in joint.axes.iter().enumerate() {
+                let f = composite[link] * s * joint.scale;
+                let row = joint.column + k;
+                for (l, other) in joint.axes.iter().enumerate() {
+                    m[(row, joint.column + l)] += joint.scale * other.dot(&f);
+                }
+                // and every joint above it
+                let mut above = self.tree.parent(link);
+                while let Some(up) = above {
+                    if let Some(other) = &a.joints[up] {
+                        for (l, s) in other.axes.iter().enumerate() {
+                            let x = other.scale * s.dot(&f);
+                            m[(row, other.column + l)] += x;
+                            m[(other.column + l, row)] += x;
+                        }
+                    }
+                    above = self.tree.parent(up);
+                }
+            }
+        }
+        for i in 0..state.nv() {
+            m[(i, i)] += ARMATURE;
+        }
+        m
+    }
+
+    // Joint accelerations under the joint `torques` (laid out like the
+    // velocity vector) and gravity, at the positions and velocities of `state`
+    pub fn forward_dynamics(
+        &self,
+        state: &JointState,
+        torques: &[f32],
+    ) -> Result<Vec<f32>, JointStateError> {
+        if torques.len() != state.nv() {
+            return Err(JointStateError::WrongLength {
+                joint: None,
+                expected: state.nv(),
+                got: torques.len(),
+            });
+        }
+        if state.mimic_ids().next().is_some() {
+            return Ok(self.forward_dynamics_crba(state, torques));
+        }
+        Ok(self.forward_dynamics_aba(state, torques))
+    }
+
+    // solves M(q) qdd = torques - bias
+    fn forward_dynamics_crba(&self, state: &JointState, torques: &[f32]) -> Vec<f32> {
+        let m = self.mass_matrix(state);
+        let rhs = DVector::from_iterator(
+            torques.len(),
+            std::iter::zip(torques, self.bias(state)).map(|(t, b)| t - b),
+        );
+        let qdd = match m.clone().cholesky() {
+            Some(c) => c.solve(&rhs),
+            None => m
+                .lu()
+                .solve(&rhs)
+                .unwrap_or_else(|| DVector::zeros(rhs.len())),
+        };
+        qdd.iter().copied().collect()
+    }
+
+    fn forward_dynamics_aba(&self, state: &JointState, torques: &[f32]) -> Vec<f32> {
+        let a = self.articulation(state);
+        let order = self.tree.topological_order();
+        let n = self.robot.links.len();
+        let qd = state.velocity_vector();
+        let root = self.tree.root();
+
+        // velocities and velocity product accelerations going out
+        let mut velocity = vec![Spatial::zeros(); n];
+        let mut bias_acceleration = vec![Spatial::zeros(); n];
+        let mut inertia = a.inertia.clone();
+        let mut bias_force = vec![Spatial::zeros(); n];
+        for &link in order {
+            if let (Some(parent), Some(joint)) = (self.tree.parent(link), &a.joints[link]) {
+                let mut joint_velocity = Spatial::zeros();
+                for (k, s) in joint.axes.iter().enumerate() {
+                    joint_velocity += s * qd[joint.column + k];
+                }
+                velocity[link] = velocity[parent] + joint_velocity;
+                // the axes are fixed in the parent, but the ones turning do so
+                // about the child origin, which moves along the ones sliding
+                let (turning, sliding) = joint.axes.iter().enumerate().fold(
+                    (glm::Vec3::zeros(), glm::Vec3::zeros()),
+                    |(w, v), (k, s)| {
+                        let q = qd[joint.column + k];
+                        if spatial::angular(s) == glm::Vec3::zeros() {
+                            (w, v + spatial::linear(s) * q)
+                        } else {
+                            (w + spatial::angular(s) * q, v)
+                        }
+                    },
+                );
+                let relative = -turning.cross(&sliding);
+                bias_acceleration[link] = spatial::cross_motion(&velocity[link]) * joint_velocity
+                    + spatial::motion(&relative, &glm::Vec3::zeros(), &glm::Vec3::zeros());
+            } else if let Some(parent) = self.tree.parent(link) {
+                velocity[link] = velocity[parent];
+            }
+            bias_force[link] =
+                spatial::cross_force(&velocity[link]) * inertia[link] * velocity[link];
+        }
+
+        // articulated inertias coming back
+        let mut solved: Vec<Option<Projection>> = vec![None; n];
+        for &link in order.iter().rev() {
+            let Some(parent) = self.tree.parent(link) else {
+                continue;
+            };
+            let (ia, pa) = match &a.joints[link] {
+                Some(joint) if !joint.axes.is_empty() => {
+                    let d = joint.axes.len();
+                    let s = DMatrix::from_fn(6, d, |r, c| joint.axes[c][r]);
+                    let ia_full = DMatrix::from_iterator(6, 6, inertia[link].iter().copied());
+                    let u = &ia_full * &s;
+                    let mut dm = s.transpose() * &u;
+                    for i in 0..d {
+                        dm[(i, i)] += ARMATURE;
+                    }
+                    let d_inv = dm.try_inverse().unwrap_or_else(|| DMatrix::zeros(d, d));
+                    let pa_full = DVector::from_iterator(6, bias_force[link].iter().copied());
+                    let tau = DVector::from_fn(d, |k, _| torques[joint.column + k]);
+                    let remaining = tau - s.transpose() * &pa_full;
+                    let ia = &ia_full - &u * &d_inv * u.transpose();
+                    let c = DVector::from_iterator(6, bias_acceleration[link].iter().copied());
+                    let pa = pa_full + &ia * c + &u * &d_inv * &remaining;
+                    solved[link] = Some(Projection {
+                        u,
+                        d_inv,
+                        remaining,
+                    });
+                    (
+                        Matrix6::from_iterator(ia.iter().copied()),
+                        Spatial::from_iterator(pa.iter().copied()),
+                    )
+                }
+                _ => (inertia[link], bias_force[link]),
+            };
+            inertia[parent] += ia;
+            bias_force[parent] += pa;
+        }
+
+        // accelerations going out, gravity as an upward acceleration of the root
+        let mut acceleration = vec![Spatial::zeros(); n];
+        let up = -self.gravity;
+        acceleration[root] = Spatial::new(0.0, 0.0, 0.0, up.x, up.y, up.z);
+        let mut qdd = vec![0.0; state.nv()];
+        for &link in &order[1..] {
+            let parent = self.tree.parent(link).expect("only the root has no parent");
+            let before = acceleration[parent] + bias_acceleration[link];
+            acceleration[link] = before;
+            if let (Some(joint), Some(p)) = (&a.joints[link], &solved[link]) {
+                let before = DVector::from_iterator(6, before.iter().copied());
+                let x = &p.d_inv * (&p.remaining - p.u.transpose() * before);
+                for (k, s) in joint.axes.iter().enumerate() {
+                    qdd[joint.column + k] = x[k];
+                    acceleration[link] += s * x[k];
+                }
+            }
+        }
+        qdd
+    }
+
+    // Damping and Coulomb friction of the joints at the velocities of
+    // `state`, opposing the motion; mimic joints act through the joint they
+    // follow
+    pub fn passive_torques(&self, state: &JointState) -> Vec<f32> {
+        let qd = state.velocity_vector();
+        let mut torques = vec![0.0; state.nv()];
+        for (j, joint) in self.robot.joints.iter().enumerate() {
+            let (Some(dynamics), Some((column, scale))) = (
+                joint.dynamics(),
+                state.velocity_column(JointId::from_index(j)),
+            ) else {
+                continue;
+            };
+            for k in 0..joint.joint_type().velocity_dofs() {
+                let v = scale * qd[column + k];
+                let friction = if v.abs() > f32::EPSILON {
+                    dynamics.friction() * v.signum()
+                } else {
+                    0.0
+                };
+                torques[column + k] -= scale * (dynamics.damping() * v + friction);
+            }
+        }
+        torques
+    }
+
+    // Advances `state` by `dt` with semi-implicit Euler, driven by its effort
+    // vector, gravity and the passive torques. Joints reaching a limit stop
+    // there.
+    pub fn step(&self, state: &mut JointState, dt: f32) -> Result<(), JointStateError> {
+        self.step_with(&SemiImplicitEuler, state, dt)
+    }
+    // step with any integrator; its intermediate stages clamp to the limits
+    pub fn step_with(
+        &self,
+        integrator: &dyn Integrator,
+        state: &mut JointState,
+        dt: f32,
+    ) -> Result<(), JointStateError> {
+        let mut system = Articulated {
+            dynamics: self,
+            state: state.clone().with_limit_policy(LimitPolicy::Clamp),
+        };
+        integrator.step(&mut system, dt);
+        let policy = state.limit_policy();
+        state.set_limit_policy(LimitPolicy::Clamp);
+        let result = state.set_position_vector(system.state.position_vector());
+        state.set_limit_policy(policy);
+        result?;
+        state.set_velocity_vector(system.state.velocity_vector())?;
+        self.stop_at_limits(state)
+    }
+
+    // zeroes the velocity of joints at a limit and moving past it
+    fn stop_at_limits(&self, state: &mut JointState) -> Result<(), JointStateError> {
+        for id in state.ids().collect::<Vec<_>>() {
+            let joint = &self.robot.joints[id.index()];
+            let (JointType::Revolute | JointType::Prismatic, Some(l)) =
+                (joint.joint_type(), joint.limits())
+            else {
+                continue;
+            };
+            let (q, v) = (
+                state.position(id).unwrap_or_default(),
+                state.velocity(id).unwrap_or_default(),
+            );
+            if (q <= l.lower() && v < 0.0) || (q >= l.upper() && v > 0.0) {
+                state.set_velocity(id, 0.0)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A robot's state driven by its efforts, gravity and passive torques, for the
+// integrators; every stage keeps the joints within their limits
+struct Articulated<'d, 'a> {
+    dynamics: &'d RobotDynamics<'a>,
+    state: JointState,
+}
+
+impl Integrable for Articulated<'_, '_> {
+    fn positions(&self) -> Vec<f32> {
+        self.state.position_vector().to_vec()
+    }
+    fn velocities(&self) -> Vec<f32> {
+        self.state.velocity_vector().to_vec()
+    }
+    fn set_state(&mut self, positions: &[f32], velocities: &[f32]) {
+        self.state
+            .set_position_vector(positions)
+            .and_then(|_| self.state.set_velocity_vector(velocities))
+            .expect("the integrator keeps the vector lengths");
+    }
+    fn accelerations(&self) -> Vec<f32> {
+        let torques: Vec<f32> = std::iter::zip(
+            self.state.effort_vector(),
+            self.dynamics.passive_torques(&self.state),
+        )
+        .map(|(effort, passive)| effort + passive)
+        .collect();
+        self.dynamics
+            .forward_dynamics(&self.state, &torques)
+            .expect("torques are laid out like the velocities")
+    }
+    fn displace(&self, positions: &[f32], velocities: &[f32], dt: f32) -> Vec<f32> {
+        let mut moved = self.state.clone();
+        moved
+            .set_position_vector(positions)
+            .and_then(|_| moved.integrate(velocities, dt))
+            .expect("the integrator keeps the vector lengths");
+        moved.position_vector().to_vec()
+    }
+}
@@ -0,0 +1,55 @@
+// Spatial vectors in world coordinates about the world origin, angular part
+// first: Featherstone's Plucker coordinates with a single frame for every
+// link, so quantities of different links add without any change of frame.
+use nalgebra::{Matrix3, Matrix6, Vector6};
+
+pub(super) type Spatial = Vector6<f32>;
+
+// motion turning by `angular` about `point` while moving `linear`
+pub(super) fn motion(linear: &glm::Vec3, angular: &glm::Vec3, point: &glm::Vec3) -> Spatial {
+    let origin = linear - angular.cross(point);
+    Spatial::new(
+        angular.x, angular.y, angular.z, origin.x, origin.y, origin.z,
+    )
+}
+
+pub(super) fn angular(v: &Spatial) -> glm::Vec3 {
+    glm::vec3(v[0], v[1], v[2])
+}
+
+pub(super) fn linear(v: &Spatial) -> glm::Vec3 {
+    glm::vec3(v[3], v[4], v[5])
+}
+
+fn skew(v: &glm::Vec3) -> Matrix3<f32> {
+    Matrix3::new(0.0, -v.z, v.y, v.z, 0.0, -v.x, -v.y, v.x, 0.0)
+}
+
+// v x, acting on motion vectors
+pub(super) fn cross_motion(v: &Spatial) -> Matrix6<f32> {
+    let (w, u) = (skew(&angular(v)), skew(&linear(v)));
+    let mut m = Matrix6::zeros();
+    m.fixed_view_mut::<3, 3>(0, 0).copy_from(&w);
+    m.fixed_view_mut::<3, 3>(3, 0).copy_from(&u);
+    m.fixed_view_mut::<3, 3>(3, 3).copy_from(&w);
+    m
+}
+
+// v x*, acting on force vectors
+pub(super) fn cross_force(v: &Spatial) -> Matrix6<f32> {
+    -cross_motion(v).transpose()
+}
+
+// of a body with `mass` at `com`, `inertia` being about the centre of mass
+pub(super) fn inertia(mass: f32, com: &glm::Vec3, inertia: &glm::Mat3) -> Matrix6<f32> {
+    let c = skew(com);
+    let mut m = Matrix6::zeros();
+    m.fixed_view_mut::<3, 3>(0, 0)
+        .copy_from(&(inertia + c * c.transpose() * mass));
+    m.fixed_view_mut::<3, 3>(0, 3).copy_from(&(c * mass));
+    m.fixed_view_mut::<3, 3>(3, 0)
+        .copy_from(&(c.transpose() * mass));
+    m.fixed_view_mut::<3, 3>(3, 3)
+        .copy_from(&(Matrix3::identity() * mass));
+    m
+}
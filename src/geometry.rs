@@ -9,6 +9,8 @@ use std::io::{BufRead, BufReader};
 use bytemuck::{Pod, Zeroable};
 use std::slice::Iter;
 // use core::error::{Error, Result};
+mod convex;
+pub use convex::{ConvexDecomposition, ConvexHull, DecompositionParams};
 
 #[derive(Debug, Copy, Clone)]
 pub struct Triangle {
@@ -52,7 +54,7 @@ pub struct TriMesh {
 }
 
 #[repr(C)]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Transform {
     pub tmatrix: glm::Mat4,
 }
@@ -146,6 +148,9 @@ pub struct Polyhedron {
 }
 
 impl TriMesh {
+    pub fn faces(&self) -> &[Triangle] {
+        &self.faces
+    }
     pub fn add_triangle(&mut self, v: [glm::Vec3; 3]) {
         self.faces.push(Triangle {
             vertices: [v[0].into(), v[1].into(), v[2].into()],
@@ -185,6 +190,9 @@ pub trait PlaneMesh: Default {
 pub trait SphereMesh: Default {
     fn create_sphere(r: f32, n_slices: usize, n_stacks: usize) -> Self;
 }
+pub trait CapsuleMesh: Default {
+    fn create_capsule(r: f32, h: f32, n_slices: usize, n_stacks: usize) -> Self;
+}
 impl BoxMesh for TriMesh {
     fn create_box(sz: glm::Vec3) -> Self {
         let [side1, side2, side3]: [glm::Vec3; 3];
@@ -290,6 +298,52 @@ impl SphereMesh for TriMesh {
         mesh
     }
 }
+impl CapsuleMesh for TriMesh {
+    // cylinder of length h along z capped by hemispheres, n_stacks rings per hemisphere
+    fn create_capsule(r: f32, h: f32, n_slices: usize, n_stacks: usize) -> Self {
+        use std::f32::consts::PI;
+        let ring = |phi: f32, z: f32| -> Vec<glm::Vec3> {
+            (0..n_slices)
+                .map(|j| {
+                    let theta = 2.0 * PI * (j as f32) / (n_slices as f32);
+                    [
+                        r * phi.sin() * theta.cos(),
+                        r * phi.sin() * theta.sin(),
+                        z + r * phi.cos(),
+                    ]
+                    .into()
+                })
+                .collect()
+        };
+        // rings from the top pole down, the equator rings bound the cylinder
+        let mut rings: Vec<Vec<glm::Vec3>> = Vec::with_capacity(2 * n_stacks);
+        for i in 1..=n_stacks {
+            rings.push(ring(0.5 * PI * (i as f32) / (n_stacks as f32), 0.5 * h));
+        }
+        for i in 0..n_stacks {
+            rings.push(ring(0.5 * PI * (1.0 + (i as f32) / (n_stacks as f32)), -0.5 * h));
+        }
+        let top: glm::Vec3 = [0.0, 0.0, 0.5 * h + r].into();
+        let bottom: glm::Vec3 = [0.0, 0.0, -0.5 * h - r].into();
+
+        let mut mesh = TriMesh::default();
+        let (first, last) = (&rings[0], &rings[rings.len() - 1]);
+        for i in 0..n_slices {
+            let k = (i + 1) % n_slices;
+            mesh.add_triangle([top, first[i], first[k]]);
+            mesh.add_triangle([bottom, last[k], last[i]]);
+        }
+        for pair in rings.windows(2) {
+            let (upper, lower) = (&pair[0], &pair[1]);
+            for i in 0..n_slices {
+                let k = (i + 1) % n_slices;
+                mesh.add_rectangle([upper[i], lower[i], lower[k], upper[k]]);
+            }
+        }
+        mesh
+    }
+}
+
 impl PlaneMesh for TriMesh {
     fn create_plane() -> Self {
         static SIZE: f32 = 100.0;
@@ -468,15 +522,18 @@ fn parse_obj(fname: String) -> TriMesh {
                         let mut face = Triangle {
                             vertices: [Vertex::default(); 3],
                         };
+                        // v, v/vt, v//vn or v/vt/vn, from 1
                         let re =
-                            regex::Regex::new(r"v?((?:[0-9])*)\/\/(?:vn)?((?:[0-9])*)").unwrap();
+                            regex::Regex::new(r"^([0-9]+)(?:/[0-9]*)?(?:/([0-9]+))?$").unwrap();
 
                         for (k, token) in tokens.enumerate().take(3) {
                             if let Some(captures) = re.captures(token) {
-                                let vidx = captures[1].parse::<usize>().unwrap_or_default() - 1;
-                                let nidx = captures[2].parse::<usize>().unwrap_or_default() - 1;
+                                let vidx = captures[1].parse::<usize>().unwrap() - 1;
                                 face.vertices[k].position = vertices.get(vidx).unwrap().clone();
-                                face.vertices[k].normal = normals.get(nidx).unwrap().clone();
+                                if let Some(n) = captures.get(2) {
+                                    let nidx = n.as_str().parse::<usize>().unwrap() - 1;
+                                    face.vertices[k].normal = normals.get(nidx).unwrap().clone();
+                                }
                             }
                         }
                         faces.push(face);
@@ -542,6 +599,94 @@ impl Polyhedron {
             .par_iter_mut() // parallelize
             .for_each(|v| v.position = glm::diagonal3x3(&factor) * v.position);
     }
+    // bounds of the vertices, empty for an empty polyhedron
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(self.verts.iter().map(|v| &v.position))
+    }
+}
+
+// Axis-aligned bounding box. The empty box has min above max, so that the
+// union with it changes nothing and it overlaps nothing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl Aabb {
+    pub fn new(min: glm::Vec3, max: glm::Vec3) -> Self {
+        Self { min, max }
+    }
+    pub fn empty() -> Self {
+        Self {
+            min: glm::Vec3::repeat(f32::INFINITY),
+            max: glm::Vec3::repeat(f32::NEG_INFINITY),
+        }
+    }
+    pub fn from_points<'a>(points: impl IntoIterator<Item = &'a glm::Vec3>) -> Self {
+        points.into_iter().fold(Self::empty(), |b, p| b.including(p))
+    }
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+    pub fn including(&self, point: &glm::Vec3) -> Self {
+        Self::new(glm::min2(&self.min, point), glm::max2(&self.max, point))
+    }
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self::new(
+            glm::min2(&self.min, &other.min),
+            glm::max2(&self.max, &other.max),
+        )
+    }
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i])
+    }
+    pub fn contains(&self, other: &Aabb) -> bool {
+        (0..3).all(|i| self.min[i] <= other.min[i] && other.max[i] <= self.max[i])
+    }
+    pub fn contains_point(&self, point: &glm::Vec3) -> bool {
+        (0..3).all(|i| self.min[i] <= point[i] && point[i] <= self.max[i])
+    }
+    // grown by `margin` on every side
+    pub fn fattened(&self, margin: f32) -> Self {
+        let margin = glm::Vec3::repeat(margin);
+        Self::new(self.min - margin, self.max + margin)
+    }
+    pub fn center(&self) -> glm::Vec3 {
+        (self.min + self.max) / 2.0
+    }
+    pub fn half_extents(&self) -> glm::Vec3 {
+        (self.max - self.min) / 2.0
+    }
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+    // bounds of the box moved by `transform`, which are loose for a rotation
+    pub fn transformed(&self, transform: &glm::Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let rotation = glm::mat4_to_mat3(transform).abs();
+        let center = transform * self.center().push(1.0);
+        let half = rotation * self.half_extents();
+        Self::new(center.xyz() - half, center.xyz() + half)
+    }
+    // the smallest value of `direction`·x over the box
+    pub fn min_along(&self, direction: &glm::Vec3) -> f32 {
+        (0..3)
+            .map(|i| direction[i] * if direction[i] >= 0.0 { self.min[i] } else { self.max[i] })
+            .sum()
+    }
 }
 
 impl From<String> for Polyhedron {
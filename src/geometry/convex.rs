@@ -0,0 +1,763 @@
+// Convex hulls, and the approximate convex decomposition of meshes, so that
+// concave meshes can collide as a few convex pieces.
+//
+// Hulls come from quickhull (Barber, Dobkin and Huhdanpaa, 1996), worked in
+// double precision. The decomposition follows V-HACD: the mesh is voxelised
+// into a solid, which is cut in two along axis-aligned planes, each time
+// where the two halves are nearest to convex, until every piece is within
+// the concavity tolerance. Neighbouring pieces are then merged again while
+// the union stays within it, or while there are more than the hulls allowed.
+//
+// The concavity of a piece is the volume of its hull the solid leaves empty,
+// as a share of the volume of the hull of the whole mesh. Pieces take their
+// hulls from points sampled over the surface of the mesh in their voxels,
+// and the centres of their voxels inside it, so that cuts through the solid
+// do not grow the hulls past the mesh.
+use super::{Aabb, Polyhedron};
+use crate::graphics::Vertex;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// A closed convex polyhedron, wound counter-clockwise seen from outside
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvexHull {
+    pub points: Vec<glm::Vec3>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DecompositionParams {
+    // voxels along the longest side of the mesh
+    pub resolution: usize,
+    // the most concavity a piece is left with, see above
+    pub concavity: f32,
+    pub max_hulls: usize,
+    // times a piece may be cut
+    pub max_depth: usize,
+}
+
+impl Default for DecompositionParams {
+    fn default() -> Self {
+        Self {
+            resolution: 32,
+            concavity: 0.01,
+            max_hulls: 16,
+            max_depth: 6,
+        }
+    }
+}
+
+// The convex pieces of a mesh
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConvexDecomposition {
+    // of the mesh and parameters it was made from, to tell stale caches
+    pub fingerprint: u64,
+    pub hulls: Vec<ConvexHull>,
+}
+
+impl ConvexHull {
+    // None for fewer than four points not all in one plane
+    pub fn new(points: &[glm::Vec3]) -> Option<Self> {
+        quickhull(points)
+    }
+    pub fn volume(&self) -> f32 {
+        let a = self.points.first().copied().unwrap_or_default();
+        self.faces
+            .iter()
+            .map(|f| {
+                let [p, q, r] = f.map(|v| self.points[v] - a);
+                p.dot(&q.cross(&r))
+            })
+            .sum::<f32>()
+            / 6.0
+    }
+    // on or inside, to within `tolerance`
+    pub fn contains(&self, point: &glm::Vec3, tolerance: f32) -> bool {
+        self.faces.iter().all(|f| {
+            let [a, b, c] = f.map(|v| self.points[v]);
+            let normal = glm::normalize(&(b - a).cross(&(c - a)));
+            normal.dot(&(point - a)) <= tolerance
+        })
+    }
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_points(&self.points)
+    }
+    // flat shaded, to draw
+    pub fn polyhedron(&self) -> Polyhedron {
+        let verts = self
+            .faces
+            .iter()
+            .flat_map(|f| {
+                let [a, b, c] = f.map(|v| self.points[v]);
+                let normal = glm::normalize(&(b - a).cross(&(c - a)));
+                [a, b, c].map(|p| Vertex {
+                    normal,
+                    ..Vertex::from(p)
+                })
+            })
+            .collect();
+        Polyhedron {
+            verts,
+            indices: (0..3 * self.faces.len() as u32).collect(),
+        }
+    }
+}
+
+impl Polyhedron {
+    pub fn convex_hull(&self) -> Option<ConvexHull> {
+        let points: Vec<glm::Vec3> = self.verts.iter().map(|v| v.position).collect();
+        ConvexHull::new(&points)
+    }
+    // Meant for closed meshes; the inside of an open one is not filled, and
+    // it comes apart into as many pieces as allowed
+    pub fn convex_decomposition(&self, params: &DecompositionParams) -> ConvexDecomposition {
+        ConvexDecomposition {
+            fingerprint: ConvexDecomposition::fingerprint(self, params),
+            hulls: decompose(self, params),
+        }
+    }
+}
+
+impl ConvexDecomposition {
+    // as OBJ, a named object to each hull; written next to `path` first and
+    // moved over it, so that a reader never sees half a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".partial");
+        let partial = PathBuf::from(partial);
+        let written = self
+            .write(&partial)
+            .and_then(|_| fs::rename(&partial, path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        writeln!(w, "# convex decomposition {:016x}", self.fingerprint)?;
+        let mut first = 1;
+        for (i, hull) in self.hulls.iter().enumerate() {
+            writeln!(w, "o hull_{}", i)?;
+            for p in &hull.points {
+                writeln!(w, "v {} {} {}", p.x, p.y, p.z)?;
+            }
+            for f in &hull.faces {
+                writeln!(w, "f {} {} {}", f[0] + first, f[1] + first, f[2] + first)?;
+            }
+            first += hull.points.len();
+        }
+        w.flush()
+    }
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let invalid = |line: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("not a convex decomposition: {:?}", line),
+            )
+        };
+        let mut lines = BufReader::new(File::open(path)?).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let fingerprint = header
+            .strip_prefix("# convex decomposition ")
+            .and_then(|hex| u64::from_str_radix(hex.trim(), 16).ok())
+            .ok_or_else(|| invalid(&header))?;
+        let mut hulls: Vec<ConvexHull> = Vec::new();
+        // OBJ counts vertices across objects, from 1
+        let mut first = 1;
+        for line in lines {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("o") => {
+                    first += hulls.last().map_or(0, |h| h.points.len());
+                    hulls.push(ConvexHull {
+                        points: Vec::new(),
+                        faces: Vec::new(),
+                    });
+                }
+                Some(kind @ ("v" | "f")) => {
+                    let hull = hulls.last_mut().ok_or_else(|| invalid(&line))?;
+                    let values: Vec<&str> = tokens.collect();
+                    if values.len() != 3 {
+                        return Err(invalid(&line));
+                    }
+                    if kind == "v" {
+                        let mut p = glm::Vec3::zeros();
+                        for (k, value) in values.iter().enumerate() {
+                            p[k] = value.parse().map_err(|_| invalid(&line))?;
+                        }
+                        hull.points.push(p);
+                    } else {
+                        let mut f = [0; 3];
+                        for (k, value) in values.iter().enumerate() {
+                            let v: usize = value.parse().map_err(|_| invalid(&line))?;
+                            f[k] = v
+                                .checked_sub(first)
+                                .filter(|&v| v < hull.points.len())
+                                .ok_or_else(|| invalid(&line))?;
+                        }
+                        hull.faces.push(f);
+                    }
+                }
+                Some(comment) if comment.starts_with('#') => {}
+                None => {}
+                Some(_) => return Err(invalid(&line)),
+            }
+        }
+        // a hull is a solid, so one cut short on disk cannot be used
+        if let Some(i) = hulls
+            .iter()
+            .position(|h| h.points.len() < 4 || h.faces.is_empty())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "hull_{} has {} points and {} faces",
+                    i,
+                    hulls[i].points.len(),
+                    hulls[i].faces.len()
+                ),
+            ));
+        }
+        Ok(Self { fingerprint, hulls })
+    }
+    // Loaded from `path` when made there from the same mesh and parameters,
+    // otherwise made and saved there, making its directory if need be
+    pub fn cached<P: AsRef<Path>>(
+        mesh: &Polyhedron,
+        params: &DecompositionParams,
+        path: P,
+    ) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Ok(cached) = Self::load(path) {
+            if cached.fingerprint == Self::fingerprint(mesh, params) {
+                return Ok(cached);
+            }
+        }
+        let decomposition = mesh.convex_decomposition(params);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        decomposition.save(path)?;
+        Ok(decomposition)
+    }
+}
+
+impl ConvexDecomposition {
+    // FNV-1a over the vertices, indices and parameters, the same from run to
+    // run
+    pub fn fingerprint(mesh: &Polyhedron, params: &DecompositionParams) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut add = |word: u64| {
+            for byte in word.to_le_bytes() {
+                hash = (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        for v in &mesh.verts {
+            v.position.iter().for_each(|x| add(x.to_bits() as u64));
+        }
+        mesh.indices.iter().for_each(|&i| add(i as u64));
+        add(params.resolution as u64);
+        add(params.concavity.to_bits() as u64);
+        add(params.max_hulls as u64);
+        add(params.max_depth as u64);
+        hash
+    }
+}
+
+struct Face {
+    corners: [usize; 3],
+    normal: glm::DVec3,
+    offset: f64,
+    outside: Vec<usize>,
+    alive: bool,
+}
+
+impl Face {
+    fn new(points: &[glm::DVec3], corners: [usize; 3]) -> Self {
+        let [a, b, c] = corners.map(|v| points[v]);
+        let normal = glm::normalize(&(b - a).cross(&(c - a)));
+        Self {
+            corners,
+            normal,
+            offset: normal.dot(&a),
+            outside: Vec::new(),
+            alive: true,
+        }
+    }
+    fn distance(&self, point: &glm::DVec3) -> f64 {
+        self.normal.dot(point) - self.offset
+    }
+    fn edges(&self) -> [(usize, usize); 3] {
+        let [a, b, c] = self.corners;
+        [(a, b), (b, c), (c, a)]
+    }
+}
+
+// Rounding left the faces a point sees in more than one piece
+struct Tangled;
+
+fn quickhull(input: &[glm::Vec3]) -> Option<ConvexHull> {
+    let mut input = input.to_vec();
+    input.sort_unstable_by_key(|p| p.map(f32::to_bits).data.0);
+    input.dedup();
+    let extent = Aabb::from_points(&input);
+    let size = glm::length(&(extent.max - extent.min)) as f64;
+    // points this close to a face count as on it, from well above the
+    // rounding of single precision, coarser each time the faces tangle
+    [1e-5, 1e-4, 1e-3]
+        .into_iter()
+        .find_map(|tolerance| hull_within(&input, tolerance * size.max(1e-12)).ok())
+        .flatten()
+}
+
+fn hull_within(input: &[glm::Vec3], epsilon: f64) -> Result<Option<ConvexHull>, Tangled> {
+    let points: Vec<glm::DVec3> = input.iter().map(|p| glm::convert(*p)).collect();
+    if points.len() < 4 {
+        return Ok(None);
+    }
+
+    // the first tetrahedron, from the points furthest apart
+    let extreme = (0..3).flat_map(|axis| {
+        let along = |&i: &usize, &j: &usize| points[i][axis].total_cmp(&points[j][axis]);
+        [
+            (0..points.len()).min_by(along).unwrap(),
+            (0..points.len()).max_by(along).unwrap(),
+        ]
+    });
+    let extreme: Vec<usize> = extreme.collect();
+    let furthest = |key: &dyn Fn(&glm::DVec3) -> f64| {
+        (0..points.len())
+            .max_by(|&i, &j| key(&points[i]).total_cmp(&key(&points[j])))
+            .unwrap()
+    };
+    let (a, b) = extreme
+        .iter()
+        .flat_map(|&i| extreme.iter().map(move |&j| (i, j)))
+        .max_by(|&(i, j), &(k, l)| {
+            glm::distance2(&points[i], &points[j])
+                .total_cmp(&glm::distance2(&points[k], &points[l]))
+        })
+        .unwrap();
+    let line = glm::normalize(&(points[b] - points[a]));
+    let c = furthest(&|p| {
+        let d = p - points[a];
+        glm::length2(&(d - line * d.dot(&line)))
+    });
+    let plane = (points[b] - points[a]).cross(&(points[c] - points[a]));
+    if glm::length(&plane) < epsilon * glm::distance(&points[a], &points[b]) {
+        return Ok(None);
+    }
+    let plane = glm::normalize(&plane);
+    let d = furthest(&|p| plane.dot(&(p - points[a])).abs());
+    if plane.dot(&(points[d] - points[a])).abs() < epsilon {
+        return Ok(None);
+    }
+
+    let mut faces: Vec<Face> = Vec::new();
+    let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+    let add = |faces: &mut Vec<Face>, edges: &mut HashMap<_, _>, face: Face| {
+        for edge in face.edges() {
+            edges.insert(edge, faces.len());
+        }
+        faces.push(face);
+    };
+    for (corners, opposite) in [
+        ([a, b, c], d),
+        ([a, d, b], c),
+        ([b, d, c], a),
+        ([c, d, a], b),
+    ] {
+        let mut face = Face::new(&points, corners);
+        if face.distance(&points[opposite]) > 0.0 {
+            face = Face::new(&points, [corners[0], corners[2], corners[1]]);
+        }
+        add(&mut faces, &mut edges, face);
+    }
+    let assign = |faces: &mut [Face], candidates: &[usize], point: usize| {
+        let best = candidates
+            .iter()
+            .map(|&f| (f, faces[f].distance(&points[point])))
+            .filter(|&(_, distance)| distance > epsilon)
+            .max_by(|x, y| x.1.total_cmp(&y.1));
+        if let Some((f, _)) = best {
+            faces[f].outside.push(point);
+        }
+    };
+    let first: Vec<usize> = (0..4).collect();
+    for p in 0..points.len() {
+        if ![a, b, c, d].contains(&p) {
+            assign(&mut faces, &first, p);
+        }
+    }
+
+    // new faces go on the end, so one pass sees them all
+    let mut next = 0;
+    while next < faces.len() {
+        if !faces[next].alive || faces[next].outside.is_empty() {
+            next += 1;
+            continue;
+        }
+        let apex = *faces[next]
+            .outside
+            .iter()
+            .max_by(|&&i, &&j| {
+                let face = &faces[next];
+                face.distance(&points[i])
+                    .total_cmp(&face.distance(&points[j]))
+            })
+            .unwrap();
+        // the faces the apex sees, grown out from this one
+        let mut visible = vec![next];
+        let mut seen: HashSet<usize> = HashSet::from([next]);
+        let mut i = 0;
+        while i < visible.len() {
+            for (u, v) in faces[visible[i]].edges() {
+                let across = *edges.get(&(v, u)).ok_or(Tangled)?;
+                if seen.insert(across) && faces[across].distance(&points[apex]) > epsilon {
+                    visible.push(across);
+                }
+            }
+            i += 1;
+        }
+        let mut horizon = Vec::new();
+        for (u, v) in visible.iter().flat_map(|&f| faces[f].edges()) {
+            if !visible.contains(&edges[&(v, u)]) {
+                horizon.push((u, v));
+            }
+        }
+        // one loop, through each of its corners once
+        let mut starts: Vec<usize> = horizon.iter().map(|&(u, _)| u).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        if starts.len() != horizon.len() {
+            return Err(Tangled);
+        }
+        let mut orphans = Vec::new();
+        for &f in &visible {
+            faces[f].alive = false;
+            orphans.append(&mut faces[f].outside);
+            for edge in faces[f].edges() {
+                edges.remove(&edge);
+            }
+        }
+        let created: Vec<usize> = (faces.len()..faces.len() + horizon.len()).collect();
+        for (u, v) in horizon {
+            add(&mut faces, &mut edges, Face::new(&points, [u, v, apex]));
+        }
+        for p in orphans {
+            if p != apex {
+                assign(&mut faces, &created, p);
+            }
+        }
+    }
+
+    let mut index: HashMap<usize, usize> = HashMap::new();
+    let mut hull = ConvexHull {
+        points: Vec::new(),
+        faces: Vec::new(),
+    };
+    for face in faces.iter().filter(|f| f.alive) {
+        hull.faces.push(face.corners.map(|v| {
+            *index.entry(v).or_insert_with(|| {
+                hull.points.push(input[v]);
+                hull.points.len() - 1
+            })
+        }));
+    }
+    Ok(Some(hull))
+}
+
+const SURFACE: u8 = 1;
+// the centre of the voxel is inside the mesh
+const INSIDE: u8 = 2;
+
+struct Voxels {
+    origin: glm::Vec3,
+    size: f32,
+    dims: [usize; 3],
+    cells: Vec<u8>,
+    // on the surface of the mesh, with their voxels
+    samples: Vec<(glm::Vec3, [usize; 3])>,
+}
+
+impl Voxels {
+    fn new(mesh: &Polyhedron, resolution: usize) -> Self {
+        let bounds = mesh.aabb();
+        let extent = bounds.max - bounds.min;
+        let size = extent.max() / resolution.max(1) as f32;
+        let dims = [0, 1, 2].map(|k| (extent[k] / size).ceil() as usize + 1);
+        let origin = bounds.center()
+            - glm::vec3(dims[0] as f32, dims[1] as f32, dims[2] as f32) * size / 2.0;
+        let mut voxels = Self {
+            origin,
+            size,
+            dims,
+            cells: vec![0; dims[0] * dims[1] * dims[2]],
+            samples: Vec::new(),
+        };
+        let triangles: Vec<[glm::Vec3; 3]> = mesh
+            .indices
+            .chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|k| mesh.verts[t[k] as usize].position))
+            .collect();
+        // points over each triangle at half a voxel apart
+        for [a, b, c] in &triangles {
+            let longest = glm::distance(a, b)
+                .max(glm::distance(b, c))
+                .max(glm::distance(c, a));
+            let n = (2.0 * longest / size).ceil().max(1.0) as usize;
+            for u in 0..=n {
+                for v in 0..=n - u {
+                    let p = a + (b - a) * (u as f32 / n as f32) + (c - a) * (v as f32 / n as f32);
+                    let cell = voxels.cell_of(&p);
+                    let i = voxels.index(cell);
+                    voxels.cells[i] |= SURFACE;
+                    voxels.samples.push((p, cell));
+                }
+            }
+        }
+        // the inside, by where each column of voxel centres crosses the
+        // surface; a little off the centre so as not to pass along edges
+        let skew = glm::vec2(0.0137, 0.0071) * size;
+        for j in 0..dims[1] {
+            for i in 0..dims[0] {
+                let centre = voxels.centre([i, j, 0]);
+                let (x, y) = (centre.x + skew.x, centre.y + skew.y);
+                let mut crossings: Vec<f32> = triangles
+                    .iter()
+                    .filter_map(|[a, b, c]| {
+                        let area = (b.x - a.x) * (c.y - a.y) - (c.x - a.x) * (b.y - a.y);
+                        if area.abs() < 1e-12 {
+                            return None;
+                        }
+                        let u = ((b.x - x) * (c.y - y) - (c.x - x) * (b.y - y)) / area;
+                        let v = ((c.x - x) * (a.y - y) - (a.x - x) * (c.y - y)) / area;
+                        let w = 1.0 - u - v;
+                        (u >= 0.0 && v >= 0.0 && w >= 0.0).then(|| u * a.z + v * b.z + w * c.z)
+                    })
+                    .collect();
+                // open along this column
+                if crossings.len() % 2 == 1 {
+                    continue;
+                }
+                crossings.sort_by(f32::total_cmp);
+                for span in crossings.chunks_exact(2) {
+                    for k in 0..dims[2] {
+                        let z = voxels.centre([i, j, k]).z;
+                        if span[0] <= z && z <= span[1] {
+                            let index = voxels.index([i, j, k]);
+                            voxels.cells[index] |= INSIDE;
+                        }
+                    }
+                }
+            }
+        }
+        voxels
+    }
+    fn index(&self, [i, j, k]: [usize; 3]) -> usize {
+        (k * self.dims[1] + j) * self.dims[0] + i
+    }
+    fn cell_of(&self, p: &glm::Vec3) -> [usize; 3] {
+        [0, 1, 2].map(|k| {
+            (((p[k] - self.origin[k]) / self.size).floor().max(0.0) as usize).min(self.dims[k] - 1)
+        })
+    }
+    fn centre(&self, [i, j, k]: [usize; 3]) -> glm::Vec3 {
+        self.origin + glm::vec3(i as f32 + 0.5, j as f32 + 0.5, k as f32 + 0.5) * self.size
+    }
+}
+
+// Part of the solid: its voxels, those with their centres inside the mesh,
+// the samples of the surface in them, and the points its hull is taken from
+struct Piece {
+    voxels: Vec<[usize; 3]>,
+    inside: Vec<[usize; 3]>,
+    samples: Vec<(glm::Vec3, [usize; 3])>,
+    points: Vec<glm::Vec3>,
+    depth: usize,
+}
+
+impl Piece {
+    // Its hull from the samples, and the centres inside on the faces of its
+    // bounds, where it may have been cut; centres within add nothing
+    fn new(
+        grid: &Voxels,
+        voxels: Vec<[usize; 3]>,
+        inside: Vec<[usize; 3]>,
+        samples: Vec<(glm::Vec3, [usize; 3])>,
+        depth: usize,
+    ) -> Self {
+        let (low, high) = bounds(&voxels);
+        let points = samples
+            .iter()
+            .map(|(p, _)| *p)
+            .chain(
+                inside
+                    .iter()
+                    .filter(|v| (0..3).any(|k| v[k] == low[k] || v[k] == high[k]))
+                    .map(|&v| grid.centre(v)),
+            )
+            .collect();
+        Self {
+            voxels,
+            inside,
+            samples,
+            points,
+            depth,
+        }
+    }
+    // from every `stride`th point, to rate cuts sooner
+    fn concavity(&self, voxel_volume: f32, total: f32, stride: usize) -> f32 {
+        let points: Vec<glm::Vec3> = self.points.iter().step_by(stride).copied().collect();
+        let hull = ConvexHull::new(&points).map_or(0.0, |h| h.volume());
+        (hull - self.voxels.len() as f32 * voxel_volume).max(0.0) / total
+    }
+    // the voxels below `at` along `axis`, and the rest
+    fn split(&self, grid: &Voxels, axis: usize, at: usize) -> (Piece, Piece) {
+        let side = |below: bool| {
+            let keep = |v: &[usize; 3]| (v[axis] < at) == below;
+            Piece::new(
+                grid,
+                self.voxels.iter().filter(|v| keep(v)).copied().collect(),
+                self.inside.iter().filter(|v| keep(v)).copied().collect(),
+                self.samples
+                    .iter()
+                    .filter(|(_, v)| keep(v))
+                    .copied()
+                    .collect(),
+                self.depth + 1,
+            )
+        };
+        (side(true), side(false))
+    }
+    // the points of both: where either was cut is on the bounds of one
+    fn merged(&self, other: &Piece) -> Piece {
+        Piece {
+            voxels: [self.voxels.as_slice(), &other.voxels].concat(),
+            inside: [self.inside.as_slice(), &other.inside].concat(),
+            samples: [self.samples.as_slice(), &other.samples].concat(),
+            points: [self.points.as_slice(), &other.points].concat(),
+            depth: self.depth.min(other.depth),
+        }
+    }
+    // sharing a face, edge or corner of their bounds
+    fn touches(&self, other: &Piece) -> bool {
+        let ((la, ha), (lb, hb)) = (bounds(&self.voxels), bounds(&other.voxels));
+        (0..3).all(|k| la[k] <= hb[k] + 1 && lb[k] <= ha[k] + 1)
+    }
+}
+
+fn bounds(voxels: &[[usize; 3]]) -> ([usize; 3], [usize; 3]) {
+    voxels
+        .iter()
+        .fold(([usize::MAX; 3], [0; 3]), |(low, high), v| {
+            (
+                [0, 1, 2].map(|k| low[k].min(v[k])),
+                [0, 1, 2].map(|k| high[k].max(v[k])),
+            )
+        })
+}
+
+// candidate cuts along each axis of a piece
+const CUTS: usize = 8;
+// points a piece is rated from when choosing where to cut it
+const ROUGH_POINTS: usize = 2000;
+
+fn decompose(mesh: &Polyhedron, params: &DecompositionParams) -> Vec<ConvexHull> {
+    let Some(whole) = mesh.convex_hull() else {
+        return Vec::new();
+    };
+    let total = whole.volume();
+    let voxels = Voxels::new(mesh, params.resolution);
+    let voxel_volume = voxels.size.powi(3);
+    let (mut solid, mut inside) = (Vec::new(), Vec::new());
+    for k in 0..voxels.dims[2] {
+        for j in 0..voxels.dims[1] {
+            for i in 0..voxels.dims[0] {
+                let cell = voxels.cells[voxels.index([i, j, k])];
+                if cell != 0 {
+                    solid.push([i, j, k]);
+                }
+                if cell & INSIDE != 0 {
+                    inside.push([i, j, k]);
+                }
+            }
+        }
+    }
+    let start = Piece::new(&voxels, solid, inside, voxels.samples.clone(), 0);
+
+    let concavity = |piece: &Piece| piece.concavity(voxel_volume, total, 1);
+    let mut pieces = Vec::new();
+    let mut open = vec![start];
+    while let Some(piece) = open.pop() {
+        if piece.depth >= params.max_depth
+            || piece.voxels.len() < 2
+            || concavity(&piece) <= params.concavity
+        {
+            pieces.push(piece);
+            continue;
+        }
+        let (low, high) = bounds(&piece.voxels);
+        let stride = piece.points.len().div_ceil(ROUGH_POINTS).max(1);
+        let best = (0..3)
+            .flat_map(|axis| {
+                let span = high[axis] - low[axis];
+                let step = span.div_ceil(CUTS).max(1);
+                (low[axis] + 1..=high[axis])
+                    .step_by(step)
+                    .map(move |at| (axis, at))
+            })
+            .map(|(axis, at)| {
+                let (below, above) = piece.split(&voxels, axis, at);
+                let rough = |side: &Piece| side.concavity(voxel_volume, total, stride);
+                let cost = rough(&below) + rough(&above);
+                (cost, below, above)
+            })
+            .filter(|(_, below, above)| !below.voxels.is_empty() && !above.voxels.is_empty())
+            .min_by(|a, b| a.0.total_cmp(&b.0));
+        match best {
+            Some((_, below, above)) => open.extend([below, above]),
+            None => pieces.push(piece),
+        }
+    }
+
+    // merge neighbours, cheapest first, keeping what each pair would cost
+    let mut pieces: Vec<Option<Piece>> = pieces.into_iter().map(Some).collect();
+    let mut costs: HashMap<(usize, usize), f32> = HashMap::new();
+    let mut count = pieces.len();
+    loop {
+        for j in 0..pieces.len() {
+            for i in 0..j {
+                let (Some(a), Some(b)) = (&pieces[i], &pieces[j]) else {
+                    continue;
+                };
+                if !costs.contains_key(&(i, j)) && (count > params.max_hulls || a.touches(b)) {
+                    costs.insert((i, j), concavity(&a.merged(b)));
+                }
+            }
+        }
+        let best = costs
+            .iter()
+            .map(|(&pair, &cost)| (pair, cost))
+            .min_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        match best {
+            Some(((i, j), cost)) if cost <= params.concavity || count > params.max_hulls => {
+                let (a, b) = (pieces[i].take().unwrap(), pieces[j].take().unwrap());
+                pieces.push(Some(a.merged(&b)));
+                costs.retain(|&(k, l), _| ![k, l].iter().any(|p| [i, j].contains(p)));
+                count -= 1;
+            }
+            _ => break,
+        }
+    }
+    pieces
+        .iter()
+        .flatten()
+        .filter_map(|piece| ConvexHull::new(&piece.points))
+        .collect()
+}
@@ -0,0 +1,279 @@
+// The topology of a robot, computed once from its joints: the root link, the
+// parent joint and children of every link, and an order that visits every
+// parent before its children. Algorithms that walk the robot (posing links,
+// kinematics, dynamics) index into this instead of searching the joint list.
+use crate::urdf::{Joint, JointType, RobotDescriptor};
+use std::collections::VecDeque;
+use std::fmt;
+
+mod forward;
+mod ik;
+mod jacobian;
+mod joint_state;
+pub use forward::{forward_kinematics, LinkPose, LinkPoses};
+pub use ik::{IkError, IkMethod, IkSolution, IkSolver, IkStatus, IkTarget};
+pub use jacobian::{jacobian, jacobian_with_poses, spatial_jacobian, Jacobian};
+pub use joint_state::{JointId, JointKey, JointState, JointStateError, LimitPolicy};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TopologyError {
+    NoLinks,
+    // a joint refers to a link index the robot does not have
+    InvalidLink { joint: String, link: usize },
+    MultipleParents { link: String, joints: Vec<String> },
+    // the links of one loop, each the parent of the next
+    Cycle { links: Vec<String> },
+    // links not reachable from the root, i.e. the roots of other trees and their descendants
+    Disconnected { root: String, links: Vec<String> },
+}
+
+impl fmt::Display for TopologyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopologyError::NoLinks => write!(f, "robot has no links"),
+            TopologyError::InvalidLink { joint, link } => {
+                write!(f, "joint \"{}\" refers to missing link {}", joint, link)
+            }
+            TopologyError::MultipleParents { link, joints } => write!(
+                f,
+                "link \"{}\" is the child of several joints: {}",
+                link,
+                joints.join(", ")
+            ),
+            TopologyError::Cycle { links } => {
+                write!(
+                    f,
+                    "joints form a cycle: {} -> {}",
+                    links.join(" -> "),
+                    links[0]
+                )
+            }
+            TopologyError::Disconnected { root, links } => write!(
+                f,
+                "links not connected to the root \"{}\": {}",
+                root,
+                links.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TopologyError {}
+
+// the default is the tree of a robot without links
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct KinematicTree {
+    root: usize,
+    // per link, indexed like RobotDescriptor::links
+    parent_joint: Vec<Option<usize>>,
+    parent: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    child_joints: Vec<Vec<usize>>,
+    depth: Vec<usize>,
+    // breadth first from the root
+    order: Vec<usize>,
+}
+
+impl KinematicTree {
+    // The root is the link no joint leads to. If there are several, links[0]
+    // is preferred, as the URDF parser has always treated it as the base.
+    pub fn new(robot: &RobotDescriptor) -> Result<Self, TopologyError> {
+        let n = robot.links.len();
+        if n == 0 {
+            return Err(TopologyError::NoLinks);
+        }
+        let link_name = |l: usize| robot.links[l].link_name.clone();
+        let mut parent_joint: Vec<Option<usize>> = vec![None; n];
+        let mut parent: Vec<Option<usize>> = vec![None; n];
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); n];
+        let mut child_joints: Vec<Vec<usize>> = vec![Vec::new(); n];
+        for (j, joint) in robot.joints.iter().enumerate() {
+            for link in [joint.parent(), joint.child()] {
+                if link >= n {
+                    return Err(TopologyError::InvalidLink {
+                        joint: joint.name().to_owned(),
+                        link,
+                    });
+                }
+            }
+            if parent_joint[joint.child()].is_some() {
+                let joints = robot
+                    .joints
+                    .iter()
+                    .filter(|other| other.child() == joint.child())
+                    .map(|other| other.name().to_owned())
+                    .collect();
+                return Err(TopologyError::MultipleParents {
+                    link: link_name(joint.child()),
+                    joints,
+                });
+            }
+            parent_joint[joint.child()] = Some(j);
+            parent[joint.child()] = Some(joint.parent());
+            children[joint.parent()].push(joint.child());
+            child_joints[joint.parent()].push(j);
+        }
+
+        let roots: Vec<usize> = (0..n).filter(|&l| parent[l].is_none()).collect();
+        // breadth first from every root, so links left over can only be on cycles
+        let mut depth = vec![usize::MAX; n];
+        let mut order = Vec::with_capacity(n);
+        let mut queue: VecDeque<usize> = roots.iter().copied().collect();
+        roots.iter().for_each(|&r| depth[r] = 0);
+        while let Some(link) = queue.pop_front() {
+            order.push(link);
+            for &child in &children[link] {
+                depth[child] = depth[link] + 1;
+                queue.push_back(child);
+            }
+        }
+        if let Some(start) = (0..n).find(|&l| depth[l] == usize::MAX) {
+            // every link has one parent, following them from `start` ends in the loop
+            let mut seen = vec![false; n];
+            let mut link = start;
+            while !seen[link] {
+                seen[link] = true;
+                link = parent[link].expect("links off the trees have parents");
+            }
+            let mut cycle = vec![link];
+            let mut next = parent[link].expect("links on a cycle have parents");
+            while next != link {
+                cycle.push(next);
+                next = parent[next].expect("links on a cycle have parents");
+            }
+            cycle.reverse();
+            return Err(TopologyError::Cycle {
+                links: cycle.into_iter().map(link_name).collect(),
+            });
+        }
+
+        let root = if parent[0].is_none() { 0 } else { roots[0] };
+        if roots.len() > 1 {
+            // the links below every other root
+            let mut other = vec![false; n];
+            for &link in &order {
+                other[link] = match parent[link] {
+                    Some(p) => other[p],
+                    None => link != root,
+                };
+            }
+            return Err(TopologyError::Disconnected {
+                root: link_name(root),
+                links: (0..n).filter(|&l| other[l]).map(link_name).collect(),
+            });
+        }
+        Ok(Self {
+            root,
+            parent_joint,
+            parent,
+            children,
+            child_joints,
+            depth,
+            order,
+        })
+    }
+    pub fn root(&self) -> usize {
+        self.root
+    }
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+    // the joint leading to `link`, None for the root
+    pub fn parent_joint(&self, link: usize) -> Option<usize> {
+        self.parent_joint[link]
+    }
+    pub fn parent(&self, link: usize) -> Option<usize> {
+        self.parent[link]
+    }
+    // child links, in the order of their joints
+    pub fn children(&self, link: usize) -> &[usize] {
+        &self.children[link]
+    }
+    pub fn child_joints(&self, link: usize) -> &[usize] {
+        &self.child_joints[link]
+    }
+    // number of joints between `link` and the root
+    pub fn depth(&self, link: usize) -> usize {
+        self.depth[link]
+    }
+    // every link, each after its parent, starting with the root
+    pub fn topological_order(&self) -> &[usize] {
+        &self.order
+    }
+    // the joints from the root down to `link`
+    pub fn joint_path(&self, link: usize) -> Vec<usize> {
+        let mut path = Vec::with_capacity(self.depth[link]);
+        let mut link = link;
+        while let Some(j) = self.parent_joint[link] {
+            path.push(j);
+            link = self.parent[link].expect("links with a parent joint have a parent");
+        }
+        path.reverse();
+        path
+    }
+}
+
+// Two unit vectors spanning the plane normal to `axis`, the x and y of a
+// planar joint; for an axis along z they are x and y
+pub fn planar_basis(axis: &glm::Vec3) -> (glm::Vec3, glm::Vec3) {
+    let n = glm::normalize(axis);
+    let e = if n.x.abs() < 0.9 {
+        glm::Vec3::x()
+    } else {
+        glm::Vec3::y()
+    };
+    let u = glm::normalize(&(e - n * glm::dot(&e, &n)));
+    (u, glm::cross(&n, &u))
+}
+
+// The motion of a joint away from its origin for the joint coordinates `q`
+// (as laid out in JointState), in the joint frame; axes need not be unit
+// length, prismatic joints move q along the direction of theirs
+pub fn joint_motion(joint: &Joint, q: &[f32]) -> glm::Mat4 {
+    let axis = || joint.axis().unwrap_or(glm::Vec3::x());
+    match joint.joint_type() {
+        JointType::Fixed => glm::Mat4::identity(),
+        JointType::Revolute | JointType::Continuous => glm::rotation(q[0], &axis()),
+        JointType::Prismatic => glm::translation(&(glm::normalize(&axis()) * q[0])),
+        JointType::Planar => {
+            let (u, v) = planar_basis(&axis());
+            glm::translation(&(u * q[0] + v * q[1])) * glm::rotation(q[2], &axis())
+        }
+        JointType::Floating => {
+            let rotation = glm::quat_normalize(&glm::quat(q[3], q[4], q[5], q[6]));
+            glm::translation(&glm::vec3(q[0], q[1], q[2])) * glm::quat_to_mat4(&rotation)
+        }
+    }
+}
+
+// The motion of the child link origin per unit velocity of each joint
+// coordinate, as (linear, angular) pairs; angular motion turns about the
+// child link origin. `rotation` takes joint frame vectors (the parent link
+// frame at the joint origin) to the frame the result is wanted in.
+pub fn motion_subspace(joint: &Joint, rotation: &glm::Mat3) -> Vec<(glm::Vec3, glm::Vec3)> {
+    let axis = joint.axis().unwrap_or(glm::Vec3::x());
+    let zero = glm::Vec3::zeros();
+    match joint.joint_type() {
+        JointType::Fixed => Vec::new(),
+        JointType::Revolute | JointType::Continuous => {
+            vec![(zero, glm::normalize(&(rotation * axis)))]
+        }
+        JointType::Prismatic => vec![(glm::normalize(&(rotation * axis)), zero)],
+        JointType::Planar => {
+            let (u, v) = planar_basis(&axis);
+            vec![
+                (rotation * u, zero),
+                (rotation * v, zero),
+                (zero, glm::normalize(&(rotation * axis))),
+            ]
+        }
+        JointType::Floating => {
+            let linear = (0..3).map(|i| (rotation.column(i).into_owned(), zero));
+            let angular = (0..3).map(|i| (zero, rotation.column(i).into_owned()));
+            linear.chain(angular).collect()
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// Forward kinematics: the world pose of every link of a robot, and of its
+// visual, collision and inertial frames, for one JointState. Nothing on the
+// robot is changed, so any number of configurations can be queried while
+// another one is being rendered.
+//
+// Frames follow URDF: a link frame is the frame of the joint leading to it,
+// at the joint origin (x y z then fixed axis roll pitch yaw) moved by the
+// joint coordinates; bodies sit at their own origin in the link frame.
+use super::{joint_motion, JointId, JointState, KinematicTree};
+use crate::urdf::RobotDescriptor;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkPose {
+    pub link: glm::Mat4,
+    pub visual: glm::Mat4,
+    pub collision: glm::Mat4,
+    pub inertial: glm::Mat4,
+}
+
+// One pose per link, indexed like RobotDescriptor::links
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkPoses {
+    poses: Vec<LinkPose>,
+    by_name: HashMap<String, usize>,
+}
+
+impl LinkPoses {
+    pub fn len(&self) -> usize {
+        self.poses.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.poses.is_empty()
+    }
+    pub fn get(&self, link: usize) -> Option<&LinkPose> {
+        self.poses.get(link)
+    }
+    pub fn by_name(&self, link: &str) -> Option<&LinkPose> {
+        self.by_name.get(link).map(|&i| &self.poses[i])
+    }
+    // world pose of the link frame
+    pub fn link(&self, link: usize) -> &glm::Mat4 {
+        &self.poses[link].link
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &LinkPose> {
+        self.poses.iter()
+    }
+}
+
+impl std::ops::Index<usize> for LinkPoses {
+    type Output = LinkPose;
+    fn index(&self, link: usize) -> &LinkPose {
+        &self.poses[link]
+    }
+}
+
+// `base` is the world pose of the root link, the identity if None; joints
+// missing from `state` (fixed ones) stay at their origin
+pub fn forward_kinematics(
+    robot: &RobotDescriptor,
+    tree: &KinematicTree,
+    state: &JointState,
+    base: Option<&glm::Mat4>,
+) -> LinkPoses {
+    let mut frames = vec![glm::Mat4::identity(); robot.links.len()];
+    frames[tree.root()] = base.copied().unwrap_or_else(glm::Mat4::identity);
+    for &link in &tree.topological_order()[1..] {
+        let j = tree
+            .parent_joint(link)
+            .expect("only the root has no parent");
+        let joint = &robot.joints[j];
+        let motion = match state.positions(JointId::from_index(j)) {
+            Some(q) => joint_motion(joint, q),
+            None => glm::Mat4::identity(),
+        };
+        frames[link] = frames[joint.parent()] * joint.origin().matrix() * motion;
+    }
+    let poses = std::iter::zip(&robot.links, frames)
+        .map(|(l, frame)| LinkPose {
+            link: frame,
+            visual: frame * l.visual.origin.matrix(),
+            collision: frame * l.collision.origin.matrix(),
+            inertial: frame * l.inertial.origin.matrix(),
+        })
+        .collect();
+    let by_name = robot
+        .links
+        .iter()
+        .enumerate()
+        .map(|(i, l)| (l.link_name.clone(), i))
+        .collect();
+    LinkPoses { poses, by_name }
+}
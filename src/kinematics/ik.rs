@@ -0,0 +1,425 @@
+// Numerical inverse kinematics for the serial chain between two links of a
+// robot. Every iteration linearises the chain with its Jacobian, takes a
+// damped least squares or Levenberg-Marquardt step on the weighted pose error
+// of the tip and clamps the joints to their limits. When an attempt does not
+// converge the solver can restart from random configurations and keeps the
+// best one it found.
+//
+// Only the revolute, continuous and prismatic joints of the chain move; a
+// mimic joint on the chain moves the joint it follows, wherever that is.
+// Planar and floating joints keep the position they have in the seed.
+use super::{forward_kinematics, jacobian, JointId, JointState, KinematicTree, LimitPolicy};
+use crate::urdf::{JointType, RobotDescriptor};
+use nalgebra::{DMatrix, DVector, UnitQuaternion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::f32::consts::PI;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IkError {
+    UnknownLink(String),
+    // `tip` is not below `base`
+    NotAChain { base: String, tip: String },
+}
+
+impl fmt::Display for IkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IkError::UnknownLink(link) => write!(f, "no link \"{}\"", link),
+            IkError::NotAChain { base, tip } => {
+                write!(f, "link \"{}\" is not below link \"{}\"", tip, base)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IkError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IkMethod {
+    // dq = J^T (J J^T + damping^2 I)^-1 e
+    DampedLeastSquares { damping: f32 },
+    // (J^T J + lambda diag(J^T J)) dq = J^T e, lambda shrinking after steps
+    // that reduce the error and growing after the ones that are rejected
+    LevenbergMarquardt { initial_lambda: f32 },
+}
+
+impl Default for IkMethod {
+    fn default() -> Self {
+        IkMethod::LevenbergMarquardt {
+            initial_lambda: 1e-2,
+        }
+    }
+}
+
+// Where the tip should be, in the frame of the base link
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IkTarget {
+    pub pose: glm::Mat4,
+    // of the x y z position error, then of the rotation error about x y z;
+    // a zero weight leaves that component free
+    pub weights: [f32; 6],
+}
+
+impl IkTarget {
+    pub fn pose(pose: glm::Mat4) -> Self {
+        Self {
+            pose,
+            weights: [1.0; 6],
+        }
+    }
+    // any orientation will do
+    pub fn position(position: glm::Vec3) -> Self {
+        Self {
+            pose: glm::translation(&position),
+            weights: [1.0, 1.0, 1.0, 0.0, 0.0, 0.0],
+        }
+    }
+    pub fn with_weights(mut self, weights: [f32; 6]) -> Self {
+        self.weights = weights;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IkStatus {
+    Converged,
+    // ran out of iterations while still improving
+    MaxIterations,
+    // the steps became too small to make progress, e.g. at a limit or a
+    // singularity, or with the target out of reach
+    Stalled,
+}
+
+#[derive(Debug, Clone)]
+pub struct IkSolution {
+    // the seed with the chain joints moved
+    pub state: JointState,
+    pub status: IkStatus,
+    // norm of the weighted pose error
+    pub residual: f32,
+    // of the weighted components, in metres and radians
+    pub position_error: f32,
+    pub orientation_error: f32,
+    // over every attempt, and the number of attempts after the first
+    pub iterations: usize,
+    pub restarts: usize,
+}
+
+impl IkSolution {
+    pub fn converged(&self) -> bool {
+        self.status == IkStatus::Converged
+    }
+}
+
+// A joint the solver moves and its column in the Jacobian
+#[derive(Debug, Clone, Copy)]
+struct Variable {
+    id: JointId,
+    column: usize,
+    bounds: Option<(f32, f32)>,
+}
+
+// The pose of the tip and its Jacobian, both in the base link frame
+struct Linearisation {
+    position: glm::Vec3,
+    rotation: UnitQuaternion<f32>,
+    jacobian: DMatrix<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IkSolver<'a> {
+    robot: &'a RobotDescriptor,
+    tree: &'a KinematicTree,
+    base: usize,
+    tip: usize,
+    tip_offset: glm::Mat4,
+    chain: Vec<usize>,
+    method: IkMethod,
+    max_iterations: usize,
+    position_tolerance: f32,
+    orientation_tolerance: f32,
+    restarts: usize,
+    seed: u64,
+}
+
+impl<'a> IkSolver<'a> {
+    pub fn new(robot: &'a RobotDescriptor, base: &str, tip: &str) -> Result<Self, IkError> {
+        let tree = robot.kinematic_tree();
+        let find = |name: &str| {
+            robot
+                .link_index(name)
+                .ok_or_else(|| IkError::UnknownLink(name.to_owned()))
+        };
+        let (base_link, tip_link) = (find(base)?, find(tip)?);
+        let above = tree.joint_path(base_link);
+        let path = tree.joint_path(tip_link);
+        if !path.starts_with(&above) || (base_link != tip_link && path.len() == above.len()) {
+            return Err(IkError::NotAChain {
+                base: base.to_owned(),
+                tip: tip.to_owned(),
+            });
+        }
+        Ok(Self {
+            robot,
+            base: base_link,
+            tip: tip_link,
+            tip_offset: glm::Mat4::identity(),
+            chain: path[above.len()..].to_vec(),
+            tree,
+            method: IkMethod::default(),
+            max_iterations: 100,
+            position_tolerance: 1e-4,
+            orientation_tolerance: 1e-3,
+            restarts: 0,
+            seed: 0,
+        })
+    }
+    // solve for a frame fixed on the tip link, e.g. a tool centre point,
+    // instead of the tip link frame
+    pub fn with_tip_offset(mut self, offset: glm::Mat4) -> Self {
+        self.tip_offset = offset;
+        self
+    }
+    pub fn with_method(mut self, method: IkMethod) -> Self {
+        self.method = method;
+        self
+    }
+    // per attempt
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+    pub fn with_tolerances(mut self, position: f32, orientation: f32) -> Self {
+        self.position_tolerance = position;
+        self.orientation_tolerance = orientation;
+        self
+    }
+    // random restarts after the attempt from the seed, drawn from `seed` so
+    // solutions are repeatable
+    pub fn with_restarts(mut self, restarts: usize, seed: u64) -> Self {
+        self.restarts = restarts;
+        self.seed = seed;
+        self
+    }
+
+    pub fn solve(&self, target: &IkTarget, seed: &JointState) -> IkSolution {
+        let policy = seed.limit_policy();
+        let mut state = seed.clone().with_limit_policy(LimitPolicy::Clamp);
+        let variables = self.variables(&state);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut best: Option<IkSolution> = None;
+        let (mut iterations, mut restarts) = (0, 0);
+        for restart in 0..=self.restarts {
+            restarts = restart;
+            if restart > 0 {
+                state = seed.clone().with_limit_policy(LimitPolicy::Clamp);
+                self.randomise(&mut state, &variables, &mut rng);
+            }
+            let (status, used) = self.attempt(target, &mut state, &variables);
+            iterations += used;
+            let error = self.target_error(target, &self.linearise(&state, &[]));
+            let solution = IkSolution {
+                state: state.clone().with_limit_policy(policy),
+                status,
+                residual: weighted_norm(&error, &target.weights),
+                position_error: masked_norm(&error[..3], &target.weights[..3]),
+                orientation_error: masked_norm(&error[3..], &target.weights[3..]),
+                iterations,
+                restarts: restart,
+            };
+            if best.as_ref().is_none_or(|b| solution.residual < b.residual) {
+                best = Some(solution);
+            }
+            if status == IkStatus::Converged {
+                break;
+            }
+        }
+        let mut best = best.expect("there is at least one attempt");
+        best.iterations = iterations;
+        best.restarts = restarts;
+        best
+    }
+
+    // the joints that move the tip, leaders of mimic joints included once
+    fn variables(&self, state: &JointState) -> Vec<Variable> {
+        let mut variables: Vec<Variable> = Vec::new();
+        for &j in &self.chain {
+            let id = match state.mimic(JointId::from_index(j)) {
+                Some((leader, _, _)) => leader,
+                None => JointId::from_index(j),
+            };
+            let joint = &self.robot.joints[id.index()];
+            let bounds = match (joint.joint_type(), joint.limits()) {
+                (JointType::Revolute | JointType::Prismatic, Some(l)) => {
+                    Some((l.lower(), l.upper()))
+                }
+                (JointType::Revolute | JointType::Continuous | JointType::Prismatic, _) => None,
+                _ => continue,
+            };
+            let Some(column) = state.velocity_offset(id) else {
+                continue;
+            };
+            if variables.iter().all(|v| v.id != id) {
+                variables.push(Variable { id, column, bounds });
+            }
+        }
+        variables
+    }
+    fn randomise(&self, state: &mut JointState, variables: &[Variable], rng: &mut StdRng) {
+        for v in variables {
+            let q = match (v.bounds, self.robot.joints[v.id.index()].joint_type()) {
+                (Some((lower, upper)), _) if lower < upper => rng.gen_range(lower..upper),
+                (None, JointType::Revolute | JointType::Continuous) => rng.gen_range(-PI..PI),
+                // unbounded prismatic joints stay where the seed has them
+                _ => continue,
+            };
+            state
+                .set_position(v.id, q)
+                .expect("variables are single coordinate joints");
+        }
+    }
+
+    fn attempt(
+        &self,
+        target: &IkTarget,
+        state: &mut JointState,
+        variables: &[Variable],
+    ) -> (IkStatus, usize) {
+        let weights = DVector::from_row_slice(&target.weights);
+        let mut lambda = match self.method {
+            IkMethod::DampedLeastSquares { damping } => damping,
+            IkMethod::LevenbergMarquardt { initial_lambda } => initial_lambda,
+        };
+        let mut current = self.linearise(state, variables);
+        let mut current_error = self.target_error(target, &current);
+        for iteration in 0..self.max_iterations {
+            if self.converged(target, &current_error) {
+                return (IkStatus::Converged, iteration);
+            }
+            let e = DVector::from_row_slice(&current_error).component_mul(&weights);
+            let jw = DMatrix::from_diagonal(&weights) * &current.jacobian;
+            let step = match self.method {
+                IkMethod::DampedLeastSquares { .. } => {
+                    let a = &jw * jw.transpose() + DMatrix::identity(6, 6) * lambda * lambda;
+                    a.lu().solve(&e).map(|x| jw.transpose() * x)
+                }
+                IkMethod::LevenbergMarquardt { .. } => {
+                    let jtj = jw.transpose() * &jw;
+                    let mut a = jtj.clone();
+                    for i in 0..a.nrows() {
+                        a[(i, i)] += lambda * jtj[(i, i)] + 1e-9;
+                    }
+                    a.cholesky().map(|c| c.solve(&(jw.transpose() * &e)))
+                }
+            };
+            let Some(step) = step else {
+                return (IkStatus::Stalled, iteration);
+            };
+            let mut candidate = state.clone();
+            let moved = self.apply(&mut candidate, variables, &step);
+            if moved < 1e-7 {
+                return (IkStatus::Stalled, iteration + 1);
+            }
+            let next = self.linearise(&candidate, variables);
+            let next_error = self.target_error(target, &next);
+            if let IkMethod::LevenbergMarquardt { .. } = self.method {
+                if weighted_norm(&next_error, &target.weights)
+                    >= weighted_norm(&current_error, &target.weights)
+                {
+                    lambda *= 10.0;
+                    if lambda > 1e8 {
+                        return (IkStatus::Stalled, iteration + 1);
+                    }
+                    continue;
+                }
+                lambda = (lambda / 10.0).max(1e-9);
+            }
+            *state = candidate;
+            current = next;
+            current_error = next_error;
+        }
+        let status = if self.converged(target, &current_error) {
+            IkStatus::Converged
+        } else {
+            IkStatus::MaxIterations
+        };
+        (status, self.max_iterations)
+    }
+    // moves the variables by `step` within their bounds, returns how far they moved
+    fn apply(&self, state: &mut JointState, variables: &[Variable], step: &DVector<f32>) -> f32 {
+        let mut moved = 0.0;
+        for (v, dq) in std::iter::zip(variables, step.iter()) {
+            let q = state
+                .position(v.id)
+                .expect("variables are single coordinate joints");
+            let mut next = q + dq;
+            if let Some((lower, upper)) = v.bounds {
+                next = next.max(lower).min(upper);
+            }
+            state
+                .set_position(v.id, next)
+                .expect("variables are single coordinate joints");
+            moved += (next - q) * (next - q);
+        }
+        moved.sqrt()
+    }
+
+    // the tip pose in the base frame and the Jacobian columns of `variables`
+    fn linearise(&self, state: &JointState, variables: &[Variable]) -> Linearisation {
+        let poses = forward_kinematics(self.robot, self.tree, state, None);
+        let base = poses.link(self.base);
+        let tip = glm::inverse(base) * poses.link(self.tip) * self.tip_offset;
+        let mut columns = DMatrix::zeros(6, variables.len());
+        if !variables.is_empty() {
+            let point = self.tip_offset.column(3).xyz();
+            let world = jacobian(self.robot, self.tree, state, None, self.tip, &point);
+            let to_base = glm::mat4_to_mat3(base).transpose();
+            for (k, v) in variables.iter().enumerate() {
+                let column = world.column(v.column);
+                let linear = to_base * glm::vec3(column[0], column[1], column[2]);
+                let angular = to_base * glm::vec3(column[3], column[4], column[5]);
+                for i in 0..3 {
+                    columns[(i, k)] = linear[i];
+                    columns[(i + 3, k)] = angular[i];
+                }
+            }
+        }
+        Linearisation {
+            position: tip.column(3).xyz(),
+            rotation: UnitQuaternion::from_matrix(&glm::mat4_to_mat3(&tip)),
+            jacobian: columns,
+        }
+    }
+    // target minus tip, the rotation error as the rotation vector taking the
+    // tip orientation to the target one, in the base frame
+    fn target_error(&self, target: &IkTarget, tip: &Linearisation) -> [f32; 6] {
+        let goal = UnitQuaternion::from_matrix(&glm::mat4_to_mat3(&target.pose));
+        let linear = target.pose.column(3).xyz() - tip.position;
+        let angular = (goal * tip.rotation.inverse()).scaled_axis();
+        [
+            linear.x, linear.y, linear.z, angular.x, angular.y, angular.z,
+        ]
+    }
+    fn converged(&self, target: &IkTarget, error: &[f32; 6]) -> bool {
+        masked_norm(&error[..3], &target.weights[..3]) <= self.position_tolerance
+            && masked_norm(&error[3..], &target.weights[3..]) <= self.orientation_tolerance
+    }
+}
+
+fn weighted_norm(error: &[f32], weights: &[f32]) -> f32 {
+    std::iter::zip(error, weights)
+        .map(|(e, w)| (e * w) * (e * w))
+        .sum::<f32>()
+        .sqrt()
+}
+
+// norm of the components with a non zero weight
+fn masked_norm(error: &[f32], weights: &[f32]) -> f32 {
+    std::iter::zip(error, weights)
+        .filter(|(_, w)| **w != 0.0)
+        .map(|(e, _)| e * e)
+        .sum::<f32>()
+        .sqrt()
+}
@@ -0,0 +1,92 @@
+// Jacobians mapping the joint velocities of a JointState to the velocity of a
+// link, or of a point fixed on it, in the world frame. The rows are the
+// linear velocity then the angular velocity, the columns follow the velocity
+// vector of the JointState; mimic joints add to the column of the joint they
+// follow, scaled by their multiplier.
+//
+// The geometric Jacobian gives the velocity of the point itself. The spatial
+// Jacobian gives the twist of the link at the world origin, i.e. the velocity
+// of the point moving with the link that is at the world origin right now.
+//
+// Planar joints move along planar_basis(axis) and turn about the axis at the
+// child link origin. Floating joints move the child link origin along and
+// turn it about the x y z axes of the frame they are attached in (the parent
+// link frame at the joint origin).
+use super::{forward_kinematics, motion_subspace, JointId, JointState, KinematicTree, LinkPoses};
+use crate::urdf::RobotDescriptor;
+
+pub type Jacobian = nalgebra::Matrix6xX<f32>;
+
+// geometric Jacobian of `point`, given in the frame of `link`; `base` is the
+// world pose of the root link as for forward_kinematics
+pub fn jacobian(
+    robot: &RobotDescriptor,
+    tree: &KinematicTree,
+    state: &JointState,
+    base: Option<&glm::Mat4>,
+    link: usize,
+    point: &glm::Vec3,
+) -> Jacobian {
+    let poses = forward_kinematics(robot, tree, state, base);
+    let point = (poses.link(link) * point.push(1.0)).xyz();
+    columns(robot, tree, state, &poses, link, &point)
+}
+
+// geometric Jacobian of the world point `point` moving with `link`, for the
+// poses forward_kinematics gave for `state`; saves posing the robot again
+// when many points are wanted
+pub fn jacobian_with_poses(
+    robot: &RobotDescriptor,
+    tree: &KinematicTree,
+    state: &JointState,
+    poses: &LinkPoses,
+    link: usize,
+    point: &glm::Vec3,
+) -> Jacobian {
+    columns(robot, tree, state, poses, link, point)
+}
+
+pub fn spatial_jacobian(
+    robot: &RobotDescriptor,
+    tree: &KinematicTree,
+    state: &JointState,
+    base: Option<&glm::Mat4>,
+    link: usize,
+) -> Jacobian {
+    let poses = forward_kinematics(robot, tree, state, base);
+    columns(robot, tree, state, &poses, link, &glm::Vec3::zeros())
+}
+
+// velocity of the world point `point` moving with `link`, per joint velocity
+fn columns(
+    robot: &RobotDescriptor,
+    tree: &KinematicTree,
+    state: &JointState,
+    poses: &LinkPoses,
+    link: usize,
+    point: &glm::Vec3,
+) -> Jacobian {
+    let mut jacobian = Jacobian::zeros(state.nv());
+    for j in tree.joint_path(link) {
+        let joint = &robot.joints[j];
+        // fixed joints have no column
+        let Some((column, scale)) = state.velocity_column(JointId::from_index(j)) else {
+            continue;
+        };
+        let frame = poses.link(joint.parent()) * joint.origin().matrix();
+        let origin = poses.link(joint.child()).column(3).xyz();
+        let arm = point - origin;
+        for (c, (linear, angular)) in motion_subspace(joint, &glm::mat4_to_mat3(&frame))
+            .into_iter()
+            .enumerate()
+        {
+            let linear = linear + angular.cross(&arm);
+            let mut col = jacobian.column_mut(column + c);
+            for i in 0..3 {
+                col[i] += scale * linear[i];
+                col[i + 3] += scale * angular[i];
+            }
+        }
+    }
+    jacobian
+}
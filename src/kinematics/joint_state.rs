@@ -0,0 +1,729 @@
+// Positions, velocities and efforts of the movable joints of a robot, looked
+// up by joint name or JointId and stored as dense vectors.
+//
+// The dense vectors hold the joints in the order of RobotDescriptor::joints,
+// fixed and mimic joints left out, with the coordinates of each joint next to
+// each other:
+//  - revolute, continuous and prismatic joints: the angle or distance
+//  - planar joints: x and y along planar_basis(axis), then the angle about the axis
+//  - floating joints: the position x y z, then the orientation as a unit
+//    quaternion i j k w; velocities and efforts are linear then angular, so
+//    these have one position coordinate more than velocity coordinates
+//
+// Mimic joints follow the joint they mimic whenever it is written and cannot
+// be set themselves. Positions written to revolute and prismatic joints with
+// limits go through the LimitPolicy, and so do the positions mimic joints
+// follow to.
+use crate::urdf::{JointLimits, JointType, RobotDescriptor};
+use std::collections::HashMap;
+use std::fmt;
+
+// A joint by its index in RobotDescriptor::joints, stable for a given robot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JointId(usize);
+
+impl JointId {
+    pub(crate) fn from_index(index: usize) -> Self {
+        Self(index)
+    }
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// What to do with a position outside the limits of a joint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LimitPolicy {
+    // move it to the nearest limit
+    #[default]
+    Clamp,
+    // refuse it with JointStateError::OutOfRange and leave the state as it was
+    Error,
+    // take it as it is and log a warning
+    Warn,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JointStateError {
+    // not a joint of the robot, or a fixed one
+    UnknownJoint(String),
+    // the number of values does not match the joint (or the whole state if `joint` is None)
+    WrongLength {
+        joint: Option<String>,
+        expected: usize,
+        got: usize,
+    },
+    OutOfRange {
+        joint: String,
+        value: f32,
+        lower: f32,
+        upper: f32,
+    },
+    // mimic joints are set through the joint they follow
+    MimicJoint(String),
+    // commands move over a time that is finite and not negative
+    InvalidTimestep(f32),
+}
+
+impl fmt::Display for JointStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JointStateError::UnknownJoint(joint) => {
+                write!(f, "no movable joint \"{}\"", joint)
+            }
+            JointStateError::WrongLength {
+                joint: Some(joint),
+                expected,
+                got,
+            } => write!(
+                f,
+                "joint \"{}\" takes {} values, got {}",
+                joint, expected, got
+            ),
+            JointStateError::WrongLength {
+                joint: None,
+                expected,
+                got,
+            } => write!(f, "expected a vector of {} values, got {}", expected, got),
+            JointStateError::OutOfRange {
+                joint,
+                value,
+                lower,
+                upper,
+            } => write!(
+                f,
+                "position {} of joint \"{}\" is outside its limits [{}, {}]",
+                value, joint, lower, upper
+            ),
+            JointStateError::MimicJoint(joint) => {
+                write!(
+                    f,
+                    "joint \"{}\" mimics another joint and cannot be set",
+                    joint
+                )
+            }
+            JointStateError::InvalidTimestep(dt) => {
+                write!(f, "cannot move joints over a timestep of {} s", dt)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JointStateError {}
+
+// How a joint is named when reading or writing the state
+pub trait JointKey {
+    fn find(&self, state: &JointState) -> Option<usize>;
+    fn describe(&self) -> String;
+}
+
+impl JointKey for &str {
+    fn find(&self, state: &JointState) -> Option<usize> {
+        state.by_name.get(*self).copied()
+    }
+    fn describe(&self) -> String {
+        (*self).to_owned()
+    }
+}
+
+impl JointKey for &String {
+    fn find(&self, state: &JointState) -> Option<usize> {
+        JointKey::find(&self.as_str(), state)
+    }
+    fn describe(&self) -> String {
+        (*self).clone()
+    }
+}
+
+impl JointKey for JointId {
+    fn find(&self, state: &JointState) -> Option<usize> {
+        state.by_id.get(self.0).copied().flatten()
+    }
+    fn describe(&self) -> String {
+        format!("#{}", self.0)
+    }
+}
+
+// position = multiplier * position of `leader` + offset, where the leader is
+// the slot of a joint that mimics nothing (chains of mimics are folded)
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SlotMimic {
+    leader: usize,
+    multiplier: f32,
+    offset: f32,
+}
+
+// A movable joint and where its coordinates start in the dense vectors, or in
+// the mimic vectors for mimic joints
+#[derive(Debug, Clone, PartialEq)]
+struct Slot {
+    joint: JointId,
+    name: String,
+    joint_type: JointType,
+    position: usize,
+    velocity: usize,
+    limits: Option<JointLimits>,
+    mimic: Option<SlotMimic>,
+}
+
+impl Slot {
+    // position bounds, for the joint types that have them
+    fn bounds(&self) -> Option<(f32, f32)> {
+        match (self.joint_type, self.limits) {
+            (JointType::Revolute | JointType::Prismatic, Some(l)) => Some((l.lower(), l.upper())),
+            _ => None,
+        }
+    }
+    // largest speed, if the joint has a positive velocity limit
+    fn max_velocity(&self) -> Option<f32> {
+        self.limits
+            .map(|l| l.velocity())
+            .filter(|v| *v > 0.0 && v.is_finite())
+    }
+}
+
+fn check_timestep(dt: f32) -> Result<(), JointStateError> {
+    if dt.is_finite() && dt >= 0.0 {
+        Ok(())
+    } else {
+        Err(JointStateError::InvalidTimestep(dt))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct JointState {
+    slots: Vec<Slot>,
+    by_name: HashMap<String, usize>,
+    // slot of each joint of the robot, None for fixed joints
+    by_id: Vec<Option<usize>>,
+    policy: LimitPolicy,
+    position: Vec<f32>,
+    velocity: Vec<f32>,
+    effort: Vec<f32>,
+    // one value per mimic joint
+    mimic_position: Vec<f32>,
+    mimic_velocity: Vec<f32>,
+    mimic_effort: Vec<f32>,
+}
+
+#[derive(Clone, Copy)]
+enum Quantity {
+    Position,
+    Velocity,
+    Effort,
+}
+
+impl JointState {
+    // every joint at zero, floating joints at the identity orientation;
+    // mimic joints at their offset
+    pub fn new(robot: &RobotDescriptor) -> Self {
+        let mut slots = Vec::new();
+        let (mut nq, mut nv, mut n_mimic) = (0, 0, 0);
+        for (i, joint) in robot.joints.iter().enumerate() {
+            let joint_type = joint.joint_type();
+            if joint_type == JointType::Fixed {
+                continue;
+            }
+            let (position, velocity) = if joint.mimic().is_some() {
+                n_mimic += 1;
+                (n_mimic - 1, n_mimic - 1)
+            } else {
+                nq += joint_type.position_dofs();
+                nv += joint_type.velocity_dofs();
+                (
+                    nq - joint_type.position_dofs(),
+                    nv - joint_type.velocity_dofs(),
+                )
+            };
+            slots.push(Slot {
+                joint: JointId(i),
+                name: joint.name().to_owned(),
+                joint_type,
+                position,
+                velocity,
+                limits: joint.limits(),
+                mimic: None,
+            });
+        }
+        let by_name: HashMap<String, usize> = slots
+            .iter()
+            .enumerate()
+            .map(|(i, s)| (s.name.clone(), i))
+            .collect();
+        // fold chains of mimics onto the joint at the end; the parser checks
+        // that they end and only connect single coordinate joints
+        for i in 0..slots.len() {
+            let mut mimic = robot.joints[slots[i].joint.0].mimic();
+            let (mut multiplier, mut offset) = (1.0, 0.0);
+            let mut leader = i;
+            while let Some(m) = mimic {
+                let Some(&next) = by_name.get(&m.joint) else {
+                    break;
+                };
+                offset += multiplier * m.offset;
+                multiplier *= m.multiplier;
+                leader = next;
+                mimic = robot.joints[slots[next].joint.0].mimic();
+            }
+            if leader != i {
+                slots[i].mimic = Some(SlotMimic {
+                    leader,
+                    multiplier,
+                    offset,
+                });
+            }
+        }
+        let mut position = vec![0.0; nq];
+        for slot in slots
+            .iter()
+            .filter(|s| s.joint_type == JointType::Floating && s.mimic.is_none())
+        {
+            position[slot.position + 6] = 1.0;
+        }
+        let mut by_id = vec![None; robot.joints.len()];
+        for (i, slot) in slots.iter().enumerate() {
+            by_id[slot.joint.0] = Some(i);
+        }
+        let mut state = Self {
+            by_id,
+            by_name,
+            slots,
+            policy: LimitPolicy::default(),
+            position,
+            velocity: vec![0.0; nv],
+            effort: vec![0.0; nv],
+            mimic_position: vec![0.0; n_mimic],
+            mimic_velocity: vec![0.0; n_mimic],
+            mimic_effort: vec![0.0; n_mimic],
+        };
+        state
+            .update_mimics()
+            .expect("clamping takes every position");
+        state
+    }
+    pub fn with_limit_policy(mut self, policy: LimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+    pub fn set_limit_policy(&mut self, policy: LimitPolicy) {
+        self.policy = policy;
+    }
+    pub fn limit_policy(&self) -> LimitPolicy {
+        self.policy
+    }
+    // number of joints in the dense vectors
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|s| s.mimic.is_none()).count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    // length of the dense position vector
+    pub fn nq(&self) -> usize {
+        self.position.len()
+    }
+    // length of the dense velocity and effort vectors
+    pub fn nv(&self) -> usize {
+        self.velocity.len()
+    }
+    pub fn id(&self, name: &str) -> Option<JointId> {
+        self.by_name.get(name).map(|&i| self.slots[i].joint)
+    }
+    pub fn name(&self, joint: impl JointKey) -> Option<&str> {
+        joint.find(self).map(|i| self.slots[i].name.as_str())
+    }
+    // the joints of the dense vectors, in their order
+    pub fn ids(&self) -> impl Iterator<Item = JointId> + '_ {
+        self.dense_slots().map(|s| s.joint)
+    }
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.dense_slots().map(|s| s.name.as_str())
+    }
+    // the mimic joints, which follow the joints of the dense vectors
+    pub fn mimic_ids(&self) -> impl Iterator<Item = JointId> + '_ {
+        self.slots
+            .iter()
+            .filter(|s| s.mimic.is_some())
+            .map(|s| s.joint)
+    }
+    pub fn joint_type(&self, joint: impl JointKey) -> Option<JointType> {
+        joint.find(self).map(|i| self.slots[i].joint_type)
+    }
+    pub fn is_mimic(&self, joint: impl JointKey) -> bool {
+        joint
+            .find(self)
+            .is_some_and(|i| self.slots[i].mimic.is_some())
+    }
+    // the joint a mimic joint follows, once chains of mimics are folded, with
+    // the multiplier and offset onto it
+    pub fn mimic(&self, joint: impl JointKey) -> Option<(JointId, f32, f32)> {
+        let m = self.slots[joint.find(self)?].mimic?;
+        Some((self.slots[m.leader].joint, m.multiplier, m.offset))
+    }
+    // where the velocities that move a joint start in the velocity vector and
+    // how much it moves per unit of them: its own with 1, or for a mimic
+    // joint those of the joint it follows with the multiplier
+    pub(crate) fn velocity_column(&self, joint: JointId) -> Option<(usize, f32)> {
+        let slot = &self.slots[joint.find(self)?];
+        match slot.mimic {
+            Some(m) => Some((self.slots[m.leader].velocity, m.multiplier)),
+            None => Some((slot.velocity, 1.0)),
+        }
+    }
+    // where the joint's coordinates start in the position vector, None for mimic joints
+    pub fn position_offset(&self, joint: impl JointKey) -> Option<usize> {
+        let slot = &self.slots[joint.find(self)?];
+        slot.mimic.is_none().then_some(slot.position)
+    }
+    // where the joint's coordinates start in the velocity and effort vectors
+    pub fn velocity_offset(&self, joint: impl JointKey) -> Option<usize> {
+        let slot = &self.slots[joint.find(self)?];
+        slot.mimic.is_none().then_some(slot.velocity)
+    }
+
+    fn dense_slots(&self) -> impl Iterator<Item = &Slot> {
+        self.slots.iter().filter(|s| s.mimic.is_none())
+    }
+    fn vector(&self, quantity: Quantity, mimic: bool) -> &Vec<f32> {
+        match (quantity, mimic) {
+            (Quantity::Position, false) => &self.position,
+            (Quantity::Velocity, false) => &self.velocity,
+            (Quantity::Effort, false) => &self.effort,
+            (Quantity::Position, true) => &self.mimic_position,
+            (Quantity::Velocity, true) => &self.mimic_velocity,
+            (Quantity::Effort, true) => &self.mimic_effort,
+        }
+    }
+    fn vector_mut(&mut self, quantity: Quantity) -> &mut Vec<f32> {
+        match quantity {
+            Quantity::Position => &mut self.position,
+            Quantity::Velocity => &mut self.velocity,
+            Quantity::Effort => &mut self.effort,
+        }
+    }
+    fn range(&self, slot: usize, quantity: Quantity) -> std::ops::Range<usize> {
+        let slot = &self.slots[slot];
+        let (start, len) = match quantity {
+            Quantity::Position => (slot.position, slot.joint_type.position_dofs()),
+            _ => (slot.velocity, slot.joint_type.velocity_dofs()),
+        };
+        start..start + len
+    }
+    fn values(&self, joint: impl JointKey, quantity: Quantity) -> Option<&[f32]> {
+        let slot = joint.find(self)?;
+        let mimic = self.slots[slot].mimic.is_some();
+        Some(&self.vector(quantity, mimic)[self.range(slot, quantity)])
+    }
+    fn value(&self, joint: impl JointKey, quantity: Quantity) -> Option<f32> {
+        match self.values(joint, quantity)? {
+            [value] => Some(*value),
+            _ => None,
+        }
+    }
+
+    // `value` for the joint in `slot` as the limit policy has it
+    fn limited(&self, slot: usize, value: f32) -> Result<f32, JointStateError> {
+        let slot = &self.slots[slot];
+        let Some((lower, upper)) = slot.bounds() else {
+            return Ok(value);
+        };
+        if (lower..=upper).contains(&value) {
+            return Ok(value);
+        }
+        let error = JointStateError::OutOfRange {
+            joint: slot.name.clone(),
+            value,
+            lower,
+            upper,
+        };
+        match self.policy {
+            LimitPolicy::Clamp => Ok(value.max(lower).min(upper)),
+            LimitPolicy::Error => Err(error),
+            LimitPolicy::Warn => {
+                log::warn!("{}", error);
+                Ok(value)
+            }
+        }
+    }
+    // the positions of every slot in `values` (laid out like the dense vector
+    // from `start`) after the limit policy
+    fn limited_positions(
+        &self,
+        slots: impl Iterator<Item = usize>,
+        start: usize,
+        values: &[f32],
+    ) -> Result<Vec<f32>, JointStateError> {
+        let mut values = values.to_vec();
+        for slot in slots {
+            if self.slots[slot].joint_type.position_dofs() == 1 {
+                let i = self.slots[slot].position - start;
+                values[i] = self.limited(slot, values[i])?;
+            }
+        }
+        Ok(values)
+    }
+    // moves the mimic joints after their leaders, their own limits applied
+    // with the limit policy; if it refuses one the mimics stay as they were
+    fn update_mimics(&mut self) -> Result<(), JointStateError> {
+        let mut position = self.mimic_position.clone();
+        let mut velocity = self.mimic_velocity.clone();
+        for (i, slot) in self.slots.iter().enumerate() {
+            if let Some(m) = slot.mimic {
+                let leader = &self.slots[m.leader];
+                position[slot.position] =
+                    self.limited(i, m.multiplier * self.position[leader.position] + m.offset)?;
+                velocity[slot.velocity] = m.multiplier * self.velocity[leader.velocity];
+            }
+        }
+        self.mimic_position = position;
+        self.mimic_velocity = velocity;
+        Ok(())
+    }
+    // runs `change` on the dense vectors and moves the mimic joints after
+    // them, undoing the change if the limit policy refuses a mimic position
+    fn change(&mut self, change: impl FnOnce(&mut Self)) -> Result<(), JointStateError> {
+        let (position, velocity) = (self.position.clone(), self.velocity.clone());
+        change(self);
+        let moved = self.update_mimics();
+        if moved.is_err() {
+            self.position = position;
+            self.velocity = velocity;
+        }
+        moved
+    }
+
+    fn set_values(
+        &mut self,
+        joint: impl JointKey,
+        quantity: Quantity,
+        values: &[f32],
+    ) -> Result<(), JointStateError> {
+        let slot = joint
+            .find(self)
+            .ok_or_else(|| JointStateError::UnknownJoint(joint.describe()))?;
+        if self.slots[slot].mimic.is_some() {
+            return Err(JointStateError::MimicJoint(self.slots[slot].name.clone()));
+        }
+        let range = self.range(slot, quantity);
+        if range.len() != values.len() {
+            return Err(JointStateError::WrongLength {
+                joint: Some(self.slots[slot].name.clone()),
+                expected: range.len(),
+                got: values.len(),
+            });
+        }
+        let values = match quantity {
+            Quantity::Position => {
+                self.limited_positions([slot].into_iter(), range.start, values)?
+            }
+            _ => values.to_vec(),
+        };
+        self.change(|state| state.vector_mut(quantity)[range].copy_from_slice(&values))
+    }
+    fn set_vector(&mut self, quantity: Quantity, values: &[f32]) -> Result<(), JointStateError> {
+        let expected = self.vector(quantity, false).len();
+        if expected != values.len() {
+            return Err(JointStateError::WrongLength {
+                joint: None,
+                expected,
+                got: values.len(),
+            });
+        }
+        let values = match quantity {
+            Quantity::Position => {
+                let dense = (0..self.slots.len()).filter(|&i| self.slots[i].mimic.is_none());
+                self.limited_positions(dense, 0, values)?
+            }
+            _ => values.to_vec(),
+        };
+        self.change(|state| state.vector_mut(quantity).copy_from_slice(&values))
+    }
+
+    // The single coordinate of a one degree of freedom joint; None for
+    // unknown, planar and floating joints, see `positions`
+    pub fn position(&self, joint: impl JointKey) -> Option<f32> {
+        self.value(joint, Quantity::Position)
+    }
+    pub fn positions(&self, joint: impl JointKey) -> Option<&[f32]> {
+        self.values(joint, Quantity::Position)
+    }
+    pub fn set_position(
+        &mut self,
+        joint: impl JointKey,
+        value: f32,
+    ) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Position, &[value])
+    }
+    pub fn set_positions(
+        &mut self,
+        joint: impl JointKey,
+        values: &[f32],
+    ) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Position, values)
+    }
+    pub fn velocity(&self, joint: impl JointKey) -> Option<f32> {
+        self.value(joint, Quantity::Velocity)
+    }
+    pub fn velocities(&self, joint: impl JointKey) -> Option<&[f32]> {
+        self.values(joint, Quantity::Velocity)
+    }
+    pub fn set_velocity(
+        &mut self,
+        joint: impl JointKey,
+        value: f32,
+    ) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Velocity, &[value])
+    }
+    pub fn set_velocities(
+        &mut self,
+        joint: impl JointKey,
+        values: &[f32],
+    ) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Velocity, values)
+    }
+    pub fn effort(&self, joint: impl JointKey) -> Option<f32> {
+        self.value(joint, Quantity::Effort)
+    }
+    pub fn efforts(&self, joint: impl JointKey) -> Option<&[f32]> {
+        self.values(joint, Quantity::Effort)
+    }
+    pub fn set_effort(&mut self, joint: impl JointKey, value: f32) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Effort, &[value])
+    }
+    pub fn set_efforts(
+        &mut self,
+        joint: impl JointKey,
+        values: &[f32],
+    ) -> Result<(), JointStateError> {
+        self.set_values(joint, Quantity::Effort, values)
+    }
+
+    // the dense vectors, in the order described at the top of this file
+    pub fn position_vector(&self) -> &[f32] {
+        &self.position
+    }
+    pub fn velocity_vector(&self) -> &[f32] {
+        &self.velocity
+    }
+    pub fn effort_vector(&self) -> &[f32] {
+        &self.effort
+    }
+    pub fn set_position_vector(&mut self, values: &[f32]) -> Result<(), JointStateError> {
+        self.set_vector(Quantity::Position, values)
+    }
+    pub fn set_velocity_vector(&mut self, values: &[f32]) -> Result<(), JointStateError> {
+        self.set_vector(Quantity::Velocity, values)
+    }
+    pub fn set_effort_vector(&mut self, values: &[f32]) -> Result<(), JointStateError> {
+        self.set_vector(Quantity::Effort, values)
+    }
+
+    // Moves the positions along `velocities` (laid out like the velocity
+    // vector) for `dt`: floating joints turn their orientation by the angular
+    // velocity, taken in the frame the joint is attached in, and keep it a
+    // unit quaternion. Positions go through the limit policy.
+    pub fn integrate(&mut self, velocities: &[f32], dt: f32) -> Result<(), JointStateError> {
+        if velocities.len() != self.nv() {
+            return Err(JointStateError::WrongLength {
+                joint: None,
+                expected: self.nv(),
+                got: velocities.len(),
+            });
+        }
+        let mut q = self.position.clone();
+        for slot in self.dense_slots() {
+            let (p, v) = (slot.position, slot.velocity);
+            match slot.joint_type {
+                JointType::Floating => {
+                    for i in 0..3 {
+                        q[p + i] += velocities[v + i] * dt;
+                    }
+                    let omega = glm::vec3(velocities[v + 3], velocities[v + 4], velocities[v + 5]);
+                    let rotation = glm::quat(q[p + 3], q[p + 4], q[p + 5], q[p + 6]);
+                    let turned = match glm::length(&omega) * dt {
+                        angle if angle > 0.0 => {
+                            glm::quat_angle_axis(angle, &glm::normalize(&omega)) * rotation
+                        }
+                        _ => rotation,
+                    };
+                    let turned = glm::quat_normalize(&turned);
+                    q[p + 3..p + 7].copy_from_slice(turned.coords.as_slice());
+                }
+                joint_type => {
+                    for i in 0..joint_type.velocity_dofs() {
+                        q[p + i] += velocities[v + i] * dt;
+                    }
+                }
+            }
+        }
+        self.set_vector(Quantity::Position, &q)
+    }
+
+    // Moves a single coordinate joint towards `target` over `dt` seconds, no
+    // faster than its velocity limit, and sets its velocity to the speed it
+    // moved at. The target goes through the limit policy first.
+    pub fn command_position(
+        &mut self,
+        joint: impl JointKey,
+        target: f32,
+        dt: f32,
+    ) -> Result<(), JointStateError> {
+        let slot = joint
+            .find(self)
+            .ok_or_else(|| JointStateError::UnknownJoint(joint.describe()))?;
+        if self.slots[slot].mimic.is_some() {
+            return Err(JointStateError::MimicJoint(self.slots[slot].name.clone()));
+        }
+        check_timestep(dt)?;
+        if self.slots[slot].joint_type.position_dofs() != 1 {
+            return Err(JointStateError::WrongLength {
+                joint: Some(self.slots[slot].name.clone()),
+                expected: self.slots[slot].joint_type.position_dofs(),
+                got: 1,
+            });
+        }
+        let target = self.limited(slot, target)?;
+        self.change(|state| state.step_towards(slot, target, dt))
+    }
+    // command_position for every joint of the dense vector at once; planar and
+    // floating joints are set to their targets directly, their velocity is kept
+    pub fn command_position_vector(
+        &mut self,
+        targets: &[f32],
+        dt: f32,
+    ) -> Result<(), JointStateError> {
+        if targets.len() != self.nq() {
+            return Err(JointStateError::WrongLength {
+                joint: None,
+                expected: self.nq(),
+                got: targets.len(),
+            });
+        }
+        check_timestep(dt)?;
+        let dense = (0..self.slots.len()).filter(|&i| self.slots[i].mimic.is_none());
+        let targets = self.limited_positions(dense, 0, targets)?;
+        self.change(|state| {
+            for i in 0..state.slots.len() {
+                if state.slots[i].mimic.is_some() {
+                    continue;
+                }
+                let range = state.range(i, Quantity::Position);
+                if range.len() == 1 {
+                    state.step_towards(i, targets[range.start], dt);
+                } else {
+                    state.position[range.clone()].copy_from_slice(&targets[range]);
+                }
+            }
+        })
+    }
+    // `dt` has been through check_timestep
+    fn step_towards(&mut self, slot: usize, target: f32, dt: f32) {
+        let (q, v) = (self.slots[slot].position, self.slots[slot].velocity);
+        let mut step = target - self.position[q];
+        if let Some(max) = self.slots[slot].max_velocity() {
+            step = step.clamp(-max * dt, max * dt);
+        }
+        self.position[q] += step;
+        self.velocity[v] = if dt > 0.0 { step / dt } else { 0.0 };
+    }
+}
@@ -2,13 +2,18 @@
 
 pub mod bindings;
 pub mod camera;
+pub mod dynamics;
 pub mod geometry;
 pub mod graphics;
+pub mod kinematics;
 pub mod light;
 pub mod urdf;
 pub mod physics;
+pub mod resource;
 pub mod shader;
+pub mod terrain;
 pub mod texture;
 pub mod util;
 pub mod wgpu_program;
+pub mod world;
 extern crate nalgebra_glm as glm;
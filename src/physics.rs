@@ -1,3 +1,29 @@
+mod body_joint;
+mod broad_phase;
+mod contact_solver;
+mod gjk;
+mod integrator;
+mod narrow_phase;
+mod rigid_body;
+mod static_mesh;
+mod world;
+pub use body_joint::{joint_frame, BodyJoint, BodyJointKind, JointLimit, JointMotor, JointSpring};
+pub use broad_phase::{Bounds, BroadPhase, DynamicTree, ProxyId};
+pub use contact_solver::{ContactImpulse, ContactSolver, Material};
+pub use integrator::{
+    ExplicitEuler, FixedTimestep, Integrable, Integrator, RungeKutta4, SemiImplicitEuler,
+    VelocityVerlet,
+};
+pub use narrow_phase::{
+    collide, CollisionShape, Contact, ContactManifold, CONTACT_MARGIN, MAX_CONTACTS,
+};
+pub use rigid_body::{RigidBody, SLEEP_ANGULAR_VELOCITY, SLEEP_LINEAR_VELOCITY, SLEEP_TIME};
+pub(crate) use static_mesh::{fold, TriangleSurface};
+pub use static_mesh::{MeshEdge, StaticMeshCollider};
+pub use world::{
+    Attachment, BodyId, BodyJointId, Collider, ColliderId, Hook, Phase, PhysicsWorld, RobotId,
+    SimulatedRobot,
+};
 
 pub trait PhysicsProgram {
     fn new() -> Self;
@@ -9,10 +35,7 @@ pub trait PhysicsProgram {
     fn solve_constraints(&mut self);
 }
 
-// pub struct PhysicsWorld {
-//     objects: Vec<Polyhedron>
-// }
-
+// A point mass with a heading in the plane; RigidBody is the full 3D body
 #[derive(Debug, Copy, Clone)]
 pub struct FreeBody {
     // linear kinematic info
@@ -27,4 +50,3 @@ pub struct FreeBody {
     pub omega: f32,
     // alpha: f32,
 }
-
@@ -0,0 +1,320 @@
+// Joints between free bodies in maximal coordinates. Each body keeps its own
+// six velocities, and a joint adds constraint rows that stop the relative
+// motions it does not allow, solved with the contacts. A joint joins a frame
+// on body `a` to a frame on body `b`, or on the world if `b` is None; the x
+// axis of the frames is the axis of hinges and sliders and the first axis of
+// universal joints, whose second axis is the y axis of `b`'s frame.
+//
+// Hinges and sliders have one free coordinate, an angle about or an offset
+// along the axis, which limits, springs and motors act on. Springs are soft
+// rows, solved implicitly so stiff ones stay stable; motors are rows driving
+// the coordinate's velocity with at most their force (or torque).
+use super::world::BodyId;
+
+// rows of a joint, so the same row finds its impulse in the next step
+const LOWER_LIMIT: usize = 6;
+const UPPER_LIMIT: usize = 7;
+const SPRING: usize = 8;
+const MOTOR: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BodyJointKind {
+    Fixed,
+    Hinge,
+    Slider,
+    Ball,
+    Universal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointLimit {
+    pub lower: f32,
+    pub upper: f32,
+}
+
+// pulls the free coordinate to `rest`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JointSpring {
+    pub stiffness: f32,
+    pub damping: f32,
+    pub rest: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JointMotor {
+    Velocity {
+        speed: f32,
+        max_force: f32,
+    },
+    // at up to `max_speed` towards `target`
+    Position {
+        target: f32,
+        max_speed: f32,
+        max_force: f32,
+    },
+}
+
+// Joined bodies do not collide with each other unless `collide_connected`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BodyJoint {
+    pub kind: BodyJointKind,
+    pub a: BodyId,
+    pub b: Option<BodyId>,
+    // in the body frames (at the centre of mass), or the world for `b`
+    pub frame_a: glm::Mat4,
+    pub frame_b: glm::Mat4,
+    pub limit: Option<JointLimit>,
+    pub spring: Option<JointSpring>,
+    pub motor: Option<JointMotor>,
+    pub collide_connected: bool,
+}
+
+// A constraint row over the velocities of `a` and `b`, each linear then
+// angular: the impulse along it keeps the relative velocity at `target`,
+// within its bounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct JointRow {
+    pub id: usize,
+    pub jacobian: [[f32; 6]; 2],
+    pub target: f32,
+    pub lower: f32,
+    pub upper: f32,
+    pub softness: f32,
+}
+
+// A body joined, or the world: the world pose of its joint frame, its centre
+// of mass and its velocity, linear then angular, before the solve
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct JointSide {
+    pub pose: glm::Mat4,
+    pub com: glm::Vec3,
+    pub velocity: [f32; 6],
+}
+
+impl BodyJoint {
+    pub fn new(
+        kind: BodyJointKind,
+        a: BodyId,
+        frame_a: glm::Mat4,
+        b: Option<BodyId>,
+        frame_b: glm::Mat4,
+    ) -> Self {
+        Self {
+            kind,
+            a,
+            b,
+            frame_a,
+            frame_b,
+            limit: None,
+            spring: None,
+            motor: None,
+            collide_connected: false,
+        }
+    }
+    pub fn with_limit(mut self, lower: f32, upper: f32) -> Self {
+        self.limit = Some(JointLimit { lower, upper });
+        self
+    }
+    pub fn with_spring(mut self, stiffness: f32, damping: f32, rest: f32) -> Self {
+        self.spring = Some(JointSpring {
+            stiffness,
+            damping,
+            rest,
+        });
+        self
+    }
+    pub fn with_motor(mut self, motor: JointMotor) -> Self {
+        self.motor = Some(motor);
+        self
+    }
+    pub fn with_collide_connected(mut self, collide: bool) -> Self {
+        self.collide_connected = collide;
+        self
+    }
+    // whether the joint has a free coordinate
+    pub fn is_single_axis(&self) -> bool {
+        matches!(self.kind, BodyJointKind::Hinge | BodyJointKind::Slider)
+    }
+
+    // The angle of `b`'s frame about the axis of `a`'s or the offset along
+    // it, for hinges and sliders with their frames at these world poses
+    pub fn position(&self, pose_a: &glm::Mat4, pose_b: &glm::Mat4) -> Option<f32> {
+        let axis = pose_a.column(0).xyz();
+        match self.kind {
+            BodyJointKind::Hinge => {
+                let (ya, yb) = (pose_a.column(1).xyz(), pose_b.column(1).xyz());
+                Some(ya.cross(&yb).dot(&axis).atan2(ya.dot(&yb)))
+            }
+            BodyJointKind::Slider => {
+                Some((pose_b.column(3).xyz() - pose_a.column(3).xyz()).dot(&axis))
+            }
+            _ => None,
+        }
+    }
+
+    // The rows of the joint with its frames and bodies as they are now.
+    // Errors are measured as they were at the start of the step, before
+    // the velocities the solve replaces moved the bodies, and `baumgarte` of
+    // them is taken out per step.
+    pub(crate) fn rows(&self, sides: &[JointSide; 2], dt: f32, baumgarte: f32) -> Vec<JointRow> {
+        let [a, b] = sides;
+        let (anchor_a, anchor_b) = (a.pose.column(3).xyz(), b.pose.column(3).xyz());
+        let (ra, rb) = (anchor_a - a.com, anchor_b - b.com);
+        let bias = -baumgarte / dt;
+        let axis = a.pose.column(0).xyz();
+        // d . (v + w x r) = d . v + w . (r x d)
+        let linear = |id: usize, d: glm::Vec3| {
+            let (wa, wb) = (ra.cross(&d), rb.cross(&d));
+            hard(
+                id,
+                [
+                    [-d.x, -d.y, -d.z, -wa.x, -wa.y, -wa.z],
+                    [d.x, d.y, d.z, wb.x, wb.y, wb.z],
+                ],
+            )
+        };
+        let angular = |id: usize, d: glm::Vec3| {
+            hard(
+                id,
+                [
+                    [0.0, 0.0, 0.0, -d.x, -d.y, -d.z],
+                    [0.0, 0.0, 0.0, d.x, d.y, d.z],
+                ],
+            )
+        };
+        // the error along a row a step ago
+        let before = |row: &JointRow, error: f32| {
+            let rate: f32 = std::iter::zip(&row.jacobian, sides)
+                .map(|(j, side)| {
+                    std::iter::zip(j, &side.velocity)
+                        .map(|(j, v)| j * v)
+                        .sum::<f32>()
+                })
+                .sum();
+            error - rate * dt
+        };
+        let mut rows = Vec::new();
+        let mut push = |mut row: JointRow, error: f32| {
+            row.target = bias * before(&row, error);
+            rows.push(row);
+        };
+
+        // the anchors together, or for sliders on the axis
+        let error = anchor_b - anchor_a;
+        let (t1, t2) = crate::kinematics::planar_basis(&axis);
+        let directions = match self.kind {
+            BodyJointKind::Slider => vec![t1, t2],
+            _ => vec![glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()],
+        };
+        for (id, d) in directions.into_iter().enumerate() {
+            push(linear(id, d), error.dot(&d));
+        }
+        let rotation_a = glm::mat4_to_mat3(&a.pose);
+        let rotation_b = glm::mat4_to_mat3(&b.pose);
+        match self.kind {
+            BodyJointKind::Fixed | BodyJointKind::Slider => {
+                // the small rotation taking a's frame to b's
+                let q = glm::mat3_to_quat(&(rotation_b * rotation_a.transpose()));
+                let sign = if q.w < 0.0 { -2.0 } else { 2.0 };
+                let theta = q.imag() * sign;
+                for (k, d) in [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()]
+                    .into_iter()
+                    .enumerate()
+                {
+                    push(angular(3 + k, d), theta.dot(&d));
+                }
+            }
+            BodyJointKind::Hinge => {
+                let theta = axis.cross(&rotation_b.column(0));
+                for (k, d) in [t1, t2].into_iter().enumerate() {
+                    push(angular(3 + k, d), theta.dot(&d));
+                }
+            }
+            BodyJointKind::Universal => {
+                // a's first axis across b's second
+                let second = rotation_b.column(1).into_owned();
+                push(angular(3, second.cross(&axis)), axis.dot(&second));
+            }
+            BodyJointKind::Ball => {}
+        }
+
+        let Some(now) = self.position(&a.pose, &b.pose) else {
+            return rows;
+        };
+        let free = |id: usize| match self.kind {
+            BodyJointKind::Slider => linear(id, axis),
+            _ => angular(id, axis),
+        };
+        let position = before(&free(0), now);
+        if let Some(limit) = self.limit {
+            // like contacts, free to close the gap to a limit but not cross it
+            let gap = |gap: f32| {
+                if gap > 0.0 {
+                    -gap / dt
+                } else {
+                    bias * gap
+                }
+            };
+            let mut lower = free(LOWER_LIMIT);
+            lower.target = gap(position - limit.lower);
+            lower.lower = 0.0;
+            rows.push(lower);
+            let mut upper = free(UPPER_LIMIT);
+            upper.target = -gap(limit.upper - position);
+            upper.upper = 0.0;
+            rows.push(upper);
+        }
+        if let Some(spring) = self.spring {
+            // implicit: the force at the end of the step
+            let stiffness = spring.damping + dt * spring.stiffness;
+            if stiffness > 0.0 {
+                let mut row = free(SPRING);
+                row.target = -spring.stiffness / stiffness * (position - spring.rest);
+                row.softness = 1.0 / (dt * stiffness);
+                rows.push(row);
+            }
+        }
+        if let Some(motor) = self.motor {
+            let (speed, max_force) = match motor {
+                JointMotor::Velocity { speed, max_force } => (speed, max_force),
+                JointMotor::Position {
+                    target,
+                    max_speed,
+                    max_force,
+                } => (
+                    ((target - position) / dt).clamp(-max_speed, max_speed),
+                    max_force,
+                ),
+            };
+            let mut row = free(MOTOR);
+            row.target = speed;
+            row.lower = -max_force * dt;
+            row.upper = max_force * dt;
+            rows.push(row);
+        }
+        rows
+    }
+}
+
+fn hard(id: usize, jacobian: [[f32; 6]; 2]) -> JointRow {
+    JointRow {
+        id,
+        jacobian,
+        target: 0.0,
+        lower: f32::NEG_INFINITY,
+        upper: f32::INFINITY,
+        softness: 0.0,
+    }
+}
+
+// a joint frame at `anchor` with its x axis along `axis`
+pub fn joint_frame(anchor: &glm::Vec3, axis: &glm::Vec3) -> glm::Mat4 {
+    let x = glm::normalize(axis);
+    let (y, z) = crate::kinematics::planar_basis(&x);
+    let mut frame = glm::Mat4::identity();
+    frame.set_column(0, &x.push(0.0));
+    frame.set_column(1, &y.push(0.0));
+    frame.set_column(2, &z.push(0.0));
+    frame.set_column(3, &anchor.push(1.0));
+    frame
+}
@@ -0,0 +1,376 @@
+// Broad phase: cheap overlap tests between bounding boxes to find the pairs
+// of colliders worth handing to the narrow phase.
+//
+// The boxes live in a dynamic AABB tree. Leaves hold boxes fattened by a
+// margin so that a collider moving a little stays in its leaf; a leaf is only
+// reinserted once the collider leaves its fat box. Inserting picks the
+// sibling by the surface area heuristic and rotations keep the tree balanced,
+// so queries stay logarithmic as objects come and go. Planes bound nothing
+// and are kept aside as half-spaces.
+use crate::geometry::Aabb;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ProxyId(usize);
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    aabb: Aabb,
+    parent: Option<usize>,
+    // None for leaves
+    children: Option<[usize; 2]>,
+    // 0 for leaves
+    height: usize,
+    data: Option<T>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DynamicTree<T> {
+    nodes: Vec<Node<T>>,
+    root: Option<usize>,
+    free: Vec<usize>,
+    leaves: usize,
+}
+
+impl<T> Default for DynamicTree<T> {
+    fn default() -> Self {
+        Self {
+            nodes: Vec::new(),
+            root: None,
+            free: Vec::new(),
+            leaves: 0,
+        }
+    }
+}
+
+impl<T: Copy> DynamicTree<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn len(&self) -> usize {
+        self.leaves
+    }
+    pub fn is_empty(&self) -> bool {
+        self.leaves == 0
+    }
+    // 0 for an empty tree or a single leaf
+    pub fn height(&self) -> usize {
+        self.root.map_or(0, |root| self.nodes[root].height)
+    }
+    // the box stored for `proxy`, as fat as it was given
+    pub fn aabb(&self, proxy: ProxyId) -> &Aabb {
+        &self.nodes[proxy.0].aabb
+    }
+    pub fn data(&self, proxy: ProxyId) -> T {
+        self.nodes[proxy.0].data.expect("proxies are leaves")
+    }
+
+    pub fn insert(&mut self, aabb: Aabb, data: T) -> ProxyId {
+        let leaf = self.allocate(Node {
+            aabb,
+            parent: None,
+            children: None,
+            height: 0,
+            data: Some(data),
+        });
+        self.insert_leaf(leaf);
+        self.leaves += 1;
+        ProxyId(leaf)
+    }
+    pub fn remove(&mut self, proxy: ProxyId) {
+        self.remove_leaf(proxy.0);
+        self.nodes[proxy.0].data = None;
+        self.free.push(proxy.0);
+        self.leaves -= 1;
+    }
+    // Moves `proxy` to cover `aabb`. Nothing changes while `aabb` stays in
+    // the stored box, otherwise the leaf is reinserted with `aabb` fattened
+    // by `margin`; returns whether it was.
+    pub fn update(&mut self, proxy: ProxyId, aabb: &Aabb, margin: f32) -> bool {
+        if self.nodes[proxy.0].aabb.contains(aabb) {
+            return false;
+        }
+        self.remove_leaf(proxy.0);
+        self.nodes[proxy.0].aabb = aabb.fattened(margin);
+        self.insert_leaf(proxy.0);
+        true
+    }
+
+    // Calls `found` for every leaf whose box passes `test`, descending only
+    // into nodes whose box passes it too
+    pub fn query_with(&self, test: impl Fn(&Aabb) -> bool, mut found: impl FnMut(ProxyId)) {
+        let mut stack: Vec<usize> = self.root.into_iter().collect();
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !test(&node.aabb) {
+                continue;
+            }
+            match node.children {
+                Some(children) => stack.extend(children),
+                None => found(ProxyId(index)),
+            }
+        }
+    }
+    pub fn query(&self, aabb: &Aabb, found: impl FnMut(ProxyId)) {
+        self.query_with(|node| node.overlaps(aabb), found)
+    }
+    // every pair of leaves whose boxes overlap, once each
+    pub fn overlapping_pairs(&self) -> Vec<(ProxyId, ProxyId)> {
+        let mut pairs = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            if node.data.is_none() {
+                continue;
+            }
+            self.query(&node.aabb, |other| {
+                if other.0 > index {
+                    pairs.push((ProxyId(index), other));
+                }
+            });
+        }
+        pairs
+    }
+
+    fn allocate(&mut self, node: Node<T>) -> usize {
+        match self.free.pop() {
+            Some(index) => {
+                self.nodes[index] = node;
+                index
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+    fn children(&self, index: usize) -> [usize; 2] {
+        self.nodes[index].children.expect("branches have children")
+    }
+    fn replace_child(&mut self, parent: Option<usize>, old: usize, new: usize) {
+        match parent {
+            Some(parent) => {
+                let children = self.nodes[parent].children.as_mut().expect("a parent");
+                let slot = children.iter().position(|&c| c == old).expect("a child");
+                children[slot] = new;
+            }
+            None => self.root = Some(new),
+        }
+    }
+
+    fn insert_leaf(&mut self, leaf: usize) {
+        let Some(root) = self.root else {
+            self.root = Some(leaf);
+            self.nodes[leaf].parent = None;
+            return;
+        };
+        // descend while splitting lower down costs less than pairing here
+        let aabb = self.nodes[leaf].aabb;
+        let mut sibling = root;
+        while let Some(children) = self.nodes[sibling].children {
+            let area = self.nodes[sibling].aabb.surface_area();
+            let combined = self.nodes[sibling].aabb.union(&aabb).surface_area();
+            let cost = 2.0 * combined;
+            let inheritance = 2.0 * (combined - area);
+            let descend = |child: usize| {
+                let node = &self.nodes[child];
+                let grown = node.aabb.union(&aabb).surface_area();
+                match node.children {
+                    None => grown + inheritance,
+                    Some(_) => grown - node.aabb.surface_area() + inheritance,
+                }
+            };
+            let (left, right) = (descend(children[0]), descend(children[1]));
+            if cost < left && cost < right {
+                break;
+            }
+            sibling = if left < right {
+                children[0]
+            } else {
+                children[1]
+            };
+        }
+
+        let old_parent = self.nodes[sibling].parent;
+        let parent = self.allocate(Node {
+            aabb: self.nodes[sibling].aabb.union(&aabb),
+            parent: old_parent,
+            children: Some([sibling, leaf]),
+            height: self.nodes[sibling].height + 1,
+            data: None,
+        });
+        self.replace_child(old_parent, sibling, parent);
+        self.nodes[sibling].parent = Some(parent);
+        self.nodes[leaf].parent = Some(parent);
+        self.refit(Some(parent));
+    }
+    fn remove_leaf(&mut self, leaf: usize) {
+        let Some(parent) = self.nodes[leaf].parent else {
+            self.root = None;
+            return;
+        };
+        let children = self.children(parent);
+        let sibling = if children[0] == leaf {
+            children[1]
+        } else {
+            children[0]
+        };
+        let grandparent = self.nodes[parent].parent;
+        self.replace_child(grandparent, parent, sibling);
+        self.nodes[sibling].parent = grandparent;
+        self.free.push(parent);
+        self.nodes[leaf].parent = None;
+        self.refit(grandparent);
+    }
+    // balances and recomputes the boxes and heights from `index` to the root
+    fn refit(&mut self, mut index: Option<usize>) {
+        while let Some(i) = index {
+            let i = self.balance(i);
+            let [a, b] = self.children(i);
+            self.nodes[i].height = 1 + self.nodes[a].height.max(self.nodes[b].height);
+            self.nodes[i].aabb = self.nodes[a].aabb.union(&self.nodes[b].aabb);
+            index = self.nodes[i].parent;
+        }
+    }
+
+    // Rotates the taller child of `a` above it if the children's heights
+    // differ by more than one, returning the node now in a's place
+    fn balance(&mut self, a: usize) -> usize {
+        if self.nodes[a].height < 2 {
+            return a;
+        }
+        let [b, c] = self.children(a);
+        let (hb, hc) = (self.nodes[b].height, self.nodes[c].height);
+        if hc > hb + 1 {
+            self.rotate_up(a, 1)
+        } else if hb > hc + 1 {
+            self.rotate_up(a, 0)
+        } else {
+            a
+        }
+    }
+    // lifts child `side` of `a` into its place; a keeps its other child and
+    // takes the lower of the lifted node's children
+    fn rotate_up(&mut self, a: usize, side: usize) -> usize {
+        let children = self.children(a);
+        let (up, kept) = (children[side], children[1 - side]);
+        let [f, g] = self.children(up);
+        let (taller, shorter) = if self.nodes[f].height > self.nodes[g].height {
+            (f, g)
+        } else {
+            (g, f)
+        };
+
+        let parent = self.nodes[a].parent;
+        self.nodes[up].parent = parent;
+        self.replace_child(parent, a, up);
+        self.nodes[a].parent = Some(up);
+        self.nodes[up].children = Some([a, taller]);
+
+        let mut a_children = children;
+        a_children[side] = shorter;
+        self.nodes[a].children = Some(a_children);
+        self.nodes[shorter].parent = Some(a);
+
+        self.nodes[a].aabb = self.nodes[kept].aabb.union(&self.nodes[shorter].aabb);
+        self.nodes[a].height = 1 + self.nodes[kept].height.max(self.nodes[shorter].height);
+        self.nodes[up].aabb = self.nodes[a].aabb.union(&self.nodes[taller].aabb);
+        self.nodes[up].height = 1 + self.nodes[a].height.max(self.nodes[taller].height);
+        up
+    }
+}
+
+// What a collider bounds in the world
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bounds {
+    Box(Aabb),
+    // the points x with normal·x <= offset
+    HalfSpace { normal: glm::Vec3, offset: f32 },
+    // no geometry, takes part in no pairs
+    Nothing,
+}
+
+// Keeps one proxy per collider, by index, across steps
+#[derive(Debug, Clone)]
+pub struct BroadPhase {
+    tree: DynamicTree<usize>,
+    proxies: Vec<Option<ProxyId>>,
+    half_spaces: Vec<(usize, glm::Vec3, f32)>,
+    margin: f32,
+}
+
+impl Default for BroadPhase {
+    fn default() -> Self {
+        Self::new(0.05)
+    }
+}
+
+impl BroadPhase {
+    // `margin` is how far a collider moves before its proxy is reinserted
+    pub fn new(margin: f32) -> Self {
+        Self {
+            tree: DynamicTree::new(),
+            proxies: Vec::new(),
+            half_spaces: Vec::new(),
+            margin,
+        }
+    }
+    pub fn margin(&self) -> f32 {
+        self.margin
+    }
+    pub fn tree(&self) -> &DynamicTree<usize> {
+        &self.tree
+    }
+
+    // brings the proxies up to date with the bounds of every collider
+    pub fn update(&mut self, bounds: &[Bounds]) {
+        let kept = bounds.len().min(self.proxies.len());
+        for proxy in self.proxies.drain(kept..).flatten() {
+            self.tree.remove(proxy);
+        }
+        self.proxies.resize(bounds.len(), None);
+        self.half_spaces.clear();
+        for (index, bound) in bounds.iter().enumerate() {
+            match (bound, self.proxies[index]) {
+                (Bounds::Box(aabb), Some(proxy)) => {
+                    self.tree.update(proxy, aabb, self.margin);
+                }
+                (Bounds::Box(aabb), None) => {
+                    self.proxies[index] = Some(self.tree.insert(aabb.fattened(self.margin), index));
+                }
+                (other, proxy) => {
+                    if let Some(proxy) = proxy {
+                        self.tree.remove(proxy);
+                        self.proxies[index] = None;
+                    }
+                    if let Bounds::HalfSpace { normal, offset } = other {
+                        self.half_spaces.push((index, *normal, *offset));
+                    }
+                }
+            }
+        }
+    }
+
+    // Pairs of collider indices, lower first and sorted, whose fat boxes
+    // overlap or reach into a half-space, and which `filter` accepts
+    pub fn pairs(&self, mut filter: impl FnMut(usize, usize) -> bool) -> Vec<(usize, usize)> {
+        let mut pairs: Vec<(usize, usize)> = self
+            .tree
+            .overlapping_pairs()
+            .into_iter()
+            .map(|(a, b)| {
+                let (a, b) = (self.tree.data(a), self.tree.data(b));
+                (a.min(b), a.max(b))
+            })
+            .collect();
+        for &(plane, normal, offset) in &self.half_spaces {
+            self.tree.query_with(
+                |aabb| aabb.min_along(&normal) <= offset,
+                |proxy| {
+                    let other = self.tree.data(proxy);
+                    pairs.push((plane.min(other), plane.max(other)));
+                },
+            );
+        }
+        pairs.retain(|&(a, b)| filter(a, b));
+        pairs.sort_unstable();
+        pairs
+    }
+}
@@ -0,0 +1,406 @@
+// Contact constraints solved by sequential impulses (projected Gauss-Seidel on
+// the velocities). Every contact point has a row along its normal, which may
+// only push, and two along the surface for friction, whose impulses together
+// stay inside the Coulomb cone of the normal impulse. Rows are solved one at a
+// time, each applying at once the impulse that meets its own condition, and
+// sweeps over all of them repeat until the impulses settle.
+//
+// A side of a contact is anything with velocities and a mass matrix: a free
+// body's linear and angular velocity with its mass and inertia, or a robot's
+// joint velocities with its joint-space mass matrix. The point Jacobian of a
+// side maps its velocities to the velocity of the contact point, so both
+// move the same way under an impulse J^T p: by M^-1 J^T p.
+//
+// Penetration beyond a small slop is pushed out over a few steps by a
+// Baumgarte bias on the normal velocity; shapes still apart within the contact
+// margin may close the gap, but not cross it. Restitution bounces contacts
+// approaching faster than a threshold. The impulses found in one step start
+// the next (warm starting), matched by pair and place, which makes stacks
+// settle in far fewer iterations.
+//
+// Joints between bodies come as single rows, each with its own bounds on the
+// impulse and a softness for springs, and are solved in the same sweeps
+// before the contacts.
+use super::world::ColliderId;
+use nalgebra::{DMatrix, DVector};
+use std::collections::HashMap;
+
+// how near a contact has to be to one of the last step to take its impulses
+const WARM_START_DISTANCE: f32 = 0.02;
+
+// Surface properties of a collider. Two touching colliders combine their
+// friction by the geometric mean and take the larger restitution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Material {
+    pub friction: f32,
+    pub restitution: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            friction: 0.5,
+            restitution: 0.0,
+        }
+    }
+}
+
+impl Material {
+    pub fn new(friction: f32, restitution: f32) -> Self {
+        Self {
+            friction,
+            restitution,
+        }
+    }
+    pub fn combine(&self, other: &Material) -> Material {
+        Material {
+            friction: (self.friction * other.friction).sqrt(),
+            restitution: self.restitution.max(other.restitution),
+        }
+    }
+}
+
+// The impulses of a contact point in the last step, normals from `a` to `b`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactImpulse {
+    pub a: ColliderId,
+    pub b: ColliderId,
+    pub point: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub normal_impulse: f32,
+    pub friction_impulse: glm::Vec3,
+}
+
+// Velocities the solver changes, with the inverse of their mass matrix
+#[derive(Debug, Clone)]
+pub(crate) struct Velocities {
+    pub velocity: DVector<f32>,
+    pub inverse_mass: DMatrix<f32>,
+}
+
+// A contact point as the solver sees it: each side that can move is an index
+// into the velocities with the 3 x n Jacobian of the point on it
+#[derive(Debug, Clone)]
+pub(crate) struct ContactPoint {
+    pub a: ColliderId,
+    pub b: ColliderId,
+    pub point: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub depth: f32,
+    pub material: Material,
+    pub sides: [Option<(usize, DMatrix<f32>)>; 2],
+}
+
+// A joint row as the solver sees it: `key` is the joint and its row, and
+// each side that can move an index into the velocities with its Jacobian row
+#[derive(Debug, Clone)]
+pub(crate) struct JointRowSides {
+    pub key: (usize, usize),
+    pub sides: [Option<(usize, DVector<f32>)>; 2],
+    pub target: f32,
+    pub lower: f32,
+    pub upper: f32,
+    pub softness: f32,
+}
+
+// One side of a row: J^T d, negated for the first side, and M^-1 J^T d
+struct Side {
+    slot: usize,
+    jacobian: DVector<f32>,
+    response: DVector<f32>,
+}
+
+// The normal row then the two friction rows of a contact point
+struct Constraint {
+    directions: [glm::Vec3; 3],
+    sides: [Vec<Side>; 3],
+    mass: [f32; 3],
+    impulse: [f32; 3],
+    // normal velocity the solve aims for
+    target: f32,
+    friction: f32,
+}
+
+struct RowConstraint {
+    key: (usize, usize),
+    sides: Vec<Side>,
+    mass: f32,
+    impulse: f32,
+    target: f32,
+    lower: f32,
+    upper: f32,
+    softness: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactSolver {
+    iterations: usize,
+    baumgarte: f32,
+    slop: f32,
+    bounce_threshold: f32,
+    warm_starting: bool,
+    impulses: Vec<ContactImpulse>,
+    joint_impulses: HashMap<(usize, usize), f32>,
+}
+
+impl Default for ContactSolver {
+    // 20 iterations, Baumgarte 0.2 with 5 mm slop, bouncing above 0.5 m/s,
+    // warm started
+    fn default() -> Self {
+        Self {
+            iterations: 20,
+            baumgarte: 0.2,
+            slop: 0.005,
+            bounce_threshold: 0.5,
+            warm_starting: true,
+            impulses: Vec::new(),
+            joint_impulses: HashMap::new(),
+        }
+    }
+}
+
+impl ContactSolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with_iterations(mut self, iterations: usize) -> Self {
+        self.iterations = iterations;
+        self
+    }
+    // the share of penetration beyond `slop` pushed out per step
+    pub fn with_baumgarte(mut self, factor: f32, slop: f32) -> Self {
+        self.baumgarte = factor;
+        self.slop = slop;
+        self
+    }
+    // slower contacts do not bounce, so resting ones stay put
+    pub fn with_bounce_threshold(mut self, speed: f32) -> Self {
+        self.bounce_threshold = speed;
+        self
+    }
+    pub fn with_warm_starting(mut self, warm_starting: bool) -> Self {
+        self.warm_starting = warm_starting;
+        self
+    }
+    pub fn iterations(&self) -> usize {
+        self.iterations
+    }
+    pub fn slop(&self) -> f32 {
+        self.slop
+    }
+    pub fn baumgarte(&self) -> f32 {
+        self.baumgarte
+    }
+    // of every contact point in the last step
+    pub fn impulses(&self) -> &[ContactImpulse] {
+        &self.impulses
+    }
+    pub fn clear(&mut self) {
+        self.impulses.clear();
+        self.joint_impulses.clear();
+    }
+
+    // Changes `velocities` so the contacts neither approach nor slip more
+    // than they may over the step `dt`, and the joints move as they allow
+    pub(crate) fn solve(
+        &mut self,
+        velocities: &mut [Velocities],
+        contacts: &[ContactPoint],
+        joints: &[JointRowSides],
+        dt: f32,
+    ) {
+        let mut rows: Vec<RowConstraint> = joints
+            .iter()
+            .map(|row| row_constraint(row, velocities))
+            .collect();
+        if self.warm_starting {
+            for row in &mut rows {
+                if let Some(&impulse) = self.joint_impulses.get(&row.key) {
+                    row.impulse = impulse.clamp(row.lower, row.upper);
+                    apply(velocities, &row.sides, row.impulse);
+                }
+            }
+        }
+        let mut constraints: Vec<Constraint> = contacts
+            .iter()
+            .map(|c| self.constraint(c, velocities, dt))
+            .collect();
+        if self.warm_starting {
+            for (constraint, contact) in constraints.iter_mut().zip(contacts) {
+                let Some(last) = self.impulses.iter().find(|last| {
+                    (last.a, last.b) == (contact.a, contact.b)
+                        && glm::distance(&last.point, &contact.point) < WARM_START_DISTANCE
+                        && last.normal.dot(&contact.normal) > 0.9
+                }) else {
+                    continue;
+                };
+                constraint.impulse = [
+                    last.normal_impulse,
+                    last.friction_impulse.dot(&constraint.directions[1]),
+                    last.friction_impulse.dot(&constraint.directions[2]),
+                ];
+                for row in 0..3 {
+                    apply(velocities, &constraint.sides[row], constraint.impulse[row]);
+                }
+            }
+        }
+        for _ in 0..self.iterations {
+            for row in &mut rows {
+                solve_row(row, velocities);
+            }
+            for constraint in &mut constraints {
+                solve_friction(constraint, velocities);
+                solve_normal(constraint, velocities);
+            }
+        }
+        self.impulses = std::iter::zip(contacts, &constraints)
+            .map(|(contact, constraint)| ContactImpulse {
+                a: contact.a,
+                b: contact.b,
+                point: contact.point,
+                normal: contact.normal,
+                normal_impulse: constraint.impulse[0],
+                friction_impulse: constraint.directions[1] * constraint.impulse[1]
+                    + constraint.directions[2] * constraint.impulse[2],
+            })
+            .collect();
+        self.joint_impulses = rows.iter().map(|row| (row.key, row.impulse)).collect();
+    }
+
+    fn constraint(&self, contact: &ContactPoint, velocities: &[Velocities], dt: f32) -> Constraint {
+        let (t1, t2) = crate::kinematics::planar_basis(&contact.normal);
+        let directions = [contact.normal, t1, t2];
+        let sides = directions.map(|d| {
+            contact
+                .sides
+                .iter()
+                .enumerate()
+                .filter_map(|(i, side)| {
+                    let (slot, point_jacobian) = side.as_ref()?;
+                    let sign = if i == 0 { -1.0 } else { 1.0 };
+                    let direction = nalgebra::Vector3::new(d.x, d.y, d.z) * sign;
+                    let jacobian = point_jacobian.tr_mul(&direction);
+                    let response = &velocities[*slot].inverse_mass * &jacobian;
+                    Some(Side {
+                        slot: *slot,
+                        jacobian,
+                        response,
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        let mass = sides.each_ref().map(|sides| {
+            let k: f32 = sides.iter().map(|s| s.jacobian.dot(&s.response)).sum();
+            if k > 1e-12 {
+                1.0 / k
+            } else {
+                0.0
+            }
+        });
+        let approach = relative_velocity(velocities, &sides[0]);
+        let restitution = contact.material.restitution;
+        let bounce = if approach < -self.bounce_threshold {
+            -restitution * approach
+        } else {
+            0.0
+        };
+        let target = if contact.depth < 0.0 {
+            // apart: free to close the gap, bouncing if it would be crossed
+            let close = contact.depth / dt;
+            if approach < close {
+                bounce.max(close)
+            } else {
+                close
+            }
+        } else {
+            bounce.max(self.baumgarte / dt * (contact.depth - self.slop).max(0.0))
+        };
+        Constraint {
+            directions,
+            sides,
+            mass,
+            impulse: [0.0; 3],
+            target,
+            friction: contact.material.friction,
+        }
+    }
+}
+
+fn row_constraint(row: &JointRowSides, velocities: &[Velocities]) -> RowConstraint {
+    let sides: Vec<Side> = row
+        .sides
+        .iter()
+        .flatten()
+        .map(|(slot, jacobian)| Side {
+            slot: *slot,
+            jacobian: jacobian.clone(),
+            response: &velocities[*slot].inverse_mass * jacobian,
+        })
+        .collect();
+    let k: f32 = sides
+        .iter()
+        .map(|s| s.jacobian.dot(&s.response))
+        .sum::<f32>()
+        + row.softness;
+    RowConstraint {
+        key: row.key,
+        sides,
+        mass: if k > 1e-12 { 1.0 / k } else { 0.0 },
+        impulse: 0.0,
+        target: row.target,
+        lower: row.lower,
+        upper: row.upper,
+        softness: row.softness,
+    }
+}
+
+fn relative_velocity(velocities: &[Velocities], sides: &[Side]) -> f32 {
+    sides
+        .iter()
+        .map(|s| s.jacobian.dot(&velocities[s.slot].velocity))
+        .sum()
+}
+
+fn apply(velocities: &mut [Velocities], sides: &[Side], impulse: f32) {
+    if impulse != 0.0 {
+        for s in sides {
+            velocities[s.slot].velocity.axpy(impulse, &s.response, 1.0);
+        }
+    }
+}
+
+fn solve_normal(c: &mut Constraint, velocities: &mut [Velocities]) {
+    let v = relative_velocity(velocities, &c.sides[0]);
+    let total = (c.impulse[0] + c.mass[0] * (c.target - v)).max(0.0);
+    let change = total - c.impulse[0];
+    c.impulse[0] = total;
+    apply(velocities, &c.sides[0], change);
+}
+
+fn solve_row(row: &mut RowConstraint, velocities: &mut [Velocities]) {
+    let v = relative_velocity(velocities, &row.sides);
+    let total = (row.impulse + row.mass * (row.target - v - row.softness * row.impulse))
+        .clamp(row.lower, row.upper);
+    let change = total - row.impulse;
+    row.impulse = total;
+    apply(velocities, &row.sides, change);
+}
+
+// both directions at once, clamped to the cone
+fn solve_friction(c: &mut Constraint, velocities: &mut [Velocities]) {
+    let mut total = [1, 2].map(|row| {
+        let v = relative_velocity(velocities, &c.sides[row]);
+        c.impulse[row] - c.mass[row] * v
+    });
+    let limit = c.friction * c.impulse[0];
+    let length = (total[0] * total[0] + total[1] * total[1]).sqrt();
+    if length > limit {
+        let scale = if length > 0.0 { limit / length } else { 0.0 };
+        total = total.map(|t| t * scale);
+    }
+    for (row, total) in [1, 2].into_iter().zip(total) {
+        let change = total - c.impulse[row];
+        c.impulse[row] = total;
+        apply(velocities, &c.sides[row], change);
+    }
+}
@@ -0,0 +1,350 @@
+// GJK and EPA over convex sets given by their support mappings. GJK walks a
+// simplex of the Minkowski difference A - B towards the origin: the closest
+// point it finds gives the distance between the sets and a witness point on
+// each, and reaching the origin means they overlap. EPA then grows that
+// simplex into a polytope until the face nearest the origin is on the
+// boundary of A - B, giving the penetration depth and direction.
+
+const MAX_ITERATIONS: usize = 64;
+// relative progress below which the search has converged
+const TOLERANCE: f32 = 1e-4;
+
+// A convex set in world space
+pub(crate) trait Convex {
+    // the point furthest along `direction`
+    fn support(&self, direction: &glm::Vec3) -> glm::Vec3;
+    // a point inside, to start the search from
+    fn center(&self) -> glm::Vec3;
+}
+
+// A point of A - B with the points of A and B it came from
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Point {
+    w: glm::Vec3,
+    a: glm::Vec3,
+    b: glm::Vec3,
+}
+
+fn support(a: &dyn Convex, b: &dyn Convex, direction: &glm::Vec3) -> Point {
+    let (pa, pb) = (a.support(direction), b.support(&-direction));
+    Point {
+        w: pa - pb,
+        a: pa,
+        b: pb,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Gjk {
+    // closest points of the two sets
+    Separated {
+        point_a: glm::Vec3,
+        point_b: glm::Vec3,
+    },
+    // the simplex enclosing (or touching) the origin, for EPA
+    Intersecting(Vec<Point>),
+}
+
+pub(crate) fn distance(a: &dyn Convex, b: &dyn Convex) -> Gjk {
+    let mut v = a.center() - b.center();
+    if glm::length2(&v) < 1e-12 {
+        v = glm::Vec3::x();
+    }
+    let mut simplex: Vec<(Point, f32)> = Vec::with_capacity(4);
+    for _ in 0..MAX_ITERATIONS {
+        let p = support(a, b, &-v);
+        if !simplex.is_empty() {
+            let vv = glm::length2(&v);
+            // no closer point of A - B than v along its direction
+            let converged = vv - v.dot(&p.w) <= TOLERANCE * vv;
+            let repeated = simplex.iter().any(|(q, _)| q.w == p.w);
+            if converged || repeated {
+                break;
+            }
+        }
+        let mut points: Vec<Point> = simplex.iter().map(|(q, _)| *q).collect();
+        points.push(p);
+        match closest(&points) {
+            None => return Gjk::Intersecting(points),
+            Some(weights) => {
+                v = weights.iter().map(|(q, l)| q.w * *l).sum();
+                simplex = weights;
+            }
+        }
+        if glm::length2(&v) < 1e-12 {
+            return Gjk::Intersecting(simplex.into_iter().map(|(q, _)| q).collect());
+        }
+    }
+    Gjk::Separated {
+        point_a: simplex.iter().map(|(q, l)| q.a * *l).sum(),
+        point_b: simplex.iter().map(|(q, l)| q.b * *l).sum(),
+    }
+}
+
+// The closest point to the origin of the simplex, as the points it lies
+// between with their barycentric weights; None inside a tetrahedron
+fn closest(points: &[Point]) -> Option<Vec<(Point, f32)>> {
+    match *points {
+        [a] => Some(vec![(a, 1.0)]),
+        [a, b] => Some(segment(a, b)),
+        [a, b, c] => Some(triangle(a, b, c)),
+        [a, b, c, d] => tetrahedron(a, b, c, d),
+        _ => unreachable!("a simplex has one to four points"),
+    }
+}
+
+fn segment(a: Point, b: Point) -> Vec<(Point, f32)> {
+    let ab = b.w - a.w;
+    let t = -a.w.dot(&ab) / glm::length2(&ab);
+    if t.is_nan() || t <= 0.0 {
+        vec![(a, 1.0)]
+    } else if t >= 1.0 {
+        vec![(b, 1.0)]
+    } else {
+        vec![(a, 1.0 - t), (b, t)]
+    }
+}
+
+// by the Voronoi regions of the triangle (Ericson, Real-Time Collision
+// Detection 5.1.5)
+fn triangle(a: Point, b: Point, c: Point) -> Vec<(Point, f32)> {
+    let (ab, ac) = (b.w - a.w, c.w - a.w);
+    let (d1, d2) = (-ab.dot(&a.w), -ac.dot(&a.w));
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return vec![(a, 1.0)];
+    }
+    let (d3, d4) = (-ab.dot(&b.w), -ac.dot(&b.w));
+    if d3 >= 0.0 && d4 <= d3 {
+        return vec![(b, 1.0)];
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        let t = d1 / (d1 - d3);
+        return vec![(a, 1.0 - t), (b, t)];
+    }
+    let (d5, d6) = (-ab.dot(&c.w), -ac.dot(&c.w));
+    if d6 >= 0.0 && d5 <= d6 {
+        return vec![(c, 1.0)];
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        let t = d2 / (d2 - d6);
+        return vec![(a, 1.0 - t), (c, t)];
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        let t = (d4 - d3) / ((d4 - d3) + (d5 - d6));
+        return vec![(b, 1.0 - t), (c, t)];
+    }
+    let sum = va + vb + vc;
+    if sum.abs() < 1e-20 {
+        // degenerate, fall back to the edges
+        return [segment(a, b), segment(a, c), segment(b, c)]
+            .into_iter()
+            .min_by(|x, y| norm2(x).total_cmp(&norm2(y)))
+            .unwrap();
+    }
+    let (v, w) = (vb / sum, vc / sum);
+    vec![(a, 1.0 - v - w), (b, v), (c, w)]
+}
+
+fn tetrahedron(a: Point, b: Point, c: Point, d: Point) -> Option<Vec<(Point, f32)>> {
+    // each face with the vertex opposite it
+    let faces = [(a, b, c, d), (a, c, d, b), (a, d, b, c), (b, d, c, a)];
+    let mut best: Option<Vec<(Point, f32)>> = None;
+    for (p, q, r, opposite) in faces {
+        let n = (q.w - p.w).cross(&(r.w - p.w));
+        let origin_side = -n.dot(&p.w);
+        let opposite_side = n.dot(&(opposite.w - p.w));
+        // a flat tetrahedron has no inside, so every face is a candidate
+        let outside = origin_side * opposite_side < 0.0 || opposite_side.abs() < 1e-12;
+        if outside {
+            let found = triangle(p, q, r);
+            if best.as_ref().is_none_or(|b| norm2(&found) < norm2(b)) {
+                best = Some(found);
+            }
+        }
+    }
+    best
+}
+
+fn norm2(weights: &[(Point, f32)]) -> f32 {
+    glm::length2(&weights.iter().map(|(q, l)| q.w * *l).sum::<glm::Vec3>())
+}
+
+// How far and along which direction (from A towards B) the sets overlap, with
+// the deepest point of each
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Penetration {
+    pub normal: glm::Vec3,
+    pub depth: f32,
+    pub point_a: glm::Vec3,
+    pub point_b: glm::Vec3,
+}
+
+struct Face {
+    vertices: [usize; 3],
+    normal: glm::Vec3,
+    distance: f32,
+}
+
+impl Face {
+    fn new(points: &[Point], vertices: [usize; 3]) -> Option<Self> {
+        let [a, b, c] = vertices.map(|i| points[i].w);
+        let normal = (b - a).cross(&(c - a));
+        let length = glm::length(&normal);
+        if length < 1e-12 {
+            return None;
+        }
+        let normal = normal / length;
+        Some(Self {
+            vertices,
+            normal,
+            distance: normal.dot(&a),
+        })
+    }
+}
+
+// From the simplex GJK ends with when the sets overlap; None when it cannot
+// be grown into a polytope, which only happens for sets with no volume
+pub(crate) fn penetration(
+    a: &dyn Convex,
+    b: &dyn Convex,
+    simplex: Vec<Point>,
+) -> Option<Penetration> {
+    let mut points = tetrahedron_around(a, b, simplex)?;
+    // faces wound outwards
+    if (points[1].w - points[0].w)
+        .cross(&(points[2].w - points[0].w))
+        .dot(&(points[3].w - points[0].w))
+        > 0.0
+    {
+        points.swap(1, 2);
+    }
+    let mut faces: Vec<Face> = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+        .into_iter()
+        .map(|f| Face::new(&points, f))
+        .collect::<Option<_>>()?;
+    for _ in 0..MAX_ITERATIONS {
+        let nearest = nearest_face(&faces);
+        let face = &faces[nearest];
+        let p = support(a, b, &face.normal);
+        let reach = face.normal.dot(&p.w);
+        if reach - face.distance <= TOLERANCE * face.distance.max(1.0)
+            || points.iter().any(|q| q.w == p.w)
+        {
+            break;
+        }
+        points.push(p);
+        let new = points.len() - 1;
+        // the faces p can see go, leaving a hole rimmed by the horizon
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        let before = faces.len();
+        faces.retain(|f| {
+            let visible = f.normal.dot(&(p.w - points[f.vertices[0]].w)) > 0.0;
+            if visible {
+                let [i, j, k] = f.vertices;
+                for edge in [(i, j), (j, k), (k, i)] {
+                    if let Some(at) = horizon.iter().position(|&e| e == (edge.1, edge.0)) {
+                        horizon.swap_remove(at);
+                    } else {
+                        horizon.push(edge);
+                    }
+                }
+            }
+            !visible
+        });
+        if faces.len() == before {
+            break;
+        }
+        for (i, j) in horizon {
+            if let Some(face) = Face::new(&points, [i, j, new]) {
+                faces.push(face);
+            }
+        }
+        if faces.is_empty() {
+            return None;
+        }
+    }
+    let face = &faces[nearest_face(&faces)];
+    let [p, q, r] = face.vertices.map(|i| points[i]);
+    let [u, v, w] = barycentric(&(face.normal * face.distance), &p.w, &q.w, &r.w);
+    Some(Penetration {
+        normal: face.normal,
+        depth: face.distance.max(0.0),
+        point_a: p.a * u + q.a * v + r.a * w,
+        point_b: p.b * u + q.b * v + r.b * w,
+    })
+}
+
+fn nearest_face(faces: &[Face]) -> usize {
+    (0..faces.len())
+        .min_by(|&i, &j| faces[i].distance.total_cmp(&faces[j].distance))
+        .expect("a polytope has faces")
+}
+
+// Adds points of A - B off the simplex until it has four that span a volume
+fn tetrahedron_around(
+    a: &dyn Convex,
+    b: &dyn Convex,
+    mut points: Vec<Point>,
+) -> Option<Vec<Point>> {
+    if points.len() == 1 {
+        let axes = [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()];
+        let found = axes
+            .iter()
+            .flat_map(|axis| [*axis, -axis])
+            .map(|d| support(a, b, &d))
+            .find(|p| glm::distance2(&p.w, &points[0].w) > 1e-12)?;
+        points.push(found);
+    }
+    if points.len() == 2 {
+        let line = points[1].w - points[0].w;
+        // the axis least along the line gives a direction across it
+        let axis = [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()]
+            .into_iter()
+            .min_by(|x, y| line.dot(x).abs().total_cmp(&line.dot(y).abs()))
+            .unwrap();
+        let across = glm::normalize(&line.cross(&axis));
+        let other = glm::normalize(&line.cross(&across));
+        let off_line = |p: &Point| glm::length2(&(p.w - points[0].w).cross(&line));
+        let found = [across, -across, other, -other]
+            .iter()
+            .map(|d| support(a, b, d))
+            .max_by(|x, y| off_line(x).total_cmp(&off_line(y)))
+            .unwrap();
+        if off_line(&found) < 1e-12 * glm::length2(&line) {
+            return None;
+        }
+        points.push(found);
+    }
+    if points.len() == 3 {
+        let normal = (points[1].w - points[0].w).cross(&(points[2].w - points[0].w));
+        let off_plane = |p: &Point| normal.dot(&(p.w - points[0].w)).abs();
+        let (up, down) = (support(a, b, &normal), support(a, b, &-normal));
+        let found = if off_plane(&up) >= off_plane(&down) {
+            up
+        } else {
+            down
+        };
+        if off_plane(&found) < 1e-12 {
+            return None;
+        }
+        points.push(found);
+    }
+    Some(points)
+}
+
+// of p in the triangle abc, clamped to it
+pub(crate) fn barycentric(p: &glm::Vec3, a: &glm::Vec3, b: &glm::Vec3, c: &glm::Vec3) -> [f32; 3] {
+    let (v0, v1, v2) = (b - a, c - a, p - a);
+    let (d00, d01, d11) = (v0.dot(&v0), v0.dot(&v1), v1.dot(&v1));
+    let (d20, d21) = (v2.dot(&v0), v2.dot(&v1));
+    let denominator = d00 * d11 - d01 * d01;
+    if denominator.abs() < 1e-20 {
+        return [1.0, 0.0, 0.0];
+    }
+    let v = ((d11 * d20 - d01 * d21) / denominator).clamp(0.0, 1.0);
+    let w = ((d00 * d21 - d01 * d20) / denominator).clamp(0.0, 1.0 - v);
+    [1.0 - v - w, v, w]
+}
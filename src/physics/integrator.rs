@@ -0,0 +1,200 @@
+// Time integration of second-order systems. A system has positions and
+// velocities that need not line up: a free-floating joint keeps a quaternion
+// among its positions but an angular velocity among its velocities. So an
+// integrator never adds velocities onto positions itself, it asks the system
+// to `displace` them, and everything else happens on plain vectors.
+use super::FreeBody;
+
+pub trait Integrable {
+    fn positions(&self) -> Vec<f32>;
+    fn velocities(&self) -> Vec<f32>;
+    fn set_state(&mut self, positions: &[f32], velocities: &[f32]);
+    // at the current positions and velocities, laid out like the velocities
+    fn accelerations(&self) -> Vec<f32>;
+    // where `positions` end up moving at `velocities` for `dt`
+    fn displace(&self, positions: &[f32], velocities: &[f32], dt: f32) -> Vec<f32>;
+}
+
+pub trait Integrator {
+    // advances `system` by `dt`
+    fn step(&self, system: &mut dyn Integrable, dt: f32);
+}
+
+// x + s * d
+fn offset(x: &[f32], d: &[f32], s: f32) -> Vec<f32> {
+    std::iter::zip(x, d).map(|(x, d)| x + d * s).collect()
+}
+
+// Positions move with the old velocities; gains energy on oscillators
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ExplicitEuler;
+
+impl Integrator for ExplicitEuler {
+    fn step(&self, system: &mut dyn Integrable, dt: f32) {
+        let (q, v) = (system.positions(), system.velocities());
+        let a = system.accelerations();
+        let q = system.displace(&q, &v, dt);
+        system.set_state(&q, &offset(&v, &a, dt));
+    }
+}
+
+// Symplectic Euler: positions move with the new velocities, which keeps the
+// energy of conservative systems bounded
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn step(&self, system: &mut dyn Integrable, dt: f32) {
+        let (q, v) = (system.positions(), system.velocities());
+        let v = offset(&v, &system.accelerations(), dt);
+        let q = system.displace(&q, &v, dt);
+        system.set_state(&q, &v);
+    }
+}
+
+// Classic fourth-order Runge-Kutta, stages taken from the start of the step
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn step(&self, system: &mut dyn Integrable, dt: f32) {
+        let (q, v) = (system.positions(), system.velocities());
+        let mut velocities = vec![v.clone()];
+        let mut accelerations = vec![system.accelerations()];
+        for h in [dt / 2.0, dt / 2.0, dt] {
+            let (vk, ak) = (velocities.last().unwrap(), accelerations.last().unwrap());
+            let stage = (system.displace(&q, vk, h), offset(&v, ak, h));
+            system.set_state(&stage.0, &stage.1);
+            accelerations.push(system.accelerations());
+            velocities.push(stage.1);
+        }
+        let weighted = |k: &[Vec<f32>]| -> Vec<f32> {
+            (0..v.len())
+                .map(|i| (k[0][i] + 2.0 * k[1][i] + 2.0 * k[2][i] + k[3][i]) / 6.0)
+                .collect()
+        };
+        let q = system.displace(&q, &weighted(&velocities), dt);
+        system.set_state(&q, &offset(&v, &weighted(&accelerations), dt));
+    }
+}
+
+// Velocity Verlet, second order and symplectic; accelerations depending on
+// velocity are taken at the half-step velocity
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn step(&self, system: &mut dyn Integrable, dt: f32) {
+        let (q, v) = (system.positions(), system.velocities());
+        let half = offset(&v, &system.accelerations(), dt / 2.0);
+        let q = system.displace(&q, &half, dt);
+        system.set_state(&q, &half);
+        let v = offset(&half, &system.accelerations(), dt / 2.0);
+        system.set_state(&q, &v);
+    }
+}
+
+// Positions are the body position then theta, velocities its velocity then
+// omega; `force` is held over the step and nothing turns the body
+impl Integrable for FreeBody {
+    fn positions(&self) -> Vec<f32> {
+        vec![self.posn.x, self.posn.y, self.posn.z, self.theta]
+    }
+    fn velocities(&self) -> Vec<f32> {
+        vec![self.vel.x, self.vel.y, self.vel.z, self.omega]
+    }
+    fn set_state(&mut self, positions: &[f32], velocities: &[f32]) {
+        self.posn = glm::vec3(positions[0], positions[1], positions[2]);
+        self.theta = positions[3];
+        self.vel = glm::vec3(velocities[0], velocities[1], velocities[2]);
+        self.omega = velocities[3];
+    }
+    fn accelerations(&self) -> Vec<f32> {
+        let a = self.force / self.mass;
+        vec![a.x, a.y, a.z, 0.0]
+    }
+    fn displace(&self, positions: &[f32], velocities: &[f32], dt: f32) -> Vec<f32> {
+        offset(positions, velocities, dt)
+    }
+}
+
+// the shortest step a FixedTimestep takes, a microsecond
+const MIN_DT: f32 = 1e-6;
+
+// Runs a simulation at a fixed step whatever the frame time: elapsed time
+// accumulates and whole steps of `dt` are taken out of it, each split into
+// `substeps` integrator steps
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedTimestep {
+    dt: f32,
+    substeps: usize,
+    max_steps: usize,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    // a dt shorter than MIN_DT, or not a finite number, is taken as MIN_DT
+    pub fn new(dt: f32) -> Self {
+        let dt = if dt.is_finite() {
+            dt.max(MIN_DT)
+        } else {
+            MIN_DT
+        };
+        Self {
+            dt,
+            substeps: 1,
+            max_steps: 8,
+            accumulator: 0.0,
+        }
+    }
+    pub fn with_substeps(mut self, substeps: usize) -> Self {
+        self.substeps = substeps.max(1);
+        self
+    }
+    // steps taken per call at most; time beyond them is dropped so a slow
+    // frame does not make the next one slower still
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps.max(1);
+        self
+    }
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+    pub fn substeps(&self) -> usize {
+        self.substeps
+    }
+    pub fn substep(&self) -> f32 {
+        self.dt / self.substeps as f32
+    }
+    // how far into the next step the accumulated time is, for interpolating
+    // what is drawn
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.dt
+    }
+
+    // Adds `elapsed` and calls `step` with the substep length for every
+    // substep of the whole steps that fit, returning the number of steps
+    pub fn advance(&mut self, elapsed: f32, mut step: impl FnMut(f32)) -> usize {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.dt && steps < self.max_steps {
+            for _ in 0..self.substeps {
+                step(self.substep());
+            }
+            self.accumulator -= self.dt;
+            steps += 1;
+        }
+        if steps == self.max_steps {
+            self.accumulator %= self.dt;
+        }
+        steps
+    }
+    pub fn integrate(
+        &mut self,
+        elapsed: f32,
+        integrator: &dyn Integrator,
+        system: &mut dyn Integrable,
+    ) -> usize {
+        self.advance(elapsed, |dt| integrator.step(system, dt))
+    }
+}
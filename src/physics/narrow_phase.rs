@@ -0,0 +1,1027 @@
+// Contacts between pairs of colliders: where they touch, along which normal
+// and how deep. Primitive pairs with a closed form have their own routines:
+// spheres and capsules against each other and against boxes, boxes against
+// boxes by separating axes, and anything against a plane. The rest go through
+// GJK for the distance between the convex shapes, and EPA for the
+// penetration once they overlap. Spheres and capsules take part in GJK as the
+// point or segment at their core, grown by their radius, so touching ones
+// are exact. Meshes on bodies and robots collide as the convex hull of their
+// vertices, or as the hulls of their convex decomposition when they have
+// one, meshes fixed in the world and height fields triangle by triangle.
+use super::gjk::{self, Convex, Gjk};
+use super::static_mesh::{MeshEdge, StaticMeshCollider, TriangleSurface};
+use super::world::{Attachment, Collider, ColliderId};
+use crate::geometry::Aabb;
+use crate::terrain::HeightField;
+use crate::urdf::GeometryShape;
+use crate::world::WorldShape;
+use std::sync::Arc;
+
+// how far apart shapes may be and still get a contact, with negative depth
+pub const CONTACT_MARGIN: f32 = 0.01;
+// contacts kept per pair
+pub const MAX_CONTACTS: usize = 4;
+
+// A collider's shape in its own frame
+#[derive(Debug, Clone, PartialEq)]
+pub enum CollisionShape {
+    Sphere { radius: f32 },
+    Box { half_extents: glm::Vec3 },
+    // a cylinder `2 * half_length` long along z between two hemispheres
+    Capsule { radius: f32, half_length: f32 },
+    // along z
+    Cylinder { radius: f32, half_length: f32 },
+    // the convex hull of the points
+    Hull { points: Vec<glm::Vec3> },
+    // convex shapes together, each colliding on its own
+    Compound { pieces: Vec<CollisionShape> },
+    // the half-space behind a plane through the origin
+    Plane { normal: glm::Vec3 },
+    // triangles fixed in the world, against which only convex shapes collide
+    TriangleMesh(Arc<StaticMeshCollider>),
+    // terrain fixed in the world, likewise
+    HeightField(Arc<HeightField>),
+}
+
+impl CollisionShape {
+    // None for meshes not loaded
+    pub fn from_collider(collider: &Collider) -> Option<Self> {
+        let shape = match &collider.shape {
+            WorldShape::Plane { normal, .. } => CollisionShape::Plane {
+                normal: glm::normalize(normal),
+            },
+            WorldShape::Solid(solid) => match *solid {
+                GeometryShape::Sphere { radius } => CollisionShape::Sphere { radius },
+                GeometryShape::Box { size } => CollisionShape::Box {
+                    half_extents: size / 2.0,
+                },
+                GeometryShape::Capsule { radius, length } => CollisionShape::Capsule {
+                    radius,
+                    half_length: length / 2.0,
+                },
+                GeometryShape::Cylinder { radius, length } => CollisionShape::Cylinder {
+                    radius,
+                    half_length: length / 2.0,
+                },
+                GeometryShape::Mesh { .. } if collider.decomposition.is_some() => {
+                    let pieces: Vec<CollisionShape> = collider
+                        .decomposition
+                        .iter()
+                        .flat_map(|d| &d.hulls)
+                        .map(|hull| CollisionShape::Hull {
+                            points: hull.points.clone(),
+                        })
+                        .collect();
+                    if pieces.is_empty() {
+                        return None;
+                    }
+                    CollisionShape::Compound { pieces }
+                }
+                GeometryShape::Mesh { .. } if collider.attachment == Attachment::Fixed => {
+                    let mesh = StaticMeshCollider::from(&collider.geometry);
+                    if mesh.is_empty() {
+                        return None;
+                    }
+                    CollisionShape::TriangleMesh(Arc::new(mesh))
+                }
+                GeometryShape::Mesh { .. } => {
+                    let mut points: Vec<glm::Vec3> =
+                        collider.geometry.verts.iter().map(|v| v.position).collect();
+                    points.sort_unstable_by(|a, b| {
+                        a.iter()
+                            .zip(b.iter())
+                            .fold(std::cmp::Ordering::Equal, |o, (x, y)| {
+                                o.then(x.total_cmp(y))
+                            })
+                    });
+                    points.dedup();
+                    if points.is_empty() {
+                        return None;
+                    }
+                    CollisionShape::Hull { points }
+                }
+            },
+        };
+        Some(shape)
+    }
+
+    // the point of the shape furthest along `direction`, in its frame
+    pub fn support(&self, direction: &glm::Vec3) -> glm::Vec3 {
+        let unit = |d: &glm::Vec3| {
+            let length = glm::length(d);
+            if length > 0.0 {
+                d / length
+            } else {
+                glm::Vec3::zeros()
+            }
+        };
+        let sign = |x: f32| if x < 0.0 { -1.0 } else { 1.0 };
+        match self {
+            CollisionShape::Sphere { radius } => unit(direction) * *radius,
+            CollisionShape::Box { half_extents } => {
+                half_extents.zip_map(direction, |h, d| h * sign(d))
+            }
+            CollisionShape::Capsule {
+                radius,
+                half_length,
+            } => glm::vec3(0.0, 0.0, half_length * sign(direction.z)) + unit(direction) * *radius,
+            CollisionShape::Cylinder {
+                radius,
+                half_length,
+            } => {
+                let across = unit(&glm::vec3(direction.x, direction.y, 0.0)) * *radius;
+                glm::vec3(across.x, across.y, half_length * sign(direction.z))
+            }
+            CollisionShape::Hull { points } => *points
+                .iter()
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .expect("hulls have points"),
+            CollisionShape::Compound { pieces } => pieces
+                .iter()
+                .map(|piece| piece.support(direction))
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .expect("compounds have pieces"),
+            // of the hull
+            CollisionShape::TriangleMesh(mesh) => *mesh
+                .vertices()
+                .iter()
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .expect("meshes kept have triangles"),
+            CollisionShape::HeightField(field) => (0..field.rows())
+                .flat_map(|row| (0..field.columns()).map(move |column| (column, row)))
+                .map(|(column, row)| field.position(column, row))
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .expect("height fields have points"),
+            // unbounded, so only ever met by the plane routines
+            CollisionShape::Plane { normal } => -normal * f32::MAX.sqrt(),
+        }
+    }
+
+    // the radius spheres and capsules have around their core
+    fn rounding(&self) -> f32 {
+        match *self {
+            CollisionShape::Sphere { radius } | CollisionShape::Capsule { radius, .. } => radius,
+            _ => 0.0,
+        }
+    }
+}
+
+// One point of contact. The normal points from the first shape to the
+// second; `point` is halfway between the surfaces, and `depth` how far they
+// overlap along the normal, negative for shapes apart within the margin.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contact {
+    pub point: glm::Vec3,
+    pub normal: glm::Vec3,
+    pub depth: f32,
+}
+
+impl Contact {
+    // between a point on the surface of each shape
+    fn between(on_a: &glm::Vec3, on_b: &glm::Vec3, normal: glm::Vec3, depth: f32) -> Self {
+        Self {
+            point: (on_a + on_b) / 2.0,
+            normal,
+            depth,
+        }
+    }
+    fn flipped(self) -> Self {
+        Self {
+            normal: -self.normal,
+            ..self
+        }
+    }
+}
+
+// The contacts of a pair of colliders, normals from `a` to `b`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContactManifold {
+    pub a: ColliderId,
+    pub b: ColliderId,
+    pub contacts: Vec<Contact>,
+}
+
+// Contacts between two shapes at their world poses, at most MAX_CONTACTS, for
+// shapes overlapping or less than `margin` apart
+pub fn collide(
+    a: &CollisionShape,
+    pose_a: &glm::Mat4,
+    b: &CollisionShape,
+    pose_b: &glm::Mat4,
+    margin: f32,
+) -> Vec<Contact> {
+    use CollisionShape::*;
+    let flip = |contacts: Vec<Contact>| contacts.into_iter().map(Contact::flipped).collect();
+    let contacts = match (a, b) {
+        (Compound { pieces }, _) => pieces
+            .iter()
+            .flat_map(|piece| collide(piece, pose_a, b, pose_b, margin))
+            .collect(),
+        (_, Compound { .. }) => flip(collide(b, pose_b, a, pose_a, margin)),
+        (TriangleMesh(_) | HeightField(_), TriangleMesh(_) | HeightField(_) | Plane { .. }) => {
+            Vec::new()
+        }
+        (Plane { .. }, TriangleMesh(_) | HeightField(_)) => Vec::new(),
+        (TriangleMesh(mesh), _) => against_mesh(mesh.as_ref(), pose_a, b, pose_b, margin),
+        (HeightField(field), _) => against_mesh(field.as_ref(), pose_a, b, pose_b, margin),
+        (_, TriangleMesh(_) | HeightField(_)) => flip(collide(b, pose_b, a, pose_a, margin)),
+        (Plane { .. }, Plane { .. }) => Vec::new(),
+        (_, Plane { normal }) => {
+            let normal = glm::normalize(&(glm::mat4_to_mat3(pose_b) * normal));
+            let offset = normal.dot(&pose_b.column(3).xyz());
+            against_plane(a, pose_a, &normal, offset, margin)
+        }
+        (Plane { .. }, _) => flip(collide(b, pose_b, a, pose_a, margin)),
+        (Sphere { radius: ra }, Sphere { radius: rb }) => {
+            spheres(&position(pose_a), *ra, &position(pose_b), *rb, margin)
+                .into_iter()
+                .collect()
+        }
+        (Sphere { radius }, Box { half_extents }) => {
+            sphere_box(&position(pose_a), *radius, pose_b, half_extents, margin)
+                .into_iter()
+                .collect()
+        }
+        (Box { .. }, Sphere { .. }) => flip(collide(b, pose_b, a, pose_a, margin)),
+        (Box { half_extents: ha }, Box { half_extents: hb }) => {
+            boxes(pose_a, ha, pose_b, hb, margin)
+        }
+        (
+            Capsule {
+                radius: ra,
+                half_length: la,
+            },
+            Capsule {
+                radius: rb,
+                half_length: lb,
+            },
+        ) => capsules(
+            &core_segment(pose_a, *la),
+            *ra,
+            &core_segment(pose_b, *lb),
+            *rb,
+            margin,
+        ),
+        (Capsule { .. }, Box { .. }) => capsule_box(a, pose_a, b, pose_b, margin),
+        (Box { .. }, Capsule { .. }) => flip(capsule_box(b, pose_b, a, pose_a, margin)),
+        _ => convex(a, pose_a, b, pose_b, margin).into_iter().collect(),
+    };
+    reduce(contacts)
+}
+
+fn position(pose: &glm::Mat4) -> glm::Vec3 {
+    pose.column(3).xyz()
+}
+
+// the ends of the segment at the core of a capsule
+fn core_segment(pose: &glm::Mat4, half_length: f32) -> [glm::Vec3; 2] {
+    let axis = glm::mat4_to_mat3(pose) * glm::vec3(0.0, 0.0, half_length);
+    let centre = position(pose);
+    [centre - axis, centre + axis]
+}
+
+// A shape at its pose, shrunk by `rounding` to its core
+struct Posed<'a> {
+    shape: &'a CollisionShape,
+    rotation: glm::Mat3,
+    translation: glm::Vec3,
+    rounding: f32,
+}
+
+impl<'a> Posed<'a> {
+    fn new(shape: &'a CollisionShape, pose: &glm::Mat4) -> Self {
+        Self {
+            shape,
+            rotation: glm::mat4_to_mat3(pose),
+            translation: position(pose),
+            rounding: 0.0,
+        }
+    }
+    fn core(shape: &'a CollisionShape, pose: &glm::Mat4) -> Self {
+        Self {
+            rounding: shape.rounding(),
+            ..Self::new(shape, pose)
+        }
+    }
+}
+
+impl Convex for Posed<'_> {
+    fn support(&self, direction: &glm::Vec3) -> glm::Vec3 {
+        let local = self.rotation.transpose() * direction;
+        let mut point = self.rotation * self.shape.support(&local) + self.translation;
+        if self.rounding > 0.0 {
+            point -= direction.normalize() * self.rounding;
+        }
+        point
+    }
+    fn center(&self) -> glm::Vec3 {
+        self.translation
+    }
+}
+
+// GJK between the cores, grown by the roundings; EPA between the whole shapes
+// when the cores overlap
+fn convex(
+    a: &CollisionShape,
+    pose_a: &glm::Mat4,
+    b: &CollisionShape,
+    pose_b: &glm::Mat4,
+    margin: f32,
+) -> Option<Contact> {
+    let (core_a, core_b) = (Posed::core(a, pose_a), Posed::core(b, pose_b));
+    let (ra, rb) = (core_a.rounding, core_b.rounding);
+    let simplex = match gjk::distance(&core_a, &core_b) {
+        Gjk::Separated { point_a, point_b } => {
+            let distance = glm::distance(&point_a, &point_b);
+            if distance > 1e-6 {
+                let depth = ra + rb - distance;
+                if depth < -margin {
+                    return None;
+                }
+                let normal = (point_b - point_a) / distance;
+                return Some(Contact::between(
+                    &(point_a + normal * ra),
+                    &(point_b - normal * rb),
+                    normal,
+                    depth,
+                ));
+            }
+            None
+        }
+        Gjk::Intersecting(simplex) => Some(simplex),
+    };
+    let (whole_a, whole_b) = (Posed::new(a, pose_a), Posed::new(b, pose_b));
+    let simplex = match simplex {
+        Some(simplex) if ra == 0.0 && rb == 0.0 => simplex,
+        _ => match gjk::distance(&whole_a, &whole_b) {
+            Gjk::Intersecting(simplex) => simplex,
+            // cores touching, the shapes a hair apart
+            Gjk::Separated { .. } => return None,
+        },
+    };
+    let found = gjk::penetration(&whole_a, &whole_b, simplex)?;
+    Some(Contact::between(
+        &found.point_a,
+        &found.point_b,
+        found.normal,
+        found.depth,
+    ))
+}
+
+fn spheres(a: &glm::Vec3, ra: f32, b: &glm::Vec3, rb: f32, margin: f32) -> Option<Contact> {
+    let distance = glm::distance(a, b);
+    let depth = ra + rb - distance;
+    if depth < -margin {
+        return None;
+    }
+    // concentric spheres push apart along any direction
+    let normal = if distance > 1e-6 {
+        (b - a) / distance
+    } else {
+        glm::Vec3::z()
+    };
+    Some(Contact::between(
+        &(a + normal * ra),
+        &(b - normal * rb),
+        normal,
+        depth,
+    ))
+}
+
+fn sphere_box(
+    centre: &glm::Vec3,
+    radius: f32,
+    pose: &glm::Mat4,
+    half_extents: &glm::Vec3,
+    margin: f32,
+) -> Option<Contact> {
+    let rotation = glm::mat4_to_mat3(pose);
+    let translation = position(pose);
+    let local = rotation.transpose() * (centre - translation);
+    let nearest = glm::clamp_vec(&local, &-half_extents, half_extents);
+    let (normal, on_box, depth) = if nearest != local {
+        let distance = glm::distance(&local, &nearest);
+        ((nearest - local) / distance, nearest, radius - distance)
+    } else {
+        // the centre is inside: out through the nearest face
+        let room = half_extents - local.abs();
+        let axis = room.imin();
+        let side = if local[axis] < 0.0 { -1.0 } else { 1.0 };
+        let mut on_box = local;
+        on_box[axis] = side * half_extents[axis];
+        let mut normal = glm::Vec3::zeros();
+        normal[axis] = -side;
+        (normal, on_box, radius + room[axis])
+    };
+    if depth < -margin {
+        return None;
+    }
+    let on_sphere = local + normal * radius;
+    Some(Contact::between(
+        &(rotation * on_sphere + translation),
+        &(rotation * on_box + translation),
+        rotation * normal,
+        depth,
+    ))
+}
+
+// closest points of segments pq and rs (Ericson, Real-Time Collision
+// Detection 5.1.9)
+fn closest_on_segments(
+    p: &glm::Vec3,
+    q: &glm::Vec3,
+    r: &glm::Vec3,
+    s: &glm::Vec3,
+) -> (glm::Vec3, glm::Vec3) {
+    let (d1, d2, between) = (q - p, s - r, p - r);
+    let (a, e, f) = (d1.dot(&d1), d2.dot(&d2), d2.dot(&between));
+    let (mut t, mut u);
+    if a <= 1e-12 && e <= 1e-12 {
+        return (*p, *r);
+    }
+    if a <= 1e-12 {
+        t = 0.0;
+        u = (f / e).clamp(0.0, 1.0);
+    } else {
+        let c = d1.dot(&between);
+        if e <= 1e-12 {
+            u = 0.0;
+            t = (-c / a).clamp(0.0, 1.0);
+        } else {
+            let b = d1.dot(&d2);
+            let denominator = a * e - b * b;
+            t = if denominator > 1e-12 {
+                ((b * f - c * e) / denominator).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            u = (b * t + f) / e;
+            if u < 0.0 {
+                u = 0.0;
+                t = (-c / a).clamp(0.0, 1.0);
+            } else if u > 1.0 {
+                u = 1.0;
+                t = ((b - c) / a).clamp(0.0, 1.0);
+            }
+        }
+    }
+    (p + d1 * t, r + d2 * u)
+}
+
+// the point of segment pq nearest x
+fn closest_on_segment(p: &glm::Vec3, q: &glm::Vec3, x: &glm::Vec3) -> glm::Vec3 {
+    let d = q - p;
+    let length2 = d.dot(&d);
+    if length2 <= 1e-12 {
+        return *p;
+    }
+    p + d * ((x - p).dot(&d) / length2).clamp(0.0, 1.0)
+}
+
+// Two contacts where parallel capsules lie along each other, else one
+fn capsules(a: &[glm::Vec3; 2], ra: f32, b: &[glm::Vec3; 2], rb: f32, margin: f32) -> Vec<Contact> {
+    let (da, db) = (a[1] - a[0], b[1] - b[0]);
+    let (la, lb) = (glm::length(&da), glm::length(&db));
+    if la > 1e-6 && lb > 1e-6 && glm::length(&da.cross(&db)) < 1e-3 * la * lb {
+        let axis = da / la;
+        let ends = [axis.dot(&(b[0] - a[0])), axis.dot(&(b[1] - a[0]))];
+        let (from, to) = (ends[0].min(ends[1]).max(0.0), ends[0].max(ends[1]).min(la));
+        if to - from > 1e-3 * la {
+            return [from, to]
+                .iter()
+                .filter_map(|t| {
+                    let on_a = a[0] + axis * *t;
+                    let on_b = closest_on_segment(&b[0], &b[1], &on_a);
+                    spheres(&on_a, ra, &on_b, rb, margin)
+                })
+                .collect();
+        }
+    }
+    let (on_a, on_b) = closest_on_segments(&a[0], &a[1], &b[0], &b[1]);
+    if glm::distance2(&on_a, &on_b) < 1e-12 {
+        // crossing cores part across both
+        let across = da.cross(&db);
+        let normal = if glm::length2(&across) > 1e-12 {
+            glm::normalize(&across)
+        } else {
+            glm::Vec3::z()
+        };
+        return vec![Contact::between(
+            &(on_a + normal * ra),
+            &(on_b - normal * rb),
+            normal,
+            ra + rb,
+        )];
+    }
+    spheres(&on_a, ra, &on_b, rb, margin).into_iter().collect()
+}
+
+// The spheres at the capsule ends against the box, which keeps a capsule
+// lying on a box steady, and the nearest point of its core when that is
+// deeper than both
+fn capsule_box(
+    capsule: &CollisionShape,
+    pose_a: &glm::Mat4,
+    cuboid: &CollisionShape,
+    pose_b: &glm::Mat4,
+    margin: f32,
+) -> Vec<Contact> {
+    let (
+        CollisionShape::Capsule {
+            radius,
+            half_length,
+        },
+        CollisionShape::Box { half_extents },
+    ) = (capsule, cuboid)
+    else {
+        unreachable!("called for a capsule and a box");
+    };
+    let ends = core_segment(pose_a, *half_length);
+    let core = Posed::core(capsule, pose_a);
+    let cuboid_posed = Posed::new(cuboid, pose_b);
+    match gjk::distance(&core, &cuboid_posed) {
+        Gjk::Separated { .. } => {
+            let mut contacts: Vec<Contact> = ends
+                .iter()
+                .filter_map(|end| sphere_box(end, *radius, pose_b, half_extents, margin))
+                .collect();
+            // lying flat, the core is as near all along and the ends will do
+            let ends_depth = contacts.iter().map(|c| c.depth).fold(f32::MIN, f32::max);
+            contacts.extend(
+                convex(capsule, pose_a, cuboid, pose_b, margin)
+                    .filter(|c| c.depth > ends_depth + 1e-4 * radius.max(1.0)),
+            );
+            contacts
+        }
+        // the core goes into the box
+        Gjk::Intersecting(_) => convex(capsule, pose_a, cuboid, pose_b, margin)
+            .into_iter()
+            .collect(),
+    }
+}
+
+// Points of the shape behind the plane n.x = offset, or within the margin
+// in front of it: the deepest point of round shapes, the corners of boxes
+// and hulls, and four points round each rim of a cylinder
+fn against_plane(
+    shape: &CollisionShape,
+    pose: &glm::Mat4,
+    normal: &glm::Vec3,
+    offset: f32,
+    margin: f32,
+) -> Vec<Contact> {
+    let rotation = glm::mat4_to_mat3(pose);
+    let translation = position(pose);
+    let world = |p: glm::Vec3| rotation * p + translation;
+    let (points, rounding): (Vec<glm::Vec3>, f32) = match shape {
+        CollisionShape::Sphere { radius } => (vec![translation], *radius),
+        CollisionShape::Capsule {
+            radius,
+            half_length,
+        } => (core_segment(pose, *half_length).to_vec(), *radius),
+        CollisionShape::Box { half_extents } => (
+            (0..8)
+                .map(|i| {
+                    let corner = glm::vec3(
+                        if i & 1 == 0 { -1.0 } else { 1.0 },
+                        if i & 2 == 0 { -1.0 } else { 1.0 },
+                        if i & 4 == 0 { -1.0 } else { 1.0 },
+                    );
+                    world(half_extents.component_mul(&corner))
+                })
+                .collect(),
+            0.0,
+        ),
+        CollisionShape::Cylinder {
+            radius,
+            half_length,
+        } => {
+            // down the plane, across the cylinder axis
+            let local = rotation.transpose() * -normal;
+            let mut down = glm::vec2(local.x, local.y);
+            if glm::length2(&down) < 1e-12 {
+                down = glm::vec2(1.0, 0.0);
+            }
+            let down = glm::normalize(&down) * *radius;
+            let across = glm::vec2(-down.y, down.x);
+            let rims = [-half_length, *half_length].into_iter().flat_map(|z| {
+                [down, -down, across, -across].map(|rim| world(glm::vec3(rim.x, rim.y, z)))
+            });
+            (rims.collect(), 0.0)
+        }
+        CollisionShape::Hull { points } => (points.iter().map(|p| world(*p)).collect(), 0.0),
+        // compounds by their pieces before here
+        CollisionShape::Plane { .. }
+        | CollisionShape::Compound { .. }
+        | CollisionShape::TriangleMesh(_)
+        | CollisionShape::HeightField(_) => (Vec::new(), 0.0),
+    };
+    points
+        .into_iter()
+        .filter_map(|centre| {
+            let deepest = centre - normal * rounding;
+            let gap = normal.dot(&deepest) - offset;
+            (gap <= margin)
+                .then(|| Contact::between(&deepest, &(deepest - normal * gap), -normal, -gap))
+        })
+        .collect()
+}
+
+// A triangle of a mesh as a convex set
+struct MeshTriangle([glm::Vec3; 3]);
+
+impl Convex for MeshTriangle {
+    fn support(&self, direction: &glm::Vec3) -> glm::Vec3 {
+        *self
+            .0
+            .iter()
+            .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+            .unwrap()
+    }
+    fn center(&self) -> glm::Vec3 {
+        (self.0[0] + self.0[1] + self.0[2]) / 3.0
+    }
+}
+
+// Contacts of a convex shape against the triangles of a mesh near it, normals
+// from the mesh to the shape
+fn against_mesh(
+    mesh: &dyn TriangleSurface,
+    pose_mesh: &glm::Mat4,
+    shape: &CollisionShape,
+    pose: &glm::Mat4,
+    margin: f32,
+) -> Vec<Contact> {
+    // everything in the frame of the mesh
+    let rotation = glm::mat4_to_mat3(pose_mesh);
+    let translation = position(pose_mesh);
+    let mut to_mesh = glm::mat3_to_mat4(&rotation.transpose());
+    to_mesh.set_column(3, &(rotation.transpose() * -translation).push(1.0));
+    let local = to_mesh * pose;
+    let whole = Posed::new(shape, &local);
+    let bounds = [glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()]
+        .iter()
+        .fold(Aabb::empty(), |b, axis| {
+            b.including(&whole.support(axis))
+                .including(&whole.support(&-axis))
+        });
+    let mut contacts: Vec<Contact> = Vec::new();
+    mesh.triangles_in(&bounds.fattened(margin), &mut |t| {
+        for contact in against_triangle(mesh, t, shape, &local, margin) {
+            // the same point found through the triangles either side of an edge
+            let found = contacts.iter().any(|c| {
+                glm::distance2(&c.point, &contact.point) < 1e-10
+                    && c.normal.dot(&contact.normal) > 0.999
+            });
+            if !found {
+                contacts.push(contact);
+            }
+        }
+    });
+    contacts
+        .into_iter()
+        .map(|c| Contact {
+            point: rotation * c.point + translation,
+            normal: rotation * c.normal,
+            depth: c.depth,
+        })
+        .collect()
+}
+
+// The part of a triangle a contact is on, by its corners
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Feature {
+    Face,
+    // from corner k to the next
+    Edge(usize),
+    Corner(usize),
+}
+
+impl Feature {
+    fn of(weights: [f32; 3]) -> Self {
+        let on: Vec<usize> = (0..3).filter(|&k| weights[k] > 1e-4).collect();
+        match on[..] {
+            [k] => Feature::Corner(k),
+            [0, 1] => Feature::Edge(0),
+            [1, 2] => Feature::Edge(1),
+            [0, 2] => Feature::Edge(2),
+            _ => Feature::Face,
+        }
+    }
+}
+
+// Contacts of a shape with the front of triangle `t`, in the mesh frame. The
+// nearest points of the two, or the deepest once they overlap, give the
+// contact when they are on an edge or corner where the mesh ends or folds
+// outwards. Otherwise the shape meets the face: its points behind the plane
+// of the triangle and over it, as against a plane, or where it only reaches
+// over in part the point it touches.
+fn against_triangle(
+    mesh: &dyn TriangleSurface,
+    t: usize,
+    shape: &CollisionShape,
+    pose: &glm::Mat4,
+    margin: f32,
+) -> Vec<Contact> {
+    let corners = mesh.triangle(t);
+    let normal = mesh.normal(t);
+    let core = Posed::core(shape, pose);
+    // shapes with their centre behind the triangle are left to the others
+    if normal.dot(&(core.center() - corners[0])) < 0.0 {
+        return Vec::new();
+    }
+    let triangle = MeshTriangle(corners);
+    // on the triangle, from it to the shape, how deep, on the shape
+    let touching = match gjk::distance(&core, &triangle) {
+        Gjk::Separated { point_a, point_b } => {
+            let distance = glm::distance(&point_a, &point_b);
+            if core.rounding - distance < -margin {
+                return Vec::new();
+            }
+            (distance > 1e-6).then(|| {
+                let towards = (point_a - point_b) / distance;
+                (
+                    point_b,
+                    towards,
+                    core.rounding - distance,
+                    point_a - towards * core.rounding,
+                )
+            })
+        }
+        Gjk::Intersecting(_) => {
+            let whole = Posed::new(shape, pose);
+            match gjk::distance(&whole, &triangle) {
+                Gjk::Intersecting(simplex) => gjk::penetration(&whole, &triangle, simplex)
+                    .map(|found| (found.point_b, -found.normal, found.depth, found.point_a)),
+                Gjk::Separated { .. } => None,
+            }
+        }
+    };
+    if let Some((on_triangle, towards, depth, on_shape)) = touching {
+        let weights = gjk::barycentric(&on_triangle, &corners[0], &corners[1], &corners[2]);
+        if let Some(normal) = edge_normal(mesh, t, Feature::of(weights), &towards) {
+            return vec![Contact::between(&on_triangle, &on_shape, normal, depth)];
+        }
+    }
+
+    let offset = normal.dot(&corners[0]);
+    let over = |p: &glm::Vec3| {
+        (0..3).all(|k| {
+            let edge = corners[(k + 1) % 3] - corners[k];
+            edge.cross(&(p - corners[k])).dot(&normal) >= -1e-4 * glm::length2(&edge)
+        })
+    };
+    let mut contacts: Vec<Contact> = against_plane(shape, pose, &normal, offset, margin)
+        .into_iter()
+        .filter(|c| over(&c.point))
+        .map(Contact::flipped)
+        .collect();
+    if contacts.is_empty() {
+        if let Some((_, _, _, on_shape)) = touching.filter(|touching| over(&touching.3)) {
+            let gap = normal.dot(&on_shape) - offset;
+            if gap <= margin {
+                contacts.push(Contact::between(
+                    &(on_shape - normal * gap),
+                    &on_shape,
+                    normal,
+                    -gap,
+                ));
+            }
+        }
+    }
+    contacts
+}
+
+// The normal of a contact on an edge or corner of triangle `t`, `towards` if
+// the mesh ends there or folds outwards, kept between the normals of the
+// faces either side; None where the face normal should be taken instead
+fn edge_normal(
+    mesh: &dyn TriangleSurface,
+    t: usize,
+    feature: Feature,
+    towards: &glm::Vec3,
+) -> Option<glm::Vec3> {
+    let edges = mesh.edges(t);
+    match feature {
+        Feature::Face => None,
+        Feature::Corner(k) => (!mesh.is_internal_corner(t, k)).then_some(*towards),
+        Feature::Edge(k) => match edges[k] {
+            MeshEdge::Boundary => Some(*towards),
+            MeshEdge::Internal { .. } => None,
+            MeshEdge::Convex { neighbour } => {
+                let corners = mesh.triangle(t);
+                let along = glm::normalize(&(corners[(k + 1) % 3] - corners[k]));
+                let across = towards - along * along.dot(towards);
+                let (n1, n2) = (mesh.normal(t), mesh.normal(neighbour));
+                if glm::length2(&across) < 1e-12 {
+                    return Some(n1);
+                }
+                let across = glm::normalize(&across);
+                let wedge = n1.dot(&n2) - 1e-4;
+                Some(if across.dot(&n1) >= wedge && across.dot(&n2) >= wedge {
+                    *towards
+                } else if across.dot(&n1) >= across.dot(&n2) {
+                    n1
+                } else {
+                    n2
+                })
+            }
+        },
+    }
+}
+
+// The separating axis test for two boxes. The face of either box, or the pair
+// of edges, the boxes overlap least across gives the normal; faces are
+// preferred unless an edge pair is clearly shallower, as their contacts are
+// steadier. A face contact clips the most opposed face of the other box to
+// the sides of the reference face.
+fn boxes(
+    pose_a: &glm::Mat4,
+    ha: &glm::Vec3,
+    pose_b: &glm::Mat4,
+    hb: &glm::Vec3,
+    margin: f32,
+) -> Vec<Contact> {
+    const PREFER_FACES: f32 = 1e-3;
+    let (ra, rb) = (glm::mat4_to_mat3(pose_a), glm::mat4_to_mat3(pose_b));
+    let (ta, tb) = (position(pose_a), position(pose_b));
+    let offset = tb - ta;
+    let axes_a: [glm::Vec3; 3] = [0, 1, 2].map(|i| ra.column(i).into_owned());
+    let axes_b: [glm::Vec3; 3] = [0, 1, 2].map(|i| rb.column(i).into_owned());
+    // how far apart the boxes are along a unit axis, and the axis turned from
+    // a to b
+    let separation = |axis: &glm::Vec3| {
+        let reach = |axes: &[glm::Vec3; 3], h: &glm::Vec3| {
+            (0..3).map(|i| h[i] * axis.dot(&axes[i]).abs()).sum::<f32>()
+        };
+        let along = axis.dot(&offset);
+        let normal = if along < 0.0 { -axis } else { *axis };
+        (
+            along.abs() - reach(&axes_a, ha) - reach(&axes_b, hb),
+            normal,
+        )
+    };
+
+    let mut face_a = (f32::MIN, 0, glm::Vec3::zeros());
+    let mut face_b = (f32::MIN, 0, glm::Vec3::zeros());
+    for i in 0..3 {
+        let (s, normal) = separation(&axes_a[i]);
+        if s > margin {
+            return Vec::new();
+        }
+        if s > face_a.0 {
+            face_a = (s, i, normal);
+        }
+        let (s, normal) = separation(&axes_b[i]);
+        if s > margin {
+            return Vec::new();
+        }
+        if s > face_b.0 {
+            face_b = (s, i, normal);
+        }
+    }
+    let mut edges = (f32::MIN, 0, 0, glm::Vec3::zeros());
+    for (i, a) in axes_a.iter().enumerate() {
+        for (j, b) in axes_b.iter().enumerate() {
+            let axis = a.cross(b);
+            let length = glm::length(&axis);
+            if length < 1e-5 {
+                continue;
+            }
+            let (s, normal) = separation(&(axis / length));
+            if s > margin {
+                return Vec::new();
+            }
+            if s > edges.0 {
+                edges = (s, i, j, normal);
+            }
+        }
+    }
+
+    let best_face = face_a.0.max(face_b.0);
+    if edges.0 > best_face + PREFER_FACES {
+        let (s, i, j, normal) = edges;
+        // the edge of each box furthest towards the other
+        let edge =
+            |t: &glm::Vec3, axes: &[glm::Vec3; 3], h: &glm::Vec3, k: usize, towards: glm::Vec3| {
+                let mut middle = *t;
+                for m in (0..3).filter(|&m| m != k) {
+                    let side = if axes[m].dot(&towards) < 0.0 {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+                    middle += axes[m] * (side * h[m]);
+                }
+                [middle - axes[k] * h[k], middle + axes[k] * h[k]]
+            };
+        let ea = edge(&ta, &axes_a, ha, i, normal);
+        let eb = edge(&tb, &axes_b, hb, j, -normal);
+        let (on_a, on_b) = closest_on_segments(&ea[0], &ea[1], &eb[0], &eb[1]);
+        return vec![Contact::between(&on_a, &on_b, normal, -s)];
+    }
+    if face_b.0 > face_a.0 + PREFER_FACES {
+        let (_, i, normal) = face_b;
+        clip_faces((&tb, &axes_b, hb), (&ta, &axes_a, ha), i, -normal, margin)
+            .into_iter()
+            .map(Contact::flipped)
+            .collect()
+    } else {
+        let (_, i, normal) = face_a;
+        clip_faces((&ta, &axes_a, ha), (&tb, &axes_b, hb), i, normal, margin)
+    }
+}
+
+type PosedBox<'a> = (&'a glm::Vec3, &'a [glm::Vec3; 3], &'a glm::Vec3);
+
+// Contacts of the incident box against face `axis` of the reference box,
+// normals from reference to incident
+fn clip_faces(
+    reference: PosedBox,
+    incident: PosedBox,
+    axis: usize,
+    normal: glm::Vec3,
+    margin: f32,
+) -> Vec<Contact> {
+    let (tr, axes_r, hr) = reference;
+    let (ti, axes_i, hi) = incident;
+    let face_centre = tr + normal * hr[axis];
+    // the face of the incident box turned most against the normal
+    let m = (0..3)
+        .max_by(|&x, &y| {
+            normal
+                .dot(&axes_i[x])
+                .abs()
+                .total_cmp(&normal.dot(&axes_i[y]).abs())
+        })
+        .unwrap();
+    let side = if normal.dot(&axes_i[m]) > 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    let centre = ti + axes_i[m] * (side * hi[m]);
+    let (u, v) = ((m + 1) % 3, (m + 2) % 3);
+    let (du, dv) = (axes_i[u] * hi[u], axes_i[v] * hi[v]);
+    let mut polygon = vec![
+        centre + du + dv,
+        centre - du + dv,
+        centre - du - dv,
+        centre + du - dv,
+    ];
+    for k in (0..3).filter(|&k| k != axis) {
+        for sign in [1.0, -1.0] {
+            let side_normal = axes_r[k] * sign;
+            let limit = side_normal.dot(tr) + hr[k];
+            polygon = clip(&polygon, &side_normal, limit);
+        }
+    }
+    polygon
+        .into_iter()
+        .filter_map(|p| {
+            let gap = normal.dot(&(p - face_centre));
+            (gap <= margin).then(|| Contact::between(&(p - normal * gap), &p, normal, -gap))
+        })
+        .collect()
+}
+
+// The part of a convex polygon with n.x <= limit (Sutherland-Hodgman)
+fn clip(polygon: &[glm::Vec3], normal: &glm::Vec3, limit: f32) -> Vec<glm::Vec3> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, p) in polygon.iter().enumerate() {
+        let q = &polygon[(i + 1) % polygon.len()];
+        let (dp, dq) = (normal.dot(p) - limit, normal.dot(q) - limit);
+        if dp <= 0.0 {
+            clipped.push(*p);
+        }
+        if (dp < 0.0 && dq > 0.0) || (dp > 0.0 && dq < 0.0) {
+            clipped.push(p + (q - p) * (dp / (dp - dq)));
+        }
+    }
+    clipped
+}
+
+// Down to MAX_CONTACTS: the deepest, the one furthest from it, and the two
+// that span the most area either side of the line between them
+fn reduce(contacts: Vec<Contact>) -> Vec<Contact> {
+    if contacts.len() <= MAX_CONTACTS {
+        return contacts;
+    }
+    let by = |key: &dyn Fn(&Contact) -> f32| {
+        (0..contacts.len())
+            .max_by(|&i, &j| key(&contacts[i]).total_cmp(&key(&contacts[j])))
+            .unwrap()
+    };
+    let first = by(&|c| c.depth);
+    let p0 = contacts[first].point;
+    let second = by(&|c| glm::distance2(&c.point, &p0));
+    let line = contacts[second].point - p0;
+    let normal = contacts[first].normal;
+    let area = |c: &Contact| normal.dot(&line.cross(&(c.point - p0)));
+    let third = by(&area);
+    let fourth = by(&|c| -area(c));
+    let mut kept = vec![first, second, third, fourth];
+    kept.sort_unstable();
+    kept.dedup();
+    kept.into_iter().map(|i| contacts[i]).collect()
+}
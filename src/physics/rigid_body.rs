@@ -0,0 +1,269 @@
+// A rigid body free in 3D space. The body frame sits at the centre of mass;
+// `com` says where that is in the frame the body was described in (a URDF
+// link frame), which `transform` gives back for drawing. Velocities and the
+// force and torque accumulators are in world coordinates, the inertia is kept
+// in the body frame and turned into the world as needed.
+//
+// A body with no mass is fixed: its inverse mass and inertia are zero, so
+// forces and impulses leave it where it is.
+use super::Integrable;
+use super::Integrator;
+use crate::urdf::InertialBody;
+
+// a body slower than these for SLEEP_TIME seconds goes to sleep
+pub const SLEEP_LINEAR_VELOCITY: f32 = 0.05;
+pub const SLEEP_ANGULAR_VELOCITY: f32 = 0.05;
+pub const SLEEP_TIME: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RigidBody {
+    pub position: glm::Vec3,
+    pub orientation: glm::Quat,
+    pub linear_velocity: glm::Vec3,
+    pub angular_velocity: glm::Vec3,
+    // whether the body may fall asleep when it comes to rest
+    pub can_sleep: bool,
+    mass: f32,
+    inverse_mass: f32,
+    com: glm::Vec3,
+    inertia: glm::Mat3,
+    inverse_inertia: glm::Mat3,
+    force: glm::Vec3,
+    torque: glm::Vec3,
+    sleeping: bool,
+    resting: f32,
+}
+
+impl RigidBody {
+    // `inertia` about the centre of mass, in the body frame; a mass of zero
+    // or less makes the body fixed
+    pub fn new(mass: f32, inertia: glm::Mat3) -> Self {
+        let fixed = mass <= 0.0;
+        Self {
+            position: glm::Vec3::zeros(),
+            orientation: glm::Quat::identity(),
+            linear_velocity: glm::Vec3::zeros(),
+            angular_velocity: glm::Vec3::zeros(),
+            can_sleep: true,
+            mass: if fixed { 0.0 } else { mass },
+            inverse_mass: if fixed { 0.0 } else { 1.0 / mass },
+            com: glm::Vec3::zeros(),
+            inertia: if fixed { glm::Mat3::zeros() } else { inertia },
+            inverse_inertia: if fixed {
+                glm::Mat3::zeros()
+            } else {
+                glm::inverse(&inertia)
+            },
+            force: glm::Vec3::zeros(),
+            torque: glm::Vec3::zeros(),
+            sleeping: false,
+            resting: 0.0,
+        }
+    }
+    pub fn fixed() -> Self {
+        Self::new(0.0, glm::Mat3::zeros())
+    }
+    // The mass properties of a link, placed where `pose` puts the link frame.
+    // The body frame keeps the link's axes, the inertia frame of the link
+    // only turns the tensor.
+    pub fn from_inertial(inertial: &InertialBody, pose: &glm::Mat4) -> Self {
+        let axes = glm::mat4_to_mat3(&inertial.origin.matrix());
+        let inertia = axes * inertial.tensor() * axes.transpose();
+        let mut body = Self::new(inertial.mass, inertia);
+        body.com = inertial.origin.xyz();
+        body.set_transform(pose);
+        body
+    }
+    // builder form of the pose fields
+    pub fn with_position(mut self, position: glm::Vec3) -> Self {
+        self.position = position;
+        self
+    }
+    pub fn with_orientation(mut self, orientation: glm::Quat) -> Self {
+        self.orientation = glm::quat_normalize(&orientation);
+        self
+    }
+    pub fn with_velocity(mut self, linear: glm::Vec3, angular: glm::Vec3) -> Self {
+        self.linear_velocity = linear;
+        self.angular_velocity = angular;
+        self
+    }
+
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+    pub fn inverse_mass(&self) -> f32 {
+        self.inverse_mass
+    }
+    pub fn is_fixed(&self) -> bool {
+        self.inverse_mass == 0.0
+    }
+    // the centre of mass in the frame the body was described in
+    pub fn com(&self) -> glm::Vec3 {
+        self.com
+    }
+    pub fn rotation(&self) -> glm::Mat3 {
+        glm::quat_to_mat3(&self.orientation)
+    }
+    // about the centre of mass, in the body frame
+    pub fn inertia(&self) -> &glm::Mat3 {
+        &self.inertia
+    }
+    pub fn inertia_world(&self) -> glm::Mat3 {
+        let r = self.rotation();
+        r * self.inertia * r.transpose()
+    }
+    pub fn inverse_inertia_world(&self) -> glm::Mat3 {
+        let r = self.rotation();
+        r * self.inverse_inertia * r.transpose()
+    }
+
+    // pose of the frame the body was described in, e.g. to draw its link
+    pub fn transform(&self) -> glm::Mat4 {
+        let r = self.rotation();
+        let mut m = glm::mat3_to_mat4(&r);
+        m.set_column(3, &(self.position - r * self.com).push(1.0));
+        m
+    }
+    pub fn set_transform(&mut self, pose: &glm::Mat4) {
+        let r = glm::mat4_to_mat3(pose);
+        self.orientation = glm::quat_normalize(&glm::mat3_to_quat(&r));
+        self.position = pose.column(3).xyz() + r * self.com;
+    }
+    // velocity of the body at the world `point`
+    pub fn velocity_at(&self, point: &glm::Vec3) -> glm::Vec3 {
+        self.linear_velocity + self.angular_velocity.cross(&(point - self.position))
+    }
+    pub fn momentum(&self) -> glm::Vec3 {
+        self.linear_velocity * self.mass
+    }
+    // about the centre of mass
+    pub fn angular_momentum(&self) -> glm::Vec3 {
+        self.inertia_world() * self.angular_velocity
+    }
+    pub fn kinetic_energy(&self) -> f32 {
+        0.5 * (self.mass * glm::length2(&self.linear_velocity)
+            + self.angular_velocity.dot(&self.angular_momentum()))
+    }
+
+    // Forces and torques add up until clear_forces, normally at the end of
+    // each step. Anything but a zero force wakes the body, so steady forces
+    // like gravity only go to bodies that are awake.
+    pub fn force(&self) -> glm::Vec3 {
+        self.force
+    }
+    pub fn torque(&self) -> glm::Vec3 {
+        self.torque
+    }
+    pub fn apply_force(&mut self, force: &glm::Vec3) {
+        self.force += force;
+        self.wake_for(force);
+    }
+    pub fn apply_torque(&mut self, torque: &glm::Vec3) {
+        self.torque += torque;
+        self.wake_for(torque);
+    }
+    // a force acting at the world `point`, also turning the body
+    pub fn apply_force_at_point(&mut self, force: &glm::Vec3, point: &glm::Vec3) {
+        self.apply_force(force);
+        self.apply_torque(&(point - self.position).cross(force));
+    }
+    pub fn clear_forces(&mut self) {
+        self.force = glm::Vec3::zeros();
+        self.torque = glm::Vec3::zeros();
+    }
+    // an instant change of momentum at the world `point`
+    pub fn apply_impulse(&mut self, impulse: &glm::Vec3, point: &glm::Vec3) {
+        self.linear_velocity += impulse * self.inverse_mass;
+        self.angular_velocity +=
+            self.inverse_inertia_world() * (point - self.position).cross(impulse);
+        self.wake_for(impulse);
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+    pub fn wake(&mut self) {
+        self.sleeping = false;
+        self.resting = 0.0;
+    }
+    // stops the body where it is until something wakes it
+    pub fn sleep(&mut self) {
+        self.sleeping = true;
+        self.linear_velocity = glm::Vec3::zeros();
+        self.angular_velocity = glm::Vec3::zeros();
+    }
+    fn wake_for(&mut self, push: &glm::Vec3) {
+        if *push != glm::Vec3::zeros() && !self.is_fixed() {
+            self.wake();
+        }
+    }
+    // puts the body to sleep once it has been slow for SLEEP_TIME
+    pub fn update_sleep(&mut self, dt: f32) {
+        if !self.can_sleep || self.sleeping {
+            return;
+        }
+        let slow = glm::length(&self.linear_velocity) < SLEEP_LINEAR_VELOCITY
+            && glm::length(&self.angular_velocity) < SLEEP_ANGULAR_VELOCITY;
+        self.resting = if slow { self.resting + dt } else { 0.0 };
+        if self.resting >= SLEEP_TIME {
+            self.sleep();
+        }
+    }
+
+    // Advances the body by `dt` under its accumulated force and torque, which
+    // are then cleared. Fixed and sleeping bodies stay put.
+    pub fn step(&mut self, integrator: &dyn Integrator, dt: f32) {
+        if !self.is_fixed() && !self.sleeping {
+            integrator.step(self, dt);
+            self.update_sleep(dt);
+        }
+        self.clear_forces();
+    }
+}
+
+impl Default for RigidBody {
+    // a solid ball of unit mass and radius
+    fn default() -> Self {
+        Self::new(1.0, glm::Mat3::identity() * 0.4)
+    }
+}
+
+// Positions are the centre of mass then the orientation quaternion i j k w,
+// velocities the linear then the angular velocity, both in world coordinates
+impl Integrable for RigidBody {
+    fn positions(&self) -> Vec<f32> {
+        let mut q = self.position.as_slice().to_vec();
+        q.extend_from_slice(self.orientation.coords.as_slice());
+        q
+    }
+    fn velocities(&self) -> Vec<f32> {
+        let mut v = self.linear_velocity.as_slice().to_vec();
+        v.extend_from_slice(self.angular_velocity.as_slice());
+        v
+    }
+    fn set_state(&mut self, positions: &[f32], velocities: &[f32]) {
+        self.position = glm::vec3(positions[0], positions[1], positions[2]);
+        self.orientation = glm::quat(positions[3], positions[4], positions[5], positions[6]);
+        self.linear_velocity = glm::vec3(velocities[0], velocities[1], velocities[2]);
+        self.angular_velocity = glm::vec3(velocities[3], velocities[4], velocities[5]);
+    }
+    // Euler's equations in the world frame, including the gyroscopic torque
+    fn accelerations(&self) -> Vec<f32> {
+        let a = self.force * self.inverse_mass;
+        let gyroscopic = self.angular_velocity.cross(&self.angular_momentum());
+        let alpha = self.inverse_inertia_world() * (self.torque - gyroscopic);
+        vec![a.x, a.y, a.z, alpha.x, alpha.y, alpha.z]
+    }
+    fn displace(&self, positions: &[f32], velocities: &[f32], dt: f32) -> Vec<f32> {
+        let omega = glm::vec3(velocities[3], velocities[4], velocities[5]);
+        let rotation = glm::quat(positions[3], positions[4], positions[5], positions[6]);
+        let turned = match glm::length(&omega) * dt {
+            angle if angle > 0.0 => glm::quat_angle_axis(angle, &glm::normalize(&omega)) * rotation,
+            _ => rotation,
+        };
+        let mut q: Vec<f32> = (0..3).map(|i| positions[i] + velocities[i] * dt).collect();
+        q.extend_from_slice(glm::quat_normalize(&turned).coords.as_slice());
+        q
+    }
+}
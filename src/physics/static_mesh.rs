@@ -0,0 +1,390 @@
+// Triangle meshes fixed in the world, for environment geometry loaded from
+// STL or OBJ: floors, terrain, tabletop scans. Unlike meshes on moving bodies,
+// which collide as their hull, these collide triangle by triangle, found
+// through a bounding volume hierarchy built once, top down, splitting each
+// node where the surface area heuristic over binned centroids says is
+// cheapest.
+//
+// Vertices are welded by position so that every edge knows the triangle
+// across it. Where the surface is flat or folds inwards an edge is internal
+// and contacts on it take the face normal, so shapes sliding over the mesh
+// do not catch on the seams between triangles.
+use crate::geometry::{Aabb, Polyhedron, TriMesh};
+use std::collections::HashMap;
+
+// triangles a node may keep without splitting
+const LEAF_SIZE: usize = 4;
+const BINS: usize = 12;
+// how far, as the sine of the angle, the triangle across an edge has to fold
+// away for the edge to be convex
+const CONVEX_FOLD: f32 = 1e-3;
+
+// What lies across an edge of a triangle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeshEdge {
+    // the rim of the mesh, or an edge of more than two triangles
+    Boundary,
+    Convex { neighbour: usize },
+    // flat or folding inwards
+    Internal { neighbour: usize },
+}
+
+// Triangles fixed in their frame, as the narrow phase sees them
+pub(crate) trait TriangleSurface {
+    fn triangle(&self, index: usize) -> [glm::Vec3; 3];
+    fn normal(&self, index: usize) -> glm::Vec3;
+    fn edges(&self, index: usize) -> [MeshEdge; 3];
+    fn is_internal_corner(&self, index: usize, corner: usize) -> bool;
+    // every triangle whose bounds may overlap `aabb`, once each
+    fn triangles_in(&self, aabb: &Aabb, found: &mut dyn FnMut(usize));
+}
+
+// Convex where `across`, the corner of the neighbour off the edge from
+// `from`, is below the plane of the triangle
+pub(crate) fn fold(
+    normal: &glm::Vec3,
+    from: &glm::Vec3,
+    across: &glm::Vec3,
+    neighbour: usize,
+) -> MeshEdge {
+    let out = across - from;
+    if normal.dot(&out) < -CONVEX_FOLD * glm::length(&out) {
+        MeshEdge::Convex { neighbour }
+    } else {
+        MeshEdge::Internal { neighbour }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Node {
+    aabb: Aabb,
+    // None for leaves, which hold `order[first..first + count]`
+    children: Option<[usize; 2]>,
+    first: usize,
+    count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticMeshCollider {
+    vertices: Vec<glm::Vec3>,
+    triangles: Vec<[usize; 3]>,
+    normals: Vec<glm::Vec3>,
+    // edge k of a triangle runs from its vertex k to the next
+    edges: Vec<[MeshEdge; 3]>,
+    // of each vertex, whether all edges to it are internal
+    internal: Vec<bool>,
+    nodes: Vec<Node>,
+    // triangles by leaf
+    order: Vec<usize>,
+}
+
+impl StaticMeshCollider {
+    // Triangles by index into `vertices`, wound counter-clockwise seen from
+    // the side shapes collide with; triangles with no area are left out
+    pub fn new(vertices: &[glm::Vec3], triangles: &[[usize; 3]]) -> Self {
+        let mut welded: HashMap<[u32; 3], usize> = HashMap::new();
+        let mut unique = Vec::new();
+        let index: Vec<usize> = vertices
+            .iter()
+            .map(|v| {
+                *welded
+                    .entry([v.x, v.y, v.z].map(f32::to_bits))
+                    .or_insert_with(|| {
+                        unique.push(*v);
+                        unique.len() - 1
+                    })
+            })
+            .collect();
+        let mut kept = Vec::new();
+        let mut normals = Vec::new();
+        for triangle in triangles {
+            let t = triangle.map(|i| index[i]);
+            let [a, b, c] = t.map(|i| unique[i]);
+            let normal = (b - a).cross(&(c - a));
+            let length = glm::length(&normal);
+            if length > 1e-12 {
+                kept.push(t);
+                normals.push(normal / length);
+            }
+        }
+        let mut mesh = Self {
+            vertices: unique,
+            triangles: kept,
+            normals,
+            edges: Vec::new(),
+            internal: Vec::new(),
+            nodes: Vec::new(),
+            order: Vec::new(),
+        };
+        mesh.edges = mesh.find_edges();
+        mesh.internal = vec![true; mesh.vertices.len()];
+        for (triangle, edges) in std::iter::zip(&mesh.triangles, &mesh.edges) {
+            for k in 0..3 {
+                if !matches!(edges[k], MeshEdge::Internal { .. }) {
+                    mesh.internal[triangle[k]] = false;
+                    mesh.internal[triangle[(k + 1) % 3]] = false;
+                }
+            }
+        }
+        mesh.build();
+        mesh
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+    pub fn vertices(&self) -> &[glm::Vec3] {
+        &self.vertices
+    }
+    pub fn triangle(&self, index: usize) -> [glm::Vec3; 3] {
+        self.triangles[index].map(|i| self.vertices[i])
+    }
+    pub fn normal(&self, index: usize) -> glm::Vec3 {
+        self.normals[index]
+    }
+    pub fn edges(&self, index: usize) -> [MeshEdge; 3] {
+        self.edges[index]
+    }
+    // whether the mesh is flat or folds inwards all round corner k of a
+    // triangle
+    pub fn is_internal_corner(&self, index: usize, corner: usize) -> bool {
+        self.internal[self.triangles[index][corner]]
+    }
+    pub fn aabb(&self) -> Aabb {
+        self.nodes
+            .first()
+            .map_or_else(Aabb::empty, |root| root.aabb)
+    }
+    // levels of the hierarchy, 0 for an empty mesh
+    pub fn depth(&self) -> usize {
+        fn depth(nodes: &[Node], index: usize) -> usize {
+            match nodes[index].children {
+                Some([left, right]) => 1 + depth(nodes, left).max(depth(nodes, right)),
+                None => 1,
+            }
+        }
+        if self.nodes.is_empty() {
+            0
+        } else {
+            depth(&self.nodes, 0)
+        }
+    }
+    // Calls `found` with every triangle whose bounds overlap `aabb`
+    pub fn query(&self, aabb: &Aabb, mut found: impl FnMut(usize)) {
+        let mut stack: Vec<usize> = if self.nodes.is_empty() {
+            Vec::new()
+        } else {
+            vec![0]
+        };
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !node.aabb.overlaps(aabb) {
+                continue;
+            }
+            match node.children {
+                Some(children) => stack.extend(children),
+                None => {
+                    for &t in &self.order[node.first..node.first + node.count] {
+                        if self.bounds(t).overlaps(aabb) {
+                            found(t);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    // the triangles as a mesh to draw
+    pub fn polyhedron(&self) -> Polyhedron {
+        let mut mesh = TriMesh::default();
+        for index in 0..self.len() {
+            mesh.add_triangle(self.triangle(index));
+        }
+        Polyhedron::from(mesh)
+    }
+
+    fn bounds(&self, index: usize) -> Aabb {
+        Aabb::from_points(&self.triangle(index))
+    }
+
+    fn find_edges(&self) -> Vec<[MeshEdge; 3]> {
+        let mut sharing: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (t, triangle) in self.triangles.iter().enumerate() {
+            for k in 0..3 {
+                let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                sharing.entry((a.min(b), a.max(b))).or_default().push(t);
+            }
+        }
+        (0..self.len())
+            .map(|t| {
+                let triangle = self.triangles[t];
+                [0, 1, 2].map(|k| {
+                    let (a, b) = (triangle[k], triangle[(k + 1) % 3]);
+                    let others = &sharing[&(a.min(b), a.max(b))];
+                    let [x, y] = others[..] else {
+                        return MeshEdge::Boundary;
+                    };
+                    let neighbour = if x == t { y } else { x };
+                    let across = self.triangles[neighbour]
+                        .into_iter()
+                        .find(|&v| v != a && v != b)
+                        .expect("triangles have three corners");
+                    fold(
+                        &self.normals[t],
+                        &self.vertices[a],
+                        &self.vertices[across],
+                        neighbour,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn build(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+        let bounds: Vec<Aabb> = (0..self.len()).map(|t| self.bounds(t)).collect();
+        let centroids: Vec<glm::Vec3> = bounds.iter().map(Aabb::center).collect();
+        self.order = (0..self.len()).collect();
+        self.split(&bounds, &centroids, 0, self.len());
+    }
+    // the node over `order[first..end]`, and those under it
+    fn split(
+        &mut self,
+        bounds: &[Aabb],
+        centroids: &[glm::Vec3],
+        first: usize,
+        end: usize,
+    ) -> usize {
+        let triangles = &self.order[first..end];
+        let aabb = triangles
+            .iter()
+            .fold(Aabb::empty(), |b, &t| b.union(&bounds[t]));
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            aabb,
+            children: None,
+            first,
+            count: end - first,
+        });
+        if end - first <= LEAF_SIZE {
+            return index;
+        }
+        let spread = Aabb::from_points(triangles.iter().map(|&t| &centroids[t]));
+        let Some((axis, at)) = best_split(triangles, bounds, centroids, &spread) else {
+            // all centred on one point
+            return index;
+        };
+        let bin = binning(spread.min[axis], spread.max[axis] - spread.min[axis]);
+        let (mut left, mut right): (Vec<usize>, Vec<usize>) = triangles
+            .iter()
+            .partition(|&&t| bin(centroids[t][axis]) <= at);
+        let middle = first + left.len();
+        left.append(&mut right);
+        self.order[first..end].copy_from_slice(&left);
+        let children = [
+            self.split(bounds, centroids, first, middle),
+            self.split(bounds, centroids, middle, end),
+        ];
+        self.nodes[index].children = Some(children);
+        index
+    }
+}
+
+// The axis and the last bin on the left of the split with the least surface
+// area times triangles either side; None if the centroids do not spread
+// along any axis
+fn best_split(
+    triangles: &[usize],
+    bounds: &[Aabb],
+    centroids: &[glm::Vec3],
+    spread: &Aabb,
+) -> Option<(usize, usize)> {
+    let mut best: Option<(f32, usize, usize)> = None;
+    for axis in 0..3 {
+        let (low, extent) = (spread.min[axis], spread.max[axis] - spread.min[axis]);
+        if extent <= 1e-12 {
+            continue;
+        }
+        let bin = binning(low, extent);
+        let mut bins = [(Aabb::empty(), 0usize); BINS];
+        for &t in triangles {
+            let b = &mut bins[bin(centroids[t][axis])];
+            b.0 = b.0.union(&bounds[t]);
+            b.1 += 1;
+        }
+        // areas and counts of everything left of each split, then right
+        let mut left = [(0.0, 0usize); BINS - 1];
+        let (mut aabb, mut count) = (Aabb::empty(), 0);
+        for (k, side) in left.iter_mut().enumerate() {
+            aabb = aabb.union(&bins[k].0);
+            count += bins[k].1;
+            *side = (aabb.surface_area(), count);
+        }
+        let (mut aabb, mut count) = (Aabb::empty(), 0);
+        for k in (0..BINS - 1).rev() {
+            aabb = aabb.union(&bins[k + 1].0);
+            count += bins[k + 1].1;
+            let (area, left_count) = left[k];
+            if left_count == 0 || count == 0 {
+                continue;
+            }
+            let cost = area * left_count as f32 + aabb.surface_area() * count as f32;
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, k));
+            }
+        }
+    }
+    best.map(|(_, axis, at)| (axis, at))
+}
+
+impl TriangleSurface for StaticMeshCollider {
+    fn triangle(&self, index: usize) -> [glm::Vec3; 3] {
+        StaticMeshCollider::triangle(self, index)
+    }
+    fn normal(&self, index: usize) -> glm::Vec3 {
+        self.normals[index]
+    }
+    fn edges(&self, index: usize) -> [MeshEdge; 3] {
+        self.edges[index]
+    }
+    fn is_internal_corner(&self, index: usize, corner: usize) -> bool {
+        StaticMeshCollider::is_internal_corner(self, index, corner)
+    }
+    fn triangles_in(&self, aabb: &Aabb, found: &mut dyn FnMut(usize)) {
+        self.query(aabb, found)
+    }
+}
+
+fn binning(low: f32, extent: f32) -> impl Fn(f32) -> usize {
+    move |x| (((x - low) / extent * BINS as f32) as usize).min(BINS - 1)
+}
+
+impl From<&TriMesh> for StaticMeshCollider {
+    fn from(mesh: &TriMesh) -> Self {
+        let vertices: Vec<glm::Vec3> = mesh
+            .faces()
+            .iter()
+            .flat_map(|f| f.vertices.map(|v| v.position))
+            .collect();
+        let triangles: Vec<[usize; 3]> = (0..mesh.faces().len())
+            .map(|f| [3 * f, 3 * f + 1, 3 * f + 2])
+            .collect();
+        Self::new(&vertices, &triangles)
+    }
+}
+
+impl From<&Polyhedron> for StaticMeshCollider {
+    fn from(polyhedron: &Polyhedron) -> Self {
+        let vertices: Vec<glm::Vec3> = polyhedron.verts.iter().map(|v| v.position).collect();
+        let triangles: Vec<[usize; 3]> = polyhedron
+            .indices
+            .chunks_exact(3)
+            .map(|t| [t[0] as usize, t[1] as usize, t[2] as usize])
+            .collect();
+        Self::new(&vertices, &triangles)
+    }
+}
@@ -0,0 +1,931 @@
+// The simulation loop. A PhysicsWorld owns free rigid bodies, robots and the
+// colliders attached to bodies or fixed in the world, and advances them all at
+// a fixed timestep: `step` takes the frame time, and for every substep that
+// fits runs the phases of PhysicsProgram in the order they are declared,
+// apply_forces, update_kinematics, detect_collisions and solve_constraints.
+// Hooks registered for a phase run right after its built-in work.
+//
+// Detecting collisions starts with the broad phase over the world bounds of
+// every collider, which leaves the candidate pairs that pass the filters; the
+// narrow phase then finds the contacts of each pair. Solving constraints
+// turns those contacts, and the joints between bodies, into impulses on the
+// bodies and robot links involved.
+use super::body_joint::{joint_frame, BodyJoint, BodyJointKind, JointSide};
+use super::broad_phase::{Bounds, BroadPhase};
+use super::contact_solver::{ContactPoint, ContactSolver, JointRowSides, Material, Velocities};
+use super::narrow_phase::{collide, CollisionShape, ContactManifold, CONTACT_MARGIN};
+use super::{
+    FixedTimestep, Integrable, Integrator, PhysicsProgram, RigidBody, SemiImplicitEuler,
+    StaticMeshCollider, SLEEP_ANGULAR_VELOCITY, SLEEP_LINEAR_VELOCITY,
+};
+use crate::dynamics::RobotDynamics;
+use crate::geometry::{Aabb, ConvexDecomposition, DecompositionParams, Polyhedron};
+use crate::kinematics::{jacobian_with_poses, JointState, LinkPoses};
+use crate::terrain::HeightField;
+use crate::urdf::{GeometryShape, RobotDescriptor, UrdfError};
+use crate::world::{World, WorldShape, DEFAULT_GRAVITY};
+use nalgebra::{DMatrix, DVector};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BodyId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RobotId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ColliderId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BodyJointId(usize);
+
+macro_rules! impl_index {
+    ($($id:ident),*) => {$(
+        impl $id {
+            pub fn index(self) -> usize {
+                self.0
+            }
+        }
+    )*};
+}
+impl_index!(BodyId, RobotId, ColliderId, BodyJointId);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Phase {
+    ApplyForces,
+    UpdateKinematics,
+    DetectCollisions,
+    SolveConstraints,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Phase::ApplyForces,
+        Phase::UpdateKinematics,
+        Phase::DetectCollisions,
+        Phase::SolveConstraints,
+    ];
+}
+
+// What a collider moves with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Attachment {
+    Fixed,
+    Body(BodyId),
+    // a link of a robot, by index into its links
+    Link { robot: RobotId, link: usize },
+}
+
+// A shape attached to a body or a robot link, or fixed in the world. `pose`
+// is relative to the body frame (its centre of mass), the link frame or the
+// world. `geometry` is the shape as a mesh, empty for planes and for meshes
+// not loaded yet. Meshes with a convex decomposition collide as its hulls.
+//
+// Two colliders are paired only if the group of each is in the mask of the
+// other.
+#[derive(Debug, Clone)]
+pub struct Collider {
+    pub attachment: Attachment,
+    pub shape: WorldShape,
+    pub pose: glm::Mat4,
+    pub geometry: Polyhedron,
+    pub decomposition: Option<Arc<ConvexDecomposition>>,
+    pub group: u32,
+    pub mask: u32,
+    pub material: Material,
+}
+
+impl Collider {
+    pub fn new(attachment: Attachment, shape: WorldShape, pose: glm::Mat4) -> Self {
+        let geometry = match &shape {
+            WorldShape::Solid(solid) => solid.primitive_polyhedron().unwrap_or_default(),
+            WorldShape::Plane { .. } => Polyhedron::default(),
+        };
+        Self {
+            attachment,
+            shape,
+            pose,
+            geometry,
+            decomposition: None,
+            group: 1,
+            mask: u32::MAX,
+            material: Material::default(),
+        }
+    }
+    pub fn fixed(shape: WorldShape, pose: glm::Mat4) -> Self {
+        Self::new(Attachment::Fixed, shape, pose)
+    }
+    pub fn on(body: BodyId, shape: WorldShape, pose: glm::Mat4) -> Self {
+        Self::new(Attachment::Body(body), shape, pose)
+    }
+    // for meshes, which are loaded elsewhere
+    pub fn with_geometry(mut self, geometry: Polyhedron) -> Self {
+        self.geometry = geometry;
+        self
+    }
+    pub fn with_decomposition(mut self, decomposition: Arc<ConvexDecomposition>) -> Self {
+        self.decomposition = Some(decomposition);
+        self
+    }
+    pub fn with_filter(mut self, group: u32, mask: u32) -> Self {
+        self.group = group;
+        self.mask = mask;
+        self
+    }
+    pub fn with_material(mut self, material: Material) -> Self {
+        self.material = material;
+        self
+    }
+    pub fn body(&self) -> Option<BodyId> {
+        match self.attachment {
+            Attachment::Body(body) => Some(body),
+            _ => None,
+        }
+    }
+    pub fn can_collide(&self, other: &Collider) -> bool {
+        self.group & other.mask != 0 && other.group & self.mask != 0
+    }
+}
+
+// A robot with its root link held at `base`; static robots keep the joint
+// positions they are given. Links joined to each other never collide, other
+// links of the robot only with `self_collision`.
+#[derive(Debug, Clone)]
+pub struct SimulatedRobot {
+    pub robot: RobotDescriptor,
+    pub state: JointState,
+    pub base: glm::Mat4,
+    pub is_static: bool,
+    pub self_collision: bool,
+}
+
+pub type Hook = Box<dyn FnMut(&mut PhysicsWorld, f32)>;
+
+pub struct PhysicsWorld {
+    gravity: glm::Vec3,
+    bodies: Vec<RigidBody>,
+    robots: Vec<SimulatedRobot>,
+    colliders: Vec<Collider>,
+    // of each collider's geometry in its own frame
+    local_bounds: Vec<Aabb>,
+    // None for meshes not loaded, which collide with nothing
+    shapes: Vec<Option<CollisionShape>>,
+    joints: Vec<BodyJoint>,
+    broad_phase: BroadPhase,
+    pairs: Vec<(ColliderId, ColliderId)>,
+    contacts: Vec<ContactManifold>,
+    solver: ContactSolver,
+    timestep: FixedTimestep,
+    integrator: Box<dyn Integrator>,
+    hooks: Vec<(Phase, Hook)>,
+    time: f32,
+}
+
+impl Default for PhysicsWorld {
+    // DEFAULT_GRAVITY, 240 Hz and semi-implicit Euler
+    fn default() -> Self {
+        Self {
+            gravity: DEFAULT_GRAVITY,
+            bodies: Vec::new(),
+            robots: Vec::new(),
+            colliders: Vec::new(),
+            local_bounds: Vec::new(),
+            shapes: Vec::new(),
+            joints: Vec::new(),
+            broad_phase: BroadPhase::default(),
+            pairs: Vec::new(),
+            contacts: Vec::new(),
+            solver: ContactSolver::default(),
+            timestep: FixedTimestep::new(1.0 / 240.0),
+            integrator: Box::new(SemiImplicitEuler),
+            hooks: Vec::new(),
+            time: 0.0,
+        }
+    }
+}
+
+impl std::fmt::Debug for PhysicsWorld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PhysicsWorld")
+            .field("gravity", &self.gravity)
+            .field("bodies", &self.bodies.len())
+            .field("robots", &self.robots.len())
+            .field("colliders", &self.colliders.len())
+            .field("joints", &self.joints.len())
+            .field("pairs", &self.pairs.len())
+            .field("contacts", &self.contacts.len())
+            .field("timestep", &self.timestep)
+            .field("hooks", &self.hooks.len())
+            .field("time", &self.time)
+            .finish()
+    }
+}
+
+impl PhysicsWorld {
+    // The robots and environment of a scene: models become robots placed at
+    // their pose, static links fixed colliders
+    pub fn from_world(world: &World) -> Self {
+        let mut physics = Self::default().with_gravity(world.gravity);
+        for model in &world.models {
+            let id = physics.add_robot(model.robot.clone(), model.pose.tmatrix);
+            physics.robots[id.0].is_static = model.is_static;
+        }
+        for body in &world.environment {
+            for shape in &body.collisions {
+                physics.add_collider(
+                    Collider::fixed(shape.shape.clone(), body.pose.tmatrix * shape.pose.tmatrix)
+                        .with_geometry(shape.geometry.clone()),
+                );
+            }
+        }
+        physics
+    }
+    // Replaces the scene with an SDFormat world as from_world makes it; the
+    // timestep, integrator, broad phase settings and hooks stay as they were.
+    // A scene that does not load leaves the world as it was.
+    pub fn load_sdf(&mut self, scene: &str) -> Result<(), UrdfError> {
+        let world = Self::from_world(&World::from_sdf_str(scene)?);
+        self.gravity = world.gravity;
+        self.bodies = world.bodies;
+        self.robots = world.robots;
+        self.colliders = world.colliders;
+        self.local_bounds = world.local_bounds;
+        self.shapes = world.shapes;
+        self.joints = world.joints;
+        self.broad_phase = BroadPhase::new(self.broad_phase.margin());
+        self.pairs.clear();
+        self.contacts.clear();
+        self.solver.clear();
+        self.time = 0.0;
+        Ok(())
+    }
+    pub fn with_gravity(mut self, gravity: glm::Vec3) -> Self {
+        self.gravity = gravity;
+        self
+    }
+    pub fn with_timestep(mut self, timestep: FixedTimestep) -> Self {
+        self.timestep = timestep;
+        self
+    }
+    pub fn with_integrator(mut self, integrator: impl Integrator + 'static) -> Self {
+        self.integrator = Box::new(integrator);
+        self
+    }
+    pub fn with_broad_phase(mut self, broad_phase: BroadPhase) -> Self {
+        self.broad_phase = broad_phase;
+        self
+    }
+    pub fn with_contact_solver(mut self, solver: ContactSolver) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    pub fn gravity(&self) -> glm::Vec3 {
+        self.gravity
+    }
+    pub fn set_gravity(&mut self, gravity: glm::Vec3) {
+        self.gravity = gravity;
+    }
+    pub fn timestep(&self) -> &FixedTimestep {
+        &self.timestep
+    }
+    // simulated time so far
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn add_body(&mut self, body: RigidBody) -> BodyId {
+        self.bodies.push(body);
+        BodyId(self.bodies.len() - 1)
+    }
+    pub fn body(&self, id: BodyId) -> &RigidBody {
+        &self.bodies[id.0]
+    }
+    pub fn body_mut(&mut self, id: BodyId) -> &mut RigidBody {
+        &mut self.bodies[id.0]
+    }
+    pub fn bodies(&self) -> &[RigidBody] {
+        &self.bodies
+    }
+    pub fn bodies_mut(&mut self) -> &mut [RigidBody] {
+        &mut self.bodies
+    }
+
+    // The robot starts at its zero position, or its reference pose for
+    // floating joints. Links with collision geometry get a collider each.
+    pub fn add_robot(&mut self, robot: RobotDescriptor, base: glm::Mat4) -> RobotId {
+        let id = RobotId(self.robots.len());
+        for (link, l) in robot.links.iter().enumerate() {
+            let Some(shape) = &l.collision.shape else {
+                continue;
+            };
+            self.add_collider(
+                Collider::new(
+                    Attachment::Link { robot: id, link },
+                    WorldShape::Solid(shape.clone()),
+                    l.collision.origin.matrix(),
+                )
+                .with_geometry(l.collision.geometry.clone()),
+            );
+        }
+        let state = JointState::new(&robot);
+        self.robots.push(SimulatedRobot {
+            robot,
+            state,
+            base,
+            is_static: false,
+            self_collision: false,
+        });
+        id
+    }
+    pub fn robot(&self, id: RobotId) -> &SimulatedRobot {
+        &self.robots[id.0]
+    }
+    pub fn robot_mut(&mut self, id: RobotId) -> &mut SimulatedRobot {
+        &mut self.robots[id.0]
+    }
+    pub fn robots(&self) -> &[SimulatedRobot] {
+        &self.robots
+    }
+
+    pub fn add_collider(&mut self, collider: Collider) -> ColliderId {
+        let shape = CollisionShape::from_collider(&collider);
+        self.insert_collider(collider, shape)
+    }
+    // Fixed at `pose`, colliding triangle by triangle; fixed colliders with
+    // mesh geometry collide like this too
+    pub fn add_static_mesh(&mut self, mesh: StaticMeshCollider, pose: glm::Mat4) -> ColliderId {
+        let shape = WorldShape::Solid(GeometryShape::Mesh {
+            filename: String::new(),
+            scale: None,
+        });
+        let collider = Collider::fixed(shape, pose).with_geometry(mesh.polyhedron());
+        let shape = (!mesh.is_empty()).then(|| CollisionShape::TriangleMesh(Arc::new(mesh)));
+        self.insert_collider(collider, shape)
+    }
+    // Terrain fixed at `pose`, drawn and colliding as its grid of triangles
+    pub fn add_height_field(&mut self, field: HeightField, pose: glm::Mat4) -> ColliderId {
+        let shape = WorldShape::Solid(GeometryShape::Mesh {
+            filename: String::new(),
+            scale: None,
+        });
+        let collider = Collider::fixed(shape, pose).with_geometry(field.polyhedron());
+        let shape = Some(CollisionShape::HeightField(Arc::new(field)));
+        self.insert_collider(collider, shape)
+    }
+    fn insert_collider(&mut self, collider: Collider, shape: Option<CollisionShape>) -> ColliderId {
+        self.local_bounds.push(collider.geometry.aabb());
+        self.shapes.push(shape);
+        self.colliders.push(collider);
+        ColliderId(self.colliders.len() - 1)
+    }
+    // Gives the mesh colliders on bodies and robots a convex decomposition,
+    // to collide as its hulls rather than as one. With a cache directory,
+    // each is kept there under its fingerprint for later runs. The number
+    // of colliders decomposed.
+    pub fn decompose_meshes(
+        &mut self,
+        params: &DecompositionParams,
+        cache: Option<&Path>,
+    ) -> io::Result<usize> {
+        // meshes loaded once for several links are decomposed once
+        let mut made: HashMap<u64, Arc<ConvexDecomposition>> = HashMap::new();
+        let mut count = 0;
+        for i in 0..self.colliders.len() {
+            let collider = &self.colliders[i];
+            let is_mesh = matches!(
+                collider.shape,
+                WorldShape::Solid(GeometryShape::Mesh { .. })
+            );
+            if !is_mesh
+                || collider.attachment == Attachment::Fixed
+                || collider.decomposition.is_some()
+                || collider.geometry.verts.is_empty()
+            {
+                continue;
+            }
+            let fingerprint = ConvexDecomposition::fingerprint(&collider.geometry, params);
+            let decomposition = match made.get(&fingerprint) {
+                Some(decomposition) => decomposition.clone(),
+                None => {
+                    let decomposition = Arc::new(match cache {
+                        Some(dir) => ConvexDecomposition::cached(
+                            &collider.geometry,
+                            params,
+                            dir.join(format!("{:016x}.obj", fingerprint)),
+                        )?,
+                        None => collider.geometry.convex_decomposition(params),
+                    });
+                    made.insert(fingerprint, decomposition.clone());
+                    decomposition
+                }
+            };
+            self.colliders[i].decomposition = Some(decomposition);
+            self.shapes[i] = CollisionShape::from_collider(&self.colliders[i]);
+            count += 1;
+        }
+        Ok(count)
+    }
+    pub fn collider(&self, id: ColliderId) -> &Collider {
+        &self.colliders[id.0]
+    }
+    pub fn colliders(&self) -> &[Collider] {
+        &self.colliders
+    }
+    // world pose of a collider as its body or link is now
+    pub fn collider_pose(&self, id: ColliderId) -> glm::Mat4 {
+        let collider = &self.colliders[id.0];
+        match collider.attachment {
+            Attachment::Fixed => collider.pose,
+            Attachment::Body(body) => body_frame(&self.bodies[body.0]) * collider.pose,
+            Attachment::Link { robot, link } => {
+                let r = &self.robots[robot.0];
+                let poses = r.robot.forward_kinematics_with_base(&r.state, &r.base);
+                poses.link(link) * collider.pose
+            }
+        }
+    }
+    // world poses of all colliders, posing each robot once
+    pub fn collider_poses(&self) -> Vec<glm::Mat4> {
+        let links: Vec<_> = self
+            .robots
+            .iter()
+            .map(|r| r.robot.forward_kinematics_with_base(&r.state, &r.base))
+            .collect();
+        self.colliders
+            .iter()
+            .map(|collider| match collider.attachment {
+                Attachment::Fixed => collider.pose,
+                Attachment::Body(body) => body_frame(&self.bodies[body.0]) * collider.pose,
+                Attachment::Link { robot, link } => links[robot.0].link(link) * collider.pose,
+            })
+            .collect()
+    }
+    // The joint goes through `anchor` along `axis`, both in world
+    // coordinates, with the bodies where they are now
+    pub fn joint_at(
+        &self,
+        kind: BodyJointKind,
+        a: BodyId,
+        b: Option<BodyId>,
+        anchor: &glm::Vec3,
+        axis: &glm::Vec3,
+    ) -> BodyJoint {
+        let frame = joint_frame(anchor, axis);
+        let local = |body: Option<BodyId>| match body {
+            Some(body) => {
+                body_frame(&self.bodies[body.0])
+                    .try_inverse()
+                    .expect("body frames are rigid")
+                    * frame
+            }
+            None => frame,
+        };
+        BodyJoint::new(kind, a, local(Some(a)), b, local(b))
+    }
+    pub fn add_joint(&mut self, joint: BodyJoint) -> BodyJointId {
+        self.joints.push(joint);
+        BodyJointId(self.joints.len() - 1)
+    }
+    pub fn joint(&self, id: BodyJointId) -> &BodyJoint {
+        &self.joints[id.0]
+    }
+    // to change motors, springs and limits
+    pub fn joint_mut(&mut self, id: BodyJointId) -> &mut BodyJoint {
+        &mut self.joints[id.0]
+    }
+    pub fn joints(&self) -> &[BodyJoint] {
+        &self.joints
+    }
+    // world poses of the two frames of a joint
+    pub fn joint_poses(&self, id: BodyJointId) -> (glm::Mat4, glm::Mat4) {
+        let joint = &self.joints[id.0];
+        let frame = |body: Option<BodyId>, local: &glm::Mat4| match body {
+            Some(body) => body_frame(&self.bodies[body.0]) * local,
+            None => *local,
+        };
+        (
+            frame(Some(joint.a), &joint.frame_a),
+            frame(joint.b, &joint.frame_b),
+        )
+    }
+    // the angle of a hinge or the offset of a slider
+    pub fn joint_position(&self, id: BodyJointId) -> Option<f32> {
+        let (pose_a, pose_b) = self.joint_poses(id);
+        self.joints[id.0].position(&pose_a, &pose_b)
+    }
+
+    // candidate pairs from the last collision phase, lower id first
+    pub fn candidate_pairs(&self) -> &[(ColliderId, ColliderId)] {
+        &self.pairs
+    }
+    // pairs in contact after the last collision phase
+    pub fn contacts(&self) -> &[ContactManifold] {
+        &self.contacts
+    }
+    // with the impulses of the last step
+    pub fn contact_solver(&self) -> &ContactSolver {
+        &self.solver
+    }
+
+    // `hook` runs with the substep length after the built-in work of `phase`,
+    // in the order hooks were added
+    pub fn add_hook(&mut self, phase: Phase, hook: impl FnMut(&mut PhysicsWorld, f32) + 'static) {
+        self.hooks.push((phase, Box::new(hook)));
+    }
+
+    // Adds `elapsed` seconds of frame time and runs every substep that fits,
+    // returning the number of whole steps taken
+    pub fn step(&mut self, elapsed: f32) -> usize {
+        let mut timestep = self.timestep.clone();
+        let steps = timestep.advance(elapsed, |dt| self.substep(dt));
+        self.timestep = timestep;
+        steps
+    }
+    // all phases once over `dt`
+    pub fn substep(&mut self, dt: f32) {
+        for phase in Phase::ALL {
+            self.run_phase(phase, dt);
+        }
+        self.time += dt;
+    }
+    pub fn run_phase(&mut self, phase: Phase, dt: f32) {
+        match phase {
+            Phase::ApplyForces => self.apply_gravity(),
+            Phase::UpdateKinematics => self.integrate(dt),
+            Phase::DetectCollisions => {
+                let poses = self.collider_poses();
+                self.find_pairs(&poses);
+                self.find_contacts(&poses);
+            }
+            Phase::SolveConstraints => self.solve_velocities(dt),
+        }
+        // hooks added while running wait for the next time round
+        let mut hooks = std::mem::take(&mut self.hooks);
+        for (_, hook) in hooks.iter_mut().filter(|(p, _)| *p == phase) {
+            hook(self, dt);
+        }
+        hooks.append(&mut self.hooks);
+        self.hooks = hooks;
+    }
+
+    fn apply_gravity(&mut self) {
+        for body in &mut self.bodies {
+            if !body.is_fixed() && !body.is_sleeping() {
+                let weight = self.gravity * body.mass();
+                body.apply_force(&weight);
+            }
+        }
+    }
+    fn find_pairs(&mut self, poses: &[glm::Mat4]) {
+        let bounds: Vec<Bounds> = std::iter::zip(&self.colliders, poses)
+            .zip(&self.local_bounds)
+            .map(|((collider, pose), local)| match &collider.shape {
+                WorldShape::Plane { normal, .. } => {
+                    let normal = glm::normalize(&(glm::mat4_to_mat3(pose) * normal));
+                    let offset = normal.dot(&pose.column(3).xyz());
+                    Bounds::HalfSpace { normal, offset }
+                }
+                _ if local.is_empty() => Bounds::Nothing,
+                _ => Bounds::Box(local.transformed(pose)),
+            })
+            .collect();
+        self.broad_phase.update(&bounds);
+        // links joined to each other, per robot
+        let adjacent: HashSet<(usize, usize, usize)> = self
+            .robots
+            .iter()
+            .enumerate()
+            .flat_map(|(r, robot)| {
+                robot
+                    .robot
+                    .joints
+                    .iter()
+                    .flat_map(move |j| [(r, j.parent(), j.child()), (r, j.child(), j.parent())])
+            })
+            .collect();
+        // bodies joined to each other
+        let joined: HashSet<(BodyId, BodyId)> = self
+            .joints
+            .iter()
+            .filter(|j| !j.collide_connected)
+            .filter_map(|j| Some((j.a, j.b?)))
+            .flat_map(|(a, b)| [(a, b), (b, a)])
+            .collect();
+        let pairs = self.broad_phase.pairs(|a, b| {
+            let (a, b) = (&self.colliders[a], &self.colliders[b]);
+            a.can_collide(b) && self.can_move(a, b) && self.apart(a, b, &adjacent, &joined)
+        });
+        self.pairs = pairs
+            .into_iter()
+            .map(|(a, b)| (ColliderId(a), ColliderId(b)))
+            .collect();
+    }
+    fn find_contacts(&mut self, poses: &[glm::Mat4]) {
+        self.contacts = self
+            .pairs
+            .iter()
+            .filter_map(|&(a, b)| {
+                let shape_a = self.shapes[a.0].as_ref()?;
+                let shape_b = self.shapes[b.0].as_ref()?;
+                let contacts = collide(shape_a, &poses[a.0], shape_b, &poses[b.0], CONTACT_MARGIN);
+                (!contacts.is_empty()).then_some(ContactManifold { a, b, contacts })
+            })
+            .collect();
+    }
+    // whether either collider can move: attached to an awake body or a
+    // robot that is not static
+    fn can_move(&self, a: &Collider, b: &Collider) -> bool {
+        let moving = |c: &Collider| match c.attachment {
+            Attachment::Fixed => false,
+            Attachment::Body(body) => {
+                let body = &self.bodies[body.0];
+                !body.is_fixed() && !body.is_sleeping()
+            }
+            Attachment::Link { robot, .. } => !self.robots[robot.0].is_static,
+        };
+        moving(a) || moving(b)
+    }
+    // not parts of one body or bodies joined to each other, and for robot
+    // links not filtered as self-collision
+    fn apart(
+        &self,
+        a: &Collider,
+        b: &Collider,
+        adjacent: &HashSet<(usize, usize, usize)>,
+        joined: &HashSet<(BodyId, BodyId)>,
+    ) -> bool {
+        match (a.attachment, b.attachment) {
+            (Attachment::Body(x), Attachment::Body(y)) => x != y && !joined.contains(&(x, y)),
+            (Attachment::Link { robot: r, link: x }, Attachment::Link { robot: s, link: y })
+                if r == s =>
+            {
+                x != y && self.robots[r.0].self_collision && !adjacent.contains(&(r.0, x, y))
+            }
+            _ => true,
+        }
+    }
+
+    // The velocities of the free bodies and robots in contact or joined,
+    // changed by the contact and joint impulses. Fixed bodies, static robots
+    // and fixed colliders do not move; sleeping bodies wake only if pushed
+    // hard enough to.
+    fn solve_velocities(&mut self, dt: f32) {
+        let mut velocities: Vec<Velocities> = Vec::new();
+        let mut body_slots: Vec<Option<usize>> = vec![None; self.bodies.len()];
+        let mut robot_slots: Vec<Option<RobotSlot>> = vec![None; self.robots.len()];
+        let mut points = Vec::new();
+        for manifold in &self.contacts {
+            let (a, b) = (&self.colliders[manifold.a.0], &self.colliders[manifold.b.0]);
+            let material = a.material.combine(&b.material);
+            for contact in &manifold.contacts {
+                let sides = [a, b].map(|collider| {
+                    self.contact_side(
+                        collider,
+                        &contact.point,
+                        &mut velocities,
+                        &mut body_slots,
+                        &mut robot_slots,
+                    )
+                });
+                points.push(ContactPoint {
+                    a: manifold.a,
+                    b: manifold.b,
+                    point: contact.point,
+                    normal: contact.normal,
+                    depth: contact.depth,
+                    material,
+                    sides,
+                });
+            }
+        }
+        let mut rows = Vec::new();
+        for (index, joint) in self.joints.iter().enumerate() {
+            let (pose_a, pose_b) = self.joint_poses(BodyJointId(index));
+            let side = |body: Option<BodyId>, pose: glm::Mat4| match body {
+                Some(body) => {
+                    let body = &self.bodies[body.0];
+                    let (v, w) = (body.linear_velocity, body.angular_velocity);
+                    JointSide {
+                        pose,
+                        com: body.position,
+                        velocity: [v.x, v.y, v.z, w.x, w.y, w.z],
+                    }
+                }
+                None => JointSide {
+                    pose,
+                    com: glm::Vec3::zeros(),
+                    velocity: [0.0; 6],
+                },
+            };
+            let sides = [side(Some(joint.a), pose_a), side(joint.b, pose_b)];
+            let slots = [Some(joint.a), joint.b]
+                .map(|body| body.and_then(|b| self.body_slot(b, &mut velocities, &mut body_slots)));
+            for row in joint.rows(&sides, dt, self.solver.baumgarte()) {
+                let sides = [0, 1].map(|side| {
+                    slots[side].map(|slot| (slot, DVector::from_row_slice(&row.jacobian[side])))
+                });
+                if sides.iter().all(Option::is_none) {
+                    continue;
+                }
+                rows.push(JointRowSides {
+                    key: (index, row.id),
+                    sides,
+                    target: row.target,
+                    lower: row.lower,
+                    upper: row.upper,
+                    softness: row.softness,
+                });
+            }
+        }
+        let before: Vec<DVector<f32>> = velocities.iter().map(|v| v.velocity.clone()).collect();
+        self.solver.solve(&mut velocities, &points, &rows, dt);
+        // The step has already moved everything with the velocities from
+        // before the impulses, so the positions take the change over it too
+        let change = |slot: usize| &velocities[slot].velocity - &before[slot];
+        for (body, slot) in self.bodies.iter_mut().zip(body_slots) {
+            let Some(slot) = slot else {
+                continue;
+            };
+            let v = &velocities[slot].velocity;
+            let linear = glm::vec3(v[0], v[1], v[2]);
+            let angular = glm::vec3(v[3], v[4], v[5]);
+            if body.is_sleeping() {
+                if glm::length(&linear) < SLEEP_LINEAR_VELOCITY
+                    && glm::length(&angular) < SLEEP_ANGULAR_VELOCITY
+                {
+                    continue;
+                }
+                body.wake();
+            }
+            let moved = body.displace(&body.positions(), change(slot).as_slice(), dt);
+            body.set_state(&moved, v.as_slice());
+        }
+        for (robot, slot) in self.robots.iter_mut().zip(robot_slots) {
+            if let Some(RobotSlot { slot, .. }) = slot {
+                let state = &mut robot.state;
+                state
+                    .integrate(change(slot).as_slice(), dt)
+                    .and_then(|_| state.set_velocity_vector(velocities[slot].velocity.as_slice()))
+                    .expect("the velocities came from this state");
+            }
+        }
+    }
+    // The slot of a body's velocities, added the first time; None if fixed
+    fn body_slot(
+        &self,
+        id: BodyId,
+        velocities: &mut Vec<Velocities>,
+        body_slots: &mut [Option<usize>],
+    ) -> Option<usize> {
+        let body = &self.bodies[id.0];
+        if body.is_fixed() {
+            return None;
+        }
+        Some(*body_slots[id.0].get_or_insert_with(|| {
+            let mut inverse_mass = DMatrix::zeros(6, 6);
+            for i in 0..3 {
+                inverse_mass[(i, i)] = body.inverse_mass();
+            }
+            let inertia = body.inverse_inertia_world();
+            for i in 0..3 {
+                for j in 0..3 {
+                    inverse_mass[(i + 3, j + 3)] = inertia[(i, j)];
+                }
+            }
+            let (v, w) = (body.linear_velocity, body.angular_velocity);
+            velocities.push(Velocities {
+                velocity: DVector::from_column_slice(&[v.x, v.y, v.z, w.x, w.y, w.z]),
+                inverse_mass,
+            });
+            velocities.len() - 1
+        }))
+    }
+    // The velocities a collider moves with and the Jacobian of `point` on it;
+    // None if it cannot move
+    fn contact_side(
+        &self,
+        collider: &Collider,
+        point: &glm::Vec3,
+        velocities: &mut Vec<Velocities>,
+        body_slots: &mut [Option<usize>],
+        robot_slots: &mut [Option<RobotSlot>],
+    ) -> Option<(usize, DMatrix<f32>)> {
+        match collider.attachment {
+            Attachment::Fixed => None,
+            Attachment::Body(id) => {
+                let slot = self.body_slot(id, velocities, body_slots)?;
+                // v + w x r, with r from the centre of mass
+                let r = point - self.bodies[id.0].position;
+                #[rustfmt::skip]
+                let point_jacobian = DMatrix::from_row_slice(3, 6, &[
+                    1.0, 0.0, 0.0, 0.0, r.z, -r.y,
+                    0.0, 1.0, 0.0, -r.z, 0.0, r.x,
+                    0.0, 0.0, 1.0, r.y, -r.x, 0.0,
+                ]);
+                Some((slot, point_jacobian))
+            }
+            Attachment::Link { robot: id, link } => {
+                let robot = &self.robots[id.0];
+                if robot.is_static {
+                    return None;
+                }
+                // the mass matrix and link poses once per robot per solve
+                let RobotSlot { slot, poses } = robot_slots[id.0].get_or_insert_with(|| {
+                    let mass = RobotDynamics::new(&robot.robot)
+                        .with_base(robot.base)
+                        .mass_matrix(&robot.state);
+                    let inverse_mass = mass
+                        .clone()
+                        .cholesky()
+                        .map(|c| c.inverse())
+                        .or_else(|| mass.try_inverse())
+                        .expect("the armature keeps the mass matrix invertible");
+                    velocities.push(Velocities {
+                        velocity: DVector::from_column_slice(robot.state.velocity_vector()),
+                        inverse_mass,
+                    });
+                    RobotSlot {
+                        slot: velocities.len() - 1,
+                        poses: robot
+                            .robot
+                            .forward_kinematics_with_base(&robot.state, &robot.base),
+                    }
+                });
+                let full = jacobian_with_poses(
+                    &robot.robot,
+                    robot.robot.kinematic_tree(),
+                    &robot.state,
+                    poses,
+                    link,
+                    point,
+                );
+                Some((
+                    *slot,
+                    DMatrix::from_fn(3, full.ncols(), |i, j| full[(i, j)]),
+                ))
+            }
+        }
+    }
+
+    fn integrate(&mut self, dt: f32) {
+        for body in &mut self.bodies {
+            body.step(self.integrator.as_ref(), dt);
+        }
+        for robot in self.robots.iter_mut().filter(|r| !r.is_static) {
+            let dynamics = RobotDynamics::new(&robot.robot)
+                .with_gravity(self.gravity)
+                .with_base(robot.base);
+            dynamics
+                .step_with(self.integrator.as_ref(), &mut robot.state, dt)
+                .expect("the state was made for this robot");
+        }
+    }
+}
+
+// A robot taking part in a solve: where its velocities are and the poses of
+// its links, which the Jacobian of every contact on it needs
+#[derive(Clone)]
+struct RobotSlot {
+    slot: usize,
+    poses: LinkPoses,
+}
+
+// One step of the fixed timestep per call; `setup` loads an SDFormat world
+// with load_sdf, logging the error if it cannot
+impl PhysicsProgram for PhysicsWorld {
+    fn new() -> Self {
+        Self::default()
+    }
+    fn setup(&mut self, scene: &str) {
+        if let Err(error) = self.load_sdf(scene) {
+            log::error!("could not load the scene: {}", error);
+        }
+    }
+    fn step(&mut self) {
+        let dt = self.timestep.dt();
+        PhysicsWorld::step(self, dt);
+    }
+    fn apply_forces(&mut self) {
+        self.run_phase(Phase::ApplyForces, self.timestep.substep());
+    }
+    fn update_kinematics(&mut self) {
+        self.run_phase(Phase::UpdateKinematics, self.timestep.substep());
+    }
+    fn detect_collisions(&mut self) {
+        self.run_phase(Phase::DetectCollisions, self.timestep.substep());
+    }
+    fn solve_constraints(&mut self) {
+        self.run_phase(Phase::SolveConstraints, self.timestep.substep());
+    }
+}
+
+// the body frame, at the centre of mass
+fn body_frame(body: &RigidBody) -> glm::Mat4 {
+    let mut frame = glm::mat3_to_mat4(&body.rotation());
+    frame.set_column(3, &body.position.push(1.0));
+    frame
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Maps a resource URI as written in a robot description (eg. a <mesh filename>)
+// to a file on disk. Returns None if this resolver cannot locate it.
+pub trait ResourceResolver {
+    fn resolve(&self, uri: &str) -> Option<PathBuf>;
+}
+
+impl<F> ResourceResolver for F
+where
+    F: Fn(&str) -> Option<PathBuf>,
+{
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        self(uri)
+    }
+}
+
+// file:///absolute/path/to/mesh.stl
+#[derive(Default, Debug, Clone, Copy)]
+pub struct FileUriResolver;
+
+impl ResourceResolver for FileUriResolver {
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        let path = PathBuf::from(uri.strip_prefix("file://")?);
+        path.is_file().then_some(path)
+    }
+}
+
+// package://<package name>/path/inside/package
+// Packages are looked up by name first, then in each directory of the search
+// path, the same way ROS_PACKAGE_PATH works.
+#[derive(Default, Debug, Clone)]
+pub struct PackageResolver {
+    packages: HashMap<String, PathBuf>,
+    search_path: Vec<PathBuf>,
+}
+
+impl PackageResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    // search path taken from the ROS_PACKAGE_PATH environment variable
+    pub fn from_env() -> Self {
+        let mut resolver = Self::new();
+        if let Some(paths) = std::env::var_os("ROS_PACKAGE_PATH") {
+            resolver.search_path = std::env::split_paths(&paths).collect();
+        }
+        resolver
+    }
+    pub fn with_package<P: Into<PathBuf>>(mut self, name: &str, dir: P) -> Self {
+        self.packages.insert(name.to_owned(), dir.into());
+        self
+    }
+    pub fn with_search_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.search_path.push(dir.into());
+        self
+    }
+    pub fn package_dir(&self, name: &str) -> Option<PathBuf> {
+        if let Some(dir) = self.packages.get(name) {
+            return Some(dir.clone());
+        }
+        self.search_path.iter().find_map(|root| {
+            // a search directory may be the package itself or contain it
+            if root.file_name().is_some_and(|n| n == name) && root.is_dir() {
+                Some(root.clone())
+            } else {
+                let dir = root.join(name);
+                dir.is_dir().then_some(dir)
+            }
+        })
+    }
+}
+
+impl ResourceResolver for PackageResolver {
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        let (package, rest) = uri.strip_prefix("package://")?.split_once('/')?;
+        let path = self.package_dir(package)?.join(rest);
+        path.is_file().then_some(path)
+    }
+}
+
+// model://<model name>/path/inside/model, as used by SDFormat worlds.
+// Model directories are found in the search path the way Gazebo finds them,
+// from GZ_SIM_RESOURCE_PATH, GAZEBO_MODEL_PATH and SDF_PATH.
+#[derive(Default, Debug, Clone)]
+pub struct ModelResolver {
+    models: PackageResolver,
+}
+
+impl ModelResolver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn from_env() -> Self {
+        let mut resolver = Self::new();
+        for var in ["GZ_SIM_RESOURCE_PATH", "GAZEBO_MODEL_PATH", "SDF_PATH"] {
+            if let Some(paths) = std::env::var_os(var) {
+                resolver
+                    .models
+                    .search_path
+                    .extend(std::env::split_paths(&paths));
+            }
+        }
+        resolver
+    }
+    pub fn with_search_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.models = self.models.with_search_dir(dir);
+        self
+    }
+    pub fn model_dir(&self, name: &str) -> Option<PathBuf> {
+        self.models.package_dir(name)
+    }
+}
+
+impl ResourceResolver for ModelResolver {
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        let (model, rest) = uri.strip_prefix("model://")?.split_once('/')?;
+        let path = self.model_dir(model)?.join(rest);
+        path.is_file().then_some(path)
+    }
+}
+
+// plain paths, relative to a base directory (usually the one holding the robot
+// description); absolute paths are used as they are
+#[derive(Debug, Clone)]
+pub struct RelativeResolver {
+    base: PathBuf,
+}
+
+impl RelativeResolver {
+    pub fn new<P: Into<PathBuf>>(base: P) -> Self {
+        Self { base: base.into() }
+    }
+    // resolves against the directory containing `file`
+    pub fn for_file<P: AsRef<Path>>(file: P) -> Self {
+        Self::new(
+            file.as_ref()
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default(),
+        )
+    }
+}
+
+impl Default for RelativeResolver {
+    // relative to the working directory of the process
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl ResourceResolver for RelativeResolver {
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        if uri.contains("://") {
+            return None;
+        }
+        let path = self.base.join(uri);
+        path.is_file().then_some(path)
+    }
+}
+
+// tries each resolver in turn, the first to find the resource wins
+#[derive(Default)]
+pub struct ResolverChain {
+    resolvers: Vec<Box<dyn ResourceResolver>>,
+}
+
+impl ResolverChain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn with<R: ResourceResolver + 'static>(mut self, resolver: R) -> Self {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+    pub fn push<R: ResourceResolver + 'static>(&mut self, resolver: R) {
+        self.resolvers.push(Box::new(resolver));
+    }
+    // file:// URIs, packages on ROS_PACKAGE_PATH, then paths relative to the
+    // working directory
+    pub fn with_defaults() -> Self {
+        Self::new()
+            .with(FileUriResolver)
+            .with(PackageResolver::from_env())
+            .with(RelativeResolver::default())
+    }
+}
+
+impl ResourceResolver for ResolverChain {
+    fn resolve(&self, uri: &str) -> Option<PathBuf> {
+        self.resolvers.iter().find_map(|r| r.resolve(uri))
+    }
+}
@@ -0,0 +1,596 @@
+// Height-field terrain: heights over a regular grid, loaded from a grayscale
+// image or generated as Perlin or fractal noise, stairs, slopes or stepping
+// stones. The grid is centred on the origin of its frame, rows along y and
+// columns along x, with the heights along z.
+//
+// Each cell is split into two triangles along the diagonal from its corner
+// nearest -x, -y. The terrain draws as one indexed mesh, with normals from
+// the slopes either side of each grid point, and collides as those
+// triangles: the cells under a shape are found straight from the grid, and
+// rays walk the cells they pass over in order.
+use crate::geometry::{Aabb, Polyhedron};
+use crate::graphics::Vertex;
+use crate::physics::{fold, MeshEdge, TriangleSurface};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightField {
+    columns: usize,
+    rows: usize,
+    spacing: f32,
+    // row by row from -y, each from -x
+    heights: Vec<f32>,
+}
+
+// Octaves of Perlin noise, each `lacunarity` times finer than the last and
+// `gain` times as high
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fractal {
+    pub wavelength: f32,
+    pub amplitude: f32,
+    pub octaves: usize,
+    pub lacunarity: f32,
+    pub gain: f32,
+    pub seed: u64,
+}
+
+impl Default for Fractal {
+    fn default() -> Self {
+        Self {
+            wavelength: 4.0,
+            amplitude: 0.2,
+            octaves: 5,
+            lacunarity: 2.0,
+            gain: 0.5,
+            seed: 0,
+        }
+    }
+}
+
+// Square stones `stone` across with trenches `gap` wide and `depth` deep
+// between them, each stone raised by up to `jitter`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SteppingStones {
+    pub stone: f32,
+    pub gap: f32,
+    pub depth: f32,
+    pub jitter: f32,
+    pub seed: u64,
+}
+
+impl Default for SteppingStones {
+    fn default() -> Self {
+        Self {
+            stone: 0.3,
+            gap: 0.1,
+            depth: 0.2,
+            jitter: 0.05,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TerrainError {
+    Image(image::ImageError),
+    // a grid needs at least 2 x 2 points to have a cell
+    TooSmall { columns: usize, rows: usize },
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerrainError::Image(e) => write!(f, "{}", e),
+            TerrainError::TooSmall { columns, rows } => write!(
+                f,
+                "height fields need at least 2 x 2 points, got {} x {}",
+                columns, rows
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {}
+
+impl From<image::ImageError> for TerrainError {
+    fn from(e: image::ImageError) -> Self {
+        TerrainError::Image(e)
+    }
+}
+
+// Where a ray meets the terrain; `distance` is along the ray direction made
+// unit length
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub distance: f32,
+    pub point: glm::Vec3,
+    pub normal: glm::Vec3,
+}
+
+impl HeightField {
+    // `heights` row by row from -y, each from -x; at least two of each
+    pub fn new(columns: usize, rows: usize, spacing: f32, heights: Vec<f32>) -> Self {
+        assert!(
+            columns >= 2 && rows >= 2,
+            "height fields need at least 2 x 2 points"
+        );
+        assert_eq!(heights.len(), columns * rows, "one height per grid point");
+        Self {
+            columns,
+            rows,
+            spacing,
+            heights,
+        }
+    }
+    // heights as a function of x and y
+    pub fn from_fn(
+        columns: usize,
+        rows: usize,
+        spacing: f32,
+        height: impl Fn(f32, f32) -> f32,
+    ) -> Self {
+        let mut field = Self::new(columns, rows, spacing, vec![0.0; columns * rows]);
+        for j in 0..rows {
+            for i in 0..columns {
+                let p = field.position(i, j);
+                field.heights[j * columns + i] = height(p.x, p.y);
+            }
+        }
+        field
+    }
+    pub fn flat(columns: usize, rows: usize, spacing: f32) -> Self {
+        Self::new(columns, rows, spacing, vec![0.0; columns * rows])
+    }
+    // A point per pixel, black at 0 and white at `max_height`; the top of the
+    // image is +y, as seen from above
+    pub fn from_image(
+        image: &image::DynamicImage,
+        spacing: f32,
+        max_height: f32,
+    ) -> Result<Self, TerrainError> {
+        let luma = image.to_luma16();
+        let (columns, rows) = (luma.width() as usize, luma.height() as usize);
+        if columns < 2 || rows < 2 {
+            return Err(TerrainError::TooSmall { columns, rows });
+        }
+        let mut heights = vec![0.0; columns * rows];
+        for (x, y, pixel) in luma.enumerate_pixels() {
+            let j = rows - 1 - y as usize;
+            heights[j * columns + x as usize] = pixel.0[0] as f32 / u16::MAX as f32 * max_height;
+        }
+        Ok(Self::new(columns, rows, spacing, heights))
+    }
+    pub fn load<P: AsRef<Path>>(
+        path: P,
+        spacing: f32,
+        max_height: f32,
+    ) -> Result<Self, TerrainError> {
+        Self::from_image(&image::open(path)?, spacing, max_height)
+    }
+    // one octave of noise, from -amplitude to amplitude
+    pub fn perlin(
+        columns: usize,
+        rows: usize,
+        spacing: f32,
+        wavelength: f32,
+        amplitude: f32,
+        seed: u64,
+    ) -> Self {
+        let noise = Perlin::new(seed);
+        Self::from_fn(columns, rows, spacing, |x, y| {
+            amplitude * noise.at(x / wavelength, y / wavelength)
+        })
+    }
+    pub fn fractal(columns: usize, rows: usize, spacing: f32, fractal: Fractal) -> Self {
+        let noise = Perlin::new(fractal.seed);
+        Self::from_fn(columns, rows, spacing, |x, y| {
+            let (mut height, mut frequency, mut amplitude) =
+                (0.0, 1.0 / fractal.wavelength, fractal.amplitude);
+            for octave in 0..fractal.octaves {
+                // each octave off to another part of the lattice
+                let offset = octave as f32 * 17.31;
+                height += amplitude * noise.at(x * frequency + offset, y * frequency - offset);
+                frequency *= fractal.lacunarity;
+                amplitude *= fractal.gain;
+            }
+            height
+        })
+    }
+    // Steps up towards +x from 0 at the -x edge, each `run` deep and `rise`
+    // high
+    pub fn stairs(columns: usize, rows: usize, spacing: f32, run: f32, rise: f32) -> Self {
+        let start = -((columns - 1) as f32) * spacing / 2.0;
+        Self::from_fn(columns, rows, spacing, |x, _| {
+            // a hair in, so points on the edge of a step stay on it
+            ((x - start + 1e-4 * spacing) / run).floor() * rise
+        })
+    }
+    // rising towards +x at `angle` from level, through 0 at the centre
+    pub fn slope(columns: usize, rows: usize, spacing: f32, angle: f32) -> Self {
+        Self::from_fn(columns, rows, spacing, |x, _| x * angle.tan())
+    }
+    pub fn stepping_stones(
+        columns: usize,
+        rows: usize,
+        spacing: f32,
+        stones: SteppingStones,
+    ) -> Self {
+        let mut rng = StdRng::seed_from_u64(stones.seed);
+        let pitch = stones.stone + stones.gap;
+        let size = ((columns.max(rows) - 1) as f32 * spacing / pitch).ceil() as usize + 1;
+        let raised: Vec<f32> = (0..size * size)
+            .map(|_| rng.gen_range(0.0..=stones.jitter))
+            .collect();
+        let start = Self::flat(columns, rows, spacing).aabb().min;
+        Self::from_fn(columns, rows, spacing, |x, y| {
+            let (u, v) = ((x - start.x) / pitch, (y - start.y) / pitch);
+            let on = |w: f32| w.fract() * pitch < stones.stone;
+            if on(u) && on(v) {
+                raised[v as usize * size + u as usize]
+            } else {
+                -stones.depth
+            }
+        })
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+    pub fn heights(&self) -> &[f32] {
+        &self.heights
+    }
+    pub fn height(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+    pub fn set_height(&mut self, column: usize, row: usize, height: f32) {
+        self.heights[row * self.columns + column] = height;
+    }
+    // of the grid point
+    pub fn position(&self, column: usize, row: usize) -> glm::Vec3 {
+        glm::vec3(
+            (column as f32 - (self.columns - 1) as f32 / 2.0) * self.spacing,
+            (row as f32 - (self.rows - 1) as f32 / 2.0) * self.spacing,
+            self.heights[row * self.columns + column],
+        )
+    }
+    pub fn aabb(&self) -> Aabb {
+        let (low, high) = self
+            .heights
+            .iter()
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(l, h), z| {
+                (l.min(*z), h.max(*z))
+            });
+        let half = glm::vec2(
+            (self.columns - 1) as f32 * self.spacing,
+            (self.rows - 1) as f32 * self.spacing,
+        ) / 2.0;
+        Aabb::new(
+            glm::vec3(-half.x, -half.y, low),
+            glm::vec3(half.x, half.y, high),
+        )
+    }
+
+    // The height of the surface over (x, y), on the triangles it collides as;
+    // None off the grid
+    pub fn height_at(&self, x: f32, y: f32) -> Option<f32> {
+        let t = self.triangle_under(x, y)?;
+        let [a, ..] = TriangleSurface::triangle(self, t);
+        let n = TriangleSurface::normal(self, t);
+        Some(a.z - (n.x * (x - a.x) + n.y * (y - a.y)) / n.z)
+    }
+    // the normal of the triangle over (x, y)
+    pub fn normal_at(&self, x: f32, y: f32) -> Option<glm::Vec3> {
+        Some(TriangleSurface::normal(self, self.triangle_under(x, y)?))
+    }
+    // smoothed over the cells round a grid point, for drawing
+    pub fn vertex_normal(&self, column: usize, row: usize) -> glm::Vec3 {
+        let slope = |before: f32, after: f32, steps: usize| {
+            (after - before) / (steps as f32 * self.spacing)
+        };
+        let (i0, i1) = (column.saturating_sub(1), (column + 1).min(self.columns - 1));
+        let (j0, j1) = (row.saturating_sub(1), (row + 1).min(self.rows - 1));
+        let dx = slope(self.height(i0, row), self.height(i1, row), i1 - i0);
+        let dy = slope(self.height(column, j0), self.height(column, j1), j1 - j0);
+        glm::normalize(&glm::vec3(-dx, -dy, 1.0))
+    }
+    // the grid as one mesh to draw
+    pub fn polyhedron(&self) -> Polyhedron {
+        let mut verts = Vec::with_capacity(self.columns * self.rows);
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                verts.push(Vertex {
+                    normal: self.vertex_normal(column, row),
+                    ..Vertex::from(self.position(column, row))
+                });
+            }
+        }
+        let indices = (0..self.cells() * 2)
+            .flat_map(|t| self.corners(t).map(|v| v as u32))
+            .collect();
+        Polyhedron { verts, indices }
+    }
+
+    // The first point where the ray meets the surface, from either side,
+    // within `max_distance`
+    pub fn ray_cast(
+        &self,
+        origin: &glm::Vec3,
+        direction: &glm::Vec3,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        if glm::length2(direction) < 1e-12 {
+            return None;
+        }
+        let direction = glm::normalize(direction);
+        let bounds = self.aabb();
+        // the part of the ray over the grid and between the lowest and
+        // highest points
+        let (mut enter, mut exit) = (0.0f32, max_distance);
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-12 {
+                if origin[axis] < bounds.min[axis] || origin[axis] > bounds.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let a = (bounds.min[axis] - origin[axis]) / direction[axis];
+            let b = (bounds.max[axis] - origin[axis]) / direction[axis];
+            enter = enter.max(a.min(b));
+            exit = exit.min(a.max(b));
+        }
+        if enter > exit {
+            return None;
+        }
+        let start = origin + direction * enter;
+        let (mut column, mut row) = self.cell_of(start.x, start.y);
+        // the distance along the ray to the next line between cells, and
+        // between lines, across each axis
+        let line = |axis: usize, cell: usize| {
+            let d = direction[axis];
+            if d.abs() < 1e-12 {
+                return (f32::INFINITY, f32::INFINITY);
+            }
+            let first = bounds.min[axis] + cell as f32 * self.spacing;
+            let next = if d > 0.0 { first + self.spacing } else { first };
+            ((next - origin[axis]) / d, self.spacing / d.abs())
+        };
+        let (mut next_x, step_x) = line(0, column);
+        let (mut next_y, step_y) = line(1, row);
+        loop {
+            let cell = row * (self.columns - 1) + column;
+            let hit = [2 * cell, 2 * cell + 1]
+                .into_iter()
+                .filter_map(|t| self.ray_triangle(t, origin, &direction))
+                .filter(|hit| hit.distance >= enter && hit.distance <= exit)
+                .min_by(|a, b| a.distance.total_cmp(&b.distance));
+            if hit.is_some() {
+                return hit;
+            }
+            if next_x.min(next_y) > exit {
+                return None;
+            }
+            if next_x < next_y {
+                next_x += step_x;
+                column = column.checked_add_signed(direction.x.signum() as isize)?;
+            } else {
+                next_y += step_y;
+                row = row.checked_add_signed(direction.y.signum() as isize)?;
+            }
+            if column >= self.columns - 1 || row >= self.rows - 1 {
+                return None;
+            }
+        }
+    }
+
+    fn cells(&self) -> usize {
+        (self.columns - 1) * (self.rows - 1)
+    }
+    // of the cell over (x, y), the last along an edge for points on it
+    fn cell_of(&self, x: f32, y: f32) -> (usize, usize) {
+        let min = self.aabb().min;
+        let along =
+            |w: f32, cells: usize| ((w / self.spacing).floor().max(0.0) as usize).min(cells - 1);
+        (
+            along(x - min.x, self.columns - 1),
+            along(y - min.y, self.rows - 1),
+        )
+    }
+    fn triangle_under(&self, x: f32, y: f32) -> Option<usize> {
+        let bounds = self.aabb();
+        if x < bounds.min.x || x > bounds.max.x || y < bounds.min.y || y > bounds.max.y {
+            return None;
+        }
+        let (column, row) = self.cell_of(x, y);
+        let corner = self.position(column, row);
+        // below the diagonal is the first triangle
+        let cell = row * (self.columns - 1) + column;
+        Some(if x - corner.x >= y - corner.y {
+            2 * cell
+        } else {
+            2 * cell + 1
+        })
+    }
+    // Grid points of triangle `t`: the first of a cell has the corners at
+    // -x -y, +x -y and +x +y, the second those at -x -y, +x +y and -x +y
+    fn corners(&self, t: usize) -> [usize; 3] {
+        let cell = t / 2;
+        let (column, row) = (cell % (self.columns - 1), cell / (self.columns - 1));
+        let at = |i: usize, j: usize| (row + j) * self.columns + column + i;
+        if t.is_multiple_of(2) {
+            [at(0, 0), at(1, 0), at(1, 1)]
+        } else {
+            [at(0, 0), at(1, 1), at(0, 1)]
+        }
+    }
+    fn point(&self, vertex: usize) -> glm::Vec3 {
+        self.position(vertex % self.columns, vertex / self.columns)
+    }
+    // the triangle across edge k of triangle t, if any
+    fn neighbour(&self, t: usize, k: usize) -> Option<usize> {
+        let cell = t / 2;
+        let (column, row) = (cell % (self.columns - 1), cell / (self.columns - 1));
+        let (last_column, last_row) = (self.columns - 2, self.rows - 2);
+        let second = |c: usize, r: usize| 2 * (r * (self.columns - 1) + c) + 1;
+        let first = |c: usize, r: usize| 2 * (r * (self.columns - 1) + c);
+        match (t % 2, k) {
+            (0, 0) => (row > 0).then(|| second(column, row - 1)),
+            (0, 1) => (column < last_column).then(|| second(column + 1, row)),
+            (0, _) => Some(t + 1),
+            (_, 0) => Some(t - 1),
+            (_, 1) => (row < last_row).then(|| first(column, row + 1)),
+            (_, _) => (column > 0).then(|| first(column - 1, row)),
+        }
+    }
+    // Möller-Trumbore
+    fn ray_triangle(&self, t: usize, origin: &glm::Vec3, direction: &glm::Vec3) -> Option<RayHit> {
+        let [a, b, c] = TriangleSurface::triangle(self, t);
+        let (ab, ac) = (b - a, c - a);
+        let p = direction.cross(&ac);
+        let determinant = ab.dot(&p);
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        let s = origin - a;
+        let u = s.dot(&p) / determinant;
+        let q = s.cross(&ab);
+        let v = direction.dot(&q) / determinant;
+        if u < 0.0 || v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        let distance = ac.dot(&q) / determinant;
+        Some(RayHit {
+            distance,
+            point: origin + direction * distance,
+            normal: TriangleSurface::normal(self, t),
+        })
+    }
+}
+
+impl TriangleSurface for HeightField {
+    fn triangle(&self, index: usize) -> [glm::Vec3; 3] {
+        self.corners(index).map(|v| self.point(v))
+    }
+    fn normal(&self, index: usize) -> glm::Vec3 {
+        let [a, b, c] = self.triangle(index);
+        glm::normalize(&(b - a).cross(&(c - a)))
+    }
+    fn edges(&self, index: usize) -> [MeshEdge; 3] {
+        let corners = self.corners(index);
+        let normal = self.normal(index);
+        [0, 1, 2].map(|k| {
+            let Some(neighbour) = self.neighbour(index, k) else {
+                return MeshEdge::Boundary;
+            };
+            let (a, b) = (corners[k], corners[(k + 1) % 3]);
+            let across = self
+                .corners(neighbour)
+                .into_iter()
+                .find(|&v| v != a && v != b)
+                .expect("triangles have three corners");
+            fold(&normal, &self.point(a), &self.point(across), neighbour)
+        })
+    }
+    // Not on the rim, and every edge of the triangles round it to it
+    // internal
+    fn is_internal_corner(&self, index: usize, corner: usize) -> bool {
+        let vertex = self.corners(index)[corner];
+        let (column, row) = (vertex % self.columns, vertex / self.columns);
+        if column == 0 || row == 0 || column == self.columns - 1 || row == self.rows - 1 {
+            return false;
+        }
+        let cells = [(0, 0), (1, 0), (0, 1), (1, 1)]
+            .map(|(i, j)| (row - j) * (self.columns - 1) + column - i);
+        cells.into_iter().flat_map(|c| [2 * c, 2 * c + 1]).all(|t| {
+            let corners = self.corners(t);
+            let edges = self.edges(t);
+            (0..3).all(|k| {
+                let touches = corners[k] == vertex || corners[(k + 1) % 3] == vertex;
+                !touches || matches!(edges[k], MeshEdge::Internal { .. })
+            })
+        })
+    }
+    fn triangles_in(&self, aabb: &Aabb, found: &mut dyn FnMut(usize)) {
+        let bounds = self.aabb();
+        if !bounds.overlaps(aabb) {
+            return;
+        }
+        let (first_column, first_row) = self.cell_of(aabb.min.x, aabb.min.y);
+        let (last_column, last_row) = self.cell_of(aabb.max.x, aabb.max.y);
+        for row in first_row..=last_row {
+            for column in first_column..=last_column {
+                let heights =
+                    [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(i, j)| self.height(column + i, row + j));
+                let low = heights.iter().copied().fold(f32::INFINITY, f32::min);
+                let high = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+                if low > aabb.max.z || high < aabb.min.z {
+                    continue;
+                }
+                let cell = row * (self.columns - 1) + column;
+                found(2 * cell);
+                found(2 * cell + 1);
+            }
+        }
+    }
+}
+
+// Gradient noise on a unit lattice (Perlin, "Improving noise", 2002), about
+// -1 to 1
+#[derive(Debug, Clone)]
+struct Perlin {
+    permutation: [u8; 512],
+}
+
+impl Perlin {
+    fn new(seed: u64) -> Self {
+        let mut values: Vec<u8> = (0..=255).collect();
+        values.shuffle(&mut StdRng::seed_from_u64(seed));
+        let mut permutation = [0; 512];
+        for (i, p) in permutation.iter_mut().enumerate() {
+            *p = values[i % 256];
+        }
+        Self { permutation }
+    }
+    fn at(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (i, j) = ((x0 as i64 & 255) as usize, (y0 as i64 & 255) as usize);
+        let p = &self.permutation;
+        let hash = |i: usize, j: usize| p[p[i] as usize + j];
+        // one of eight directions
+        let gradient = |h: u8, x: f32, y: f32| match h & 7 {
+            0 => x + y,
+            1 => -x + y,
+            2 => x - y,
+            3 => -x - y,
+            4 => x,
+            5 => -x,
+            6 => y,
+            _ => -y,
+        };
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let (u, v) = (fade(fx), fade(fy));
+        let lerp = |t: f32, a: f32, b: f32| a + t * (b - a);
+        let bottom = lerp(
+            u,
+            gradient(hash(i, j), fx, fy),
+            gradient(hash(i + 1, j), fx - 1.0, fy),
+        );
+        let top = lerp(
+            u,
+            gradient(hash(i, j + 1), fx, fy - 1.0),
+            gradient(hash(i + 1, j + 1), fx - 1.0, fy - 1.0),
+        );
+        // the largest the sum of two unit gradients reaches is about 1.4
+        lerp(v, bottom, top) / 1.4
+    }
+}
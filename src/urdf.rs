@@ -1,22 +1,80 @@
-use crate::bindings::create_uniform_bind_group;
-use crate::geometry::{BoxMesh, CylinderMesh, Polyhedron, SphereMesh, Transform, TriMesh};
-use crate::texture::Texture;
+use crate::geometry::{
+    BoxMesh, CapsuleMesh, CylinderMesh, Polyhedron, SphereMesh, Transform, TriMesh,
+};
+use crate::kinematics::{
+    forward_kinematics, jacobian, joint_motion, spatial_jacobian, Jacobian, JointId, JointState,
+    KinematicTree, LinkPoses, TopologyError,
+};
+use crate::resource::{
+    FileUriResolver, PackageResolver, RelativeResolver, ResolverChain, ResourceResolver,
+};
 use crate::wgpu_program::{MeshBuffer, WGPUGraphics};
 use glm;
-use itertools::Itertools;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use xml::attribute::OwnedAttribute;
+use xml::common::Position;
 use xml::reader::{XmlEvent, XmlEvent::*};
-use xml::EventReader;
+use xml::writer::{EventWriter, XmlEvent as WriterEvent};
+use xml::{EmitterConfig, EventReader};
 
-#[derive(Default, Debug, Copy, Clone)]
+pub mod mjcf;
+pub mod sdf;
+mod tree;
+pub mod xacro;
+use xacro::{Xacro, XacroError};
+
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct Origin {
     xyz: glm::Vec3,
     rpy: Option<glm::Vec3>,
 }
 
+impl Origin {
+    pub fn new(xyz: glm::Vec3, rpy: Option<glm::Vec3>) -> Self {
+        Self { xyz, rpy }
+    }
+    pub fn xyz(&self) -> glm::Vec3 {
+        self.xyz
+    }
+    pub fn rpy(&self) -> glm::Vec3 {
+        self.rpy.unwrap_or_default()
+    }
+    // The pose as URDF defines it: translate, then R = Rz(yaw) Ry(pitch) Rx(roll)
+    pub fn matrix(&self) -> glm::Mat4 {
+        let rpy = self.rpy();
+        glm::translation(&self.xyz)
+            * glm::rotation(rpy.z, &glm::Vec3::z())
+            * glm::rotation(rpy.y, &glm::Vec3::y())
+            * glm::rotation(rpy.x, &glm::Vec3::x())
+    }
+    // Inverse of `matrix` for a rigid pose; rpy is left out when there is no rotation
+    pub fn from_matrix(pose: &glm::Mat4) -> Self {
+        let xyz = glm::vec3(pose[(0, 3)], pose[(1, 3)], pose[(2, 3)]);
+        let r = glm::mat4_to_mat3(pose);
+        if (r - glm::Mat3::identity()).abs().max() < 1e-6 {
+            return Origin { xyz, rpy: None };
+        }
+        let pitch = (-r[(2, 0)]).clamp(-1.0, 1.0).asin();
+        let (roll, yaw) = if r[(2, 0)].abs() > 1.0 - 1e-6 {
+            // pitched straight up or down, only roll - yaw is defined; keep roll 0
+            (0.0, (-r[(0, 1)]).atan2(r[(1, 1)]) + 0.0)
+        } else {
+            (r[(2, 1)].atan2(r[(2, 2)]), r[(1, 0)].atan2(r[(0, 0)]))
+        };
+        Origin {
+            xyz,
+            rpy: Some(glm::vec3(roll, pitch, yaw)),
+        }
+    }
+}
+
 impl From<Origin> for Transform {
     fn from(value: Origin) -> Self {
-        Transform::new(value.xyz, value.rpy.unwrap_or_default())
+        Transform {
+            tmatrix: value.matrix(),
+        }
     }
 }
 
@@ -33,10 +91,79 @@ pub struct InertialBody {
     pub iyz: f32,
 }
 
+impl InertialBody {
+    // mass properties from the inertia tensor about the centre of mass, in link coordinates
+    pub fn from_tensor(mass: f32, com: glm::Vec3, i: &glm::Mat3) -> Self {
+        let origin = Origin {
+            xyz: com,
+            rpy: None,
+        };
+        InertialBody {
+            origin,
+            transform: origin.into(),
+            mass,
+            ixx: i[(0, 0)],
+            iyy: i[(1, 1)],
+            izz: i[(2, 2)],
+            ixy: i[(0, 1)],
+            ixz: i[(0, 2)],
+            iyz: i[(1, 2)],
+        }
+    }
+    // the inertia tensor about the centre of mass, in the frame of `origin`
+    pub fn tensor(&self) -> glm::Mat3 {
+        glm::mat3(
+            self.ixx, self.ixy, self.ixz, self.ixy, self.iyy, self.iyz, self.ixz, self.iyz, self.izz,
+        )
+    }
+}
+
+// The shape as written in the description; `geometry` holds the mesh built from it
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeometryShape {
+    Box {
+        size: glm::Vec3,
+    },
+    Cylinder {
+        radius: f32,
+        length: f32,
+    },
+    Capsule {
+        radius: f32,
+        length: f32,
+    },
+    Sphere {
+        radius: f32,
+    },
+    Mesh {
+        filename: String, // URI as written, before resolving
+        scale: Option<glm::Vec3>,
+    },
+}
+
+impl GeometryShape {
+    // tessellates the primitive shapes; None for meshes, which are loaded from file
+    pub(crate) fn primitive_polyhedron(&self) -> Option<Polyhedron> {
+        let mesh = match *self {
+            GeometryShape::Box { size } => TriMesh::create_box(size),
+            GeometryShape::Cylinder { radius, length } => {
+                TriMesh::create_cylinder(radius, length, 30)
+            }
+            GeometryShape::Capsule { radius, length } => {
+                TriMesh::create_capsule(radius, length, 20, 5)
+            }
+            GeometryShape::Sphere { radius } => TriMesh::create_sphere(radius, 20, 20),
+            GeometryShape::Mesh { .. } => return None,
+        };
+        Some(Polyhedron::from(mesh))
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct VisualBody {
     pub origin: Origin,
     pub transform: Transform,
+    pub shape: Option<GeometryShape>,
     pub geometry: Polyhedron,
     pub material: Option<String>,
 }
@@ -45,10 +172,36 @@ pub struct VisualBody {
 pub struct CollisionBody {
     pub origin: Origin,
     pub transform: Transform,
+    pub shape: Option<GeometryShape>,
     pub geometry: Polyhedron,
 }
 
-#[derive(Default, Debug, Clone)]
+// Bodies and joints compare by their description only: the posed transforms and
+// the meshes generated from a shape are left out
+impl PartialEq for InertialBody {
+    fn eq(&self, other: &Self) -> bool {
+        self.origin == other.origin
+            && [
+                self.mass, self.ixx, self.iyy, self.izz, self.ixy, self.ixz, self.iyz,
+            ] == [
+                other.mass, other.ixx, other.iyy, other.izz, other.ixy, other.ixz, other.iyz,
+            ]
+    }
+}
+
+impl PartialEq for VisualBody {
+    fn eq(&self, other: &Self) -> bool {
+        self.origin == other.origin && self.shape == other.shape && self.material == other.material
+    }
+}
+
+impl PartialEq for CollisionBody {
+    fn eq(&self, other: &Self) -> bool {
+        self.origin == other.origin && self.shape == other.shape
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Link {
     pub link_name: String,
     pub visual: VisualBody,
@@ -56,16 +209,38 @@ pub struct Link {
     pub collision: CollisionBody,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum JointType {
     Revolute,
     Fixed,
     Continuous,
     Prismatic,
     Floating,
+    // moves in the plane normal to its axis
+    Planar,
+}
+
+impl JointType {
+    // number of position coordinates: a unit quaternion and a translation for
+    // floating joints, two translations and a rotation for planar joints
+    pub fn position_dofs(&self) -> usize {
+        match self {
+            JointType::Fixed => 0,
+            JointType::Revolute | JointType::Continuous | JointType::Prismatic => 1,
+            JointType::Planar => 3,
+            JointType::Floating => 7,
+        }
+    }
+    // number of velocity (and effort) coordinates
+    pub fn velocity_dofs(&self) -> usize {
+        match self {
+            JointType::Floating => 6,
+            other => other.position_dofs(),
+        }
+    }
 }
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct JointLimits {
     effort: f32,
     velocity: f32,
@@ -73,12 +248,57 @@ pub struct JointLimits {
     upper: f32,
 }
 
-#[derive(Default, Debug, Copy, Clone)]
+impl JointLimits {
+    pub fn new(lower: f32, upper: f32, effort: f32, velocity: f32) -> Self {
+        Self {
+            effort,
+            velocity,
+            lower,
+            upper,
+        }
+    }
+    pub fn effort(&self) -> f32 {
+        self.effort
+    }
+    pub fn velocity(&self) -> f32 {
+        self.velocity
+    }
+    pub fn lower(&self) -> f32 {
+        self.lower
+    }
+    pub fn upper(&self) -> f32 {
+        self.upper
+    }
+}
+
+// <mimic>: the joint follows `joint` as position = multiplier * other + offset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mimic {
+    pub joint: String,
+    pub multiplier: f32,
+    pub offset: f32,
+}
+
+#[derive(Default, Debug, Copy, Clone, PartialEq)]
 pub struct JointDynamics {
     damping: f32,
     friction: f32,
 }
 
+impl JointDynamics {
+    pub fn new(damping: f32, friction: f32) -> Self {
+        Self { damping, friction }
+    }
+    // torque per unit of joint velocity
+    pub fn damping(&self) -> f32 {
+        self.damping
+    }
+    // Coulomb friction torque
+    pub fn friction(&self) -> f32 {
+        self.friction
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Joint {
     joint_name: String,
@@ -90,130 +310,567 @@ pub struct Joint {
     axis: Option<glm::Vec3>, // axis in joint frame
     limits: Option<JointLimits>,
     dynamics: Option<JointDynamics>,
+    mimic: Option<Mimic>,
 }
-#[derive(Default, Debug, Clone)]
+
+impl Joint {
+    pub fn name(&self) -> &str {
+        &self.joint_name
+    }
+    pub fn joint_type(&self) -> JointType {
+        self.joint_type
+    }
+    // index of the parent link
+    pub fn parent(&self) -> usize {
+        self.parent
+    }
+    // index of the child link
+    pub fn child(&self) -> usize {
+        self.child
+    }
+    pub fn origin(&self) -> Origin {
+        self.origin
+    }
+    pub fn axis(&self) -> Option<glm::Vec3> {
+        self.axis
+    }
+    pub fn limits(&self) -> Option<JointLimits> {
+        self.limits
+    }
+    pub fn dynamics(&self) -> Option<JointDynamics> {
+        self.dynamics
+    }
+    pub fn mimic(&self) -> Option<&Mimic> {
+        self.mimic.as_ref()
+    }
+}
+
+impl PartialEq for Joint {
+    fn eq(&self, other: &Self) -> bool {
+        self.joint_name == other.joint_name
+            && self.joint_type == other.joint_type
+            && self.parent == other.parent
+            && self.child == other.child
+            && self.origin == other.origin
+            && self.axis == other.axis
+            && self.limits == other.limits
+            && self.dynamics == other.dynamics
+            && self.mimic == other.mimic
+    }
+}
+
+// A named colour; visuals refer to it by name
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Material {
+    pub name: String,
+    pub rgba: glm::Vec4,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct RobotDescriptor {
     pub name: Option<String>,
     pub links: Vec<Link>,
     pub joints: Vec<Joint>,
+    pub materials: Vec<Material>,
+    pub transmissions: Vec<Transmission>,
+    pub extensions: Vec<ExtensionElement>,
+    // checked when the robot is loaded, see update_kinematic_tree
+    tree: KinematicTree,
 }
 
-type ParseRobotError = Box<dyn std::error::Error>;
-
-//gets position, rotation from origin element
-fn parse_3f(s: &str) -> Result<glm::Vec3, ParseRobotError> {
-    let v: [f32; 3] = s
-        .split_whitespace()
-        .map(|ns| ns.parse::<f32>())
-        .collect::<Result<Vec<f32>, _>>()
-        .unwrap()
-        .try_into()
-        .unwrap();
-    Ok(v.into())
-}
-fn parse_4f(s: &str) -> Result<glm::Vec4, ParseRobotError> {
-    let v: [f32; 4] = s
-        .split_whitespace()
-        .map(|ns| ns.parse::<f32>())
-        .collect::<Result<Vec<f32>, _>>()
-        .unwrap()
-        .try_into()
-        .unwrap();
-    Ok(v.into())
-}
-fn parse_origin(origin_event: XmlEvent) -> Result<Origin, ParseRobotError> {
-    if let XmlEvent::StartElement { attributes, .. } = origin_event {
-        let xyz_attr = attributes
-            .iter()
-            .find(|&a| a.name.local_name == "xyz")
-            .ok_or("expected attribute xyz")
-            .unwrap();
-        let xyz = parse_3f(&xyz_attr.value).unwrap();
-        let rpy_attr = attributes.iter().find(|&a| a.name.local_name == "rpy");
-        let rpy = if let Some(attr) = rpy_attr {
-            let value = parse_3f(&attr.value).ok();
-            value
-        } else {
-            None
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TransmissionJoint {
+    pub name: String,
+    pub hardware_interfaces: Vec<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct TransmissionActuator {
+    pub name: String,
+    pub hardware_interfaces: Vec<String>,
+    pub mechanical_reduction: Option<f32>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Transmission {
+    pub name: Option<String>,
+    pub transmission_type: String,
+    pub joints: Vec<TransmissionJoint>,
+    pub actuators: Vec<TransmissionActuator>,
+}
+
+// A top-level element the parser does not interpret itself (eg. <gazebo>,
+// <sensor>), kept verbatim so downstream code can read it
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ExtensionElement {
+    pub name: String,
+    pub xml: String,
+}
+
+// Where in the source document an error was found: the chain of enclosing
+// elements (named elements are tagged with their name) and the 1-based line/column
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub path: String,
+    pub line: u64,
+    pub column: u64,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.path, self.line, self.column
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum UrdfError {
+    // the document is not well-formed XML
+    Xml {
+        message: String,
+        location: SourceLocation,
+    },
+    MissingAttribute {
+        attribute: String,
+        location: SourceLocation,
+    },
+    MissingElement {
+        element: String,
+        location: SourceLocation,
+    },
+    InvalidNumber {
+        attribute: String,
+        value: String,
+        location: SourceLocation,
+    },
+    // a well-formed value that is not one of the allowed options (eg. joint type)
+    InvalidValue {
+        attribute: String,
+        value: String,
+        location: SourceLocation,
+    },
+    UnknownLink {
+        link: String,
+        location: SourceLocation,
+    },
+    UnknownJoint {
+        joint: String,
+        location: SourceLocation,
+    },
+    DuplicateName {
+        kind: &'static str,
+        name: String,
+        location: SourceLocation,
+    },
+    UnsupportedElement {
+        element: String,
+        location: SourceLocation,
+    },
+    // no resolver could locate the file a URI refers to
+    UnresolvedResource {
+        uri: String,
+        location: SourceLocation,
+    },
+    UnsupportedMeshFormat {
+        uri: String,
+        location: SourceLocation,
+    },
+    // the description file itself could not be read
+    Io {
+        path: PathBuf,
+        message: String,
+    },
+    Xacro(XacroError),
+    // the joints do not connect the links into one tree
+    Topology(TopologyError),
+}
+
+impl UrdfError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        Some(match self {
+            UrdfError::Xml { location, .. }
+            | UrdfError::MissingAttribute { location, .. }
+            | UrdfError::MissingElement { location, .. }
+            | UrdfError::InvalidNumber { location, .. }
+            | UrdfError::InvalidValue { location, .. }
+            | UrdfError::UnknownLink { location, .. }
+            | UrdfError::UnknownJoint { location, .. }
+            | UrdfError::DuplicateName { location, .. }
+            | UrdfError::UnsupportedElement { location, .. }
+            | UrdfError::UnresolvedResource { location, .. }
+            | UrdfError::UnsupportedMeshFormat { location, .. } => location,
+            UrdfError::Io { .. } | UrdfError::Topology(_) => return None,
+            UrdfError::Xacro(e) => return e.location(),
+        })
+    }
+}
+
+impl fmt::Display for UrdfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrdfError::Xml { message, location } => {
+                write!(f, "malformed xml at {}: {}", location, message)
+            }
+            UrdfError::MissingAttribute {
+                attribute,
+                location,
+            } => write!(f, "missing attribute \"{}\" at {}", attribute, location),
+            UrdfError::MissingElement { element, location } => {
+                write!(f, "missing element <{}> at {}", element, location)
+            }
+            UrdfError::InvalidNumber {
+                attribute,
+                value,
+                location,
+            } => write!(
+                f,
+                "attribute \"{}\" is not a valid number (\"{}\") at {}",
+                attribute, value, location
+            ),
+            UrdfError::InvalidValue {
+                attribute,
+                value,
+                location,
+            } => write!(
+                f,
+                "invalid value \"{}\" for attribute \"{}\" at {}",
+                value, attribute, location
+            ),
+            UrdfError::UnknownLink { link, location } => {
+                write!(f, "no known link with name \"{}\" at {}", link, location)
+            }
+            UrdfError::UnknownJoint { joint, location } => {
+                write!(f, "no known joint with name \"{}\" at {}", joint, location)
+            }
+            UrdfError::DuplicateName {
+                kind,
+                name,
+                location,
+            } => write!(f, "duplicate {} name \"{}\" at {}", kind, name, location),
+            UrdfError::UnsupportedElement { element, location } => {
+                write!(f, "unsupported element <{}> at {}", element, location)
+            }
+            UrdfError::UnresolvedResource { uri, location } => {
+                write!(f, "could not locate resource \"{}\" at {}", uri, location)
+            }
+            UrdfError::UnsupportedMeshFormat { uri, location } => {
+                write!(f, "unsupported mesh format \"{}\" at {}", uri, location)
+            }
+            UrdfError::Io { path, message } => {
+                write!(f, "could not read {}: {}", path.display(), message)
+            }
+            UrdfError::Xacro(e) => write!(f, "xacro: {}", e),
+            UrdfError::Topology(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for UrdfError {}
+
+impl From<XacroError> for UrdfError {
+    fn from(e: XacroError) -> Self {
+        UrdfError::Xacro(e)
+    }
+}
+
+impl From<TopologyError> for UrdfError {
+    fn from(e: TopologyError) -> Self {
+        UrdfError::Topology(e)
+    }
+}
+
+// Wraps the xml event stream, keeping track of the element path so errors can
+// say where they happened
+struct UrdfReader<'a> {
+    events: EventReader<&'a [u8]>,
+    resolver: &'a dyn ResourceResolver,
+    path: Vec<String>,
+    // the last event closed an element, which leaves the path on the next event
+    closed: bool,
+}
+
+impl<'a> UrdfReader<'a> {
+    fn new(s: &'a str, resolver: &'a dyn ResourceResolver) -> Self {
+        Self {
+            events: EventReader::from_str(s),
+            resolver,
+            path: Vec::new(),
+            closed: false,
+        }
+    }
+    fn location(&self) -> SourceLocation {
+        let pos = self.events.position();
+        SourceLocation {
+            path: self.path.join("/"),
+            line: pos.row + 1,
+            column: pos.column + 1,
+        }
+    }
+    fn next(&mut self) -> Result<XmlEvent, UrdfError> {
+        // errors raised while handling an end element still point at it,
+        // so it is only popped once the following event is read
+        if self.closed {
+            self.path.pop();
+            self.closed = false;
+        }
+        let event = self.events.next().map_err(|e| UrdfError::Xml {
+            message: e.msg().to_owned(),
+            location: SourceLocation {
+                path: self.path.join("/"),
+                line: e.position().row + 1,
+                column: e.position().column + 1,
+            },
+        })?;
+        match &event {
+            StartElement {
+                name, attributes, ..
+            } => {
+                let segment = match attributes.iter().find(|a| a.name.local_name == "name") {
+                    Some(a) => format!("{}[{}]", name.local_name, a.value),
+                    None => name.local_name.clone(),
+                };
+                self.path.push(segment);
+            }
+            EndElement { .. } => self.closed = true,
+            EndDocument => {
+                return Err(UrdfError::MissingElement {
+                    element: self.path.first().cloned().unwrap_or("robot".into()),
+                    location: self.location(),
+                })
+            }
+            _ => {}
+        }
+        Ok(event)
+    }
+    // consume events up to and including the end of the element that was just started
+    fn skip_element(&mut self) -> Result<(), UrdfError> {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next()? {
+                StartElement { .. } => depth += 1,
+                EndElement { .. } => depth -= 1,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // the text content of the element that was just started, consuming its end
+    fn read_text(&mut self) -> Result<String, UrdfError> {
+        let mut text = String::new();
+        loop {
+            match self.next()? {
+                Characters(s) | CData(s) => text.push_str(&s),
+                StartElement { name, .. } => return Err(self.unsupported(&name.local_name)),
+                EndElement { .. } => return Ok(text.trim().to_owned()),
+                _ => {}
+            }
+        }
+    }
+    // re-serializes the element that was just started, children included
+    fn read_raw(&mut self, start: &XmlEvent) -> Result<String, UrdfError> {
+        let mut writer = EmitterConfig::new()
+            .write_document_declaration(false)
+            .create_writer(Vec::new());
+        let mut depth = 0;
+        let mut event = start.clone();
+        loop {
+            match event {
+                StartElement { .. } => depth += 1,
+                EndElement { .. } => depth -= 1,
+                _ => {}
+            }
+            // indentation is dropped so the element can be written out again
+            // at any depth
+            if let (false, Some(e)) = (matches!(event, Whitespace(_)), event.as_writer_event()) {
+                writer.write(e).map_err(|e| UrdfError::Xml {
+                    message: e.to_string(),
+                    location: self.location(),
+                })?;
+            }
+            if depth == 0 {
+                break;
+            }
+            event = self.next()?;
+        }
+        Ok(String::from_utf8_lossy(&writer.into_inner()).into_owned())
+    }
+
+    // locates a mesh file, checking it is in a format the geometry module can load
+    fn resolve_mesh(&self, uri: &str) -> Result<PathBuf, UrdfError> {
+        resolve_mesh_uri(self.resolver, uri, || self.location())
+    }
+
+    fn missing_element(&self, element: &str) -> UrdfError {
+        UrdfError::MissingElement {
+            element: element.to_owned(),
+            location: self.location(),
+        }
+    }
+    fn unsupported(&self, element: &str) -> UrdfError {
+        UrdfError::UnsupportedElement {
+            element: element.to_owned(),
+            location: self.location(),
+        }
+    }
+
+    fn attr<'b>(&self, attributes: &'b [OwnedAttribute], name: &str) -> Result<&'b str, UrdfError> {
+        find_attr(attributes, name).ok_or_else(|| UrdfError::MissingAttribute {
+            attribute: name.to_owned(),
+            location: self.location(),
+        })
+    }
+    fn parse_f32(&self, attribute: &str, value: &str) -> Result<f32, UrdfError> {
+        value
+            .trim()
+            .parse::<f32>()
+            .map_err(|_| UrdfError::InvalidNumber {
+                attribute: attribute.to_owned(),
+                value: value.to_owned(),
+                location: self.location(),
+            })
+    }
+    fn parse_nf<const N: usize>(
+        &self,
+        attribute: &str,
+        value: &str,
+    ) -> Result<[f32; N], UrdfError> {
+        let invalid = || UrdfError::InvalidNumber {
+            attribute: attribute.to_owned(),
+            value: value.to_owned(),
+            location: self.location(),
         };
-        Ok(Origin { xyz, rpy })
-    } else {
-        unreachable!();
+        value
+            .split_whitespace()
+            .map(|ns| ns.parse::<f32>().map_err(|_| invalid()))
+            .collect::<Result<Vec<f32>, _>>()?
+            .try_into()
+            .map_err(|_| invalid())
+    }
+    fn f32_attr(&self, attributes: &[OwnedAttribute], name: &str) -> Result<f32, UrdfError> {
+        self.parse_f32(name, self.attr(attributes, name)?)
+    }
+    fn opt_f32_attr(
+        &self,
+        attributes: &[OwnedAttribute],
+        name: &str,
+    ) -> Result<Option<f32>, UrdfError> {
+        find_attr(attributes, name)
+            .map(|v| self.parse_f32(name, v))
+            .transpose()
+    }
+    //gets position, rotation from origin element
+    fn parse_3f(&self, attribute: &str, value: &str) -> Result<glm::Vec3, UrdfError> {
+        Ok(self.parse_nf::<3>(attribute, value)?.into())
     }
+    fn parse_4f(&self, attribute: &str, value: &str) -> Result<glm::Vec4, UrdfError> {
+        Ok(self.parse_nf::<4>(attribute, value)?.into())
+    }
+}
+
+// locates a mesh file, checking it is in a format the geometry module can load
+fn resolve_mesh_uri(
+    resolver: &dyn ResourceResolver,
+    uri: &str,
+    location: impl Fn() -> SourceLocation,
+) -> Result<PathBuf, UrdfError> {
+    let path = resolver
+        .resolve(uri)
+        .ok_or_else(|| UrdfError::UnresolvedResource {
+            uri: uri.to_owned(),
+            location: location(),
+        })?;
+    let supported = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| matches!(e.to_lowercase().as_str(), "stl" | "obj"));
+    if !supported {
+        return Err(UrdfError::UnsupportedMeshFormat {
+            uri: uri.to_owned(),
+            location: location(),
+        });
+    }
+    Ok(path)
+}
+
+fn find_attr<'b>(attributes: &'b [OwnedAttribute], name: &str) -> Option<&'b str> {
+    attributes
+        .iter()
+        .find(|a| a.name.local_name == name)
+        .map(|a| a.value.as_str())
+}
+
+fn parse_origin(
+    xml_parser: &UrdfReader,
+    attributes: &[OwnedAttribute],
+) -> Result<Origin, UrdfError> {
+    let xyz = match find_attr(attributes, "xyz") {
+        Some(v) => xml_parser.parse_3f("xyz", v)?,
+        None => glm::Vec3::zeros(),
+    };
+    let rpy = find_attr(attributes, "rpy")
+        .map(|v| xml_parser.parse_3f("rpy", v))
+        .transpose()?;
+    Ok(Origin { xyz, rpy })
+}
+
+fn primitive(shape: GeometryShape) -> (GeometryShape, Polyhedron) {
+    let poly = shape
+        .primitive_polyhedron()
+        .expect("only called for primitive shapes");
+    (shape, poly)
 }
 
-fn parse_link_geometry(xml_parser: &mut EventReader<&[u8]>) -> Result<Polyhedron, ParseRobotError> {
-    let mut shape: Option<Polyhedron> = None;
+fn parse_link_geometry(
+    xml_parser: &mut UrdfReader,
+) -> Result<(GeometryShape, Polyhedron), UrdfError> {
+    let mut shape: Option<(GeometryShape, Polyhedron)> = None;
     loop {
-        let event = xml_parser.next();
-        match event.unwrap() {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
                 "mesh" => {
-                    let fname = attributes
-                        .iter()
-                        .find(|&a| a.name.local_name == "filename")
-                        .ok_or("expected file name attribute")
-                        .unwrap()
-                        .value
-                        .to_owned();
-                    // if fname.starts_with("package://") {
-                    // }
-                    let mut poly = Polyhedron::from(fname.to_owned());
-                    if let Some(scale) = attributes.iter().find(|&a| a.name.local_name == "scale") {
-                        poly.scale_xyz(parse_3f(&scale.value).unwrap());
+                    let uri = xml_parser.attr(&attributes, "filename")?;
+                    let path = xml_parser.resolve_mesh(uri)?;
+                    let mut poly = Polyhedron::from(path.to_string_lossy().into_owned());
+                    let scale = find_attr(&attributes, "scale")
+                        .map(|scale| xml_parser.parse_3f("scale", scale))
+                        .transpose()?;
+                    if let Some(scale) = scale {
+                        poly.scale_xyz(scale);
                     }
-                    shape = Some(poly);
+                    let filename = uri.to_owned();
+                    shape = Some((GeometryShape::Mesh { filename, scale }, poly));
                 }
-                "box" | "cylinder" | "sphere" => match name.local_name.as_str() {
-                    "box" => {
-                        let size_attr = attributes.get(0).ok_or("expected sized").unwrap();
-                        if size_attr.name.local_name == "size" {
-                            let size = parse_3f(&size_attr.value).unwrap();
-                            shape = Polyhedron::from(TriMesh::create_box(size)).into();
-                        } else {
-                            return Err("box requires size attribute".into());
-                        }
-                    }
-                    "cylinder" => {
-                        let l = attributes
-                            .iter()
-                            .find(|&a| a.name.local_name == "length")
-                            .ok_or("cylinder requires length")
-                            .unwrap()
-                            .value
-                            .parse::<f32>()
-                            .unwrap();
-                        let r = attributes
-                            .iter()
-                            .find(|&a| a.name.local_name == "radius")
-                            .ok_or("cylinder requires radius")
-                            .unwrap()
-                            .value
-                            .parse::<f32>()
-                            .unwrap();
-                        shape = Polyhedron::from(TriMesh::create_cylinder(r, l, 30)).into();
-                    }
-                    "sphere" => {
-                        let r = attributes
-                            .iter()
-                            .find(|&a| a.name.local_name == "radius")
-                            .ok_or("sphere requires radius")
-                            .unwrap()
-                            .value
-                            .parse::<f32>()
-                            .unwrap();
-                        shape = Polyhedron::from(TriMesh::create_sphere(r, 20, 20)).into();
-                    }
-                    _ => return Err("unknown element".into()),
-                },
-                _ => return Err("unknown element".into()),
-            },
-            EndElement { name } => {
-                if name.local_name == "geometry" {
-                    return shape.ok_or("no shape provided?".into());
+                "box" => {
+                    let size =
+                        xml_parser.parse_3f("size", xml_parser.attr(&attributes, "size")?)?;
+                    shape = Some(primitive(GeometryShape::Box { size }));
                 }
+                "cylinder" => {
+                    let length = xml_parser.f32_attr(&attributes, "length")?;
+                    let radius = xml_parser.f32_attr(&attributes, "radius")?;
+                    shape = Some(primitive(GeometryShape::Cylinder { radius, length }));
+                }
+                // not part of the URDF spec, but used by drake (eg. LittleDog)
+                "capsule" => {
+                    let length = xml_parser.f32_attr(&attributes, "length")?;
+                    let radius = xml_parser.f32_attr(&attributes, "radius")?;
+                    shape = Some(primitive(GeometryShape::Capsule { radius, length }));
+                }
+                "sphere" => {
+                    let radius = xml_parser.f32_attr(&attributes, "radius")?;
+                    shape = Some(primitive(GeometryShape::Sphere { radius }));
+                }
+                other => return Err(xml_parser.unsupported(other)),
+            },
+            EndElement { name } if name.local_name == "geometry" => {
+                return shape
+                    .ok_or_else(|| xml_parser.missing_element("box|cylinder|capsule|sphere|mesh"));
             }
             _ => {}
         }
@@ -221,153 +878,107 @@ fn parse_link_geometry(xml_parser: &mut EventReader<&[u8]>) -> Result<Polyhedron
 }
 
 fn parse_link_visual(
-    xml_parser: &mut EventReader<&[u8]>,
+    xml_parser: &mut UrdfReader,
     mut link: Link,
     materials: &mut Vec<Material>,
-) -> Result<Link, ParseRobotError> {
-    let mut transform: Option<Transform> = None;
+) -> Result<Link, UrdfError> {
+    let mut origin: Option<Origin> = None;
     loop {
-        let event = xml_parser.next();
-        match event.clone().unwrap() {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "origin" => {
-                    let Origin { xyz, rpy } = parse_origin(event.unwrap()).unwrap();
-                    transform = Some(Transform::new(xyz, rpy.unwrap_or_default()));
+                "origin" => origin = Some(parse_origin(xml_parser, &attributes)?),
+                "geometry" => {
+                    let (shape, geometry) = parse_link_geometry(xml_parser)?;
+                    link.visual.shape = Some(shape);
+                    link.visual.geometry = geometry;
                 }
-                "geometry" => link.visual.geometry = parse_link_geometry(xml_parser).unwrap(),
                 "material" => {
+                    let mat_name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    // a material may be defined inline, or refer to one defined elsewhere
+                    if let Some(mat) = parse_material(xml_parser, mat_name.clone())? {
+                        materials.push(mat);
+                    }
                     if link.visual.material.is_none() {
-                        let mat_name = &attributes
-                            .iter()
-                            .find(|&a| a.name.local_name == "name")
-                            .ok_or("material requires name")
-                            .unwrap()
-                            .value
-                            .to_owned();
-                        link.visual.material = Some(mat_name.to_owned());
-                        // if let Some(mat) = materials.iter().find(|m| m.name == *mat_name) {
-                        //     link.geometry.set_color(mat.color);
-                        // } else {
-                        if let Ok(mat) = parse_material(xml_parser, mat_name.to_owned()) {
-                            materials.push(mat);
-                        }
-                        // }
+                        link.visual.material = Some(mat_name);
                     }
                 }
                 _ => {}
             },
-            EndElement { name } => {
-                link.visual.transform = transform.unwrap_or_default();
-                if name.local_name == "visual" {
-                    return Ok(link);
-                }
+            EndElement { name } if name.local_name == "visual" => {
+                link.visual.origin = origin.unwrap_or_default();
+                link.visual.transform = link.visual.origin.into();
+                return Ok(link);
             }
             _ => {}
         }
     }
 }
 
-fn parse_link_collision(
-    xml_parser: &mut EventReader<&[u8]>,
-    mut link: Link,
-) -> Result<Link, ParseRobotError> {
+fn parse_link_collision(xml_parser: &mut UrdfReader, mut link: Link) -> Result<Link, UrdfError> {
+    let mut origin: Option<Origin> = None;
     loop {
-        let event = xml_parser.next();
-        match event.clone().unwrap() {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "origin" => link.collision.transform = parse_origin(event.unwrap()).unwrap().into(),
+                "origin" => origin = Some(parse_origin(xml_parser, &attributes)?),
                 "geometry" => {
-                    link.collision.geometry = parse_link_geometry(xml_parser).unwrap();
+                    let (shape, geometry) = parse_link_geometry(xml_parser)?;
+                    link.collision.shape = Some(shape);
+                    link.collision.geometry = geometry;
                 }
                 _ => {}
             },
-            EndElement { name } => {
-                if name.local_name == "collision" {
-                    return Ok(link);
-                }
+            EndElement { name } if name.local_name == "collision" => {
+                link.collision.origin = origin.unwrap_or_default();
+                link.collision.transform = link.collision.origin.into();
+                return Ok(link);
             }
             _ => {}
         }
     }
 }
-fn parse_link_inertial(
-    xml_parser: &mut EventReader<&[u8]>,
-    mut link: Link,
-) -> Result<Link, ParseRobotError> {
+fn parse_link_inertial(xml_parser: &mut UrdfReader, mut link: Link) -> Result<Link, UrdfError> {
     let mut origin: Option<Origin> = None;
     let mut mass: Option<f32> = None;
     let mut inertia: Option<[f32; 6]> = None;
     loop {
-        let event = xml_parser.next();
-        match event.clone().unwrap() {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "origin" => origin = parse_origin(event.unwrap()).ok(),
-                "mass" => mass = attributes.get(0).unwrap().value.parse::<f32>().ok(),
+                "origin" => origin = Some(parse_origin(xml_parser, &attributes)?),
+                "mass" => mass = Some(xml_parser.f32_attr(&attributes, "value")?),
                 "inertia" => {
-                    inertia = Some([
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "ixx") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "iyy") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "izz") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "ixy") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "ixz") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                        if let Some(a) = attributes.iter().find(|a| a.name.local_name == "iyz") {
-                            a.value.parse::<f32>().unwrap()
-                        } else {
-                            0.0
-                        },
-                    ]);
+                    let mut moments = [0.0; 6];
+                    for (m, key) in moments
+                        .iter_mut()
+                        .zip(["ixx", "iyy", "izz", "ixy", "ixz", "iyz"])
+                    {
+                        *m = xml_parser.opt_f32_attr(&attributes, key)?.unwrap_or(0.0);
+                    }
+                    inertia = Some(moments);
                 }
                 _ => {}
             },
-            EndElement { name } => {
-                if name.local_name == "inertial" {
-                    if let Some(mass) = mass {
-                        if let Some([ixx, iyy, izz, ixy, ixz, iyz]) = inertia {
-                            link.inertial = InertialBody {
-                                origin: origin.unwrap_or_default(),
-                                transform: origin.unwrap_or_default().into(),
-                                mass,
-                                ixx,
-                                iyy,
-                                izz,
-                                ixy,
-                                ixz,
-                                iyz,
-                            };
-                            return Ok(link);
-                        } else {
-                            panic!("inertial body requires moments of inertia!")
-                        }
-                    } else {
-                        panic!("inertial body requires mass!")
-                    }
-                }
+            EndElement { name } if name.local_name == "inertial" => {
+                let mass = mass.ok_or_else(|| xml_parser.missing_element("mass"))?;
+                let [ixx, iyy, izz, ixy, ixz, iyz] =
+                    inertia.ok_or_else(|| xml_parser.missing_element("inertia"))?;
+                link.inertial = InertialBody {
+                    origin: origin.unwrap_or_default(),
+                    transform: origin.unwrap_or_default().into(),
+                    mass,
+                    ixx,
+                    iyy,
+                    izz,
+                    ixy,
+                    ixz,
+                    iyz,
+                };
+                return Ok(link);
             }
             _ => {}
         }
@@ -375,340 +986,802 @@ fn parse_link_inertial(
 }
 
 fn parse_link(
-    xml_parser: &mut EventReader<&[u8]>,
+    xml_parser: &mut UrdfReader,
     link_name: String,
     materials: &mut Vec<Material>,
-) -> Result<Link, ParseRobotError> {
-    let mut link = Link::default();
-    link.link_name = link_name;
+) -> Result<Link, UrdfError> {
+    let mut link = Link {
+        link_name,
+        ..Default::default()
+    };
     loop {
-        let event = xml_parser.next();
-        match event.clone().unwrap() {
+        match xml_parser.next()? {
             StartElement { name, .. } => match name.local_name.as_str() {
-                "visual" => {
-                    link = parse_link_visual(xml_parser, link, materials).unwrap();
-                }
-                "inertial" => link = parse_link_inertial(xml_parser, link).unwrap(),
-                "collision" => link = parse_link_collision(xml_parser, link).unwrap(),
+                "visual" => link = parse_link_visual(xml_parser, link, materials)?,
+                "inertial" => link = parse_link_inertial(xml_parser, link)?,
+                "collision" => link = parse_link_collision(xml_parser, link)?,
                 _ => {}
             },
-            EndElement { name } => {
-                if name.local_name == "link" {
-                    return Ok(link);
-                }
+            EndElement { name } if name.local_name == "link" => {
+                return Ok(link);
             }
             _ => {}
         }
     }
 }
 
-pub fn parse_joint(
-    xml_parser: &mut EventReader<&[u8]>,
+// A joint as read from the document; links are resolved by name once the
+// whole robot has been read, since joints may come before the links they connect
+struct JointElement {
+    joint: Joint,
+    parent_name: String,
+    child_name: String,
+    location: SourceLocation,
+}
+
+fn parse_joint(
+    xml_parser: &mut UrdfReader,
     joint_name: String,
     joint_type: JointType,
-    links: &Vec<Link>,
-) -> Result<Joint, ParseRobotError> {
+) -> Result<JointElement, UrdfError> {
+    let location = xml_parser.location();
     let mut parent_name: Option<String> = None;
     let mut child_name: Option<String> = None;
     let mut origin: Option<Origin> = None;
     let mut axis: Option<glm::Vec3> = None;
     let mut limits: Option<JointLimits> = None;
     let mut dynamics: Option<JointDynamics> = None;
+    let mut mimic: Option<Mimic> = None;
 
     loop {
-        let event = xml_parser.next();
-        match event.clone().unwrap() {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
-                "parent" => {
-                    parent_name = Some(
-                        attributes
-                            .get(0)
-                            .expect("parent requires name")
-                            .value
-                            .clone(),
-                    )
-                }
-                "child" => {
-                    child_name = Some(
-                        attributes
-                            .get(0)
-                            .expect("child requires name")
-                            .value
-                            .clone(),
-                    )
-                }
-                "origin" => origin = Some(parse_origin(event.clone().unwrap()).unwrap()),
+                "parent" => parent_name = Some(xml_parser.attr(&attributes, "link")?.to_owned()),
+                "child" => child_name = Some(xml_parser.attr(&attributes, "link")?.to_owned()),
+                "origin" => origin = Some(parse_origin(xml_parser, &attributes)?),
                 "axis" => {
-                    let attr = attributes.get(0).ok_or("expected attribute xyz").unwrap();
-                    axis = Some(parse_3f(&attr.value).unwrap());
+                    axis = Some(xml_parser.parse_3f("xyz", xml_parser.attr(&attributes, "xyz")?)?);
                 }
                 "limit" => {
-                    let (mut effort, mut lower, mut upper, mut velocity) = (0., 0., 0., 0.);
-                    for attr in attributes {
-                        match attr.name.local_name.as_str() {
-                            "effort" => effort = attr.value.parse::<f32>().unwrap(),
-                            "lower" => lower = attr.value.parse::<f32>().unwrap(),
-                            "upper" => upper = attr.value.parse::<f32>().unwrap(),
-                            "velocity" => velocity = attr.value.parse::<f32>().unwrap(),
-                            _ => {
-                                panic!("unkown attribute in limit")
-                            }
-                        }
-                    }
                     limits = Some(JointLimits {
-                        effort,
-                        velocity,
-                        lower,
-                        upper,
+                        effort: xml_parser
+                            .opt_f32_attr(&attributes, "effort")?
+                            .unwrap_or(0.),
+                        velocity: xml_parser
+                            .opt_f32_attr(&attributes, "velocity")?
+                            .unwrap_or(0.),
+                        lower: xml_parser.opt_f32_attr(&attributes, "lower")?.unwrap_or(0.),
+                        upper: xml_parser.opt_f32_attr(&attributes, "upper")?.unwrap_or(0.),
                     });
                 }
                 "dynamics" => {
-                    let (mut damping, mut friction) = (0.0, 0.0);
-                    for attr in attributes {
-                        match attr.name.local_name.as_str() {
-                            "damping" => damping = attr.value.parse::<f32>().unwrap(),
-                            "friction" => friction = attr.value.parse::<f32>().unwrap(),
-                            _ => {
-                                panic!("unknown attribute in dynamics")
-                            }
-                        }
-                    }
-                    dynamics = Some(JointDynamics { damping, friction });
+                    dynamics = Some(JointDynamics {
+                        damping: xml_parser
+                            .opt_f32_attr(&attributes, "damping")?
+                            .unwrap_or(0.),
+                        friction: xml_parser
+                            .opt_f32_attr(&attributes, "friction")?
+                            .unwrap_or(0.),
+                    });
                 }
-                _ => {
-                    eprintln!("{}", name.local_name.as_str());
-                    return Err("possibly unsupported feature".into());
+                "mimic" => {
+                    mimic = Some(Mimic {
+                        joint: xml_parser.attr(&attributes, "joint")?.to_owned(),
+                        multiplier: xml_parser
+                            .opt_f32_attr(&attributes, "multiplier")?
+                            .unwrap_or(1.),
+                        offset: xml_parser.opt_f32_attr(&attributes, "offset")?.unwrap_or(0.),
+                    });
                 }
+                // valid URDF, but nothing in the simulator uses them
+                "calibration" | "safety_controller" => xml_parser.skip_element()?,
+                other => return Err(xml_parser.unsupported(other)),
             },
-            EndElement { name } => {
-                if name.local_name == "joint" {
-                    break;
-                }
+            EndElement { name } if name.local_name == "joint" => {
+                break;
             }
-            Whitespace(..) => {}
             _ => {}
         }
     }
-    let (parent, child);
-    let p_name = parent_name.ok_or("parent element is required!").unwrap();
-    parent = links
-        .iter()
-        .position(|l| l.link_name == p_name)
-        .ok_or(format!("no known link with name {}", p_name))
-        .unwrap();
-    let c_name = child_name.ok_or("child element is required!").unwrap();
-    child = links
-        .iter()
-        .position(|l| l.link_name == c_name)
-        .ok_or(format!("no known link with name {}", c_name))
-        .unwrap();
-    let transform = if let Some(Origin { xyz, rpy }) = origin {
-        Transform::new(xyz, rpy.unwrap_or_default())
-    } else {
-        Transform::default()
-    };
-    Ok(Joint {
-        joint_name,
-        joint_type,
-        parent,
-        child,
-        origin: origin.unwrap_or_default(),
-        transform,
-        axis,
-        limits,
-        dynamics,
+    let parent_name = parent_name.ok_or_else(|| xml_parser.missing_element("parent"))?;
+    let child_name = child_name.ok_or_else(|| xml_parser.missing_element("child"))?;
+    let origin = origin.unwrap_or_default();
+    // URDF defaults the axis of a moving joint to x
+    if axis.is_none() && !matches!(joint_type, JointType::Fixed | JointType::Floating) {
+        axis = Some(glm::Vec3::x());
+    }
+    Ok(JointElement {
+        joint: Joint {
+            joint_name,
+            joint_type,
+            parent: 0,
+            child: 0,
+            origin,
+            transform: origin.into(),
+            axis,
+            limits,
+            dynamics,
+            mimic,
+        },
+        parent_name,
+        child_name,
+        location,
     })
 }
 
-#[derive(Debug, Default, PartialEq, Clone)]
-struct Material {
-    name: String,
-    color: glm::Vec3,
-}
+// returns None for a reference to a material that is defined elsewhere
 fn parse_material(
-    xml_parser: &mut EventReader<&[u8]>,
+    xml_parser: &mut UrdfReader,
     material_name: String,
-) -> Result<Material, ParseRobotError> {
-    let color: glm::Vec3;
+) -> Result<Option<Material>, UrdfError> {
+    let mut rgba: Option<glm::Vec4> = None;
     loop {
-        let event = xml_parser.next().unwrap();
-        match event {
+        match xml_parser.next()? {
             StartElement {
                 name, attributes, ..
             } => match name.local_name.as_str() {
                 "color" => {
-                    let attr = attributes
-                        .iter()
-                        .find(|&a| a.name.local_name == "rgba")
-                        .ok_or("color must have rgba value")
-                        .unwrap();
-                    color = parse_4f(&attr.value).unwrap().xyz();
-                    return Ok(Material {
-                        name: material_name,
-                        color,
+                    rgba =
+                        Some(xml_parser.parse_4f("rgba", xml_parser.attr(&attributes, "rgba")?)?);
+                }
+                other => return Err(xml_parser.unsupported(other)),
+            },
+            EndElement { name } if name.local_name == "material" => {
+                return Ok(rgba.map(|rgba| Material {
+                    name: material_name,
+                    rgba,
+                }));
+            }
+            _ => {}
+        }
+    }
+}
+// Reads both the current (<type> child) and the legacy (type attribute,
+// transmission-level <mechanicalReduction>) transmission formats
+fn parse_transmission(
+    xml_parser: &mut UrdfReader,
+    name: Option<String>,
+    type_attr: Option<String>,
+) -> Result<Transmission, UrdfError> {
+    let mut transmission = Transmission {
+        name,
+        transmission_type: type_attr.unwrap_or_default(),
+        joints: Vec::new(),
+        actuators: Vec::new(),
+    };
+    let mut reduction: Option<f32> = None;
+    loop {
+        match xml_parser.next()? {
+            StartElement {
+                name, attributes, ..
+            } => match name.local_name.as_str() {
+                "type" => transmission.transmission_type = xml_parser.read_text()?,
+                "joint" => {
+                    let name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    let (hardware_interfaces, _) = parse_transmission_member(xml_parser)?;
+                    transmission.joints.push(TransmissionJoint {
+                        name,
+                        hardware_interfaces,
                     });
                 }
-                "texture" => {
-                    unimplemented!()
+                "actuator" => {
+                    let name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    let (hardware_interfaces, mechanical_reduction) =
+                        parse_transmission_member(xml_parser)?;
+                    transmission.actuators.push(TransmissionActuator {
+                        name,
+                        hardware_interfaces,
+                        mechanical_reduction,
+                    });
+                }
+                "mechanicalReduction" => {
+                    let text = xml_parser.read_text()?;
+                    reduction = Some(xml_parser.parse_f32("mechanicalReduction", &text)?);
                 }
-                _ => return Err("unknown element in material".into()),
+                _ => xml_parser.skip_element()?,
             },
-            EndElement { name } => {
-                if name.local_name == "material" {
-                    return Err("could not parse material".into());
+            EndElement { name } if name.local_name == "transmission" => {
+                if transmission.transmission_type.is_empty() {
+                    return Err(xml_parser.missing_element("type"));
                 }
+                for actuator in transmission.actuators.iter_mut() {
+                    actuator.mechanical_reduction = actuator.mechanical_reduction.or(reduction);
+                }
+                return Ok(transmission);
             }
-            Whitespace(..) => {}
-            _ => {
-                return Err("could not parse material".into());
-            }
+            _ => {}
         }
     }
 }
-fn parse_transmission(
-    mut xml_parser: EventReader<&[u8]>,
-    name: Option<String>
-    ) -> Result<(), ParseRobotError> {
-    let joints: Vec<String> = Vec::new();
+// reads the hardware interfaces and mechanical reduction of a transmission <joint> or <actuator>
+fn parse_transmission_member(
+    xml_parser: &mut UrdfReader,
+) -> Result<(Vec<String>, Option<f32>), UrdfError> {
+    let mut hardware_interfaces = Vec::new();
+    let mut reduction = None;
     loop {
-        let event = xml_parser.next();
-        match event.unwrap() {
-            StartElement { name, attributes, .. } =>
-                match name.local_name.as_str() {
-                    "type" => {},
-                    "joint" => {},
-                    "actuator" => {},
-                    _=> {}
-                },
-            EndElement { name } => if name.local_name.as_str() == "transmission" { return Ok(()) }
+        match xml_parser.next()? {
+            StartElement { name, .. } => match name.local_name.as_str() {
+                "hardwareInterface" => hardware_interfaces.push(xml_parser.read_text()?),
+                "mechanicalReduction" => {
+                    let text = xml_parser.read_text()?;
+                    reduction = Some(xml_parser.parse_f32("mechanicalReduction", &text)?);
+                }
+                _ => xml_parser.skip_element()?,
+            },
+            EndElement { .. } => return Ok((hardware_interfaces, reduction)),
             _ => {}
         }
     }
 }
+// A mimic joint and the joint it follows both move along one coordinate, and
+// following the mimics from any joint never comes back to it
+fn check_mimics(joints: &[(Joint, SourceLocation)]) -> Result<(), UrdfError> {
+    let single_dof = |j: &Joint| j.joint_type.position_dofs() == 1 && j.joint_type.velocity_dofs() == 1;
+    for (joint, location) in joints {
+        let mut current = joint;
+        let mut steps = 0;
+        while let Some(mimic) = &current.mimic {
+            let invalid = || UrdfError::InvalidValue {
+                attribute: "mimic joint".into(),
+                value: mimic.joint.clone(),
+                location: location.clone(),
+            };
+            let followed = joints
+                .iter()
+                .map(|(j, _)| j)
+                .find(|j| j.joint_name == mimic.joint)
+                .ok_or_else(|| UrdfError::UnknownJoint {
+                    joint: mimic.joint.clone(),
+                    location: location.clone(),
+                })?;
+            steps += 1;
+            if !single_dof(current) || !single_dof(followed) || steps > joints.len() {
+                return Err(invalid());
+            }
+            current = followed;
+        }
+    }
+    Ok(())
+}
+
 fn parse_robot(
-    mut xml_parser: EventReader<&[u8]>,
+    xml_parser: &mut UrdfReader,
     robot_name: Option<String>,
-) -> Result<RobotDescriptor, ParseRobotError> {
-    let mut links = Vec::new();
-    let mut joints = Vec::new();
+) -> Result<RobotDescriptor, UrdfError> {
+    let mut links: Vec<Link> = Vec::new();
+    let mut joint_elements: Vec<JointElement> = Vec::new();
     let mut materials = Vec::<Material>::new();
-    // let mut attr_name: String = "".into();
+    let mut defined_materials: Vec<String> = Vec::new();
+    let mut transmissions: Vec<(Transmission, SourceLocation)> = Vec::new();
+    let mut extensions: Vec<ExtensionElement> = Vec::new();
     loop {
-        let event = xml_parser.next();
-        match event.unwrap() {
+        match xml_parser.next()? {
             StartElement {
-                name, attributes, ..
-            } => {
-                match name.local_name.as_str() {
-                    "link" => {
-                        let attr = attributes
-                            .iter()
-                            .find(|a| a.name.local_name == "name")
-                            .ok_or("link requires name")
-                            .unwrap();
-                        // attr_name = attr.value.to_owned();
-                        links.push(parse_link(&mut xml_parser, attr.value.to_owned(), &mut materials).unwrap())
+                name,
+                attributes,
+                namespace,
+            } => match name.local_name.as_str() {
+                "link" => {
+                    let link_name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    if links.iter().any(|l| l.link_name == link_name) {
+                        return Err(UrdfError::DuplicateName {
+                            kind: "link",
+                            name: link_name,
+                            location: xml_parser.location(),
+                        });
                     }
-                    "joint" => {
-                        let attr = attributes
-                            .iter()
-                            .find(|a| a.name.local_name == "name")
-                            .ok_or("joint requires name")
-                            .unwrap();
-                        // attr_name = attr.value.to_owned();
-                        let joint_type: JointType;
-                        if let Some(attr) = attributes.get(1) {
-                            assert!(attr.name.local_name == "type");
-                            match attr.value.as_str() {
-                                "fixed" => joint_type = JointType::Fixed,
-                                "revolute" => joint_type = JointType::Revolute,
-                                "continuous" => joint_type = JointType::Continuous,
-                                "prismatic" => joint_type = JointType::Prismatic,
-                                "floating" => joint_type = JointType::Floating,
-                                _ => return Err("unrecognized joint type".into()),
-                            }
-                        } else {
-                            return Err("joint requires type attribute".into());
-                        }
-                        joints.push(
-                            parse_joint(&mut xml_parser, attr.value.to_owned(), joint_type, &links).unwrap(),
-                        )
+                    links.push(parse_link(xml_parser, link_name, &mut materials)?)
+                }
+                "joint" => {
+                    let joint_name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    if joint_elements
+                        .iter()
+                        .any(|j| j.joint.joint_name == joint_name)
+                    {
+                        return Err(UrdfError::DuplicateName {
+                            kind: "joint",
+                            name: joint_name,
+                            location: xml_parser.location(),
+                        });
                     }
-                    "transmission" => {panic!("unsupported feature: transmission")},
-                    "sensor" => {panic!("unsupported feature: sensor")},
-                    "material" => {
-                        let attr = attributes
-                            .iter()
-                            .find(|a| a.name.local_name == "name")
-                            .ok_or("material requires name")
-                            .unwrap();
-                        materials.push(parse_material(&mut xml_parser, attr.value.to_owned()).unwrap())
+                    let joint_type = match xml_parser.attr(&attributes, "type")? {
+                        "fixed" => JointType::Fixed,
+                        "revolute" => JointType::Revolute,
+                        "continuous" => JointType::Continuous,
+                        "prismatic" => JointType::Prismatic,
+                        "floating" => JointType::Floating,
+                        "planar" => JointType::Planar,
+                        other => {
+                            return Err(UrdfError::InvalidValue {
+                                attribute: "type".into(),
+                                value: other.into(),
+                                location: xml_parser.location(),
+                            })
+                        }
+                    };
+                    joint_elements.push(parse_joint(xml_parser, joint_name, joint_type)?)
+                }
+                "material" => {
+                    let mat_name = xml_parser.attr(&attributes, "name")?.to_owned();
+                    if defined_materials.contains(&mat_name) {
+                        return Err(UrdfError::DuplicateName {
+                            kind: "material",
+                            name: mat_name,
+                            location: xml_parser.location(),
+                        });
                     }
-                    _ => {
-                        return Err(
-                            format!("unexpected element name! \"{}\"", name.local_name).into()
-                        )
+                    defined_materials.push(mat_name.clone());
+                    if let Some(mat) = parse_material(xml_parser, mat_name)? {
+                        materials.push(mat)
                     }
                 }
-            }
-            EndElement { name } => {
-                if name.local_name == "robot" {
-                    break;
+                "transmission" => {
+                    let location = xml_parser.location();
+                    let name = find_attr(&attributes, "name").map(str::to_owned);
+                    let type_attr = find_attr(&attributes, "type").map(str::to_owned);
+                    transmissions
+                        .push((parse_transmission(xml_parser, name, type_attr)?, location));
                 }
+                // anything else (<gazebo>, <sensor>, tool specific tags) is kept for
+                // downstream code to interpret
+                other => {
+                    let xml = xml_parser.read_raw(&StartElement {
+                        name: name.clone(),
+                        attributes: attributes.clone(),
+                        namespace: namespace.clone(),
+                    })?;
+                    extensions.push(ExtensionElement {
+                        name: other.to_owned(),
+                        xml,
+                    });
+                }
+            },
+            EndElement { name } if name.local_name == "robot" => {
+                break;
             }
             _ => {}
         }
     }
 
+    if links.is_empty() {
+        return Err(xml_parser.missing_element("link"));
+    }
+
+    // resolve the links each joint connects
+    let find_link = |name: &str, location: &SourceLocation| {
+        links
+            .iter()
+            .position(|l| l.link_name == name)
+            .ok_or_else(|| UrdfError::UnknownLink {
+                link: name.to_owned(),
+                location: location.clone(),
+            })
+    };
+    let joints = joint_elements
+        .into_iter()
+        .map(|je| {
+            let mut joint = je.joint;
+            joint.parent = find_link(&je.parent_name, &je.location)?;
+            joint.child = find_link(&je.child_name, &je.location)?;
+            Ok((joint, je.location))
+        })
+        .collect::<Result<Vec<(Joint, SourceLocation)>, UrdfError>>()?;
+    check_mimics(&joints)?;
+    let joints: Vec<Joint> = joints.into_iter().map(|(joint, _)| joint).collect();
+
+    let transmissions = transmissions
+        .into_iter()
+        .map(|(transmission, location)| {
+            match transmission
+                .joints
+                .iter()
+                .find(|tj| !joints.iter().any(|j| j.joint_name == tj.name))
+            {
+                Some(tj) => Err(UrdfError::UnknownJoint {
+                    joint: tj.name.clone(),
+                    location,
+                }),
+                None => Ok(transmission),
+            }
+        })
+        .collect::<Result<Vec<Transmission>, UrdfError>>()?;
+
+    // a later definition of a material overrides an earlier one
+    let materials = materials
+        .into_iter()
+        .fold(Vec::<Material>::new(), |mut acc, mat| {
+            match acc.iter_mut().find(|m| m.name == mat.name) {
+                Some(m) => *m = mat,
+                None => acc.push(mat),
+            }
+            acc
+        });
+
     //setup colors
-    for mat in materials {
+    for mat in &materials {
         for link in links.iter_mut() {
-            // println!("{:?}", link.visual.material);
             if link
                 .visual
                 .material
-                .clone()
-                .is_some_and(|mn| mn == mat.name)
+                .as_ref()
+                .is_some_and(|mn| *mn == mat.name)
             {
-                // println!("{:?}", mat);
-                link.visual.geometry.set_color(mat.color);
+                link.visual.geometry.set_color(mat.rgba.xyz());
             }
         }
     }
 
-    return Ok(RobotDescriptor {
+    let mut robot = RobotDescriptor {
         name: robot_name,
         links,
         joints,
-    });
+        materials,
+        transmissions,
+        extensions,
+        tree: KinematicTree::default(),
+    };
+    robot.update_kinematic_tree()?;
+    Ok(robot)
 }
 
 impl FromStr for RobotDescriptor {
-    type Err = ParseRobotError;
-    fn from_str(s: &str) -> Result<RobotDescriptor, ParseRobotError> {
-        let mut xml_parser = EventReader::from_str(s);
-        let mut robot_name: Option<String> = None;
-        let event = xml_parser.next();
-        match event.unwrap() {
-            StartDocument { .. } => {}
-            _ => return Err("Is this a valid XML URDF file.unwrap()".into()),
-        }
-        let event = xml_parser.next();
-        match event.unwrap() {
-            StartElement {
-                name, attributes, ..
-            } => {
-                assert!(name.local_name == "robot");
-                if let Some(attr) = attributes.iter().find(|a| a.name.local_name == "name") {
-                    robot_name = Some(attr.value.clone());
+    type Err = UrdfError;
+    // mesh paths are resolved with ResolverChain::with_defaults
+    fn from_str(s: &str) -> Result<RobotDescriptor, UrdfError> {
+        RobotDescriptor::from_str_with_resolver(s, &ResolverChain::with_defaults())
+    }
+}
+
+impl RobotDescriptor {
+    pub fn from_str_with_resolver(
+        s: &str,
+        resolver: &dyn ResourceResolver,
+    ) -> Result<RobotDescriptor, UrdfError> {
+        let mut xml_parser = UrdfReader::new(s, resolver);
+        // skip the prolog up to the root element
+        loop {
+            match xml_parser.next()? {
+                StartElement {
+                    name, attributes, ..
+                } => {
+                    if name.local_name != "robot" {
+                        return Err(xml_parser.missing_element("robot"));
+                    }
+                    let robot_name = find_attr(&attributes, "name").map(str::to_owned);
+                    return parse_robot(&mut xml_parser, robot_name);
                 }
-                return parse_robot(xml_parser, robot_name);
+                EndElement { .. } => return Err(xml_parser.missing_element("robot")),
+                _ => {}
             }
-            _ => Err("expected robot element as first element".into()),
         }
     }
+    // Relative mesh paths are looked up next to the file first, then in the
+    // working directory; package:// and file:// URIs as in from_str
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<RobotDescriptor, UrdfError> {
+        let resolver = ResolverChain::new()
+            .with(FileUriResolver)
+            .with(PackageResolver::from_env())
+            .with(RelativeResolver::for_file(&path))
+            .with(RelativeResolver::default());
+        RobotDescriptor::from_file_with_resolver(path, resolver)
+    }
+    // `resolver` is tried first, falling back to paths relative to the file
+    pub fn from_file_with_resolver<P: AsRef<Path>, R: ResourceResolver + 'static>(
+        path: P,
+        resolver: R,
+    ) -> Result<RobotDescriptor, UrdfError> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path).map_err(|e| UrdfError::Io {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        let resolver = ResolverChain::new()
+            .with(resolver)
+            .with(RelativeResolver::for_file(path));
+        RobotDescriptor::from_str_with_resolver(&s, &resolver)
+    }
+    // expands a .urdf.xacro file first; package:// mesh URIs are looked up in
+    // the packages known to `xacro`, relative paths as in from_file
+    pub fn from_xacro_file<P: AsRef<Path>>(
+        path: P,
+        xacro: &Xacro,
+    ) -> Result<RobotDescriptor, UrdfError> {
+        let s = xacro.expand_file(&path)?;
+        let resolver = ResolverChain::new()
+            .with(FileUriResolver)
+            .with(xacro.packages().clone())
+            .with(RelativeResolver::for_file(&path))
+            .with(RelativeResolver::default());
+        RobotDescriptor::from_str_with_resolver(&s, &resolver)
+    }
+    // MuJoCo MJCF models, see mjcf.rs for how bodies and joints become links
+    // and joints; mesh files are resolved as in from_str
+    pub fn from_mjcf_str(s: &str) -> Result<RobotDescriptor, UrdfError> {
+        mjcf::parse_mjcf(s, &ResolverChain::with_defaults())
+    }
+    // mesh files are looked up relative to the model file, then as in from_mjcf_str
+    pub fn from_mjcf_file<P: AsRef<Path>>(path: P) -> Result<RobotDescriptor, UrdfError> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path).map_err(|e| UrdfError::Io {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        let resolver = ResolverChain::new()
+            .with(FileUriResolver)
+            .with(PackageResolver::from_env())
+            .with(RelativeResolver::for_file(path))
+            .with(RelativeResolver::default());
+        mjcf::parse_mjcf(&s, &resolver)
+    }
+    // Writes the description back out as URDF: materials first, visuals refer
+    // to them by name, and mesh URIs are kept as they were written
+    pub fn to_urdf_string(&self) -> String {
+        let mut writer = EmitterConfig::new()
+            .perform_indent(true)
+            .create_writer(Vec::new());
+        write_robot(&mut writer, self).expect("writing well formed xml to memory");
+        String::from_utf8(writer.into_inner()).expect("xml-rs writes utf-8")
+    }
+}
+
+type UrdfWriter = EventWriter<Vec<u8>>;
+
+fn floats(values: &[f32]) -> String {
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// writes <name attr="value".../> with no children
+fn write_empty(
+    w: &mut UrdfWriter,
+    name: &str,
+    attributes: &[(&str, &str)],
+) -> xml::writer::Result<()> {
+    let mut element = WriterEvent::start_element(name);
+    for (k, v) in attributes {
+        element = element.attr(*k, v);
+    }
+    w.write(element)?;
+    w.write(WriterEvent::end_element())
+}
+
+fn write_text_element(w: &mut UrdfWriter, name: &str, text: &str) -> xml::writer::Result<()> {
+    w.write(WriterEvent::start_element(name))?;
+    w.write(WriterEvent::characters(text))?;
+    w.write(WriterEvent::end_element())
+}
+
+fn write_origin(w: &mut UrdfWriter, origin: &Origin) -> xml::writer::Result<()> {
+    let xyz = floats(origin.xyz.as_slice());
+    match origin.rpy {
+        Some(rpy) => write_empty(
+            w,
+            "origin",
+            &[("xyz", &xyz), ("rpy", &floats(rpy.as_slice()))],
+        ),
+        None => write_empty(w, "origin", &[("xyz", &xyz)]),
+    }
+}
+
+fn write_geometry(w: &mut UrdfWriter, shape: &GeometryShape) -> xml::writer::Result<()> {
+    w.write(WriterEvent::start_element("geometry"))?;
+    match shape {
+        GeometryShape::Box { size } => {
+            write_empty(w, "box", &[("size", &floats(size.as_slice()))])?
+        }
+        GeometryShape::Cylinder { radius, length } => write_empty(
+            w,
+            "cylinder",
+            &[
+                ("radius", &radius.to_string()),
+                ("length", &length.to_string()),
+            ],
+        )?,
+        GeometryShape::Capsule { radius, length } => write_empty(
+            w,
+            "capsule",
+            &[
+                ("radius", &radius.to_string()),
+                ("length", &length.to_string()),
+            ],
+        )?,
+        GeometryShape::Sphere { radius } => {
+            write_empty(w, "sphere", &[("radius", &radius.to_string())])?
+        }
+        GeometryShape::Mesh { filename, scale } => match scale {
+            Some(scale) => write_empty(
+                w,
+                "mesh",
+                &[("filename", filename), ("scale", &floats(scale.as_slice()))],
+            )?,
+            None => write_empty(w, "mesh", &[("filename", filename)])?,
+        },
+    }
+    w.write(WriterEvent::end_element())
+}
+
+fn write_link(w: &mut UrdfWriter, link: &Link) -> xml::writer::Result<()> {
+    w.write(WriterEvent::start_element("link").attr("name", &link.link_name))?;
+    let inertial = &link.inertial;
+    if *inertial != InertialBody::default() {
+        w.write(WriterEvent::start_element("inertial"))?;
+        write_origin(w, &inertial.origin)?;
+        write_empty(w, "mass", &[("value", &inertial.mass.to_string())])?;
+        let moments = [
+            ("ixx", inertial.ixx),
+            ("ixy", inertial.ixy),
+            ("ixz", inertial.ixz),
+            ("iyy", inertial.iyy),
+            ("iyz", inertial.iyz),
+            ("izz", inertial.izz),
+        ]
+        .map(|(k, v)| (k, v.to_string()));
+        let moments = moments
+            .iter()
+            .map(|(k, v)| (*k, v.as_str()))
+            .collect::<Vec<_>>();
+        write_empty(w, "inertia", &moments)?;
+        w.write(WriterEvent::end_element())?;
+    }
+    let visual = &link.visual;
+    if visual.shape.is_some() || visual.material.is_some() {
+        w.write(WriterEvent::start_element("visual"))?;
+        write_origin(w, &visual.origin)?;
+        if let Some(shape) = &visual.shape {
+            write_geometry(w, shape)?;
+        }
+        if let Some(material) = &visual.material {
+            write_empty(w, "material", &[("name", material)])?;
+        }
+        w.write(WriterEvent::end_element())?;
+    }
+    if let Some(shape) = &link.collision.shape {
+        w.write(WriterEvent::start_element("collision"))?;
+        write_origin(w, &link.collision.origin)?;
+        write_geometry(w, shape)?;
+        w.write(WriterEvent::end_element())?;
+    }
+    w.write(WriterEvent::end_element())
+}
+
+fn write_joint(
+    w: &mut UrdfWriter,
+    robot: &RobotDescriptor,
+    joint: &Joint,
+) -> xml::writer::Result<()> {
+    let joint_type = match joint.joint_type {
+        JointType::Revolute => "revolute",
+        JointType::Fixed => "fixed",
+        JointType::Continuous => "continuous",
+        JointType::Prismatic => "prismatic",
+        JointType::Floating => "floating",
+        JointType::Planar => "planar",
+    };
+    w.write(
+        WriterEvent::start_element("joint")
+            .attr("name", &joint.joint_name)
+            .attr("type", joint_type),
+    )?;
+    write_empty(
+        w,
+        "parent",
+        &[("link", &robot.links[joint.parent].link_name)],
+    )?;
+    write_empty(w, "child", &[("link", &robot.links[joint.child].link_name)])?;
+    write_origin(w, &joint.origin)?;
+    if let Some(axis) = joint.axis {
+        write_empty(w, "axis", &[("xyz", &floats(axis.as_slice()))])?;
+    }
+    if let Some(limits) = joint.limits {
+        write_empty(
+            w,
+            "limit",
+            &[
+                ("effort", &limits.effort.to_string()),
+                ("velocity", &limits.velocity.to_string()),
+                ("lower", &limits.lower.to_string()),
+                ("upper", &limits.upper.to_string()),
+            ],
+        )?;
+    }
+    if let Some(dynamics) = joint.dynamics {
+        write_empty(
+            w,
+            "dynamics",
+            &[
+                ("damping", &dynamics.damping.to_string()),
+                ("friction", &dynamics.friction.to_string()),
+            ],
+        )?;
+    }
+    if let Some(mimic) = &joint.mimic {
+        write_empty(
+            w,
+            "mimic",
+            &[
+                ("joint", &mimic.joint),
+                ("multiplier", &mimic.multiplier.to_string()),
+                ("offset", &mimic.offset.to_string()),
+            ],
+        )?;
+    }
+    w.write(WriterEvent::end_element())
+}
+
+fn write_transmission(w: &mut UrdfWriter, transmission: &Transmission) -> xml::writer::Result<()> {
+    let mut element = WriterEvent::start_element("transmission");
+    if let Some(name) = &transmission.name {
+        element = element.attr("name", name);
+    }
+    w.write(element)?;
+    write_text_element(w, "type", &transmission.transmission_type)?;
+    for joint in &transmission.joints {
+        w.write(WriterEvent::start_element("joint").attr("name", &joint.name))?;
+        for interface in &joint.hardware_interfaces {
+            write_text_element(w, "hardwareInterface", interface)?;
+        }
+        w.write(WriterEvent::end_element())?;
+    }
+    for actuator in &transmission.actuators {
+        w.write(WriterEvent::start_element("actuator").attr("name", &actuator.name))?;
+        for interface in &actuator.hardware_interfaces {
+            write_text_element(w, "hardwareInterface", interface)?;
+        }
+        if let Some(reduction) = actuator.mechanical_reduction {
+            write_text_element(w, "mechanicalReduction", &reduction.to_string())?;
+        }
+        w.write(WriterEvent::end_element())?;
+    }
+    w.write(WriterEvent::end_element())
+}
+
+// extension elements are stored as xml text, replay them into the output
+fn write_extension(w: &mut UrdfWriter, extension: &ExtensionElement) -> xml::writer::Result<()> {
+    for event in EventReader::from_str(&extension.xml) {
+        let event = event.map_err(std::io::Error::other)?;
+        match event {
+            StartDocument { .. } | Whitespace(_) => {}
+            EndDocument => break,
+            event => {
+                if let Some(e) = event.as_writer_event() {
+                    w.write(e)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_robot(w: &mut UrdfWriter, robot: &RobotDescriptor) -> xml::writer::Result<()> {
+    let mut element = WriterEvent::start_element("robot");
+    if let Some(name) = &robot.name {
+        element = element.attr("name", name);
+    }
+    w.write(element)?;
+    for material in &robot.materials {
+        w.write(WriterEvent::start_element("material").attr("name", &material.name))?;
+        write_empty(w, "color", &[("rgba", &floats(material.rgba.as_slice()))])?;
+        w.write(WriterEvent::end_element())?;
+    }
+    for link in &robot.links {
+        write_link(w, link)?;
+    }
+    for joint in &robot.joints {
+        write_joint(w, robot, joint)?;
+    }
+    for transmission in &robot.transmissions {
+        write_transmission(w, transmission)?;
+    }
+    for extension in &robot.extensions {
+        write_extension(w, extension)?;
+    }
+    w.write(WriterEvent::end_element())
 }
 
 impl RobotDescriptor {
@@ -735,10 +1808,31 @@ impl RobotDescriptor {
                         .rotate(j.axis.expect("revolute joint requires axis!"), th);
                 }
                 JointType::Floating => { /* do nothing */ }
+                JointType::Planar => { /* do nothing */ }
                 JointType::Fixed => { /* do nothing */ }
             }
         }
     }
+    pub fn joint_id(&self, name: &str) -> Option<JointId> {
+        self.joints
+            .iter()
+            .position(|j| j.joint_name == name)
+            .map(JointId::from_index)
+    }
+    pub fn link_index(&self, name: &str) -> Option<usize> {
+        self.links.iter().position(|l| l.link_name == name)
+    }
+    // Poses every joint as `state` says, like set_joint_position without the
+    // need to count joints; fixed joints go back to their origin
+    pub fn set_joint_state(&mut self, state: &JointState) {
+        for (i, j) in self.joints.iter_mut().enumerate() {
+            let motion = match state.positions(JointId::from_index(i)) {
+                Some(q) => joint_motion(j, q),
+                None => glm::Mat4::identity(),
+            };
+            j.transform = Transform::from(j.origin) * Transform { tmatrix: motion };
+        }
+    }
     pub fn reset_joint_transforms(&mut self) {
         self.links.iter_mut().for_each(|l| {
             l.inertial.transform = l.inertial.origin.into();
@@ -746,48 +1840,107 @@ impl RobotDescriptor {
             l.collision.transform = l.collision.origin.into();
         })
     }
-    fn walk_children(&self, cur_link: &Link) -> Vec<(usize, Transform)> {
-        self.joints
-            .iter()
-            .filter(|j| self.links[j.parent].link_name == cur_link.link_name)
-            .map(|j| {
-                let tf = cur_link.inertial.transform
-                    * j.transform
-                    * self.links[j.child].inertial.transform;
-                (j.child, tf)
-            })
-            .collect()
+    // The tree worked out when the robot was loaded or last updated; it goes
+    // stale when links or joints are added or reconnected by hand, until
+    // update_kinematic_tree is called
+    pub fn kinematic_tree(&self) -> &KinematicTree {
+        &self.tree
+    }
+    // works the tree out again after links or joints were edited by hand
+    pub fn update_kinematic_tree(&mut self) -> Result<(), TopologyError> {
+        self.tree = KinematicTree::new(self)?;
+        Ok(())
+    }
+    // World poses of the links for `state` with the root at the world origin,
+    // leaving the transforms on the robot as they are; the links are walked
+    // in the order of kinematic_tree, so it must be up to date
+    pub fn forward_kinematics(&self, state: &JointState) -> LinkPoses {
+        forward_kinematics(self, &self.tree, state, None)
     }
-    // Walk the DAG
+    // forward_kinematics with the root link at `base`, for floating-base robots
+    pub fn forward_kinematics_with_base(&self, state: &JointState, base: &glm::Mat4) -> LinkPoses {
+        forward_kinematics(self, &self.tree, state, Some(base))
+    }
+    // Geometric Jacobian of `point`, in the frame of `link`, with the root at
+    // the world origin; see kinematics::jacobian for the layout
+    pub fn jacobian(&self, state: &JointState, link: usize, point: &glm::Vec3) -> Jacobian {
+        jacobian(self, &self.tree, state, None, link, point)
+    }
+    pub fn spatial_jacobian(&self, state: &JointState, link: usize) -> Jacobian {
+        spatial_jacobian(self, &self.tree, state, None, link)
+    }
+    // The robot free in space: a new root link "world" (numbered if the name
+    // is taken) holding the old root with a floating joint at the world origin
+    pub fn with_floating_base(mut self) -> Self {
+        let root = self.tree.root();
+        let mut link_name = String::from("world");
+        let mut n = 1;
+        while self.links.iter().any(|l| l.link_name == link_name) {
+            n += 1;
+            link_name = format!("world_{}", n);
+        }
+        let world = self.links.len();
+        self.links.push(Link {
+            link_name,
+            ..Default::default()
+        });
+        self.joints.push(Joint {
+            joint_name: format!("{}_floating", self.links[root].link_name),
+            joint_type: JointType::Floating,
+            parent: world,
+            child: root,
+            origin: Origin::default(),
+            transform: Transform::default(),
+            axis: None,
+            limits: None,
+            dynamics: None,
+            mimic: None,
+        });
+        self.update_kinematic_tree()
+            .expect("a tree under a new root is a tree");
+        self
+    }
+    // Poses the visual, collision and inertial frames of every link from the
+    // joint transforms as last set, each frame at its link pose times its own
+    // origin; the root link stays at the world origin
     pub fn build(&mut self) {
-        //next, setup transforms
-        self.reset_joint_transforms();
-        let base_link = self.links.get(0).expect("No links found.unwrap()");
-        let mut child_transforms = self.walk_children(base_link);
-        loop {
-            let mut queue: Option<Vec<(usize, Transform)>> = None;
-            for (c_id, c_tf) in &child_transforms {
-                // update link with new transform
-                self.links[*c_id].inertial.transform = *c_tf;
-                self.links[*c_id].visual.transform = *c_tf;
-                // query for correct transforms of children links
-                let mut v = self.walk_children(&self.links[*c_id]);
-                v.extend(queue.unwrap_or_default());
-                queue = Some(v);
-            }
-            if let Some(qlist) = queue {
-                child_transforms = qlist;
-            } else {
-                break;
-            }
+        let mut frames = vec![glm::Mat4::identity(); self.links.len()];
+        for &link in &self.tree.topological_order()[1..] {
+            let j = self
+                .tree
+                .parent_joint(link)
+                .expect("only the root has no parent");
+            let joint = &self.joints[j];
+            frames[link] = frames[joint.parent] * joint.transform.tmatrix;
+        }
+        for (link, frame) in std::iter::zip(&mut self.links, frames) {
+            link.inertial.transform = Transform {
+                tmatrix: frame * link.inertial.origin.matrix(),
+            };
+            link.visual.transform = Transform {
+                tmatrix: frame * link.visual.origin.matrix(),
+            };
+            link.collision.transform = Transform {
+                tmatrix: frame * link.collision.origin.matrix(),
+            };
         }
     }
+    // build with the joints posed as `state` says, matching forward_kinematics
+    pub fn build_with(&mut self, state: &JointState) {
+        self.set_joint_state(state);
+        self.build();
+    }
 }
 
 pub trait RobotGraphics {
     fn robot_create_mesh_buffers(&mut self, robot: &RobotDescriptor) -> Vec<MeshBuffer>;
     fn robot_assign_mesh_buffers(&mut self, robot: &RobotDescriptor, buffers: &Vec<MeshBuffer>);
-    fn draw_robot(&mut self, robot: &RobotDescriptor, buffers: &Vec<MeshBuffer>, pipeline: &wgpu::RenderPipeline);
+    fn draw_robot(
+        &mut self,
+        robot: &RobotDescriptor,
+        buffers: &Vec<MeshBuffer>,
+        pipeline: &wgpu::RenderPipeline,
+    );
     fn robot_create_transform_buffers(&mut self, robot: &RobotDescriptor) -> Vec<wgpu::Buffer>;
     fn robot_assign_transform_buffers(
         &mut self,
@@ -803,13 +1956,20 @@ impl RobotGraphics for WGPUGraphics<'_> {
     }
     fn robot_assign_mesh_buffers(&mut self, robot: &RobotDescriptor, buffers: &Vec<MeshBuffer>) {
         // Warning: order matters!
-        std::iter::zip(buffers, &robot.links).map(|(buf, link)| self.assign_mesh_buffer(&link.visual.geometry, buf)).collect()
+        std::iter::zip(buffers, &robot.links)
+            .map(|(buf, link)| self.assign_mesh_buffer(&link.visual.geometry, buf))
+            .collect()
     }
-    fn draw_robot(&mut self, robot: &RobotDescriptor, buffers: &Vec<MeshBuffer>, pipeline: &wgpu::RenderPipeline) {
+    fn draw_robot(
+        &mut self,
+        robot: &RobotDescriptor,
+        buffers: &Vec<MeshBuffer>,
+        pipeline: &wgpu::RenderPipeline,
+    ) {
         self.draw_mesh_list(pipeline, &buffers);
     }
     fn robot_create_transform_buffers(&mut self, robot: &RobotDescriptor) -> Vec<wgpu::Buffer> {
-        self.create_transform_buffers(robot.links.iter().map(|l| l.inertial.transform))
+        self.create_transform_buffers(robot.links.iter().map(|l| l.visual.transform))
     }
     fn robot_assign_transform_buffers(
         &mut self,
@@ -817,6 +1977,6 @@ impl RobotGraphics for WGPUGraphics<'_> {
         buffers: &Vec<wgpu::Buffer>,
     ) {
         // std::iter::zip(buffers, &robot.links).for_each(|(b,l)| self.assign_uniform(b, &[l.inertial.transform]))
-        self.update_transforms(buffers, robot.links.iter().map(|l| l.inertial.transform))
+        self.update_transforms(buffers, robot.links.iter().map(|l| l.visual.transform))
     }
 }
@@ -0,0 +1,851 @@
+// Imports MuJoCo MJCF models into the structures the URDF parser builds.
+//
+// Every <body> becomes a link and is connected to its parent by its joints.
+// URDF puts a joint at the origin of its child link, so the link frame of a
+// body sits at the anchor (pos) of its last joint and the body's geoms and
+// inertial are offset to match. A body with several joints gets an extra,
+// massless link between each pair of them, and a ball joint is split into
+// three continuous joints about x, y and z. A body without joints is fixed to
+// its parent, and the <worldbody> itself becomes the root link "world".
+// Only the first geom of a body is its visual and collision, the others are
+// put on links fixed to it. Planes are left out, the ground is not part of a robot.
+use super::tree::{read_tree, XmlElement};
+use super::{
+    primitive, resolve_mesh_uri, CollisionBody, GeometryShape, InertialBody, Joint, JointDynamics,
+    JointLimits, JointType, Link, Material, Origin, RobotDescriptor, SourceLocation, UrdfError,
+    VisualBody,
+};
+use crate::geometry::Polyhedron;
+use crate::resource::ResourceResolver;
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+// MuJoCo's defaults for attributes that are not given anywhere
+const DEFAULT_RGBA: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+const DEFAULT_DENSITY: f32 = 1000.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum InertiaFromGeom {
+    Never,
+    Always,
+    // only for bodies without an <inertial>
+    Auto,
+}
+
+// the <compiler> settings that change how the model is read
+#[derive(Debug, Clone)]
+struct Compiler {
+    degrees: bool,
+    eulerseq: Vec<char>,
+    meshdir: Option<String>,
+    autolimits: bool,
+    inertia_from_geom: InertiaFromGeom,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Self {
+            degrees: true,
+            eulerseq: vec!['x', 'y', 'z'],
+            meshdir: None,
+            autolimits: true,
+            inertia_from_geom: InertiaFromGeom::Auto,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MeshAsset {
+    file: String,
+    scale: Option<glm::Vec3>,
+}
+
+// default attribute values per element type ("geom", "joint", ...)
+type DefaultClass = HashMap<String, Vec<(String, String)>>;
+
+fn rotation_about(axis: &glm::Vec3, angle: f32) -> glm::Mat3 {
+    glm::mat4_to_mat3(&glm::rotation(angle, axis))
+}
+
+// rotation taking +z onto `z` by the shortest arc
+fn rotation_to_z(z: &glm::Vec3) -> glm::Mat3 {
+    let z = glm::normalize(z);
+    let up = glm::Vec3::z();
+    let axis = glm::cross(&up, &z);
+    if glm::length(&axis) < 1e-6 {
+        return if z.z > 0.0 {
+            glm::Mat3::identity()
+        } else {
+            rotation_about(&glm::Vec3::x(), PI)
+        };
+    }
+    rotation_about(
+        &glm::normalize(&axis),
+        glm::dot(&up, &z).clamp(-1.0, 1.0).acos(),
+    )
+}
+
+fn with_rotation(pos: &glm::Vec3, rot: &glm::Mat3) -> glm::Mat4 {
+    glm::translation(pos) * glm::mat3_to_mat4(rot)
+}
+
+// a joint of a body, before the links it connects are made
+struct BodyJoint {
+    name: String,
+    joint_type: JointType,
+    // anchor in the body frame
+    pos: glm::Vec3,
+    axis: Option<glm::Vec3>,
+    limits: Option<JointLimits>,
+    dynamics: Option<JointDynamics>,
+}
+
+// a geom as placed in its body
+struct Geom {
+    name: Option<String>,
+    shape: GeometryShape,
+    polyhedron: Polyhedron,
+    pose: glm::Mat4,
+    material: String,
+    mass: f32,
+    // about the geom origin, in the geom frame
+    inertia: glm::Mat3,
+}
+
+struct Importer<'a> {
+    resolver: &'a dyn ResourceResolver,
+    compiler: Compiler,
+    defaults: HashMap<String, DefaultClass>,
+    meshes: HashMap<String, MeshAsset>,
+    asset_materials: HashMap<String, glm::Vec4>,
+    robot: RobotDescriptor,
+}
+
+impl Importer<'_> {
+    fn angle(&self, value: f32) -> f32 {
+        if self.compiler.degrees {
+            value.to_radians()
+        } else {
+            value
+        }
+    }
+
+    fn read_compiler(&mut self, compiler: &XmlElement) -> Result<(), UrdfError> {
+        match compiler.attr("angle") {
+            Some("degree") => self.compiler.degrees = true,
+            Some("radian") => self.compiler.degrees = false,
+            Some(other) => return Err(compiler.invalid_value("angle", other)),
+            None => {}
+        }
+        if let Some(seq) = compiler.attr("eulerseq") {
+            if seq.len() != 3 || !seq.chars().all(|c| "xyzXYZ".contains(c)) {
+                return Err(compiler.invalid_value("eulerseq", seq));
+            }
+            self.compiler.eulerseq = seq.chars().collect();
+        }
+        // meshdir takes precedence over assetdir for meshes
+        if let Some(dir) = compiler.attr("meshdir").or(compiler.attr("assetdir")) {
+            self.compiler.meshdir = Some(dir.to_owned());
+        }
+        if let Some(autolimits) = compiler.bool("autolimits")? {
+            self.compiler.autolimits = autolimits;
+        }
+        match compiler.attr("inertiafromgeom") {
+            Some("false") => self.compiler.inertia_from_geom = InertiaFromGeom::Never,
+            Some("true") => self.compiler.inertia_from_geom = InertiaFromGeom::Always,
+            Some("auto") => self.compiler.inertia_from_geom = InertiaFromGeom::Auto,
+            Some(other) => return Err(compiler.invalid_value("inertiafromgeom", other)),
+            None => {}
+        }
+        Ok(())
+    }
+
+    // nested classes start from a copy of their parent's defaults
+    fn read_defaults(&mut self, default: &XmlElement, parent: Option<&str>) {
+        let class = default.attr("class").unwrap_or("main").to_owned();
+        let mut values = parent
+            .and_then(|p| self.defaults.get(p).cloned())
+            .unwrap_or_default();
+        for element in default.children.iter().filter(|c| c.name != "default") {
+            let entry = values.entry(element.name.clone()).or_default();
+            for (k, v) in &element.attributes {
+                match entry.iter_mut().find(|(key, _)| key == k) {
+                    Some(existing) => existing.1 = v.clone(),
+                    None => entry.push((k.clone(), v.clone())),
+                }
+            }
+        }
+        self.defaults.insert(class.clone(), values);
+        for child in default.children_named("default") {
+            self.read_defaults(child, Some(&class));
+        }
+    }
+
+    fn read_assets(&mut self, asset: &XmlElement) -> Result<(), UrdfError> {
+        for element in &asset.children {
+            match element.name.as_str() {
+                "mesh" => {
+                    let file = element.required_attr("file")?.to_owned();
+                    let name = match element.attr("name") {
+                        Some(name) => name.to_owned(),
+                        // named after the file without its extension
+                        None => std::path::Path::new(&file)
+                            .file_stem()
+                            .map(|s| s.to_string_lossy().into_owned())
+                            .unwrap_or_default(),
+                    };
+                    let scale = element.vec3("scale")?;
+                    self.meshes.insert(name, MeshAsset { file, scale });
+                }
+                "material" => {
+                    let name = element.required_attr("name")?.to_owned();
+                    let rgba = element.floats_n::<4>("rgba")?.unwrap_or([1.0; 4]);
+                    self.asset_materials.insert(name, rgba.into());
+                }
+                // textures and heightfields are not used
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // the element with the attributes of its class filled in
+    fn with_defaults(
+        &self,
+        element: &XmlElement,
+        childclass: &str,
+    ) -> Result<XmlElement, UrdfError> {
+        let class = element.attr("class").unwrap_or(childclass);
+        let defaults = match self.defaults.get(class) {
+            Some(defaults) => defaults.get(&element.name),
+            None if class == "main" => None,
+            None => return Err(element.invalid_value("class", class)),
+        };
+        let mut resolved = element.clone();
+        for (k, v) in defaults.into_iter().flatten() {
+            if element.attr(k).is_none() {
+                resolved.attributes.push((k.clone(), v.clone()));
+            }
+        }
+        Ok(resolved)
+    }
+
+    // pos and orientation of a body, geom or inertial relative to its body
+    fn frame(&self, element: &XmlElement) -> Result<glm::Mat4, UrdfError> {
+        let pos = element.vec3("pos")?.unwrap_or_default();
+        let rot = if let Some([w, x, y, z]) = element.floats_n::<4>("quat")? {
+            glm::quat_to_mat3(&glm::quat_normalize(&glm::quat(x, y, z, w)))
+        } else if let Some([x, y, z, angle]) = element.floats_n::<4>("axisangle")? {
+            rotation_about(&glm::normalize(&glm::vec3(x, y, z)), self.angle(angle))
+        } else if let Some(euler) = element.vec3("euler")? {
+            // lower case axes rotate with the frame, upper case are fixed
+            let mut rot = glm::Mat3::identity();
+            for (axis, angle) in self.compiler.eulerseq.iter().zip(euler.iter()) {
+                let r = rotation_about(
+                    &match axis.to_ascii_lowercase() {
+                        'x' => glm::Vec3::x(),
+                        'y' => glm::Vec3::y(),
+                        _ => glm::Vec3::z(),
+                    },
+                    self.angle(*angle),
+                );
+                rot = if axis.is_ascii_lowercase() {
+                    rot * r
+                } else {
+                    r * rot
+                };
+            }
+            rot
+        } else if let Some(v) = element.floats_n::<6>("xyaxes")? {
+            let x = glm::normalize(&glm::vec3(v[0], v[1], v[2]));
+            let y = glm::vec3(v[3], v[4], v[5]);
+            let y = glm::normalize(&(y - x * glm::dot(&x, &y)));
+            glm::Mat3::from_columns(&[x, y, glm::cross(&x, &y)])
+        } else if let Some(z) = element.vec3("zaxis")? {
+            rotation_to_z(&z)
+        } else {
+            glm::Mat3::identity()
+        };
+        Ok(with_rotation(&pos, &rot))
+    }
+
+    fn material_for(&mut self, geom: &XmlElement) -> Result<String, UrdfError> {
+        let rgba: glm::Vec4 = match (geom.floats_n::<4>("rgba")?, geom.attr("material")) {
+            (Some(rgba), _) => rgba.into(),
+            (None, Some(name)) => {
+                let rgba = *self
+                    .asset_materials
+                    .get(name)
+                    .ok_or_else(|| geom.invalid_value("material", name))?;
+                return Ok(self.add_material(name.to_owned(), rgba));
+            }
+            (None, None) => DEFAULT_RGBA.into(),
+        };
+        let name = format!("rgba({} {} {} {})", rgba.x, rgba.y, rgba.z, rgba.w);
+        Ok(self.add_material(name, rgba))
+    }
+
+    fn add_material(&mut self, name: String, rgba: glm::Vec4) -> String {
+        if !self.robot.materials.iter().any(|m| m.name == name) {
+            self.robot.materials.push(Material {
+                name: name.clone(),
+                rgba,
+            });
+        }
+        name
+    }
+
+    // None for planes, which are not part of a robot
+    fn read_geom(&mut self, geom: &XmlElement) -> Result<Option<Geom>, UrdfError> {
+        let geom_type = geom.attr("type").unwrap_or("sphere");
+        let size = geom.floats("size")?.unwrap_or_default();
+        let size_at = |i: usize| {
+            size.get(i)
+                .copied()
+                .ok_or_else(|| geom.invalid_value("size", geom.attr("size").unwrap_or("")))
+        };
+        let mut pose = self.frame(geom)?;
+        // capsules and cylinders may be given by the ends of their axis instead
+        let fromto = match geom_type {
+            "capsule" | "cylinder" => geom.floats_n::<6>("fromto")?,
+            _ => None,
+        };
+        let length = match fromto {
+            Some(v) => {
+                let from = glm::vec3(v[0], v[1], v[2]);
+                let to = glm::vec3(v[3], v[4], v[5]);
+                pose = with_rotation(&((from + to) * 0.5), &rotation_to_z(&(to - from)));
+                glm::distance(&from, &to)
+            }
+            None if matches!(geom_type, "capsule" | "cylinder") => 2.0 * size_at(1)?,
+            None => 0.0,
+        };
+        let shape = match geom_type {
+            "sphere" => GeometryShape::Sphere {
+                radius: size_at(0)?,
+            },
+            "capsule" => GeometryShape::Capsule {
+                radius: size_at(0)?,
+                length,
+            },
+            "cylinder" => GeometryShape::Cylinder {
+                radius: size_at(0)?,
+                length,
+            },
+            "box" => GeometryShape::Box {
+                size: glm::vec3(size_at(0)?, size_at(1)?, size_at(2)?) * 2.0,
+            },
+            "mesh" => {
+                let name = geom.required_attr("mesh")?;
+                let mesh = self
+                    .meshes
+                    .get(name)
+                    .ok_or_else(|| geom.invalid_value("mesh", name))?;
+                let filename = match &self.compiler.meshdir {
+                    Some(dir) if !mesh.file.starts_with('/') => {
+                        format!("{}/{}", dir.trim_end_matches('/'), mesh.file)
+                    }
+                    _ => mesh.file.clone(),
+                };
+                GeometryShape::Mesh {
+                    filename,
+                    scale: mesh.scale,
+                }
+            }
+            "plane" => return Ok(None),
+            other => {
+                return Err(UrdfError::UnsupportedElement {
+                    element: format!("geom type=\"{}\"", other),
+                    location: geom.location.clone(),
+                })
+            }
+        };
+        let mut polyhedron = match &shape {
+            GeometryShape::Mesh { filename, scale } => {
+                let path = resolve_mesh_uri(self.resolver, filename, || geom.location.clone())?;
+                let mut poly = Polyhedron::from(path.to_string_lossy().into_owned());
+                if let Some(scale) = scale {
+                    poly.scale_xyz(*scale);
+                }
+                poly
+            }
+            _ => primitive(shape.clone()).1,
+        };
+        let material = self.material_for(geom)?;
+        let rgba = self.robot.materials.iter().find(|m| m.name == material);
+        polyhedron.set_color(rgba.map(|m| m.rgba.xyz()).unwrap_or_default());
+
+        let (volume, unit_inertia) = volume_and_inertia(&shape, &polyhedron);
+        let mass = match geom.f32("mass")? {
+            Some(mass) => mass,
+            None => geom.f32("density")?.unwrap_or(DEFAULT_DENSITY) * volume,
+        };
+        Ok(Some(Geom {
+            name: geom.attr("name").map(str::to_owned),
+            shape,
+            polyhedron,
+            pose,
+            material,
+            mass,
+            inertia: unit_inertia * mass,
+        }))
+    }
+
+    fn add_link(&mut self, link: Link, location: &SourceLocation) -> Result<usize, UrdfError> {
+        if self
+            .robot
+            .links
+            .iter()
+            .any(|l| l.link_name == link.link_name)
+        {
+            return Err(UrdfError::DuplicateName {
+                kind: "link",
+                name: link.link_name,
+                location: location.clone(),
+            });
+        }
+        self.robot.links.push(link);
+        Ok(self.robot.links.len() - 1)
+    }
+
+    fn add_joint(
+        &mut self,
+        joint: &BodyJoint,
+        parent: usize,
+        child: usize,
+        pose: &glm::Mat4,
+        location: &SourceLocation,
+    ) -> Result<(), UrdfError> {
+        if self.robot.joints.iter().any(|j| j.joint_name == joint.name) {
+            return Err(UrdfError::DuplicateName {
+                kind: "joint",
+                name: joint.name.clone(),
+                location: location.clone(),
+            });
+        }
+        let origin = Origin::from_matrix(pose);
+        self.robot.joints.push(Joint {
+            joint_name: joint.name.clone(),
+            joint_type: joint.joint_type,
+            parent,
+            child,
+            origin,
+            transform: origin.into(),
+            axis: joint.axis,
+            limits: joint.limits,
+            dynamics: joint.dynamics,
+            mimic: None,
+        });
+        Ok(())
+    }
+
+    fn add_fixed_joint(
+        &mut self,
+        name: String,
+        parent: usize,
+        child: usize,
+        pose: &glm::Mat4,
+        location: &SourceLocation,
+    ) -> Result<(), UrdfError> {
+        let joint = BodyJoint {
+            name,
+            joint_type: JointType::Fixed,
+            pos: glm::Vec3::zeros(),
+            axis: None,
+            limits: None,
+            dynamics: None,
+        };
+        self.add_joint(&joint, parent, child, pose, location)
+    }
+
+    // the geoms of a body go on `link`, the first as its visual and collision,
+    // the rest on links fixed to it; `offset` takes body coordinates to link coordinates
+    fn attach_geoms(
+        &mut self,
+        link: usize,
+        geoms: Vec<Geom>,
+        offset: &glm::Mat4,
+        location: &SourceLocation,
+    ) -> Result<(), UrdfError> {
+        let link_name = self.robot.links[link].link_name.clone();
+        for (i, geom) in geoms.into_iter().enumerate() {
+            let pose = offset * geom.pose;
+            let (target, origin) = if i == 0 {
+                (link, Origin::from_matrix(&pose))
+            } else {
+                let name = geom
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("{}_geom{}", link_name, i));
+                let child = self.add_link(
+                    Link {
+                        link_name: name.clone(),
+                        ..Default::default()
+                    },
+                    location,
+                )?;
+                self.add_fixed_joint(format!("{}_fixed", name), link, child, &pose, location)?;
+                (child, Origin::default())
+            };
+            let link = &mut self.robot.links[target];
+            link.visual = VisualBody {
+                origin,
+                transform: origin.into(),
+                shape: Some(geom.shape.clone()),
+                geometry: geom.polyhedron.clone(),
+                material: Some(geom.material),
+            };
+            link.collision = CollisionBody {
+                origin,
+                transform: origin.into(),
+                shape: Some(geom.shape),
+                geometry: geom.polyhedron,
+            };
+        }
+        Ok(())
+    }
+
+    fn read_inertial(
+        &self,
+        inertial: &XmlElement,
+    ) -> Result<(f32, glm::Vec3, glm::Mat3), UrdfError> {
+        let frame = self.frame(inertial)?;
+        let mass = inertial
+            .f32("mass")?
+            .ok_or_else(|| UrdfError::MissingAttribute {
+                attribute: "mass".into(),
+                location: inertial.location.clone(),
+            })?;
+        let tensor = if let Some(d) = inertial.vec3("diaginertia")? {
+            let r = glm::mat4_to_mat3(&frame);
+            r * glm::Mat3::from_diagonal(&d) * r.transpose()
+        } else if let Some([ixx, iyy, izz, ixy, ixz, iyz]) =
+            inertial.floats_n::<6>("fullinertia")?
+        {
+            let r = glm::mat4_to_mat3(&frame);
+            r * glm::mat3(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz) * r.transpose()
+        } else {
+            return Err(UrdfError::MissingAttribute {
+                attribute: "diaginertia|fullinertia".into(),
+                location: inertial.location.clone(),
+            });
+        };
+        let com = glm::vec3(frame[(0, 3)], frame[(1, 3)], frame[(2, 3)]);
+        Ok((mass, com, tensor))
+    }
+
+    fn add_body(
+        &mut self,
+        body: &XmlElement,
+        parent: usize,
+        // where the parent's link frame is in the parent body
+        parent_anchor: glm::Vec3,
+        childclass: &str,
+    ) -> Result<(), UrdfError> {
+        let childclass = body.attr("childclass").unwrap_or(childclass).to_owned();
+        let body_name = match body.attr("name") {
+            Some(name) => name.to_owned(),
+            None => format!("body{}", self.robot.links.len()),
+        };
+        let location = &body.location;
+
+        // the joints of the body in order, with the anchor of each
+        let mut joints: Vec<BodyJoint> = Vec::new();
+        let joint_elements = body
+            .children
+            .iter()
+            .filter(|c| c.name == "joint" || c.name == "freejoint");
+        for (index, element) in joint_elements.enumerate() {
+            let element = match element.name.as_str() {
+                "joint" => self.with_defaults(element, &childclass)?,
+                "freejoint" => {
+                    let mut free = element.clone();
+                    free.attributes.push(("type".into(), "free".into()));
+                    free
+                }
+                _ => continue,
+            };
+            let name = match element.attr("name") {
+                Some(name) => name.to_owned(),
+                None => format!("{}_joint{}", body_name, index),
+            };
+            let pos = element.vec3("pos")?.unwrap_or_default();
+            let axis = glm::normalize(&element.vec3("axis")?.unwrap_or(glm::Vec3::z()));
+            let range = element.floats_n::<2>("range")?;
+            let limited = match element.attr("limited") {
+                Some("true") => true,
+                Some("false") => false,
+                Some("auto") | None => self.compiler.autolimits && range.is_some(),
+                Some(other) => return Err(element.invalid_value("limited", other)),
+            };
+            let effort = element
+                .floats_n::<2>("actuatorfrcrange")?
+                .map(|[lo, hi]| lo.abs().max(hi.abs()))
+                .unwrap_or(0.0);
+            let damping = element.f32("damping")?.unwrap_or(0.0);
+            let friction = element.f32("frictionloss")?.unwrap_or(0.0);
+            let dynamics =
+                (damping != 0.0 || friction != 0.0).then_some(JointDynamics { damping, friction });
+            // a hinge about `axis`, limited to lower..upper if the joint is limited
+            let hinge = |name: String, axis: glm::Vec3, lower: f32, upper: f32| BodyJoint {
+                name,
+                joint_type: if limited {
+                    JointType::Revolute
+                } else {
+                    JointType::Continuous
+                },
+                pos,
+                axis: Some(axis),
+                limits: limited.then_some(JointLimits {
+                    effort,
+                    velocity: 0.0,
+                    lower,
+                    upper,
+                }),
+                dynamics,
+            };
+            match element.attr("type").unwrap_or("hinge") {
+                "hinge" => {
+                    let [lower, upper] = range.unwrap_or_default().map(|a| self.angle(a));
+                    joints.push(hinge(name, axis, lower, upper));
+                }
+                "slide" => {
+                    let [lower, upper] = range.unwrap_or_default();
+                    joints.push(BodyJoint {
+                        joint_type: JointType::Prismatic,
+                        ..hinge(name, axis, lower, upper)
+                    });
+                }
+                "ball" => {
+                    // the range of a ball joint is the largest rotation from rest
+                    let max = self.angle(range.unwrap_or_default()[1]);
+                    for (suffix, axis) in [
+                        ("x", glm::Vec3::x()),
+                        ("y", glm::Vec3::y()),
+                        ("z", glm::Vec3::z()),
+                    ] {
+                        joints.push(hinge(format!("{}_{}", name, suffix), axis, -max, max));
+                    }
+                }
+                "free" => joints.push(BodyJoint {
+                    name,
+                    joint_type: JointType::Floating,
+                    pos,
+                    axis: None,
+                    limits: None,
+                    dynamics: None,
+                }),
+                other => return Err(element.invalid_value("type", other)),
+            }
+        }
+
+        // chain the joints, each through a massless link, ending in the body's link
+        let mut pose = glm::translation(&-parent_anchor) * self.frame(body)?;
+        let mut anchor = glm::Vec3::zeros();
+        let mut link = parent;
+        if joints.is_empty() {
+            let child = self.add_link(
+                Link {
+                    link_name: body_name.clone(),
+                    ..Default::default()
+                },
+                location,
+            )?;
+            self.add_fixed_joint(format!("{}_fixed", body_name), link, child, &pose, location)?;
+            link = child;
+        }
+        let n_joints = joints.len();
+        for (i, joint) in joints.into_iter().enumerate() {
+            let link_name = if i + 1 == n_joints {
+                body_name.clone()
+            } else {
+                format!("{}_link", joint.name)
+            };
+            let child = self.add_link(
+                Link {
+                    link_name,
+                    ..Default::default()
+                },
+                location,
+            )?;
+            let joint_pose = pose * glm::translation(&(joint.pos - anchor));
+            pose = glm::Mat4::identity();
+            anchor = joint.pos;
+            self.add_joint(&joint, link, child, &joint_pose, location)?;
+            link = child;
+        }
+
+        let mut geoms = Vec::new();
+        for geom in body.children_named("geom") {
+            let geom = self.with_defaults(geom, &childclass)?;
+            geoms.extend(self.read_geom(&geom)?);
+        }
+        let inertial = body.children_named("inertial").next();
+        let from_geoms = match self.compiler.inertia_from_geom {
+            InertiaFromGeom::Never => false,
+            InertiaFromGeom::Always => true,
+            InertiaFromGeom::Auto => inertial.is_none(),
+        };
+        let mass_properties = if from_geoms {
+            combine_geom_inertia(&geoms)
+        } else {
+            inertial.map(|i| self.read_inertial(i)).transpose()?
+        };
+        if let Some((mass, com, tensor)) = mass_properties {
+            self.robot.links[link].inertial = InertialBody::from_tensor(mass, com - anchor, &tensor);
+        }
+        self.attach_geoms(link, geoms, &glm::translation(&-anchor), location)?;
+
+        for child in body.children_named("body") {
+            self.add_body(child, link, anchor, &childclass)?;
+        }
+        Ok(())
+    }
+}
+
+// volume and the inertia per unit mass about the geom origin
+fn volume_and_inertia(shape: &GeometryShape, polyhedron: &Polyhedron) -> (f32, glm::Mat3) {
+    let box_inertia = |s: glm::Vec3| {
+        glm::Mat3::from_diagonal(&glm::vec3(
+            s.y * s.y + s.z * s.z,
+            s.x * s.x + s.z * s.z,
+            s.x * s.x + s.y * s.y,
+        )) / 12.0
+    };
+    match *shape {
+        GeometryShape::Sphere { radius: r } => (
+            4.0 / 3.0 * PI * r.powi(3),
+            glm::Mat3::identity() * (0.4 * r * r),
+        ),
+        GeometryShape::Box { size } => (size.x * size.y * size.z, box_inertia(size)),
+        GeometryShape::Cylinder {
+            radius: r,
+            length: l,
+        } => {
+            let side = (3.0 * r * r + l * l) / 12.0;
+            (
+                PI * r * r * l,
+                glm::Mat3::from_diagonal(&glm::vec3(side, side, 0.5 * r * r)),
+            )
+        }
+        GeometryShape::Capsule {
+            radius: r,
+            length: l,
+        } => {
+            // a cylinder and two hemispheres, weighted by their share of the volume
+            let (v_cyl, v_sph) = (PI * r * r * l, 4.0 / 3.0 * PI * r.powi(3));
+            let (w_cyl, w_sph) = (v_cyl / (v_cyl + v_sph), v_sph / (v_cyl + v_sph));
+            let side = w_cyl * (l * l / 12.0 + r * r / 4.0)
+                + w_sph * (0.4 * r * r + l * l / 4.0 + 3.0 * l * r / 8.0);
+            let axial = w_cyl * 0.5 * r * r + w_sph * 0.4 * r * r;
+            (
+                v_cyl + v_sph,
+                glm::Mat3::from_diagonal(&glm::vec3(side, side, axial)),
+            )
+        }
+        // meshes are approximated by their bounding box
+        GeometryShape::Mesh { .. } => {
+            let (mut lo, mut hi) = (glm::Vec3::repeat(f32::MAX), glm::Vec3::repeat(f32::MIN));
+            for v in &polyhedron.verts {
+                lo = glm::min2(&lo, &v.position);
+                hi = glm::max2(&hi, &v.position);
+            }
+            if polyhedron.verts.is_empty() {
+                return (0.0, glm::Mat3::zeros());
+            }
+            let size = hi - lo;
+            let centre = (hi + lo) * 0.5;
+            let shift =
+                glm::Mat3::identity() * glm::dot(&centre, &centre) - centre * centre.transpose();
+            (size.x * size.y * size.z, box_inertia(size) + shift)
+        }
+    }
+}
+
+// total mass, centre of mass and inertia about it, in body coordinates
+fn combine_geom_inertia(geoms: &[Geom]) -> Option<(f32, glm::Vec3, glm::Mat3)> {
+    let mass: f32 = geoms.iter().map(|g| g.mass).sum();
+    if geoms.is_empty() || mass <= 0.0 {
+        return None;
+    }
+    let position = |g: &Geom| glm::vec3(g.pose[(0, 3)], g.pose[(1, 3)], g.pose[(2, 3)]);
+    let com = geoms
+        .iter()
+        .map(|g| position(g) * g.mass)
+        .sum::<glm::Vec3>()
+        / mass;
+    // parallel axis theorem, moving each geom's inertia to the centre of mass
+    let tensor = geoms
+        .iter()
+        .map(|g| {
+            let r = glm::mat4_to_mat3(&g.pose);
+            let d = position(g) - com;
+            r * g.inertia * r.transpose()
+                + (glm::Mat3::identity() * glm::dot(&d, &d) - d * d.transpose()) * g.mass
+        })
+        .sum::<glm::Mat3>();
+    Some((mass, com, tensor))
+}
+
+pub(super) fn parse_mjcf(
+    s: &str,
+    resolver: &dyn ResourceResolver,
+) -> Result<RobotDescriptor, UrdfError> {
+    let root = read_tree(s, resolver)?;
+    if root.name != "mujoco" {
+        return Err(UrdfError::MissingElement {
+            element: "mujoco".into(),
+            location: root.location.clone(),
+        });
+    }
+    let mut importer = Importer {
+        resolver,
+        compiler: Compiler::default(),
+        defaults: HashMap::new(),
+        meshes: HashMap::new(),
+        asset_materials: HashMap::new(),
+        robot: RobotDescriptor {
+            name: root.attr("model").map(str::to_owned),
+            ..Default::default()
+        },
+    };
+    // settings, classes and assets apply to the whole model wherever they are
+    for compiler in root.children_named("compiler") {
+        importer.read_compiler(compiler)?;
+    }
+    for default in root.children_named("default") {
+        importer.read_defaults(default, None);
+    }
+    for asset in root.children_named("asset") {
+        importer.read_assets(asset)?;
+    }
+
+    let world = importer.add_link(
+        Link {
+            link_name: "world".into(),
+            ..Default::default()
+        },
+        &root.location,
+    )?;
+    let mut found_worldbody = false;
+    for worldbody in root.children_named("worldbody") {
+        found_worldbody = true;
+        let mut geoms = Vec::new();
+        for geom in worldbody.children_named("geom") {
+            let geom = importer.with_defaults(geom, "main")?;
+            geoms.extend(importer.read_geom(&geom)?);
+        }
+        importer.attach_geoms(world, geoms, &glm::Mat4::identity(), &worldbody.location)?;
+        for body in worldbody.children_named("body") {
+            importer.add_body(body, world, glm::Vec3::zeros(), "main")?;
+        }
+    }
+    if !found_worldbody {
+        return Err(UrdfError::MissingElement {
+            element: "worldbody".into(),
+            location: root.location.clone(),
+        });
+    }
+    importer.robot.update_kinematic_tree()?;
+    Ok(importer.robot)
+}
@@ -0,0 +1,906 @@
+// Loads SDFormat (1.6 and later) worlds into a World.
+//
+// A model that is not static becomes a RobotDescriptor. SDF poses every link
+// in the model frame and every joint in the frame of its child link, while
+// URDF puts the frame of a child link at its joint; so a link's frame here is
+// the frame of its parent joint, and its visuals, collisions and inertial are
+// offset to match. URDF links have one visual and one collision, the others
+// go on links fixed to it. Joints to "world" get a root link "world", links
+// with no parent joint besides the first are free bodies, attached to it by
+// floating joints. Static models become environment, one StaticBody per link.
+// <include> takes a URDF, xacro or SDF model file, or a model directory
+// holding a model.config.
+use super::tree::{read_tree, XmlElement};
+use super::xacro::Xacro;
+use super::{
+    primitive, resolve_mesh_uri, CollisionBody, GeometryShape, InertialBody, Joint, JointDynamics,
+    JointLimits, JointType, Link, Material, Origin, RobotDescriptor, UrdfError, VisualBody,
+};
+use crate::geometry::{Polyhedron, Transform, TriMesh};
+use crate::kinematics::TopologyError;
+use crate::resource::{RelativeResolver, ResourceResolver};
+use crate::world::{
+    LightKind, ModelInstance, StaticBody, StaticShape, World, WorldLight, WorldShape,
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const MIN_VERSION: (u32, u32) = (1, 6);
+const MAX_INCLUDE_DEPTH: usize = 16;
+// joint limits at or beyond this are SDF's way of saying there are none
+const UNLIMITED: f32 = 1e16;
+
+fn translation_of(pose: &glm::Mat4) -> glm::Vec3 {
+    glm::vec3(pose[(0, 3)], pose[(1, 3)], pose[(2, 3)])
+}
+
+fn check_version(root: &XmlElement) -> Result<(), UrdfError> {
+    let version = root.required_attr("version")?;
+    let parsed = version
+        .split_once('.')
+        .and_then(|(major, minor)| Some((major.parse().ok()?, minor.parse().ok()?)));
+    match parsed {
+        Some(v) if v >= MIN_VERSION => Ok(()),
+        _ => Err(root.invalid_value("version", version)),
+    }
+}
+
+// the <pose> of an element and the frame it is given in (None for the default)
+fn read_pose(element: &XmlElement) -> Result<(glm::Mat4, Option<&str>), UrdfError> {
+    let Some(pose) = element.child("pose") else {
+        return Ok((glm::Mat4::identity(), None));
+    };
+    let v = pose.text_floats()?;
+    let degrees = pose.bool("degrees")?.unwrap_or(false);
+    let matrix = match (pose.attr("rotation_format").unwrap_or("euler_rpy"), v.len()) {
+        ("euler_rpy", 6) => {
+            let rpy = glm::vec3(v[3], v[4], v[5]);
+            let rpy = if degrees {
+                rpy.map(f32::to_radians)
+            } else {
+                rpy
+            };
+            Origin::new(glm::vec3(v[0], v[1], v[2]), Some(rpy)).matrix()
+        }
+        ("quat_xyzw", 7) => {
+            let q = glm::quat_normalize(&glm::quat(v[3], v[4], v[5], v[6]));
+            glm::translation(&glm::vec3(v[0], v[1], v[2])) * glm::quat_to_mat4(&q)
+        }
+        _ => return Err(pose.invalid_value("pose", &pose.text)),
+    };
+    let relative_to = pose.attr("relative_to").filter(|f| !f.is_empty());
+    Ok((matrix, relative_to))
+}
+
+// visuals, collisions and inertials are posed in their link and nowhere else
+fn read_local_pose(element: &XmlElement, link: &str) -> Result<glm::Mat4, UrdfError> {
+    match read_pose(element)? {
+        (pose, None) => Ok(pose),
+        (pose, Some(frame)) if frame == link => Ok(pose),
+        (_, Some(frame)) => Err(UrdfError::UnsupportedElement {
+            element: format!("pose relative_to=\"{}\"", frame),
+            location: element.location.clone(),
+        }),
+    }
+}
+
+fn unknown_frame(element: &XmlElement, frame: &str) -> UrdfError {
+    element.invalid_value("relative_to", frame)
+}
+
+fn plane_polyhedron(normal: &glm::Vec3, size: &glm::Vec2) -> Polyhedron {
+    let n = glm::normalize(normal);
+    let helper = if n.x.abs() < 0.9 {
+        glm::Vec3::x()
+    } else {
+        glm::Vec3::y()
+    };
+    let u = glm::normalize(&glm::cross(&helper, &n)) * (size.x * 0.5);
+    let v = glm::normalize(&glm::cross(&n, &u)) * (size.y * 0.5);
+    let mut mesh = TriMesh::default();
+    mesh.add_rectangle([-u - v, u - v, u + v, -u + v]);
+    Polyhedron::from(mesh)
+}
+
+// the colour of a <visual>, from its material's diffuse or ambient colour
+fn read_rgba(visual: &XmlElement) -> Result<Option<glm::Vec4>, UrdfError> {
+    let Some(material) = visual.child("material") else {
+        return Ok(None);
+    };
+    let diffuse = material.child_floats_n::<4>("diffuse")?;
+    let ambient = material.child_floats_n::<4>("ambient")?;
+    Ok(diffuse.or(ambient).map(glm::Vec4::from))
+}
+
+fn read_inertial(inertial: &XmlElement, link: &str) -> Result<InertialBody, UrdfError> {
+    let pose = read_local_pose(inertial, link)?;
+    let mass = inertial.child_f32("mass")?.unwrap_or(1.0);
+    let tensor = match inertial.child("inertia") {
+        Some(i) => {
+            let value = |name: &str, default: f32| -> Result<f32, UrdfError> {
+                Ok(i.child_f32(name)?.unwrap_or(default))
+            };
+            let (ixx, iyy, izz) = (value("ixx", 1.0)?, value("iyy", 1.0)?, value("izz", 1.0)?);
+            let (ixy, ixz, iyz) = (value("ixy", 0.0)?, value("ixz", 0.0)?, value("iyz", 0.0)?);
+            glm::mat3(ixx, ixy, ixz, ixy, iyy, iyz, ixz, iyz, izz)
+        }
+        None => glm::Mat3::identity(),
+    };
+    let r = glm::mat4_to_mat3(&pose);
+    Ok(InertialBody::from_tensor(
+        mass,
+        translation_of(&pose),
+        &(r * tensor * r.transpose()),
+    ))
+}
+
+// a <visual> or <collision> read from a link
+struct Shape {
+    name: String,
+    pose: glm::Mat4,
+    shape: WorldShape,
+    geometry: Polyhedron,
+    rgba: Option<glm::Vec4>,
+}
+
+// a joint read from a model, posed in the model frame
+struct SdfJoint {
+    name: String,
+    joint_type: JointType,
+    parent: Option<usize>, // None for "world"
+    child: usize,
+    pose: glm::Mat4,
+    axis: glm::Vec3,
+    limits: Option<JointLimits>,
+    dynamics: Option<JointDynamics>,
+}
+
+enum Model {
+    Robot(Box<ModelInstance>),
+    Static(Vec<StaticBody>),
+}
+
+struct SdfLoader<'a> {
+    resolver: &'a dyn ResourceResolver,
+    depth: usize,
+}
+
+impl SdfLoader<'_> {
+    fn read_geometry(&self, geometry: &XmlElement) -> Result<(WorldShape, Polyhedron), UrdfError> {
+        let shape = geometry
+            .children
+            .first()
+            .ok_or_else(|| UrdfError::MissingElement {
+                element: "box|cylinder|capsule|sphere|mesh|plane".into(),
+                location: geometry.location.clone(),
+            })?;
+        let required = |name: &str| {
+            shape
+                .child_f32(name)?
+                .ok_or_else(|| UrdfError::MissingElement {
+                    element: name.to_owned(),
+                    location: shape.location.clone(),
+                })
+        };
+        let solid = match shape.name.as_str() {
+            "box" => GeometryShape::Box {
+                size: shape
+                    .child_vec3("size")?
+                    .unwrap_or(glm::vec3(1.0, 1.0, 1.0)),
+            },
+            "sphere" => GeometryShape::Sphere {
+                radius: required("radius")?,
+            },
+            "cylinder" => GeometryShape::Cylinder {
+                radius: required("radius")?,
+                length: required("length")?,
+            },
+            "capsule" => GeometryShape::Capsule {
+                radius: required("radius")?,
+                length: required("length")?,
+            },
+            "mesh" => {
+                let filename = shape.required_child("uri")?.text.clone();
+                let scale = shape.child_vec3("scale")?;
+                let path = resolve_mesh_uri(self.resolver, &filename, || shape.location.clone())?;
+                let mut geometry = Polyhedron::from(path.to_string_lossy().into_owned());
+                if let Some(scale) = scale {
+                    geometry.scale_xyz(scale);
+                }
+                return Ok((
+                    WorldShape::Solid(GeometryShape::Mesh { filename, scale }),
+                    geometry,
+                ));
+            }
+            "plane" => {
+                let normal = shape.child_vec3("normal")?.unwrap_or(glm::Vec3::z());
+                let size = shape
+                    .child_floats_n::<2>("size")?
+                    .map(glm::Vec2::from)
+                    .unwrap_or(glm::vec2(1.0, 1.0));
+                let geometry = plane_polyhedron(&normal, &size);
+                return Ok((WorldShape::Plane { normal, size }, geometry));
+            }
+            other => {
+                return Err(UrdfError::UnsupportedElement {
+                    element: other.to_owned(),
+                    location: shape.location.clone(),
+                })
+            }
+        };
+        let (solid, geometry) = primitive(solid);
+        Ok((WorldShape::Solid(solid), geometry))
+    }
+
+    // the <visual> or <collision> children of a link
+    fn read_shapes(&self, link: &XmlElement, kind: &str) -> Result<Vec<Shape>, UrdfError> {
+        let link_name = link.required_attr("name")?;
+        link.children_named(kind)
+            .enumerate()
+            .map(|(i, element)| {
+                let (shape, mut geometry) =
+                    self.read_geometry(element.required_child("geometry")?)?;
+                let rgba = if kind == "visual" {
+                    read_rgba(element)?
+                } else {
+                    None
+                };
+                if let Some(rgba) = rgba {
+                    geometry.set_color(rgba.xyz());
+                }
+                Ok(Shape {
+                    name: element
+                        .attr("name")
+                        .map(str::to_owned)
+                        .unwrap_or_else(|| format!("{}_{}", kind, i)),
+                    pose: read_local_pose(element, link_name)?,
+                    shape,
+                    geometry,
+                    rgba,
+                })
+            })
+            .collect()
+    }
+
+    // link poses in the model frame, in document order
+    fn link_poses<'e>(
+        &self,
+        model: &'e XmlElement,
+    ) -> Result<Vec<(&'e XmlElement, String, glm::Mat4)>, UrdfError> {
+        let mut links: Vec<(&XmlElement, String, glm::Mat4)> = Vec::new();
+        for link in model.children_named("link") {
+            let name = link.required_attr("name")?.to_owned();
+            if links.iter().any(|(_, n, _)| *n == name) {
+                return Err(UrdfError::DuplicateName {
+                    kind: "link",
+                    name,
+                    location: link.location.clone(),
+                });
+            }
+            let (pose, frame) = read_pose(link)?;
+            let frame = match frame {
+                None | Some("__model__") => glm::Mat4::identity(),
+                Some(frame) => links
+                    .iter()
+                    .find(|(_, n, _)| n == frame)
+                    .map(|(_, _, p)| *p)
+                    .ok_or_else(|| unknown_frame(link, frame))?,
+            };
+            links.push((link, name, frame * pose));
+        }
+        Ok(links)
+    }
+
+    fn read_static_model(
+        &self,
+        model: &XmlElement,
+        name: &str,
+        pose: &glm::Mat4,
+    ) -> Result<Vec<StaticBody>, UrdfError> {
+        let to_static = |s: Shape| StaticShape {
+            name: s.name,
+            pose: Transform { tmatrix: s.pose },
+            shape: s.shape,
+            geometry: s.geometry,
+            rgba: s.rgba,
+        };
+        self.link_poses(model)?
+            .into_iter()
+            .map(|(link, link_name, link_pose)| {
+                Ok(StaticBody {
+                    name: format!("{}::{}", name, link_name),
+                    pose: Transform {
+                        tmatrix: pose * link_pose,
+                    },
+                    visuals: self
+                        .read_shapes(link, "visual")?
+                        .into_iter()
+                        .map(to_static)
+                        .collect(),
+                    collisions: self
+                        .read_shapes(link, "collision")?
+                        .into_iter()
+                        .map(to_static)
+                        .collect(),
+                })
+            })
+            .collect()
+    }
+
+    fn read_joint(
+        &self,
+        joint: &XmlElement,
+        links: &[(&XmlElement, String, glm::Mat4)],
+    ) -> Result<SdfJoint, UrdfError> {
+        let name = joint.required_attr("name")?.to_owned();
+        let find_link = |element: &str| {
+            let link = &joint.required_child(element)?.text;
+            links
+                .iter()
+                .position(|(_, n, _)| n == link)
+                .ok_or_else(|| UrdfError::UnknownLink {
+                    link: link.clone(),
+                    location: joint.location.clone(),
+                })
+        };
+        let parent = match joint.required_child("parent")?.text.as_str() {
+            "world" => None,
+            _ => Some(find_link("parent")?),
+        };
+        let child = find_link("child")?;
+        // a frame of the model: itself, one of its links, or the joint's child
+        let frame = |frame: Option<&str>| match frame {
+            None => Ok(links[child].2),
+            Some("__model__") => Ok(glm::Mat4::identity()),
+            Some(frame) => links
+                .iter()
+                .find(|(_, n, _)| n == frame)
+                .map(|(_, _, p)| *p)
+                .ok_or_else(|| unknown_frame(joint, frame)),
+        };
+        let (pose, relative_to) = read_pose(joint)?;
+        let pose = frame(relative_to)? * pose;
+
+        let axis_element = joint.child("axis");
+        let axis = match axis_element {
+            Some(axis) => {
+                let xyz = axis.child_vec3("xyz")?.unwrap_or(glm::Vec3::z());
+                let expressed_in = axis.child("xyz").and_then(|x| x.attr("expressed_in"));
+                let in_model = axis.child_bool("use_parent_model_frame")?.unwrap_or(false);
+                let frame = match expressed_in {
+                    _ if in_model => glm::Mat4::identity(),
+                    None => pose,
+                    Some(f) => frame(Some(f))?,
+                };
+                glm::normalize(&(glm::mat4_to_mat3(&frame) * xyz))
+            }
+            None => glm::mat4_to_mat3(&pose) * glm::Vec3::z(),
+        };
+        let limit = axis_element.and_then(|a| a.child("limit"));
+        let value = |name: &str| -> Result<Option<f32>, UrdfError> {
+            Ok(match limit {
+                Some(limit) => limit.child_f32(name)?,
+                None => None,
+            })
+        };
+        let (lower, upper) = (value("lower")?, value("upper")?);
+        let bounded = |v: Option<f32>| v.filter(|v| v.abs() < UNLIMITED);
+        let limited = bounded(lower).is_some() && bounded(upper).is_some();
+        let joint_type = match joint.required_attr("type")? {
+            "revolute" if limited => JointType::Revolute,
+            "revolute" | "continuous" => JointType::Continuous,
+            "prismatic" => JointType::Prismatic,
+            "fixed" => JointType::Fixed,
+            other => {
+                return Err(UrdfError::UnsupportedElement {
+                    element: format!("joint type=\"{}\"", other),
+                    location: joint.location.clone(),
+                })
+            }
+        };
+        // SDF marks missing effort and velocity limits with -1
+        let positive = |v: Option<f32>| match v {
+            Some(v) if v >= 0.0 => v,
+            _ => f32::INFINITY,
+        };
+        let (effort, velocity) = (value("effort")?, value("velocity")?);
+        let limits = limit.map(|_| JointLimits {
+            effort: positive(effort),
+            velocity: positive(velocity),
+            lower: bounded(lower).unwrap_or(0.0),
+            upper: bounded(upper).unwrap_or(0.0),
+        });
+        let dynamics = match axis_element.and_then(|a| a.child("dynamics")) {
+            Some(d) => Some(JointDynamics {
+                damping: d.child_f32("damping")?.unwrap_or(0.0),
+                friction: d.child_f32("friction")?.unwrap_or(0.0),
+            }),
+            None => None,
+        };
+        Ok(SdfJoint {
+            name,
+            joint_type,
+            parent,
+            child,
+            pose,
+            axis,
+            limits,
+            dynamics,
+        })
+    }
+
+    // see the top of the file for how links and joints are laid out
+    fn read_robot_model(
+        &self,
+        model: &XmlElement,
+        name: &str,
+        pose: &glm::Mat4,
+        is_static: bool,
+    ) -> Result<ModelInstance, UrdfError> {
+        let links = self.link_poses(model)?;
+        let joints = model
+            .children_named("joint")
+            .map(|j| self.read_joint(j, &links))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut parent_joint: Vec<Option<usize>> = vec![None; links.len()];
+        for (i, (joint, element)) in joints.iter().zip(model.children_named("joint")).enumerate() {
+            if joints[..i].iter().any(|j| j.name == joint.name) {
+                return Err(UrdfError::DuplicateName {
+                    kind: "joint",
+                    name: joint.name.clone(),
+                    location: element.location.clone(),
+                });
+            }
+            if parent_joint[joint.child].replace(i).is_some() {
+                let joints = joints
+                    .iter()
+                    .filter(|other| other.child == joint.child)
+                    .map(|other| other.name.clone())
+                    .collect();
+                return Err(TopologyError::MultipleParents {
+                    link: links[joint.child].1.clone(),
+                    joints,
+                }
+                .into());
+            }
+        }
+        if links.is_empty() {
+            return Err(UrdfError::MissingElement {
+                element: "link".into(),
+                location: model.location.clone(),
+            });
+        }
+        // where each link's frame is in the model
+        let frames: Vec<glm::Mat4> = (0..links.len())
+            .map(|l| parent_joint[l].map_or(links[l].2, |j| joints[j].pose))
+            .collect();
+
+        let mut robot = RobotDescriptor {
+            name: Some(name.to_owned()),
+            ..Default::default()
+        };
+        let mut index: Vec<usize> = vec![0; links.len()];
+        let has_world = joints.iter().any(|j| j.parent.is_none());
+        if has_world {
+            robot.links.push(Link {
+                link_name: "world".into(),
+                ..Default::default()
+            });
+        }
+        // roots first so that the first link is the base of the tree
+        let order = (0..links.len())
+            .filter(|&l| parent_joint[l].is_none())
+            .chain((0..links.len()).filter(|&l| parent_joint[l].is_some()));
+        for l in order {
+            index[l] = robot.links.len();
+            robot.links.push(Link {
+                link_name: links[l].1.clone(),
+                ..Default::default()
+            });
+        }
+        let root = if has_world {
+            None
+        } else {
+            (0..links.len()).find(|&l| parent_joint[l].is_none())
+        };
+        let frame_of = |link: Option<usize>| link.map_or(glm::Mat4::identity(), |l| frames[l]);
+        let origin_between = |parent: Option<usize>, child: usize| {
+            Origin::from_matrix(&(glm::inverse(&frame_of(parent)) * frames[child]))
+        };
+
+        for joint in &joints {
+            let origin = origin_between(joint.parent, joint.child);
+            let axis = glm::mat4_to_mat3(&frames[joint.child]).transpose() * joint.axis;
+            robot.joints.push(Joint {
+                joint_name: joint.name.clone(),
+                joint_type: joint.joint_type,
+                parent: joint.parent.map_or(0, |p| index[p]),
+                child: index[joint.child],
+                origin,
+                transform: origin.into(),
+                axis: (joint.joint_type != JointType::Fixed).then_some(axis),
+                limits: joint.limits,
+                dynamics: joint.dynamics,
+                mimic: None,
+            });
+        }
+        for l in (0..links.len()).filter(|&l| parent_joint[l].is_none() && Some(l) != root) {
+            let base = if has_world { None } else { root };
+            let origin = origin_between(base, l);
+            robot.joints.push(Joint {
+                joint_name: format!("{}_floating", links[l].1),
+                joint_type: JointType::Floating,
+                parent: base.map_or(0, |b| index[b]),
+                child: index[l],
+                origin,
+                transform: origin.into(),
+                axis: None,
+                limits: None,
+                dynamics: None,
+                mimic: None,
+            });
+        }
+
+        for (l, (link, link_name, link_pose)) in links.iter().enumerate() {
+            // SDF link coordinates to the coordinates of the link frame
+            let offset = glm::inverse(&frames[l]) * link_pose;
+            let target = index[l];
+            if let Some(inertial) = link.child("inertial") {
+                let mut inertial = read_inertial(inertial, link_name)?;
+                let r = glm::mat4_to_mat3(&offset);
+                let tensor = glm::mat3(
+                    inertial.ixx,
+                    inertial.ixy,
+                    inertial.ixz,
+                    inertial.ixy,
+                    inertial.iyy,
+                    inertial.iyz,
+                    inertial.ixz,
+                    inertial.iyz,
+                    inertial.izz,
+                );
+                let com = translation_of(&(offset * glm::translation(&inertial.origin.xyz)));
+                inertial =
+                    InertialBody::from_tensor(inertial.mass, com, &(r * tensor * r.transpose()));
+                robot.links[target].inertial = inertial;
+            }
+            for (i, visual) in self.read_shapes(link, "visual")?.into_iter().enumerate() {
+                let WorldShape::Solid(shape) = visual.shape else {
+                    continue;
+                };
+                let material = visual.rgba.map(|rgba| add_material(&mut robot, rgba));
+                let (holder, origin) =
+                    attach_point(&mut robot, target, i, &visual.name, &(offset * visual.pose));
+                robot.links[holder].visual = VisualBody {
+                    origin,
+                    transform: origin.into(),
+                    shape: Some(shape),
+                    geometry: visual.geometry,
+                    material,
+                };
+            }
+            for (i, collision) in self.read_shapes(link, "collision")?.into_iter().enumerate() {
+                let WorldShape::Solid(shape) = collision.shape else {
+                    continue;
+                };
+                let (holder, origin) = attach_point(
+                    &mut robot,
+                    target,
+                    i,
+                    &collision.name,
+                    &(offset * collision.pose),
+                );
+                robot.links[holder].collision = CollisionBody {
+                    origin,
+                    transform: origin.into(),
+                    shape: Some(shape),
+                    geometry: collision.geometry,
+                };
+            }
+        }
+        robot.update_kinematic_tree()?;
+        Ok(ModelInstance {
+            name: name.to_owned(),
+            pose: Transform {
+                tmatrix: pose * frame_of(root),
+            },
+            robot,
+            is_static,
+        })
+    }
+
+    fn read_model(
+        &self,
+        model: &XmlElement,
+        name: &str,
+        pose: &glm::Mat4,
+        is_static: Option<bool>,
+    ) -> Result<Model, UrdfError> {
+        if let Some(nested) = model
+            .children
+            .iter()
+            .find(|c| c.name == "model" || c.name == "include")
+        {
+            return Err(UrdfError::UnsupportedElement {
+                element: format!("{} inside a model", nested.name),
+                location: nested.location.clone(),
+            });
+        }
+        let is_static = match is_static {
+            Some(s) => s,
+            None => model.child_bool("static")?.unwrap_or(false),
+        };
+        if is_static {
+            Ok(Model::Static(self.read_static_model(model, name, pose)?))
+        } else {
+            let instance = self.read_robot_model(model, name, pose, false)?;
+            Ok(Model::Robot(Box::new(instance)))
+        }
+    }
+
+    // the file an <include> refers to: a model file or a model directory
+    fn resolve_include(&self, include: &XmlElement, uri: &str) -> Result<PathBuf, UrdfError> {
+        let unresolved = || UrdfError::UnresolvedResource {
+            uri: uri.to_owned(),
+            location: include.location.clone(),
+        };
+        if let Some(path) = self.resolver.resolve(uri) {
+            return Ok(path);
+        }
+        let dir = uri.trim_end_matches('/');
+        if let Some(config) = self.resolver.resolve(&format!("{}/model.config", dir)) {
+            let s = read_file(&config)?;
+            let config_tree = read_tree(&s, self.resolver)?;
+            let sdf = config_tree.child("sdf").ok_or_else(unresolved)?;
+            let path = config.parent().unwrap_or(Path::new("")).join(&sdf.text);
+            return path.is_file().then_some(path).ok_or_else(unresolved);
+        }
+        ["model.sdf", "model.urdf"]
+            .iter()
+            .find_map(|file| self.resolver.resolve(&format!("{}/{}", dir, file)))
+            .ok_or_else(unresolved)
+    }
+
+    fn read_include(
+        &self,
+        include: &XmlElement,
+        pose: Option<glm::Mat4>,
+    ) -> Result<(String, glm::Mat4, Model), UrdfError> {
+        if self.depth >= MAX_INCLUDE_DEPTH {
+            return Err(UrdfError::UnsupportedElement {
+                element: format!("include nested more than {} deep", MAX_INCLUDE_DEPTH),
+                location: include.location.clone(),
+            });
+        }
+        let uri = &include.required_child("uri")?.text;
+        let path = self.resolve_include(include, uri)?;
+        // URIs in the included file are looked up next to it first
+        let relative = RelativeResolver::for_file(&path);
+        let resolver = |uri: &str| relative.resolve(uri).or_else(|| self.resolver.resolve(uri));
+        let s = if path.to_string_lossy().ends_with(".xacro") {
+            Xacro::new().expand_file(&path)?
+        } else {
+            read_file(&path)?
+        };
+        let name = include.child_text("name");
+        let is_static = include.child_bool("static")?;
+        let root = read_tree(&s, &resolver)?;
+        if root.name == "robot" {
+            let robot = RobotDescriptor::from_str_with_resolver(&s, &resolver)?;
+            let name = name
+                .map(str::to_owned)
+                .or_else(|| robot.name.clone())
+                .unwrap_or_else(|| uri.clone());
+            let pose = pose.unwrap_or_else(glm::Mat4::identity);
+            let instance = ModelInstance {
+                name: name.clone(),
+                pose: Transform { tmatrix: pose },
+                robot,
+                is_static: is_static.unwrap_or(false),
+            };
+            return Ok((name, pose, Model::Robot(Box::new(instance))));
+        }
+        if root.name != "sdf" {
+            return Err(UrdfError::MissingElement {
+                element: "sdf|robot".into(),
+                location: root.location.clone(),
+            });
+        }
+        check_version(&root)?;
+        let model = root.required_child("model")?;
+        let name = match name {
+            Some(name) => name.to_owned(),
+            None => model.required_attr("name")?.to_owned(),
+        };
+        let pose = match pose {
+            Some(pose) => pose,
+            None => read_pose(model)?.0,
+        };
+        let loader = SdfLoader {
+            resolver: &resolver,
+            depth: self.depth + 1,
+        };
+        let model = loader.read_model(model, &name, &pose, is_static)?;
+        Ok((name, pose, model))
+    }
+
+    fn read_light(&self, light: &XmlElement, pose: glm::Mat4) -> Result<WorldLight, UrdfError> {
+        let kind = match light.attr("type").unwrap_or("point") {
+            "directional" => LightKind::Directional,
+            "point" => LightKind::Point,
+            "spot" => {
+                let spot = light.child("spot");
+                let value = |name: &str, default: f32| -> Result<f32, UrdfError> {
+                    Ok(match spot {
+                        Some(spot) => spot.child_f32(name)?.unwrap_or(default),
+                        None => default,
+                    })
+                };
+                LightKind::Spot {
+                    inner_angle: value("inner_angle", 0.0)?,
+                    outer_angle: value("outer_angle", 0.0)?,
+                    falloff: value("falloff", 0.0)?,
+                }
+            }
+            other => return Err(light.invalid_value("type", other)),
+        };
+        let range = match light.child("attenuation") {
+            Some(attenuation) => attenuation.child_f32("range")?.unwrap_or(10.0),
+            None => 10.0,
+        };
+        Ok(WorldLight {
+            name: light.required_attr("name")?.to_owned(),
+            kind,
+            pose: Transform { tmatrix: pose },
+            direction: light
+                .child_vec3("direction")?
+                .unwrap_or(glm::vec3(0.0, 0.0, -1.0)),
+            diffuse: light
+                .child_floats_n::<4>("diffuse")?
+                .map_or(glm::vec4(1.0, 1.0, 1.0, 1.0), glm::Vec4::from),
+            specular: light
+                .child_floats_n::<4>("specular")?
+                .map_or(glm::vec4(0.1, 0.1, 0.1, 1.0), glm::Vec4::from),
+            range,
+            cast_shadows: light.child_bool("cast_shadows")?.unwrap_or(false),
+        })
+    }
+
+    // models, includes and lights of a <world>, or of an <sdf> holding models directly
+    fn read_world(&self, element: &XmlElement, world: &mut World) -> Result<(), UrdfError> {
+        let mut frames: HashMap<String, glm::Mat4> = HashMap::new();
+        let world_pose = |element: &XmlElement,
+                          frames: &HashMap<String, glm::Mat4>|
+         -> Result<Option<glm::Mat4>, UrdfError> {
+            if element.child("pose").is_none() {
+                return Ok(None);
+            }
+            let (pose, frame) = read_pose(element)?;
+            let frame = match frame {
+                None | Some("world") => glm::Mat4::identity(),
+                Some(frame) => *frames
+                    .get(frame)
+                    .ok_or_else(|| unknown_frame(element, frame))?,
+            };
+            Ok(Some(frame * pose))
+        };
+        for child in &element.children {
+            let (name, pose, model) = match child.name.as_str() {
+                "model" => {
+                    let name = child.required_attr("name")?.to_owned();
+                    let pose = world_pose(child, &frames)?.unwrap_or_else(glm::Mat4::identity);
+                    let model = self.read_model(child, &name, &pose, None)?;
+                    (name, pose, model)
+                }
+                "include" => self.read_include(child, world_pose(child, &frames)?)?,
+                "light" => {
+                    let pose = world_pose(child, &frames)?.unwrap_or_else(glm::Mat4::identity);
+                    world.lights.push(self.read_light(child, pose)?);
+                    continue;
+                }
+                _ => continue,
+            };
+            if frames.insert(name.clone(), pose).is_some() {
+                return Err(UrdfError::DuplicateName {
+                    kind: "model",
+                    name,
+                    location: child.location.clone(),
+                });
+            }
+            match model {
+                Model::Robot(instance) => world.models.push(*instance),
+                Model::Static(bodies) => world.environment.extend(bodies),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, UrdfError> {
+    std::fs::read_to_string(path).map_err(|e| UrdfError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+fn add_material(robot: &mut RobotDescriptor, rgba: glm::Vec4) -> String {
+    let name = format!("rgba({} {} {} {})", rgba.x, rgba.y, rgba.z, rgba.w);
+    if !robot.materials.iter().any(|m| m.name == name) {
+        robot.materials.push(Material {
+            name: name.clone(),
+            rgba,
+        });
+    }
+    name
+}
+
+// the link that holds the i-th visual or collision of `link` and its origin
+// there: the link itself for the first, a new link fixed to it for the rest
+fn attach_point(
+    robot: &mut RobotDescriptor,
+    link: usize,
+    i: usize,
+    name: &str,
+    pose: &glm::Mat4,
+) -> (usize, Origin) {
+    if i == 0 {
+        return (link, Origin::from_matrix(pose));
+    }
+    let base = format!("{}_{}", robot.links[link].link_name, name);
+    let mut link_name = base.clone();
+    let mut n = 1;
+    while robot.links.iter().any(|l| l.link_name == link_name) {
+        n += 1;
+        link_name = format!("{}_{}", base, n);
+    }
+    let child = robot.links.len();
+    robot.links.push(Link {
+        link_name: link_name.clone(),
+        ..Default::default()
+    });
+    let origin = Origin::from_matrix(pose);
+    robot.joints.push(Joint {
+        joint_name: format!("{}_fixed", link_name),
+        joint_type: JointType::Fixed,
+        parent: link,
+        child,
+        origin,
+        transform: origin.into(),
+        axis: None,
+        limits: None,
+        dynamics: None,
+        mimic: None,
+    });
+    (child, Origin::default())
+}
+
+pub(crate) fn parse_sdf(s: &str, resolver: &dyn ResourceResolver) -> Result<World, UrdfError> {
+    let root = read_tree(s, resolver)?;
+    if root.name != "sdf" {
+        return Err(UrdfError::MissingElement {
+            element: "sdf".into(),
+            location: root.location.clone(),
+        });
+    }
+    check_version(&root)?;
+    let loader = SdfLoader { resolver, depth: 0 };
+    let mut world = World::default();
+    match root.child("world") {
+        Some(element) => {
+            world.name = element.attr("name").map(str::to_owned);
+            let gravity = match element.child("gravity") {
+                Some(_) => element.child_vec3("gravity")?,
+                None => match element.child("physics") {
+                    Some(physics) => physics.child_vec3("gravity")?,
+                    None => None,
+                },
+            };
+            if let Some(gravity) = gravity {
+                world.gravity = gravity;
+            }
+            loader.read_world(element, &mut world)?;
+        }
+        None => loader.read_world(&root, &mut world)?,
+    }
+    Ok(world)
+}
@@ -0,0 +1,160 @@
+// The whole document as a tree of elements, for the formats that are easier
+// to read with random access than as a stream of events (MJCF refers to
+// <default> and <asset> from anywhere, SDF keeps its values in child elements).
+use super::{SourceLocation, UrdfError, UrdfReader};
+use crate::resource::ResourceResolver;
+use xml::reader::XmlEvent;
+
+#[derive(Debug, Clone)]
+pub(super) struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<XmlElement>,
+    // the character data directly inside the element, trimmed
+    pub text: String,
+    pub location: SourceLocation,
+}
+
+impl XmlElement {
+    pub fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+    pub fn required_attr(&self, name: &str) -> Result<&str, UrdfError> {
+        self.attr(name).ok_or_else(|| UrdfError::MissingAttribute {
+            attribute: name.to_owned(),
+            location: self.location.clone(),
+        })
+    }
+    pub fn child(&self, name: &str) -> Option<&XmlElement> {
+        self.children.iter().find(|c| c.name == name)
+    }
+    pub fn required_child(&self, name: &str) -> Result<&XmlElement, UrdfError> {
+        self.child(name).ok_or_else(|| UrdfError::MissingElement {
+            element: name.to_owned(),
+            location: self.location.clone(),
+        })
+    }
+    pub fn children_named<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a XmlElement> {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+    pub fn invalid_value(&self, attribute: &str, value: &str) -> UrdfError {
+        UrdfError::InvalidValue {
+            attribute: attribute.to_owned(),
+            value: value.to_owned(),
+            location: self.location.clone(),
+        }
+    }
+    fn parse_floats(&self, attribute: &str, value: &str) -> Result<Vec<f32>, UrdfError> {
+        value
+            .split_whitespace()
+            .map(|v| {
+                v.parse::<f32>().map_err(|_| UrdfError::InvalidNumber {
+                    attribute: attribute.to_owned(),
+                    value: value.to_owned(),
+                    location: self.location.clone(),
+                })
+            })
+            .collect()
+    }
+    pub fn floats(&self, attribute: &str) -> Result<Option<Vec<f32>>, UrdfError> {
+        self.attr(attribute)
+            .map(|value| self.parse_floats(attribute, value))
+            .transpose()
+    }
+    // a vector of at least N numbers, extra ones are ignored
+    pub fn floats_n<const N: usize>(&self, attribute: &str) -> Result<Option<[f32; N]>, UrdfError> {
+        match self.floats(attribute)? {
+            Some(v) if v.len() >= N => Ok(Some(std::array::from_fn(|i| v[i]))),
+            Some(_) => Err(self.invalid_value(attribute, self.attr(attribute).unwrap_or(""))),
+            None => Ok(None),
+        }
+    }
+    pub fn f32(&self, attribute: &str) -> Result<Option<f32>, UrdfError> {
+        Ok(self.floats_n::<1>(attribute)?.map(|[v]| v))
+    }
+    pub fn vec3(&self, attribute: &str) -> Result<Option<glm::Vec3>, UrdfError> {
+        Ok(self.floats_n::<3>(attribute)?.map(glm::Vec3::from))
+    }
+    pub fn bool(&self, attribute: &str) -> Result<Option<bool>, UrdfError> {
+        match self.attr(attribute) {
+            Some("true") => Ok(Some(true)),
+            Some("false") => Ok(Some(false)),
+            Some(other) => Err(self.invalid_value(attribute, other)),
+            None => Ok(None),
+        }
+    }
+
+    // the same accessors for values kept as the text of a child element
+    pub fn text_floats(&self) -> Result<Vec<f32>, UrdfError> {
+        self.parse_floats(&self.name, &self.text)
+    }
+    pub fn child_text(&self, name: &str) -> Option<&str> {
+        self.child(name).map(|c| c.text.as_str())
+    }
+    pub fn child_floats_n<const N: usize>(
+        &self,
+        name: &str,
+    ) -> Result<Option<[f32; N]>, UrdfError> {
+        let Some(child) = self.child(name) else {
+            return Ok(None);
+        };
+        let v = child.text_floats()?;
+        if v.len() < N {
+            return Err(child.invalid_value(name, &child.text));
+        }
+        Ok(Some(std::array::from_fn(|i| v[i])))
+    }
+    pub fn child_f32(&self, name: &str) -> Result<Option<f32>, UrdfError> {
+        Ok(self.child_floats_n::<1>(name)?.map(|[v]| v))
+    }
+    pub fn child_vec3(&self, name: &str) -> Result<Option<glm::Vec3>, UrdfError> {
+        Ok(self.child_floats_n::<3>(name)?.map(glm::Vec3::from))
+    }
+    pub fn child_bool(&self, name: &str) -> Result<Option<bool>, UrdfError> {
+        let Some(child) = self.child(name) else {
+            return Ok(None);
+        };
+        match child.text.as_str() {
+            "true" | "1" => Ok(Some(true)),
+            "false" | "0" => Ok(Some(false)),
+            other => Err(child.invalid_value(name, other)),
+        }
+    }
+}
+
+pub(super) fn read_tree(s: &str, resolver: &dyn ResourceResolver) -> Result<XmlElement, UrdfError> {
+    let mut reader = UrdfReader::new(s, resolver);
+    let mut stack: Vec<XmlElement> = Vec::new();
+    loop {
+        match reader.next()? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => stack.push(XmlElement {
+                name: name.local_name,
+                attributes: attributes
+                    .into_iter()
+                    .map(|a| (a.name.local_name, a.value))
+                    .collect(),
+                children: Vec::new(),
+                text: String::new(),
+                location: reader.location(),
+            }),
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some(element) = stack.last_mut() {
+                    element.text.push_str(text.trim());
+                }
+            }
+            XmlEvent::EndElement { .. } => {
+                let element = stack.pop().expect("xml reader checks nesting");
+                match stack.last_mut() {
+                    Some(parent) => parent.children.push(element),
+                    None => return Ok(element),
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,1399 @@
+// Expands xacro (XML macro) robot descriptions into plain URDF that
+// RobotDescriptor can parse. Supported: xacro:property (values and blocks),
+// xacro:arg, xacro:macro (params, defaults, ^ inheritance, *block and **block
+// params), xacro:if/unless, xacro:include, xacro:insert_block, xacro:element,
+// xacro:attribute, ${} expressions and $(arg|find|env|optenv|eval|cwd) substitutions.
+use super::SourceLocation;
+use crate::resource::{PackageResolver, ResourceResolver};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use xml::common::Position;
+use xml::name::OwnedName;
+use xml::reader::XmlEvent;
+use xml::EventReader;
+
+// guards against macros that (indirectly) call themselves forever
+const MAX_MACRO_DEPTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XacroError {
+    Xml {
+        message: String,
+        location: SourceLocation,
+    },
+    Io {
+        path: PathBuf,
+        message: String,
+    },
+    MissingAttribute {
+        attribute: String,
+        location: SourceLocation,
+    },
+    UndefinedProperty {
+        name: String,
+        location: SourceLocation,
+    },
+    UndefinedArg {
+        name: String,
+        location: SourceLocation,
+    },
+    UndefinedEnv {
+        name: String,
+        location: SourceLocation,
+    },
+    UndefinedMacro {
+        name: String,
+        location: SourceLocation,
+    },
+    UnknownPackage {
+        name: String,
+        location: SourceLocation,
+    },
+    MissingParameter {
+        macro_name: String,
+        param: String,
+        location: SourceLocation,
+    },
+    UnexpectedParameter {
+        macro_name: String,
+        param: String,
+        location: SourceLocation,
+    },
+    MissingBlock {
+        name: String,
+        location: SourceLocation,
+    },
+    Expression {
+        expression: String,
+        message: String,
+        location: SourceLocation,
+    },
+    RecursionLimit {
+        macro_name: String,
+        location: SourceLocation,
+    },
+    // the files open when one of them was included again, ending with it
+    IncludeCycle {
+        cycle: Vec<PathBuf>,
+        location: SourceLocation,
+    },
+}
+
+impl XacroError {
+    pub fn location(&self) -> Option<&SourceLocation> {
+        Some(match self {
+            XacroError::Xml { location, .. }
+            | XacroError::MissingAttribute { location, .. }
+            | XacroError::UndefinedProperty { location, .. }
+            | XacroError::UndefinedArg { location, .. }
+            | XacroError::UndefinedEnv { location, .. }
+            | XacroError::UndefinedMacro { location, .. }
+            | XacroError::UnknownPackage { location, .. }
+            | XacroError::MissingParameter { location, .. }
+            | XacroError::UnexpectedParameter { location, .. }
+            | XacroError::MissingBlock { location, .. }
+            | XacroError::Expression { location, .. }
+            | XacroError::RecursionLimit { location, .. }
+            | XacroError::IncludeCycle { location, .. } => location,
+            XacroError::Io { .. } => return None,
+        })
+    }
+}
+
+impl fmt::Display for XacroError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XacroError::Xml { message, location } => {
+                write!(f, "malformed xml at {}: {}", location, message)
+            }
+            XacroError::Io { path, message } => {
+                write!(f, "could not read {}: {}", path.display(), message)
+            }
+            XacroError::MissingAttribute {
+                attribute,
+                location,
+            } => write!(f, "missing attribute \"{}\" at {}", attribute, location),
+            XacroError::UndefinedProperty { name, location } => {
+                write!(f, "undefined property \"{}\" at {}", name, location)
+            }
+            XacroError::UndefinedArg { name, location } => {
+                write!(f, "undefined arg \"{}\" at {}", name, location)
+            }
+            XacroError::UndefinedEnv { name, location } => {
+                write!(
+                    f,
+                    "undefined environment variable \"{}\" at {}",
+                    name, location
+                )
+            }
+            XacroError::UndefinedMacro { name, location } => {
+                write!(f, "undefined macro \"{}\" at {}", name, location)
+            }
+            XacroError::UnknownPackage { name, location } => {
+                write!(f, "could not find package \"{}\" at {}", name, location)
+            }
+            XacroError::MissingParameter {
+                macro_name,
+                param,
+                location,
+            } => write!(
+                f,
+                "macro \"{}\" requires parameter \"{}\" at {}",
+                macro_name, param, location
+            ),
+            XacroError::UnexpectedParameter {
+                macro_name,
+                param,
+                location,
+            } => write!(
+                f,
+                "macro \"{}\" has no parameter \"{}\" at {}",
+                macro_name, param, location
+            ),
+            XacroError::MissingBlock { name, location } => {
+                write!(f, "no block named \"{}\" at {}", name, location)
+            }
+            XacroError::Expression {
+                expression,
+                message,
+                location,
+            } => write!(
+                f,
+                "could not evaluate \"{}\" ({}) at {}",
+                expression, message, location
+            ),
+            XacroError::RecursionLimit {
+                macro_name,
+                location,
+            } => write!(
+                f,
+                "macro \"{}\" nested more than {} deep at {}",
+                macro_name, MAX_MACRO_DEPTH, location
+            ),
+            XacroError::IncludeCycle { cycle, location } => {
+                let cycle: Vec<_> = cycle.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "include cycle {} at {}", cycle.join(" -> "), location)
+            }
+        }
+    }
+}
+
+impl std::error::Error for XacroError {}
+
+// The xacro front-end: holds the values of $(arg ...) and where to find $(find ...) packages
+#[derive(Debug, Clone)]
+pub struct Xacro {
+    args: HashMap<String, String>,
+    packages: PackageResolver,
+}
+
+impl Default for Xacro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Xacro {
+    // packages are searched for on ROS_PACKAGE_PATH
+    pub fn new() -> Self {
+        Self {
+            args: HashMap::new(),
+            packages: PackageResolver::from_env(),
+        }
+    }
+    pub fn with_arg(mut self, name: &str, value: &str) -> Self {
+        self.args.insert(name.to_owned(), value.to_owned());
+        self
+    }
+    pub fn with_packages(mut self, packages: PackageResolver) -> Self {
+        self.packages = packages;
+        self
+    }
+    pub fn packages(&self) -> &PackageResolver {
+        &self.packages
+    }
+    // includes are relative to the working directory
+    pub fn expand_str(&self, source: &str) -> Result<String, XacroError> {
+        self.expand(source, "<string>", PathBuf::new(), Vec::new())
+    }
+    pub fn expand_file<P: AsRef<Path>>(&self, path: P) -> Result<String, XacroError> {
+        let path = path.as_ref();
+        let source = read_file(path)?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        self.expand(&source, &path.to_string_lossy(), dir, vec![open_path(path)])
+    }
+    fn expand(
+        &self,
+        source: &str,
+        file: &str,
+        dir: PathBuf,
+        includes: Vec<PathBuf>,
+    ) -> Result<String, XacroError> {
+        let root = read_document(source, file)?;
+        let mut expander = Expander {
+            packages: &self.packages,
+            args: self.args.clone(),
+            scopes: vec![Scope::default()],
+            dirs: vec![dir],
+            includes,
+            depth: 0,
+        };
+        let mut expanded = Vec::new();
+        expander.expand_element(&root, &mut expanded)?;
+        let mut out = String::from("<?xml version=\"1.0\"?>\n");
+        for node in &expanded {
+            if let Node::Element(e) = node {
+                write_element(&mut out, e, 0);
+            }
+        }
+        Ok(out)
+    }
+}
+
+// the same file reached through different relative paths compares equal
+fn open_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn read_file(path: &Path) -> Result<String, XacroError> {
+    std::fs::read_to_string(path).map_err(|e| XacroError::Io {
+        path: path.to_path_buf(),
+        message: e.to_string(),
+    })
+}
+
+fn is_xacro_namespace(prefix: &str, uri: &str) -> bool {
+    prefix == "xacro" || uri.contains("ros.org/wiki/xacro")
+}
+
+// a minimal DOM, xacro needs to revisit macro bodies and blocks
+#[derive(Debug, Clone)]
+enum Node {
+    Element(Element),
+    Text(String),
+}
+
+#[derive(Debug, Clone)]
+struct Element {
+    prefix: Option<String>,
+    name: String,
+    // qualified name, value
+    attributes: Vec<(String, String)>,
+    // namespaces declared on this element: prefix ("" for the default), uri
+    namespaces: Vec<(String, String)>,
+    children: Vec<Node>,
+    location: SourceLocation,
+}
+
+impl Element {
+    fn is_xacro(&self) -> bool {
+        self.prefix.as_deref() == Some("xacro")
+    }
+    fn qualified_name(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.name),
+            None => self.name.clone(),
+        }
+    }
+    fn attr(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+    fn required_attr(&self, name: &str) -> Result<&str, XacroError> {
+        self.attr(name).ok_or_else(|| XacroError::MissingAttribute {
+            attribute: name.to_owned(),
+            location: self.location.clone(),
+        })
+    }
+    fn child_elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|n| match n {
+            Node::Element(e) => Some(e),
+            Node::Text(_) => None,
+        })
+    }
+}
+
+fn read_document(source: &str, file: &str) -> Result<Element, XacroError> {
+    let mut events = EventReader::from_str(source);
+    // elements still open, with the namespace mapping in scope for each
+    let mut stack: Vec<(Element, Vec<(String, String)>)> = Vec::new();
+    let mut path: Vec<String> = Vec::new();
+    let location = |path: &[String], pos: xml::common::TextPosition| SourceLocation {
+        path: format!("{}:{}", file, path.join("/")),
+        line: pos.row + 1,
+        column: pos.column + 1,
+    };
+    loop {
+        let event = events.next().map_err(|e| XacroError::Xml {
+            message: e.msg().to_owned(),
+            location: location(&path, e.position()),
+        })?;
+        match event {
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                namespace,
+            } => {
+                let in_scope: Vec<(String, String)> = namespace
+                    .into_iter()
+                    .filter(|(prefix, uri)| {
+                        !uri.is_empty() && *prefix != "xml" && *prefix != "xmlns"
+                    })
+                    .map(|(p, u)| (p.to_owned(), u.to_owned()))
+                    .collect();
+                let parent_scope = stack.last().map(|(_, ns)| ns.as_slice()).unwrap_or(&[]);
+                let namespaces = in_scope
+                    .iter()
+                    .filter(|m| !parent_scope.contains(m))
+                    .cloned()
+                    .collect();
+                let qualified = qualified_name(&name);
+                let segment = match attributes.iter().find(|a| a.name.local_name == "name") {
+                    Some(a) => format!("{}[{}]", qualified, a.value),
+                    None => qualified,
+                };
+                path.push(segment);
+                let element = Element {
+                    prefix: name.prefix.clone(),
+                    name: name.local_name,
+                    attributes: attributes
+                        .into_iter()
+                        .map(|a| (qualified_name(&a.name), a.value))
+                        .collect(),
+                    namespaces,
+                    children: Vec::new(),
+                    location: location(&path, events.position()),
+                };
+                stack.push((element, in_scope));
+            }
+            XmlEvent::EndElement { .. } => {
+                path.pop();
+                let (element, _) = stack.pop().expect("xml reader checks nesting");
+                match stack.last_mut() {
+                    Some((parent, _)) => parent.children.push(Node::Element(element)),
+                    None => return Ok(element),
+                }
+            }
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => {
+                if let Some((parent, _)) = stack.last_mut() {
+                    parent.children.push(Node::Text(text));
+                }
+            }
+            XmlEvent::EndDocument => {
+                return Err(XacroError::Xml {
+                    message: "no root element".into(),
+                    location: location(&path, events.position()),
+                })
+            }
+            _ => {}
+        }
+    }
+}
+
+// prefix:local, without the namespace uri OwnedName's Display adds
+fn qualified_name(name: &OwnedName) -> String {
+    match &name.prefix {
+        Some(prefix) => format!("{}:{}", prefix, name.local_name),
+        None => name.local_name.clone(),
+    }
+}
+
+fn escape(s: &str, attribute: bool) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' if attribute => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn write_element(out: &mut String, element: &Element, indent: usize) {
+    let pad = "  ".repeat(indent);
+    out.push_str(&pad);
+    out.push('<');
+    out.push_str(&element.qualified_name());
+    for (prefix, uri) in &element.namespaces {
+        if prefix.is_empty() {
+            out.push_str(&format!(" xmlns=\"{}\"", escape(uri, true)));
+        } else {
+            out.push_str(&format!(" xmlns:{}=\"{}\"", prefix, escape(uri, true)));
+        }
+    }
+    for (k, v) in &element.attributes {
+        out.push_str(&format!(" {}=\"{}\"", k, escape(v, true)));
+    }
+    if element.children.is_empty() {
+        out.push_str("/>\n");
+        return;
+    }
+    // elements holding text are written on one line so the text is unchanged
+    if element.children.iter().any(|n| matches!(n, Node::Text(_))) {
+        out.push('>');
+        for node in &element.children {
+            match node {
+                Node::Text(text) => out.push_str(&escape(text, false)),
+                Node::Element(e) => {
+                    let mut inner = String::new();
+                    write_element(&mut inner, e, 0);
+                    out.push_str(inner.trim_end());
+                }
+            }
+        }
+    } else {
+        out.push_str(">\n");
+        for e in element.child_elements() {
+            write_element(out, e, indent + 1);
+        }
+        out.push_str(&pad);
+    }
+    out.push_str(&format!("</{}>\n", element.qualified_name()));
+}
+
+#[derive(Debug, Clone)]
+enum Property {
+    Value(String),
+    Block(Vec<Node>),
+}
+
+#[derive(Debug, Clone)]
+enum Param {
+    // name:=default, name:=^ (inherit from the calling scope), name:=^|default
+    Value {
+        name: String,
+        default: Option<String>,
+        inherit: bool,
+    },
+    // *name takes the next element of the call
+    Block(String),
+    // **name takes the children of the next element of the call
+    Blocks(String),
+}
+
+#[derive(Debug)]
+struct Macro {
+    name: String,
+    params: Vec<Param>,
+    body: Vec<Node>,
+}
+
+#[derive(Default)]
+struct Scope {
+    properties: HashMap<String, Property>,
+    macros: HashMap<String, Rc<Macro>>,
+}
+
+struct Expander<'a> {
+    packages: &'a PackageResolver,
+    args: HashMap<String, String>,
+    scopes: Vec<Scope>,
+    // directory of the file being expanded, for relative includes
+    dirs: Vec<PathBuf>,
+    // files being expanded, innermost last, so that includes cannot cycle
+    includes: Vec<PathBuf>,
+    depth: usize,
+}
+
+impl Expander<'_> {
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.properties.get(name))
+    }
+    fn find_macro(&self, name: &str) -> Option<Rc<Macro>> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.macros.get(name).cloned())
+    }
+    fn scope_for(&mut self, element: &Element) -> &mut Scope {
+        let n = self.scopes.len();
+        match element.attr("scope") {
+            Some("global") => &mut self.scopes[0],
+            Some("parent") if n > 1 => &mut self.scopes[n - 2],
+            _ => &mut self.scopes[n - 1],
+        }
+    }
+
+    fn expand_nodes(
+        &mut self,
+        nodes: &[Node],
+        out: &mut Vec<Node>,
+        parent: &Element,
+    ) -> Result<(), XacroError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push(Node::Text(self.eval_text(text, &parent.location)?)),
+                Node::Element(e) => self.expand_element(e, out)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn expand_element(&mut self, element: &Element, out: &mut Vec<Node>) -> Result<(), XacroError> {
+        let location = &element.location;
+        if !element.is_xacro() {
+            let mut children = Vec::new();
+            self.expand_nodes(&element.children, &mut children, element)?;
+            let mut attributes = element
+                .attributes
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), self.eval_text(v, location)?)))
+                .collect::<Result<Vec<_>, XacroError>>()?;
+            // xacro:attribute children were expanded into placeholder elements
+            children.retain(|n| match n {
+                Node::Element(e) if e.name == "\u{0}attribute" => {
+                    attributes.push(e.attributes[0].clone());
+                    false
+                }
+                _ => true,
+            });
+            // whitespace only text is formatting and is dropped
+            if children.iter().any(|n| matches!(n, Node::Element(_))) {
+                children.retain(|n| !matches!(n, Node::Text(t) if t.trim().is_empty()));
+            }
+            out.push(Node::Element(Element {
+                attributes,
+                namespaces: element
+                    .namespaces
+                    .iter()
+                    .filter(|(prefix, uri)| !is_xacro_namespace(prefix, uri))
+                    .cloned()
+                    .collect(),
+                children,
+                ..element.clone()
+            }));
+            return Ok(());
+        }
+        match element.name.as_str() {
+            "property" => {
+                let name = self.eval_text(element.required_attr("name")?, location)?;
+                let property = if let Some(value) = element.attr("value") {
+                    Property::Value(self.eval_text(value, location)?)
+                } else if let Some(default) = element.attr("default") {
+                    if self.property(&name).is_some() {
+                        return Ok(());
+                    }
+                    Property::Value(self.eval_text(default, location)?)
+                } else {
+                    Property::Block(element.children.clone())
+                };
+                self.scope_for(element).properties.insert(name, property);
+            }
+            "arg" => {
+                let name = element.required_attr("name")?.to_owned();
+                if !self.args.contains_key(&name) {
+                    if let Some(default) = element.attr("default") {
+                        let value = self.eval_text(default, location)?;
+                        self.args.insert(name, value);
+                    }
+                }
+            }
+            "macro" => {
+                let name = element.required_attr("name")?.to_owned();
+                let params = parse_params(element.attr("params").unwrap_or(""));
+                let m = Macro {
+                    name: name.clone(),
+                    params,
+                    body: element.children.clone(),
+                };
+                self.scope_for(element).macros.insert(name, Rc::new(m));
+            }
+            "if" | "unless" => {
+                let value = self.eval_text(element.required_attr("value")?, location)?;
+                let condition = match value.trim() {
+                    "true" | "True" | "1" => true,
+                    "false" | "False" | "0" => false,
+                    other => match other.parse::<f64>() {
+                        Ok(n) => n != 0.0,
+                        Err(_) => {
+                            return Err(XacroError::Expression {
+                                expression: value.clone(),
+                                message: "not a boolean".into(),
+                                location: location.clone(),
+                            })
+                        }
+                    },
+                };
+                if condition == (element.name == "if") {
+                    self.expand_nodes(&element.children, out, element)?;
+                }
+            }
+            "include" => {
+                let filename = self.eval_text(element.required_attr("filename")?, location)?;
+                let path = if filename.starts_with("package://") {
+                    self.packages.resolve(&filename)
+                } else {
+                    let path = self
+                        .dirs
+                        .last()
+                        .cloned()
+                        .unwrap_or_default()
+                        .join(&filename);
+                    path.is_file().then_some(path)
+                }
+                .ok_or_else(|| XacroError::Io {
+                    path: PathBuf::from(&filename),
+                    message: format!("included from {}", location),
+                })?;
+                let open = open_path(&path);
+                if let Some(first) = self.includes.iter().position(|p| *p == open) {
+                    let mut cycle = self.includes[first..].to_vec();
+                    cycle.push(open);
+                    return Err(XacroError::IncludeCycle {
+                        cycle,
+                        location: location.clone(),
+                    });
+                }
+                let source = read_file(&path)?;
+                let included = read_document(&source, &path.to_string_lossy())?;
+                self.dirs
+                    .push(path.parent().map(Path::to_path_buf).unwrap_or_default());
+                self.includes.push(open);
+                let result = self.expand_nodes(&included.children, out, &included);
+                self.includes.pop();
+                self.dirs.pop();
+                result?;
+            }
+            "insert_block" => {
+                let name = self.eval_text(element.required_attr("name")?, location)?;
+                match self.property(&name) {
+                    Some(Property::Block(nodes)) => {
+                        let nodes = nodes.clone();
+                        self.expand_nodes(&nodes, out, element)?;
+                    }
+                    _ => {
+                        return Err(XacroError::MissingBlock {
+                            name,
+                            location: location.clone(),
+                        })
+                    }
+                }
+            }
+            "element" => {
+                let name = self.eval_text(element.required_attr("xacro:name")?, location)?;
+                let (prefix, name) = match name.split_once(':') {
+                    Some((p, n)) => (Some(p.to_owned()), n.to_owned()),
+                    None => (None, name),
+                };
+                let plain = Element {
+                    prefix,
+                    name,
+                    attributes: element
+                        .attributes
+                        .iter()
+                        .filter(|(k, _)| k != "xacro:name")
+                        .cloned()
+                        .collect(),
+                    ..element.clone()
+                };
+                self.expand_element(&plain, out)?;
+            }
+            "attribute" => {
+                let name = self.eval_text(element.required_attr("name")?, location)?;
+                let value = self.eval_text(element.required_attr("value")?, location)?;
+                // picked up by the enclosing element
+                out.push(Node::Element(Element {
+                    prefix: None,
+                    name: "\u{0}attribute".into(),
+                    attributes: vec![(name, value)],
+                    namespaces: Vec::new(),
+                    children: Vec::new(),
+                    location: location.clone(),
+                }));
+            }
+            name => {
+                let m = self
+                    .find_macro(name)
+                    .ok_or_else(|| XacroError::UndefinedMacro {
+                        name: name.to_owned(),
+                        location: location.clone(),
+                    })?;
+                self.call_macro(&m, element, out)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn call_macro(
+        &mut self,
+        m: &Macro,
+        call: &Element,
+        out: &mut Vec<Node>,
+    ) -> Result<(), XacroError> {
+        let location = &call.location;
+        if self.depth >= MAX_MACRO_DEPTH {
+            return Err(XacroError::RecursionLimit {
+                macro_name: m.name.clone(),
+                location: location.clone(),
+            });
+        }
+        // arguments are evaluated in the scope of the caller
+        let mut given = HashMap::new();
+        for (k, v) in &call.attributes {
+            given.insert(k.clone(), self.eval_text(v, location)?);
+        }
+        let mut blocks = call.child_elements();
+        let mut scope = Scope::default();
+        for param in &m.params {
+            let missing = |param: &str| XacroError::MissingParameter {
+                macro_name: m.name.clone(),
+                param: param.to_owned(),
+                location: location.clone(),
+            };
+            match param {
+                Param::Value {
+                    name,
+                    default,
+                    inherit,
+                } => {
+                    let value = match given.remove(name) {
+                        Some(v) => v,
+                        None => match (inherit, self.property(name), default) {
+                            (true, Some(Property::Value(v)), _) => v.clone(),
+                            (_, _, Some(default)) => {
+                                // defaults may refer to the parameters before them
+                                self.scopes.push(scope);
+                                let value = self.eval_text(default, location);
+                                scope = self.scopes.pop().expect("pushed above");
+                                value?
+                            }
+                            _ => return Err(missing(name)),
+                        },
+                    };
+                    scope
+                        .properties
+                        .insert(name.clone(), Property::Value(value));
+                }
+                Param::Block(name) => {
+                    let block = blocks.next().ok_or_else(|| missing(name))?;
+                    scope.properties.insert(
+                        name.clone(),
+                        Property::Block(vec![Node::Element(block.clone())]),
+                    );
+                }
+                Param::Blocks(name) => {
+                    let block = blocks.next().ok_or_else(|| missing(name))?;
+                    scope
+                        .properties
+                        .insert(name.clone(), Property::Block(block.children.clone()));
+                }
+            }
+        }
+        if let Some(param) = given.into_keys().next() {
+            return Err(XacroError::UnexpectedParameter {
+                macro_name: m.name.clone(),
+                param,
+                location: location.clone(),
+            });
+        }
+        self.scopes.push(scope);
+        self.depth += 1;
+        let result = self.expand_nodes(&m.body, out, call);
+        self.depth -= 1;
+        self.scopes.pop();
+        result
+    }
+
+    // substitutes $(...) and ${...} in attribute values and text
+    fn eval_text(&self, text: &str, location: &SourceLocation) -> Result<String, XacroError> {
+        if !text.contains('$') {
+            return Ok(text.to_owned());
+        }
+        let mut out = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('$') {
+            out.push_str(&rest[..i]);
+            rest = &rest[i..];
+            if let Some(escaped) = rest.strip_prefix("$$") {
+                // $${ and $$( are literal
+                out.push('$');
+                rest = escaped;
+                continue;
+            }
+            let (open, close) = match rest.as_bytes().get(1) {
+                Some(b'{') => ('{', '}'),
+                Some(b'(') => ('(', ')'),
+                _ => {
+                    out.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+            };
+            let end =
+                matching_close(&rest[2..], open, close).ok_or_else(|| XacroError::Expression {
+                    expression: rest.to_owned(),
+                    message: format!("unclosed {}", open),
+                    location: location.clone(),
+                })?;
+            let inner = &rest[2..2 + end];
+            if open == '{' {
+                out.push_str(&self.eval_expression(inner, location)?.to_text());
+            } else {
+                out.push_str(&self.substitution(inner, location)?);
+            }
+            rest = &rest[2 + end + 1..];
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    fn eval_expression(
+        &self,
+        expression: &str,
+        location: &SourceLocation,
+    ) -> Result<Value, XacroError> {
+        let lookup = |name: &str| match self.property(name) {
+            Some(Property::Value(v)) => Some(Value::from_text(v)),
+            _ => None,
+        };
+        evaluate(expression, &lookup).map_err(|e| match e {
+            ExprError::Undefined(name) => XacroError::UndefinedProperty {
+                name,
+                location: location.clone(),
+            },
+            ExprError::Invalid(message) => XacroError::Expression {
+                expression: expression.to_owned(),
+                message,
+                location: location.clone(),
+            },
+        })
+    }
+
+    // $(arg name), $(find package), $(env VAR), $(optenv VAR default), $(eval expr), $(cwd)
+    fn substitution(&self, inner: &str, location: &SourceLocation) -> Result<String, XacroError> {
+        let inner = self.eval_text(inner, location)?;
+        let (command, argument) = inner
+            .trim()
+            .split_once(char::is_whitespace)
+            .map(|(c, a)| (c, a.trim()))
+            .unwrap_or((inner.trim(), ""));
+        match command {
+            "arg" => self
+                .args
+                .get(argument)
+                .cloned()
+                .ok_or_else(|| XacroError::UndefinedArg {
+                    name: argument.to_owned(),
+                    location: location.clone(),
+                }),
+            "find" => self
+                .packages
+                .package_dir(argument)
+                .map(|p| p.to_string_lossy().into_owned())
+                .ok_or_else(|| XacroError::UnknownPackage {
+                    name: argument.to_owned(),
+                    location: location.clone(),
+                }),
+            "env" => std::env::var(argument).map_err(|_| XacroError::UndefinedEnv {
+                name: argument.to_owned(),
+                location: location.clone(),
+            }),
+            "optenv" => {
+                let (var, default) = argument
+                    .split_once(char::is_whitespace)
+                    .unwrap_or((argument, ""));
+                Ok(std::env::var(var).unwrap_or_else(|_| default.trim().to_owned()))
+            }
+            "eval" => Ok(self.eval_expression(argument, location)?.to_text()),
+            "cwd" => std::env::current_dir()
+                .map(|d| d.to_string_lossy().into_owned())
+                .map_err(|e| XacroError::Expression {
+                    expression: inner.clone(),
+                    message: e.to_string(),
+                    location: location.clone(),
+                }),
+            _ => Err(XacroError::Expression {
+                expression: inner.clone(),
+                message: format!("unknown substitution \"{}\"", command),
+                location: location.clone(),
+            }),
+        }
+    }
+}
+
+// byte offset of the bracket closing one that was just opened, skipping quoted strings
+fn matching_close(s: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    for (i, c) in s.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c == open => depth += 1,
+            None if c == close => {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+            None => {}
+        }
+    }
+    None
+}
+
+// splits on whitespace outside of quotes and drops the quotes, so that
+// defaults such as xyz:='0 0 0' stay in one piece
+fn split_params(params: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    for c in params.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => current.push(c),
+            None if c == '\'' || c == '"' => quote = Some(c),
+            None if c.is_whitespace() => {
+                if !current.is_empty() {
+                    out.push(std::mem::take(&mut current));
+                }
+            }
+            None => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+    out
+}
+
+fn parse_params(params: &str) -> Vec<Param> {
+    split_params(params)
+        .into_iter()
+        .map(|p| {
+            let p = p.as_str();
+            if let Some(name) = p.strip_prefix("**") {
+                Param::Blocks(name.to_owned())
+            } else if let Some(name) = p.strip_prefix('*') {
+                Param::Block(name.to_owned())
+            } else {
+                match p.split_once(":=") {
+                    Some((name, default)) => {
+                        let (inherit, default) = match default.strip_prefix('^') {
+                            Some(rest) => (true, rest.strip_prefix('|')),
+                            None => (false, Some(default)),
+                        };
+                        Param::Value {
+                            name: name.to_owned(),
+                            default: default.map(str::to_owned),
+                            inherit,
+                        }
+                    }
+                    None => Param::Value {
+                        name: p.to_owned(),
+                        default: None,
+                        inherit: false,
+                    },
+                }
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    // property values are text; numbers and booleans are recognised when used
+    fn from_text(s: &str) -> Value {
+        match s.trim() {
+            "true" | "True" => Value::Bool(true),
+            "false" | "False" => Value::Bool(false),
+            t => t
+                .parse::<f64>()
+                .map(Value::Number)
+                .unwrap_or(Value::Str(s.to_owned())),
+        }
+    }
+    fn to_text(&self) -> String {
+        match self {
+            Value::Number(n) => format!("{}", n),
+            Value::Bool(b) => format!("{}", b),
+            Value::Str(s) => s.clone(),
+        }
+    }
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Number(n) => *n != 0.0,
+            Value::Bool(b) => *b,
+            Value::Str(s) => !s.is_empty(),
+        }
+    }
+    fn number(&self) -> Result<f64, ExprError> {
+        match self {
+            Value::Number(n) => Ok(*n),
+            Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+            Value::Str(s) => Err(ExprError::Invalid(format!("\"{}\" is not a number", s))),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum ExprError {
+    Undefined(String),
+    Invalid(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ExprError> {
+    const OPS: [&str; 20] = [
+        "**", "//", "==", "!=", "<=", ">=", "+", "-", "*", "/", "%", "<", ">", "(", ")", ",", "[",
+        "]", "{", "}",
+    ];
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text
+                .parse::<f64>()
+                .map_err(|_| ExprError::Invalid(format!("bad number \"{}\"", text)))?;
+            tokens.push(Token::Number(n));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            let end = chars[i + 1..]
+                .iter()
+                .position(|&q| q == c)
+                .ok_or_else(|| ExprError::Invalid("unterminated string".into()))?;
+            tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+            i += end + 2;
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let op = OPS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| ExprError::Invalid(format!("unexpected character '{}'", c)))?;
+            tokens.push(Token::Op(op));
+            i += op.len();
+        }
+    }
+    Ok(tokens)
+}
+
+fn evaluate(expression: &str, lookup: &dyn Fn(&str) -> Option<Value>) -> Result<Value, ExprError> {
+    let tokens = tokenize(expression)?;
+    let mut parser = ExprParser {
+        tokens,
+        pos: 0,
+        lookup,
+    };
+    let value = parser.ternary()?;
+    match parser.tokens.get(parser.pos) {
+        None => Ok(value),
+        Some(t) => Err(ExprError::Invalid(format!("unexpected {:?}", t))),
+    }
+}
+
+// recursive descent over python-like expression syntax, evaluating as it goes
+struct ExprParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    lookup: &'a dyn Fn(&str) -> Option<Value>,
+}
+
+impl ExprParser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn eat_op(&mut self, op: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn eat_ident(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn expect_op(&mut self, op: &str) -> Result<(), ExprError> {
+        if self.eat_op(op) {
+            Ok(())
+        } else {
+            Err(ExprError::Invalid(format!("expected '{}'", op)))
+        }
+    }
+
+    fn ternary(&mut self) -> Result<Value, ExprError> {
+        let value = self.or()?;
+        if self.eat_ident("if") {
+            let condition = self.or()?;
+            if !self.eat_ident("else") {
+                return Err(ExprError::Invalid("expected 'else'".into()));
+            }
+            let other = self.ternary()?;
+            return Ok(if condition.truthy() { value } else { other });
+        }
+        Ok(value)
+    }
+    fn or(&mut self) -> Result<Value, ExprError> {
+        let mut value = self.and()?;
+        while self.eat_ident("or") {
+            let rhs = self.and()?;
+            value = if value.truthy() { value } else { rhs };
+        }
+        Ok(value)
+    }
+    fn and(&mut self) -> Result<Value, ExprError> {
+        let mut value = self.not()?;
+        while self.eat_ident("and") {
+            let rhs = self.not()?;
+            value = if value.truthy() { rhs } else { value };
+        }
+        Ok(value)
+    }
+    fn not(&mut self) -> Result<Value, ExprError> {
+        if self.eat_ident("not") {
+            return Ok(Value::Bool(!self.not()?.truthy()));
+        }
+        self.comparison()
+    }
+    fn comparison(&mut self) -> Result<Value, ExprError> {
+        let lhs = self.sum()?;
+        for op in ["==", "!=", "<=", ">=", "<", ">"] {
+            if self.eat_op(op) {
+                let rhs = self.sum()?;
+                let result = match (&lhs, &rhs) {
+                    (Value::Str(a), Value::Str(b)) => match op {
+                        "==" => a == b,
+                        "!=" => a != b,
+                        "<=" => a <= b,
+                        ">=" => a >= b,
+                        "<" => a < b,
+                        _ => a > b,
+                    },
+                    _ => {
+                        let (a, b) = (lhs.number()?, rhs.number()?);
+                        match op {
+                            "==" => a == b,
+                            "!=" => a != b,
+                            "<=" => a <= b,
+                            ">=" => a >= b,
+                            "<" => a < b,
+                            _ => a > b,
+                        }
+                    }
+                };
+                return Ok(Value::Bool(result));
+            }
+        }
+        Ok(lhs)
+    }
+    fn sum(&mut self) -> Result<Value, ExprError> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat_op("+") {
+                let rhs = self.product()?;
+                value = match (value, rhs) {
+                    (Value::Str(a), Value::Str(b)) => Value::Str(a + &b),
+                    (a, b) => Value::Number(a.number()? + b.number()?),
+                };
+            } else if self.eat_op("-") {
+                let rhs = self.product()?;
+                value = Value::Number(value.number()? - rhs.number()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+    fn product(&mut self) -> Result<Value, ExprError> {
+        let mut value = self.unary()?;
+        loop {
+            let op = ["*", "//", "/", "%"].into_iter().find(|op| self.eat_op(op));
+            let Some(op) = op else {
+                return Ok(value);
+            };
+            let (a, b) = (value.number()?, self.unary()?.number()?);
+            if b == 0.0 && op != "*" {
+                return Err(ExprError::Invalid("division by zero".into()));
+            }
+            value = Value::Number(match op {
+                "*" => a * b,
+                "//" => (a / b).floor(),
+                "/" => a / b,
+                _ => a - b * (a / b).floor(),
+            });
+        }
+    }
+    fn unary(&mut self) -> Result<Value, ExprError> {
+        if self.eat_op("-") {
+            return Ok(Value::Number(-self.unary()?.number()?));
+        }
+        if self.eat_op("+") {
+            return Ok(Value::Number(self.unary()?.number()?));
+        }
+        self.power()
+    }
+    fn power(&mut self) -> Result<Value, ExprError> {
+        let base = self.atom()?;
+        if self.eat_op("**") {
+            let exponent = self.unary()?.number()?;
+            return Ok(Value::Number(base.number()?.powf(exponent)));
+        }
+        Ok(base)
+    }
+    fn atom(&mut self) -> Result<Value, ExprError> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| ExprError::Invalid("unexpected end of expression".into()))?;
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Value::Number(n)),
+            Token::Str(s) => Ok(Value::Str(s)),
+            Token::Op("(") => {
+                let value = self.ternary()?;
+                self.expect_op(")")?;
+                Ok(value)
+            }
+            Token::Ident(name) => {
+                let name = name.strip_prefix("math.").unwrap_or(&name).to_owned();
+                if self.eat_op("(") {
+                    let mut args = Vec::new();
+                    if !self.eat_op(")") {
+                        loop {
+                            args.push(self.ternary()?);
+                            if self.eat_op(")") {
+                                break;
+                            }
+                            self.expect_op(",")?;
+                        }
+                    }
+                    return call_function(&name, &args);
+                }
+                match name.as_str() {
+                    "pi" => Ok(Value::Number(std::f64::consts::PI)),
+                    "e" => Ok(Value::Number(std::f64::consts::E)),
+                    "inf" => Ok(Value::Number(f64::INFINITY)),
+                    "nan" => Ok(Value::Number(f64::NAN)),
+                    "True" | "true" => Ok(Value::Bool(true)),
+                    "False" | "false" => Ok(Value::Bool(false)),
+                    _ => (self.lookup)(&name).ok_or(ExprError::Undefined(name)),
+                }
+            }
+            Token::Op(op) => Err(ExprError::Invalid(format!("unexpected '{}'", op))),
+        }
+    }
+}
+
+fn call_function(name: &str, args: &[Value]) -> Result<Value, ExprError> {
+    let arity = |n: usize| {
+        if args.len() == n {
+            Ok(())
+        } else {
+            Err(ExprError::Invalid(format!(
+                "{}() takes {} argument(s), got {}",
+                name,
+                n,
+                args.len()
+            )))
+        }
+    };
+    let x = || args[0].number();
+    let n = match name {
+        "sin" | "cos" | "tan" | "asin" | "acos" | "atan" | "sqrt" | "abs" | "fabs" | "radians"
+        | "degrees" | "floor" | "ceil" | "exp" | "log" | "log10" | "round" | "int" | "float" => {
+            arity(1)?;
+            let x = x()?;
+            match name {
+                "sin" => x.sin(),
+                "cos" => x.cos(),
+                "tan" => x.tan(),
+                "asin" => x.asin(),
+                "acos" => x.acos(),
+                "atan" => x.atan(),
+                "sqrt" => x.sqrt(),
+                "abs" | "fabs" => x.abs(),
+                "radians" => x.to_radians(),
+                "degrees" => x.to_degrees(),
+                "floor" => x.floor(),
+                "ceil" => x.ceil(),
+                "exp" => x.exp(),
+                "log" => x.ln(),
+                "log10" => x.log10(),
+                "round" => x.round(),
+                "int" => x.trunc(),
+                _ => x,
+            }
+        }
+        "atan2" | "pow" | "fmod" => {
+            arity(2)?;
+            let (a, b) = (args[0].number()?, args[1].number()?);
+            match name {
+                "atan2" => a.atan2(b),
+                "pow" => a.powf(b),
+                _ => a % b,
+            }
+        }
+        "min" | "max" if !args.is_empty() => {
+            let values = args
+                .iter()
+                .map(Value::number)
+                .collect::<Result<Vec<_>, _>>()?;
+            let fold = if name == "min" { f64::min } else { f64::max };
+            values.into_iter().reduce(fold).unwrap_or_default()
+        }
+        "str" => {
+            arity(1)?;
+            return Ok(Value::Str(args[0].to_text()));
+        }
+        _ => return Err(ExprError::Invalid(format!("unknown function {}()", name))),
+    };
+    Ok(Value::Number(n))
+}
@@ -0,0 +1,122 @@
+// A scene to simulate: robots placed in the world, the static environment
+// around them, lights and gravity. Worlds are read from SDFormat, see
+// urdf/sdf.rs for how SDF models map onto RobotDescriptor.
+use crate::geometry::{Polyhedron, Transform};
+use crate::resource::{
+    FileUriResolver, ModelResolver, PackageResolver, RelativeResolver, ResolverChain,
+};
+use crate::urdf::{sdf, GeometryShape, RobotDescriptor, UrdfError};
+use std::path::Path;
+
+// standard gravity along -z, used when the world does not set it
+pub const DEFAULT_GRAVITY: glm::Vec3 = glm::Vec3::new(0.0, 0.0, -9.80665);
+
+#[derive(Debug, Clone)]
+pub struct World {
+    pub name: Option<String>,
+    pub gravity: glm::Vec3,
+    pub models: Vec<ModelInstance>,
+    pub environment: Vec<StaticBody>,
+    pub lights: Vec<WorldLight>,
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self {
+            name: None,
+            gravity: DEFAULT_GRAVITY,
+            models: Vec::new(),
+            environment: Vec::new(),
+            lights: Vec::new(),
+        }
+    }
+}
+
+// A robot placed in the world; `pose` is where its root link is.
+// Static SDF models become environment instead, a static URDF model is kept
+// as a robot with `is_static` set so its joints can still be posed.
+#[derive(Debug, Clone)]
+pub struct ModelInstance {
+    pub name: String,
+    pub pose: Transform,
+    pub robot: RobotDescriptor,
+    pub is_static: bool,
+}
+
+// A link of a static model, posed in the world
+#[derive(Debug, Clone)]
+pub struct StaticBody {
+    pub name: String,
+    pub pose: Transform,
+    pub visuals: Vec<StaticShape>,
+    pub collisions: Vec<StaticShape>,
+}
+
+// `pose` is relative to the body
+#[derive(Debug, Clone)]
+pub struct StaticShape {
+    pub name: String,
+    pub pose: Transform,
+    pub shape: WorldShape,
+    pub geometry: Polyhedron,
+    pub rgba: Option<glm::Vec4>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorldShape {
+    // through the body origin; `size` is only the extent drawn
+    Plane { normal: glm::Vec3, size: glm::Vec2 },
+    Solid(GeometryShape),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_angle: f32,
+        outer_angle: f32,
+        falloff: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct WorldLight {
+    pub name: String,
+    pub kind: LightKind,
+    pub pose: Transform,
+    // in the light frame; unused by point lights
+    pub direction: glm::Vec3,
+    pub diffuse: glm::Vec4,
+    pub specular: glm::Vec4,
+    pub range: f32,
+    pub cast_shadows: bool,
+}
+
+impl World {
+    // model:// URIs are looked up in the Gazebo model path; package://, file://
+    // and relative URIs as for URDF
+    pub fn from_sdf_str(s: &str) -> Result<World, UrdfError> {
+        let resolver = ResolverChain::with_defaults().with(ModelResolver::from_env());
+        sdf::parse_sdf(s, &resolver)
+    }
+    // relative URIs and model:// directories are also looked up next to the file
+    pub fn from_sdf_file<P: AsRef<Path>>(path: P) -> Result<World, UrdfError> {
+        let path = path.as_ref();
+        let s = std::fs::read_to_string(path).map_err(|e| UrdfError::Io {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        let resolver = ResolverChain::new()
+            .with(FileUriResolver)
+            .with(ModelResolver::from_env().with_search_dir(dir))
+            .with(PackageResolver::from_env())
+            .with(RelativeResolver::for_file(path))
+            .with(RelativeResolver::default());
+        sdf::parse_sdf(&s, &resolver)
+    }
+    pub fn model(&self, name: &str) -> Option<&ModelInstance> {
+        self.models.iter().find(|m| m.name == name)
+    }
+}
@@ -0,0 +1,301 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::{run, G};
+use std::f32::consts::PI;
+use wgpu_robotic_simulator::physics::{
+    BodyId, BodyJointKind, Collider, JointMotor, PhysicsWorld, RigidBody,
+};
+use wgpu_robotic_simulator::urdf::GeometryShape;
+use wgpu_robotic_simulator::world::WorldShape;
+
+// a cube of 0.2 m and 1 kg that does not sleep off its motion
+fn add_cube(world: &mut PhysicsWorld, at: glm::Vec3) -> BodyId {
+    let inertia = glm::Mat3::identity() * (0.04 / 6.0);
+    let body = world.add_body(RigidBody::new(1.0, inertia).with_position(at));
+    world.add_collider(Collider::on(
+        body,
+        WorldShape::Solid(GeometryShape::Box {
+            size: glm::vec3(0.2, 0.2, 0.2),
+        }),
+        glm::Mat4::identity(),
+    ));
+    body
+}
+
+#[test]
+fn hinged_pendulums_swing_about_their_axis() {
+    let mut world = PhysicsWorld::default();
+    let length = 1.0;
+    let bob = add_cube(&mut world, glm::vec3(length, 0.0, 0.0));
+    let hinge = world.joint_at(
+        BodyJointKind::Hinge,
+        bob,
+        None,
+        &glm::Vec3::zeros(),
+        &glm::Vec3::y(),
+    );
+    let hinge = world.add_joint(hinge);
+    // from level, till it is level on the other side
+    let mut lowest = f32::MAX;
+    let mut lowest_angle = 0.0;
+    let mut back = None;
+    let mut moving_down = true;
+    for step in 0..4 * 240 {
+        world.step(world.timestep().dt());
+        let body = world.body(bob);
+        let arm = body.position.norm();
+        assert!((arm - length).abs() < 0.01, "{}", arm);
+        assert!(body.position.y.abs() < 1e-3);
+        if body.position.z < lowest {
+            lowest = body.position.z;
+            lowest_angle = world.joint_position(hinge).unwrap();
+        }
+        if moving_down && body.linear_velocity.z > 0.0 {
+            moving_down = false;
+        }
+        if !moving_down && body.linear_velocity.z < 0.0 && back.is_none() {
+            back = Some((step + 1) as f32 * world.timestep().dt());
+        }
+    }
+    assert!((lowest + length).abs() < 0.02, "{}", lowest);
+    assert!(
+        (lowest_angle.abs() - PI / 2.0).abs() < 0.02,
+        "{}",
+        lowest_angle
+    );
+    // a compound pendulum; from level it takes longer than small swings
+    let pivot = 1.0 + 0.04 / 6.0;
+    let small = 2.0 * PI * (pivot / (G * length)).sqrt();
+    // the period from level is 1.18 times the small-swing one
+    let period = 2.0 * back.unwrap();
+    assert!(
+        (period - 1.18 * small).abs() < 0.04 * small,
+        "{} vs {}",
+        period,
+        1.18 * small
+    );
+}
+
+#[test]
+fn sliders_stop_at_their_limits() {
+    let mut world = PhysicsWorld::default();
+    let drawer = add_cube(&mut world, glm::vec3(0.0, 0.0, 1.0));
+    // up the z axis
+    let slider = world
+        .joint_at(
+            BodyJointKind::Slider,
+            drawer,
+            None,
+            &glm::vec3(0.0, 0.0, 1.0),
+            &glm::Vec3::z(),
+        )
+        .with_limit(-0.5, 0.5);
+    let slider = world.add_joint(slider);
+    world.body_mut(drawer).linear_velocity = glm::vec3(1.0, 0.0, 0.0);
+    world.body_mut(drawer).angular_velocity = glm::vec3(0.0, 0.0, 2.0);
+    run(&mut world, 2.0);
+    // b is the world, so the offset grows as the drawer falls
+    let offset = world.joint_position(slider).unwrap();
+    assert!((offset - 0.5).abs() < 0.01, "{}", offset);
+    let body = world.body(drawer);
+    assert!((body.position.z - 0.5).abs() < 0.01, "{:?}", body.position);
+    assert!(body.position.xy().norm() < 1e-3, "{:?}", body.position);
+    assert!(glm::length(&body.angular_velocity) < 1e-3);
+    assert!(body.orientation.imag().norm() < 1e-3);
+}
+
+#[test]
+fn springs_hold_the_weight_they_balance() {
+    let mut world = PhysicsWorld::default();
+    let weight = add_cube(&mut world, glm::vec3(0.0, 0.0, 1.0));
+    let stiffness = 1.0e4;
+    let spring = world
+        .joint_at(
+            BodyJointKind::Slider,
+            weight,
+            None,
+            &glm::vec3(0.0, 0.0, 1.0),
+            &glm::Vec3::z(),
+        )
+        .with_spring(stiffness, 50.0, 0.0);
+    world.add_joint(spring);
+    run(&mut world, 1.0);
+    // stiff springs are stable at 240 Hz and stretch by m g / k
+    let stretch = 1.0 - world.body(weight).position.z;
+    let expected = G / stiffness;
+    assert!((stretch - expected).abs() < 0.05 * expected, "{}", stretch);
+}
+
+#[test]
+fn motors_drive_hinges_with_at_most_their_torque() {
+    let wheel = |motor: JointMotor| {
+        let mut world = PhysicsWorld::default().with_gravity(glm::Vec3::zeros());
+        let body = add_cube(&mut world, glm::Vec3::zeros());
+        let hinge = world
+            .joint_at(
+                BodyJointKind::Hinge,
+                body,
+                None,
+                &glm::Vec3::zeros(),
+                &glm::Vec3::z(),
+            )
+            .with_motor(motor);
+        let hinge = world.add_joint(hinge);
+        run(&mut world, 0.5);
+        (
+            world.body(body).angular_velocity,
+            world.joint_position(hinge).unwrap(),
+        )
+    };
+    // b is the world, so it turns the other way to the body
+    let (spin, _) = wheel(JointMotor::Velocity {
+        speed: 2.0,
+        max_force: 100.0,
+    });
+    assert!(
+        (spin - glm::vec3(0.0, 0.0, -2.0)).norm() < 1e-3,
+        "{:?}",
+        spin
+    );
+    // 0.01 N m speeds the cube up by 1.5 rad/s^2
+    let (spin, _) = wheel(JointMotor::Velocity {
+        speed: 2.0,
+        max_force: 0.01,
+    });
+    let expected = 0.01 / (0.04 / 6.0) * 0.5;
+    assert!(
+        (spin.z + expected).abs() < 0.02,
+        "{:?} vs {}",
+        spin,
+        expected
+    );
+    let (spin, angle) = wheel(JointMotor::Position {
+        target: 1.0,
+        max_speed: 4.0,
+        max_force: 100.0,
+    });
+    assert!((angle - 1.0).abs() < 1e-3, "{}", angle);
+    assert!(spin.norm() < 1e-3);
+}
+
+#[test]
+fn fixed_joints_hold_bodies_in_place() {
+    let mut world = PhysicsWorld::default();
+    // the cube held out from its joint, so its weight makes a torque
+    let cube = add_cube(&mut world, glm::vec3(0.5, 0.0, 1.0));
+    let joint = world.joint_at(
+        BodyJointKind::Fixed,
+        cube,
+        None,
+        &glm::vec3(0.0, 0.0, 1.0),
+        &glm::Vec3::x(),
+    );
+    world.add_joint(joint);
+    run(&mut world, 1.0);
+    let body = world.body(cube);
+    // the Baumgarte bias leaves a small sag; imag is sin(angle / 2)
+    assert!(glm::distance(&body.position, &glm::vec3(0.5, 0.0, 1.0)) < 5e-3);
+    assert!(body.orientation.imag().norm() < 5e-3);
+    assert!(glm::length(&body.linear_velocity) < 1e-2);
+}
+
+#[test]
+fn ropes_of_ball_joints_keep_their_length() {
+    let mut world = PhysicsWorld::default();
+    let links: Vec<BodyId> = (0..8)
+        .map(|i| add_cube(&mut world, glm::vec3(0.3 * i as f32 + 0.15, 0.0, 2.0)))
+        .collect();
+    let first = world.joint_at(
+        BodyJointKind::Ball,
+        links[0],
+        None,
+        &glm::vec3(0.0, 0.0, 2.0),
+        &glm::Vec3::x(),
+    );
+    let mut joints = vec![world.add_joint(first)];
+    for pair in links.windows(2) {
+        let (a, b) = (world.body(pair[0]).position, world.body(pair[1]).position);
+        let joint = world.joint_at(
+            BodyJointKind::Ball,
+            pair[0],
+            Some(pair[1]),
+            &((a + b) / 2.0),
+            &glm::Vec3::x(),
+        );
+        joints.push(world.add_joint(joint));
+    }
+    world.body_mut(links[7]).linear_velocity = glm::vec3(0.0, 2.0, 0.0);
+    run(&mut world, 3.0);
+    for joint in joints {
+        let (a, b) = world.joint_poses(joint);
+        let gap = glm::distance(&a.column(3).xyz(), &b.column(3).xyz());
+        assert!(gap < 0.01, "{}", gap);
+    }
+    // neighbours overlap where they bend, but never collide
+    assert!(world
+        .contacts()
+        .iter()
+        .all(|m| m.a.index().abs_diff(m.b.index()) > 1));
+    // the far end swings within reach of the anchor, and has fallen
+    let end = world.body(links[7]).position;
+    let reach = glm::distance(&end, &glm::vec3(0.0, 0.0, 2.0));
+    assert!(reach < 8.0 * 0.3 + 0.02, "{}", reach);
+    assert!(end.z < 2.0 - 0.5, "{:?}", end);
+}
+
+#[test]
+fn universal_joints_keep_their_axes_crossed() {
+    let mut world = PhysicsWorld::default().with_gravity(glm::Vec3::zeros());
+    let shaft = add_cube(&mut world, glm::vec3(0.0, 0.0, 0.5));
+    let joint = world.joint_at(
+        BodyJointKind::Universal,
+        shaft,
+        None,
+        &glm::Vec3::zeros(),
+        &glm::Vec3::x(),
+    );
+    let joint = world.add_joint(joint);
+    world.body_mut(shaft).angular_velocity = glm::vec3(1.0, 2.0, 3.0);
+    for _ in 0..240 {
+        world.step(world.timestep().dt());
+        let (pose_a, pose_b) = world.joint_poses(joint);
+        let cross = pose_a.column(0).xyz().dot(&pose_b.column(1).xyz());
+        assert!(cross.abs() < 0.02, "{}", cross);
+        assert!(glm::distance(&pose_a.column(3).xyz(), &pose_b.column(3).xyz()) < 0.01);
+    }
+    // turning about the shaft's own axis is blocked, so the spin about z
+    // goes into the two free axes
+    let spin = world.body(shaft).angular_velocity;
+    let (pose_a, pose_b) = world.joint_poses(joint);
+    let blocked = pose_a.column(0).xyz().cross(&pose_b.column(1).xyz());
+    assert!(
+        spin.dot(&glm::normalize(&blocked)).abs() < 0.05,
+        "{:?}",
+        spin
+    );
+}
+
+#[test]
+fn joined_bodies_collide_only_if_asked_to() {
+    let overlapping = |collide: bool| {
+        let mut world = PhysicsWorld::default().with_gravity(glm::Vec3::zeros());
+        let a = add_cube(&mut world, glm::Vec3::zeros());
+        let b = add_cube(&mut world, glm::vec3(0.15, 0.0, 0.0));
+        let joint = world
+            .joint_at(
+                BodyJointKind::Fixed,
+                a,
+                Some(b),
+                &glm::Vec3::zeros(),
+                &glm::Vec3::x(),
+            )
+            .with_collide_connected(collide);
+        world.add_joint(joint);
+        world.step(world.timestep().dt());
+        world.contacts().len()
+    };
+    assert_eq!(overlapping(false), 0);
+    assert_eq!(overlapping(true), 1);
+}
@@ -0,0 +1,290 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::{add_ball, ball, ground};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use wgpu_robotic_simulator::geometry::Aabb;
+use wgpu_robotic_simulator::physics::{
+    Attachment, BroadPhase, Collider, ColliderId, DynamicTree, Material, PhysicsWorld, ProxyId,
+    RigidBody,
+};
+use wgpu_robotic_simulator::urdf::RobotDescriptor;
+
+fn random_box(rng: &mut StdRng) -> Aabb {
+    let min = glm::vec3(
+        rng.gen_range(-20.0..20.0),
+        rng.gen_range(-20.0..20.0),
+        rng.gen_range(-20.0..20.0),
+    );
+    let size = glm::vec3(
+        rng.gen_range(0.1..2.0),
+        rng.gen_range(0.1..2.0),
+        rng.gen_range(0.1..2.0),
+    );
+    Aabb::new(min, min + size)
+}
+
+// pairs of proxy data found by comparing every box with every other
+fn brute_force(tree: &DynamicTree<usize>, proxies: &[Option<ProxyId>]) -> Vec<(usize, usize)> {
+    let live: Vec<_> = proxies.iter().flatten().copied().collect();
+    let mut pairs = Vec::new();
+    for (i, &a) in live.iter().enumerate() {
+        for &b in &live[i + 1..] {
+            if tree.aabb(a).overlaps(tree.aabb(b)) {
+                let (a, b) = (tree.data(a), tree.data(b));
+                pairs.push((a.min(b), a.max(b)));
+            }
+        }
+    }
+    pairs.sort_unstable();
+    pairs
+}
+
+fn tree_pairs(tree: &DynamicTree<usize>) -> Vec<(usize, usize)> {
+    let mut pairs: Vec<_> = tree
+        .overlapping_pairs()
+        .into_iter()
+        .map(|(a, b)| {
+            let (a, b) = (tree.data(a), tree.data(b));
+            (a.min(b), a.max(b))
+        })
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+#[test]
+fn tree_finds_the_same_pairs_as_brute_force() {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut tree = DynamicTree::new();
+    let mut proxies: Vec<Option<ProxyId>> = (0..800)
+        .map(|i| Some(tree.insert(random_box(&mut rng), i)))
+        .collect();
+    assert_eq!(tree.len(), 800);
+    assert!(tree.height() <= 24, "height {}", tree.height());
+    let pairs = tree_pairs(&tree);
+    assert!(!pairs.is_empty());
+    assert_eq!(pairs, brute_force(&tree, &proxies));
+
+    // move some, drop some and add some back
+    for _ in 0..300 {
+        let i = rng.gen_range(0..proxies.len());
+        if let Some(proxy) = proxies[i] {
+            let moved = random_box(&mut rng);
+            tree.update(proxy, &moved, 0.1);
+            assert!(tree.aabb(proxy).contains(&moved));
+        }
+    }
+    for i in (0..proxies.len()).step_by(3) {
+        tree.remove(proxies[i].take().unwrap());
+    }
+    for i in 800..900 {
+        proxies.push(Some(tree.insert(random_box(&mut rng), i)));
+    }
+    assert_eq!(tree.len(), proxies.iter().flatten().count());
+    assert!(tree.height() <= 24, "height {}", tree.height());
+    assert_eq!(tree_pairs(&tree), brute_force(&tree, &proxies));
+
+    // a box query
+    let query = Aabb::new(glm::vec3(-5.0, -5.0, -5.0), glm::vec3(5.0, 5.0, 5.0));
+    let mut found = Vec::new();
+    tree.query(&query, |proxy| found.push(tree.data(proxy)));
+    found.sort_unstable();
+    let mut expected: Vec<_> = proxies
+        .iter()
+        .flatten()
+        .filter(|&&p| tree.aabb(p).overlaps(&query))
+        .map(|&p| tree.data(p))
+        .collect();
+    expected.sort_unstable();
+    assert_eq!(found, expected);
+}
+
+#[test]
+fn small_moves_stay_in_the_fat_box() {
+    let mut tree = DynamicTree::new();
+    let aabb = Aabb::new(glm::vec3(0.0, 0.0, 0.0), glm::vec3(1.0, 1.0, 1.0));
+    let proxy = tree.insert(aabb.fattened(0.1), 0);
+    let nudged = Aabb::new(glm::vec3(0.05, 0.0, 0.0), glm::vec3(1.05, 1.0, 1.0));
+    assert!(!tree.update(proxy, &nudged, 0.1));
+    let moved = Aabb::new(glm::vec3(0.5, 0.0, 0.0), glm::vec3(1.5, 1.0, 1.0));
+    assert!(tree.update(proxy, &moved, 0.1));
+    assert_eq!(*tree.aabb(proxy), moved.fattened(0.1));
+}
+
+#[test]
+fn boxes_turn_with_their_transform() {
+    let aabb = Aabb::new(glm::vec3(-1.0, -0.5, -0.25), glm::vec3(1.0, 0.5, 0.25));
+    let turned = aabb.transformed(
+        &(glm::translation(&glm::vec3(0.0, 0.0, 3.0))
+            * glm::rotation(std::f32::consts::FRAC_PI_2, &glm::Vec3::z())),
+    );
+    assert!((turned.min - glm::vec3(-0.5, -1.0, 2.75)).abs().max() < 1e-6);
+    assert!((turned.max - glm::vec3(0.5, 1.0, 3.25)).abs().max() < 1e-6);
+    assert_eq!(aabb.min_along(&glm::vec3(0.0, 0.0, 1.0)), -0.25);
+    assert!(Aabb::empty().is_empty());
+    assert!(!Aabb::empty().overlaps(&aabb));
+    assert_eq!(Aabb::empty().union(&aabb), aabb);
+}
+
+fn pairs_after_a_step(world: &mut PhysicsWorld) -> Vec<(ColliderId, ColliderId)> {
+    world.set_gravity(glm::Vec3::zeros());
+    world.step(world.timestep().dt());
+    world.candidate_pairs().to_vec()
+}
+
+#[test]
+fn world_pairs_touching_colliders() {
+    let mut world = PhysicsWorld::default();
+    let plane = world.add_collider(ground());
+    let low = add_ball(
+        &mut world,
+        glm::vec3(0.0, 0.0, 0.4),
+        0.5,
+        Material::default(),
+    );
+    let touching = add_ball(
+        &mut world,
+        glm::vec3(0.9, 0.0, 0.4),
+        0.5,
+        Material::default(),
+    );
+    let high = add_ball(
+        &mut world,
+        glm::vec3(0.0, 0.0, 5.0),
+        0.5,
+        Material::default(),
+    );
+    // a second shape on the same body as `high`, which never pairs with it
+    let same_body = world.add_collider(Collider::on(
+        world.collider(high).body().unwrap(),
+        ball(0.5),
+        glm::translation(&glm::vec3(0.5, 0.0, 0.0)),
+    ));
+    let filtered = {
+        let body = world.add_body(RigidBody::default().with_position(glm::vec3(0.0, 0.9, 0.4)));
+        world.add_collider(Collider::on(body, ball(0.5), glm::Mat4::identity()).with_filter(2, !1))
+    };
+    // fixed shapes only pair with things that move
+    let post = world.add_collider(Collider::fixed(ball(0.5), glm::Mat4::identity()));
+    let pairs = pairs_after_a_step(&mut world);
+    assert_eq!(
+        pairs,
+        vec![
+            (plane, low),
+            (plane, touching),
+            (low, touching),
+            (low, post),
+            (touching, post),
+        ]
+    );
+    assert!(!pairs.iter().any(|&(a, b)| a == same_body || b == same_body));
+    assert!(!pairs.iter().any(|&(a, b)| a == filtered || b == filtered));
+    assert!(!pairs.iter().any(|&(a, b)| a == high || b == high));
+}
+
+#[test]
+fn sleeping_bodies_pair_only_with_awake_ones() {
+    let mut world = PhysicsWorld::default();
+    let a = add_ball(
+        &mut world,
+        glm::vec3(0.0, 0.0, 0.0),
+        0.5,
+        Material::default(),
+    );
+    let b = add_ball(
+        &mut world,
+        glm::vec3(0.9, 0.0, 0.0),
+        0.5,
+        Material::default(),
+    );
+    for id in [a, b] {
+        let body = world.collider(id).body().unwrap();
+        world.body_mut(body).sleep();
+    }
+    assert!(pairs_after_a_step(&mut world).is_empty());
+    let body = world.collider(b).body().unwrap();
+    world.body_mut(body).wake();
+    assert_eq!(pairs_after_a_step(&mut world), vec![(a, b)]);
+}
+
+// three links in a row, each box overlapping its neighbours
+const CHAIN: &str = r#"<robot name="chain">
+  <link name="a"><collision><geometry><box size="1.2 0.2 0.2"/></geometry></collision></link>
+  <link name="b"><collision><geometry><box size="1.2 0.2 0.2"/></geometry></collision></link>
+  <link name="c"><collision><geometry><box size="1.2 0.2 0.2"/></geometry></collision></link>
+  <joint name="ab" type="revolute">
+    <origin xyz="0.5 0 0"/>
+    <parent link="a"/>
+    <child link="b"/>
+    <axis xyz="0 0 1"/>
+    <limit effort="1" lower="-3" upper="3" velocity="1"/>
+  </joint>
+  <joint name="bc" type="revolute">
+    <origin xyz="0.5 0 0"/>
+    <parent link="b"/>
+    <child link="c"/>
+    <axis xyz="0 0 1"/>
+    <limit effort="1" lower="-3" upper="3" velocity="1"/>
+  </joint>
+</robot>"#;
+
+#[test]
+fn adjacent_links_never_collide() {
+    let mut world = PhysicsWorld::default();
+    let robot = world.add_robot(
+        RobotDescriptor::from_str(CHAIN).unwrap(),
+        glm::Mat4::identity(),
+    );
+    assert_eq!(world.colliders().len(), 3);
+    assert!(pairs_after_a_step(&mut world).is_empty());
+    world.robot_mut(robot).self_collision = true;
+    // only the two ends, which no joint connects
+    let links: Vec<_> = pairs_after_a_step(&mut world)
+        .into_iter()
+        .map(|(a, b)| (world.collider(a).attachment, world.collider(b).attachment))
+        .collect();
+    assert_eq!(
+        links,
+        vec![(
+            Attachment::Link { robot, link: 0 },
+            Attachment::Link { robot, link: 2 }
+        )]
+    );
+}
+
+#[test]
+fn hundreds_of_bodies() {
+    let mut rng = StdRng::seed_from_u64(9);
+    let mut world = PhysicsWorld::default();
+    let positions: Vec<glm::Vec3> = (0..500)
+        .map(|_| {
+            glm::vec3(
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(-10.0..10.0),
+                rng.gen_range(0.0..10.0),
+            )
+        })
+        .collect();
+    let ids: Vec<_> = positions
+        .iter()
+        .map(|p| add_ball(&mut world, *p, 0.5, Material::default()))
+        .collect();
+    let pairs = pairs_after_a_step(&mut world);
+    // every pair of balls that touch, plus some within the margin
+    let margin = BroadPhase::default().margin();
+    for (i, a) in positions.iter().enumerate() {
+        for (j, b) in positions.iter().enumerate().skip(i + 1) {
+            let d = (a - b).abs().max();
+            if glm::distance(a, b) < 1.0 {
+                assert!(pairs.contains(&(ids[i], ids[j])));
+            }
+            if d > 1.0 + 2.0 * margin + 0.1 {
+                assert!(!pairs.contains(&(ids[i], ids[j])));
+            }
+        }
+    }
+}
@@ -0,0 +1,58 @@
+// Fixtures shared by the integration tests; each test crate uses some of them
+#![allow(dead_code)]
+
+use nalgebra_glm as glm;
+use std::path::PathBuf;
+use wgpu_robotic_simulator::physics::{Collider, ColliderId, Material, PhysicsWorld, RigidBody};
+use wgpu_robotic_simulator::urdf::{GeometryShape, RobotDescriptor};
+use wgpu_robotic_simulator::world::WorldShape;
+
+pub const G: f32 = 9.80665;
+
+pub fn assert_close(a: &glm::Vec3, b: &glm::Vec3, tolerance: f32) {
+    assert!(glm::length(&(a - b)) < tolerance, "{:?} != {:?}", a, b);
+}
+
+// steps the world one timestep at a time for `seconds`
+pub fn run(world: &mut PhysicsWorld, seconds: f32) {
+    for _ in 0..(seconds / world.timestep().dt()).round() as usize {
+        world.step(world.timestep().dt());
+    }
+}
+
+// a fixed 10 x 10 plane through the origin, facing up
+pub fn ground() -> Collider {
+    Collider::fixed(
+        WorldShape::Plane {
+            normal: glm::Vec3::z(),
+            size: glm::vec2(10.0, 10.0),
+        },
+        glm::Mat4::identity(),
+    )
+}
+
+pub fn ball(radius: f32) -> WorldShape {
+    WorldShape::Solid(GeometryShape::Sphere { radius })
+}
+
+// a ball of `radius` on a body of its own at `position`
+pub fn add_ball(
+    world: &mut PhysicsWorld,
+    position: glm::Vec3,
+    radius: f32,
+    material: Material,
+) -> ColliderId {
+    let body = world.add_body(RigidBody::default().with_position(position));
+    world.add_collider(
+        Collider::on(body, ball(radius), glm::Mat4::identity()).with_material(material),
+    )
+}
+
+pub fn xarm() -> RobotDescriptor {
+    // only the kinematics matter, any mesh will do for the ones not checked in
+    let s = std::fs::read_to_string("assets/xarm.urdf").unwrap();
+    RobotDescriptor::from_str_with_resolver(&s, &|_: &str| {
+        Some(PathBuf::from("assets/meshes/3D_model_of_a_Cube.stl"))
+    })
+    .unwrap()
+}
@@ -0,0 +1,233 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::{add_ball, ground, run, G};
+use wgpu_robotic_simulator::kinematics::JointId;
+use wgpu_robotic_simulator::physics::{
+    BodyId, Collider, ContactSolver, FixedTimestep, Material, Phase, PhysicsWorld, RigidBody,
+};
+use wgpu_robotic_simulator::urdf::{GeometryShape, RobotDescriptor};
+use wgpu_robotic_simulator::world::WorldShape;
+
+// the ground tilted about y
+fn add_ground(world: &mut PhysicsWorld, tilt: f32, material: Material) {
+    let mut ground = ground().with_material(material);
+    ground.pose = glm::rotation(tilt, &glm::Vec3::y());
+    world.add_collider(ground);
+}
+
+fn add_box(world: &mut PhysicsWorld, pose: glm::Mat4, material: Material) -> BodyId {
+    let size = glm::vec3(0.4, 0.4, 0.4);
+    let inertia = glm::Mat3::identity() * (2.0 * 0.32 / 12.0);
+    let body = world.add_body(
+        RigidBody::new(2.0, inertia)
+            .with_position(pose.column(3).xyz())
+            .with_orientation(glm::to_quat(&pose)),
+    );
+    world.add_collider(
+        Collider::on(
+            body,
+            WorldShape::Solid(GeometryShape::Box { size }),
+            glm::Mat4::identity(),
+        )
+        .with_material(material),
+    );
+    body
+}
+
+#[test]
+fn boxes_come_to_rest_on_the_ground() {
+    let mut world = PhysicsWorld::default();
+    add_ground(&mut world, 0.0, Material::default());
+    let cube = add_box(
+        &mut world,
+        glm::translation(&glm::vec3(0.0, 0.0, 0.5)),
+        Material::default(),
+    );
+    run(&mut world, 2.0);
+    let body = world.body(cube);
+    let slop = world.contact_solver().slop();
+    assert!(body.position.z < 0.2 + 1e-3, "{}", body.position.z);
+    assert!(body.position.z > 0.2 - slop - 2e-3, "{}", body.position.z);
+    assert!(glm::length(&body.linear_velocity) < 0.05);
+    assert!(glm::length(&body.angular_velocity) < 0.05);
+    // the impulses of a step carry the weight over it
+    let dt = world.timestep().dt();
+    let impulses = world.contact_solver().impulses();
+    assert_eq!(impulses.len(), 4);
+    let total: f32 = impulses.iter().map(|i| i.normal_impulse).sum();
+    if !body.is_sleeping() {
+        assert!(
+            (total - 2.0 * G * dt).abs() < 0.05 * 2.0 * G * dt,
+            "{}",
+            total
+        );
+    }
+}
+
+#[test]
+fn balls_bounce_with_their_restitution() {
+    let mut world = PhysicsWorld::default();
+    add_ground(&mut world, 0.0, Material::new(0.5, 0.8));
+    let ball = add_ball(
+        &mut world,
+        glm::vec3(0.0, 0.0, 1.1),
+        0.1,
+        Material::new(0.5, 0.0),
+    );
+    let ball = world.collider(ball).body().unwrap();
+    // up to the first bounce and the top of the next
+    let mut bounced = false;
+    let mut top: f32 = 0.0;
+    for _ in 0..2 * 240 {
+        world.step(world.timestep().dt());
+        let body = world.body(ball);
+        bounced |= body.linear_velocity.z > 0.0;
+        if bounced {
+            top = top.max(body.position.z);
+        }
+    }
+    let expected = 0.1 + 0.8 * 0.8 * 1.0;
+    assert!((top - expected).abs() < 0.05, "{} vs {}", top, expected);
+
+    // nothing bounces without restitution
+    let mut world = PhysicsWorld::default();
+    add_ground(&mut world, 0.0, Material::default());
+    let ball = add_ball(
+        &mut world,
+        glm::vec3(0.0, 0.0, 1.1),
+        0.1,
+        Material::default(),
+    );
+    let ball = world.collider(ball).body().unwrap();
+    run(&mut world, 1.0);
+    assert!(world.body(ball).position.z < 0.1 + 0.01);
+}
+
+#[test]
+fn friction_holds_boxes_on_slopes_until_too_steep() {
+    let tilt = 0.3_f32;
+    // tan 0.3 = 0.31
+    for (friction, slides) in [(0.6, false), (0.1, true)] {
+        let mut world = PhysicsWorld::default();
+        // both sides have the same friction, which they keep
+        add_ground(&mut world, tilt, Material::new(friction, 0.0));
+        let up = glm::rotation(tilt, &glm::Vec3::y());
+        let start = up * glm::translation(&glm::vec3(0.0, 0.0, 0.2));
+        let cube = add_box(&mut world, start, Material::new(friction, 0.0));
+        run(&mut world, 0.5);
+        let v0 = world.body(cube).linear_velocity;
+        run(&mut world, 0.5);
+        let body = world.body(cube);
+        let downhill = glm::mat4_to_mat3(&up) * glm::Vec3::x();
+        let downhill = if downhill.z > 0.0 {
+            -downhill
+        } else {
+            downhill
+        };
+        if slides {
+            // a = g (sin - mu cos)
+            let a = G * (tilt.sin() - friction * tilt.cos());
+            let gained = (body.linear_velocity - v0).dot(&downhill) / 0.5;
+            assert!((gained - a).abs() < 0.1 * a, "{} vs {}", gained, a);
+        } else {
+            assert!(glm::length(&body.linear_velocity) < 0.02);
+            let moved = glm::distance(&body.position, &start.column(3).xyz());
+            assert!(moved < 0.02, "{}", moved);
+        }
+    }
+}
+
+#[test]
+fn stacks_stand_with_warm_starting() {
+    let mut world = PhysicsWorld::default();
+    add_ground(&mut world, 0.0, Material::default());
+    let cubes: Vec<BodyId> = (0..4)
+        .map(|i| {
+            let at = glm::vec3(0.0, 0.0, 0.2 + 0.4 * i as f32);
+            add_box(&mut world, glm::translation(&at), Material::default())
+        })
+        .collect();
+    run(&mut world, 3.0);
+    for (i, cube) in cubes.iter().enumerate() {
+        let body = world.body(*cube);
+        assert!(body.position.xy().norm() < 0.01, "{:?}", body.position);
+        let expected = 0.2 + 0.4 * i as f32;
+        assert!(
+            (body.position.z - expected).abs() < 0.03,
+            "{} {}",
+            i,
+            body.position.z
+        );
+    }
+    // the same stack without warm starting and few iterations squashes more
+    let squash = |warm| {
+        let mut world = PhysicsWorld::default().with_contact_solver(
+            ContactSolver::new()
+                .with_iterations(4)
+                .with_warm_starting(warm),
+        );
+        add_ground(&mut world, 0.0, Material::default());
+        let top = (0..4)
+            .map(|i| {
+                let at = glm::vec3(0.0, 0.0, 0.2 + 0.4 * i as f32);
+                add_box(&mut world, glm::translation(&at), Material::default())
+            })
+            .last()
+            .unwrap();
+        run(&mut world, 1.0);
+        1.4 - world.body(top).position.z
+    };
+    assert!(squash(true) < squash(false));
+}
+
+#[test]
+fn the_little_dog_stands_on_the_ground() {
+    let robot = RobotDescriptor::from_file("assets/LittleDog.urdf")
+        .unwrap()
+        .with_floating_base();
+    // the light lower legs need short substeps to be held stiffly
+    let mut world =
+        PhysicsWorld::default().with_timestep(FixedTimestep::new(1.0 / 240.0).with_substeps(8));
+    add_ground(&mut world, 0.0, Material::new(1.0, 0.0));
+    // the feet a few millimetres up
+    let dog = world.add_robot(robot, glm::Mat4::identity());
+    let floating = world.robot(dog).state.id("body_floating").unwrap();
+    world
+        .robot_mut(dog)
+        .state
+        .set_positions(floating, &[0.0, 0.0, 0.19, 0.0, 0.0, 0.0, 1.0])
+        .unwrap();
+    // the joints held straight
+    let joints: Vec<JointId> = {
+        let state = &world.robot(dog).state;
+        state.ids().filter(|&id| id != floating).collect()
+    };
+    world.add_hook(Phase::ApplyForces, move |world, _| {
+        let state = &mut world.robot_mut(dog).state;
+        for &joint in &joints {
+            let (q, v) = (
+                state.position(joint).unwrap(),
+                state.velocity(joint).unwrap(),
+            );
+            state.set_effort(joint, -10.0 * q - 0.02 * v).unwrap();
+        }
+    });
+    // settled well within half a second
+    run(&mut world, 0.5);
+    let state = &world.robot(dog).state;
+    let base = state.positions(floating).unwrap();
+    let standing = 0.1736 + 0.0103;
+    assert!((base[2] - standing).abs() < 0.01, "{:?}", base);
+    assert!(base[0].abs() < 0.01 && base[1].abs() < 0.01, "{:?}", base);
+    let speed: f32 = state
+        .velocities(floating)
+        .unwrap()
+        .iter()
+        .map(|v| v * v)
+        .sum();
+    assert!(speed.sqrt() < 0.05, "{}", speed.sqrt());
+    // on all four feet
+    assert_eq!(world.contacts().len(), 4);
+}
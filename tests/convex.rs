@@ -0,0 +1,291 @@
+extern crate nalgebra_glm as glm;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::Arc;
+use wgpu_robotic_simulator::geometry::{
+    BoxMesh, ConvexDecomposition, ConvexHull, DecompositionParams, Polyhedron, TriMesh,
+};
+use wgpu_robotic_simulator::physics::{
+    collide, Collider, CollisionShape, PhysicsWorld, RigidBody, CONTACT_MARGIN,
+};
+use wgpu_robotic_simulator::urdf::GeometryShape;
+use wgpu_robotic_simulator::world::WorldShape;
+
+// Three unit cubes in an L, a unit high, with the notch over (1.5, 1.5)
+fn l_shape() -> Polyhedron {
+    let mut mesh = TriMesh::default();
+    let at = |x: f32, y: f32, z: f32| glm::vec3(x, y, z);
+    for [x, y] in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] {
+        let square = [
+            at(x, y, 0.0),
+            at(x + 1.0, y, 0.0),
+            at(x + 1.0, y + 1.0, 0.0),
+            at(x, y + 1.0, 0.0),
+        ];
+        mesh.add_rectangle([square[3], square[2], square[1], square[0]]);
+        mesh.add_rectangle(square.map(|p| p + glm::Vec3::z()));
+    }
+    let outline = [
+        [0.0, 0.0],
+        [2.0, 0.0],
+        [2.0, 1.0],
+        [1.0, 1.0],
+        [1.0, 2.0],
+        [0.0, 2.0],
+    ];
+    for k in 0..outline.len() {
+        let ([x0, y0], [x1, y1]) = (outline[k], outline[(k + 1) % outline.len()]);
+        mesh.add_rectangle([
+            at(x0, y0, 0.0),
+            at(x1, y1, 0.0),
+            at(x1, y1, 1.0),
+            at(x0, y0, 1.0),
+        ]);
+    }
+    Polyhedron::from(mesh)
+}
+
+fn closed_and_outward(hull: &ConvexHull, points: &[glm::Vec3]) {
+    // every edge once each way, and V - E + F = 2
+    let mut edges: Vec<(usize, usize)> = hull
+        .faces
+        .iter()
+        .flat_map(|f| [(f[0], f[1]), (f[1], f[2]), (f[2], f[0])])
+        .collect();
+    edges.sort_unstable();
+    for &(a, b) in &edges {
+        assert!(edges.binary_search(&(b, a)).is_ok());
+    }
+    assert_eq!(edges.len(), 3 * hull.faces.len());
+    assert_eq!(hull.points.len() + hull.faces.len(), edges.len() / 2 + 2);
+    for p in points {
+        assert!(hull.contains(p, 1e-4), "{:?}", p);
+    }
+}
+
+#[test]
+fn hulls_wrap_every_point() {
+    let mut rng = StdRng::seed_from_u64(25);
+    // points in a cube, with its corners
+    let mut points: Vec<glm::Vec3> = (0..500)
+        .map(|_| {
+            glm::vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            )
+        })
+        .collect();
+    for i in 0..8 {
+        let corner = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+        points.push(glm::vec3(corner(1), corner(2), corner(4)));
+    }
+    let hull = ConvexHull::new(&points).unwrap();
+    closed_and_outward(&hull, &points);
+    assert_eq!(hull.points.len(), 8);
+    assert!((hull.volume() - 8.0).abs() < 1e-4);
+
+    // on a sphere every point is on the hull
+    let sphere: Vec<glm::Vec3> = (0..300)
+        .map(|_| {
+            let d = glm::vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            glm::normalize(&d) * 2.0
+        })
+        .collect();
+    let hull = ConvexHull::new(&sphere).unwrap();
+    closed_and_outward(&hull, &sphere);
+    assert_eq!(hull.points.len(), 300);
+    let ball = 4.0 / 3.0 * std::f32::consts::PI * 8.0;
+    assert!(hull.volume() < ball && hull.volume() > 0.9 * ball);
+
+    // nothing to wrap
+    let flat: Vec<glm::Vec3> = sphere.iter().map(|p| glm::vec3(p.x, p.y, 0.0)).collect();
+    assert_eq!(ConvexHull::new(&flat), None);
+    assert_eq!(ConvexHull::new(&points[..3]), None);
+}
+
+#[test]
+fn hulls_of_meshes_draw_facing_out() {
+    let cube = Polyhedron::from(TriMesh::create_box(glm::vec3(1.0, 2.0, 3.0)));
+    let hull = cube.convex_hull().unwrap();
+    assert_eq!(hull.points.len(), 8);
+    assert!((hull.volume() - 6.0).abs() < 1e-4);
+    let drawn = hull.polyhedron();
+    assert_eq!(drawn.verts.len(), 3 * hull.faces.len());
+    for v in &drawn.verts {
+        assert!(v.normal.dot(&v.position) > 0.0, "{:?}", v);
+    }
+    let l = l_shape();
+    let hull = l.convex_hull().unwrap();
+    // the notch filled in, up to the diagonal
+    assert!((hull.volume() - 3.5).abs() < 1e-4);
+}
+
+#[test]
+fn concave_meshes_come_apart_into_convex_pieces() {
+    let params = DecompositionParams::default();
+    let l = l_shape();
+    let decomposition = l.convex_decomposition(&params);
+    assert!(
+        (2..=3).contains(&decomposition.hulls.len()),
+        "{}",
+        decomposition.hulls.len()
+    );
+    let volume: f32 = decomposition.hulls.iter().map(|h| h.volume()).sum();
+    assert!((volume - 3.0).abs() < 0.1, "{}", volume);
+    // each corner of the mesh in some piece, and the notch in none
+    for v in &l.verts {
+        assert!(decomposition
+            .hulls
+            .iter()
+            .any(|h| h.contains(&v.position, 1e-3)));
+    }
+    let notch = glm::vec3(1.4, 1.4, 0.5);
+    assert!(decomposition.hulls.iter().all(|h| !h.contains(&notch, 0.0)));
+
+    // convex already
+    let cube = Polyhedron::from(TriMesh::create_box(glm::vec3(1.0, 1.0, 1.0)));
+    let decomposition = cube.convex_decomposition(&params);
+    assert_eq!(decomposition.hulls.len(), 1);
+    assert!((decomposition.hulls[0].volume() - 1.0).abs() < 1e-3);
+
+    // held to the hulls allowed
+    let one = DecompositionParams {
+        max_hulls: 1,
+        ..params
+    };
+    assert_eq!(l.convex_decomposition(&one).hulls.len(), 1);
+}
+
+#[test]
+fn decompositions_cache_to_disk() {
+    let dir = std::env::temp_dir().join("decompositions_cache_to_disk");
+    let _ = std::fs::remove_dir_all(&dir);
+    // the cache makes its own directories
+    let path = dir.join("meshes/l.obj");
+    let params = DecompositionParams::default();
+    let l = l_shape();
+
+    let made = ConvexDecomposition::cached(&l, &params, &path).unwrap();
+    assert_eq!(
+        made.fingerprint,
+        ConvexDecomposition::fingerprint(&l, &params)
+    );
+    assert_eq!(ConvexDecomposition::load(&path).unwrap(), made);
+    let files = std::fs::read_dir(dir.join("meshes")).unwrap().count();
+    assert_eq!(files, 1);
+    // a stale cache is made again
+    let stale = ConvexDecomposition {
+        fingerprint: 1,
+        hulls: Vec::new(),
+    };
+    stale.save(&path).unwrap();
+    assert_eq!(
+        ConvexDecomposition::cached(&l, &params, &path).unwrap(),
+        made
+    );
+    assert_eq!(ConvexDecomposition::load(&path).unwrap(), made);
+    // other parameters are another decomposition
+    let finer = DecompositionParams {
+        resolution: 40,
+        ..params
+    };
+    assert_ne!(
+        ConvexDecomposition::fingerprint(&l, &finer),
+        made.fingerprint
+    );
+
+    std::fs::write(&path, "v 1 2 3\n").unwrap();
+    let error = ConvexDecomposition::load(&path).unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    // so is a file cut short, even with the right fingerprint
+    let header = format!("# convex decomposition {:016x}\n", made.fingerprint);
+    for cut in [
+        "o hull_0\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        "o hull_0\nv 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\n",
+    ] {
+        std::fs::write(&path, format!("{}{}", header, cut)).unwrap();
+        let error = ConvexDecomposition::load(&path).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(
+            ConvexDecomposition::cached(&l, &params, &path).unwrap(),
+            made
+        );
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn decomposed_meshes_collide_as_their_pieces() {
+    let l = l_shape();
+    let decomposition = Arc::new(l.convex_decomposition(&DecompositionParams::default()));
+    let mesh = WorldShape::Solid(GeometryShape::Mesh {
+        filename: "l.obj".to_string(),
+        scale: None,
+    });
+    let mut world = PhysicsWorld::default();
+    let body = world.add_body(RigidBody::default());
+    let whole = Collider::on(body, mesh.clone(), glm::Mat4::identity()).with_geometry(l.clone());
+    let pieces = whole.clone().with_decomposition(decomposition);
+    let whole = CollisionShape::from_collider(&whole).unwrap();
+    let pieces = CollisionShape::from_collider(&pieces).unwrap();
+    assert!(matches!(whole, CollisionShape::Hull { .. }));
+    assert!(matches!(pieces, CollisionShape::Compound { .. }));
+    // a ball in the notch is inside the one hull, clear of the pieces
+    let ball = CollisionShape::Sphere { radius: 0.3 };
+    let pose = glm::translation(&glm::vec3(1.5, 1.5, 0.5));
+    let identity = glm::Mat4::identity();
+    assert!(!collide(&whole, &identity, &ball, &pose, CONTACT_MARGIN).is_empty());
+    assert!(collide(&pieces, &identity, &ball, &pose, CONTACT_MARGIN).is_empty());
+    // and against the inside of the notch from either side
+    let pose = glm::translation(&glm::vec3(1.25, 1.5, 0.5));
+    for contacts in [
+        collide(&pieces, &identity, &ball, &pose, CONTACT_MARGIN),
+        collide(&ball, &pose, &pieces, &identity, CONTACT_MARGIN),
+    ] {
+        assert!(!contacts.is_empty());
+        for c in contacts {
+            assert!((c.normal.x.abs() - 1.0).abs() < 1e-3, "{:?}", c);
+        }
+    }
+
+    // the world decomposes its own, once each
+    let mut world = PhysicsWorld::default();
+    for x in [0.0, 5.0] {
+        let body = world.add_body(RigidBody::default().with_position(glm::vec3(x, 0.0, 0.0)));
+        world.add_collider(
+            Collider::on(body, mesh.clone(), glm::Mat4::identity()).with_geometry(l.clone()),
+        );
+    }
+    world.add_collider(Collider::fixed(mesh, glm::Mat4::identity()).with_geometry(l));
+    let count = world
+        .decompose_meshes(&DecompositionParams::default(), None)
+        .unwrap();
+    assert_eq!(count, 2);
+    let [a, b, fixed] = [0, 1, 2].map(|i| world.colliders()[i].decomposition.clone());
+    assert!(Arc::ptr_eq(a.as_ref().unwrap(), b.as_ref().unwrap()));
+    assert!(fixed.is_none());
+}
+
+#[test]
+fn little_dog_parts_load_and_come_apart() {
+    // faces written v/vt/vn
+    let leg = Polyhedron::from("assets/meshes/front_left_lower.obj".to_string());
+    let hull = leg.convex_hull().unwrap();
+    assert!(hull.volume() > 1.0, "{}", hull.volume());
+    let decomposition = leg.convex_decomposition(&DecompositionParams::default());
+    assert!(decomposition.hulls.len() >= 2);
+    let volume: f32 = decomposition.hulls.iter().map(|h| h.volume()).sum();
+    assert!(volume < 0.8 * hull.volume(), "{} {}", volume, hull.volume());
+    for v in &leg.verts {
+        assert!(decomposition
+            .hulls
+            .iter()
+            .any(|h| h.contains(&v.position, 1e-3)));
+    }
+}
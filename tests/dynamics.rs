@@ -0,0 +1,340 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::G;
+use std::str::FromStr;
+use wgpu_robotic_simulator::dynamics::RobotDynamics;
+use wgpu_robotic_simulator::kinematics::JointState;
+use wgpu_robotic_simulator::urdf::{JointType, RobotDescriptor};
+
+// a rod swinging about y, its centre of mass 0.5 along x
+const PENDULUM: &str = r#"<robot name="pendulum">
+  <link name="base"/>
+  <link name="rod">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="2"/>
+      <inertia ixx="0.01" iyy="0.2" izz="0.2" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <joint name="hinge" type="continuous">
+    <parent link="base"/>
+    <child link="rod"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+</robot>"#;
+
+// two links turning about z, lengths 1 and 0.8 with centres of mass half way
+const ARM: &str = r#"<robot name="arm">
+  <link name="base"/>
+  <link name="upper">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="3"/>
+      <inertia ixx="0.01" iyy="0.25" izz="0.25" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <link name="lower">
+    <inertial>
+      <origin xyz="0.4 0 0" rpy="0.3 0 0"/>
+      <mass value="2"/>
+      <inertia ixx="0.01" iyy="0.1" izz="0.1" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <joint name="shoulder" type="continuous">
+    <parent link="base"/>
+    <child link="upper"/>
+    <axis xyz="0 0 1"/>
+  </joint>
+  <joint name="elbow" type="continuous">
+    <origin xyz="1 0 0"/>
+    <parent link="upper"/>
+    <child link="lower"/>
+    <axis xyz="0 0 1"/>
+  </joint>
+</robot>"#;
+
+fn assert_all_close(a: &[f32], b: &[f32]) {
+    assert_eq!(a.len(), b.len());
+    for (x, y) in a.iter().zip(b) {
+        assert!((x - y).abs() < 1e-3 * (1.0 + y.abs()), "{:?} != {:?}", a, b);
+    }
+}
+
+#[test]
+fn pendulum_torques() {
+    let robot = RobotDescriptor::from_str(PENDULUM).unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let mut state = JointState::new(&robot);
+    let (m, l, i) = (2.0, 0.5, 0.2);
+    for theta in [0.0f32, 0.4, -1.2, 2.5] {
+        state.set_position("hinge", theta).unwrap();
+        state.set_velocity("hinge", 1.5).unwrap();
+        // turning about y takes x towards -z, so gravity helps positive angles
+        let gravity = -m * G * l * theta.cos();
+        assert_all_close(&dynamics.gravity_compensation(&state), &[gravity]);
+        assert_all_close(&dynamics.coriolis(&state), &[0.0]);
+        let tau = dynamics.inverse_dynamics(&state, &[3.0]).unwrap();
+        assert_all_close(&tau, &[(i + m * l * l) * 3.0 + gravity]);
+    }
+}
+
+#[test]
+fn two_link_arm_matches_the_closed_form() {
+    let g = 4.0;
+    let robot = RobotDescriptor::from_str(ARM).unwrap();
+    let dynamics = RobotDynamics::new(&robot).with_gravity(glm::vec3(0.0, -g, 0.0));
+    let (m1, m2, l1, c1, c2, i1, i2) = (3.0, 2.0, 1.0, 0.5, 0.4, 0.25, 0.1);
+    let mut state = JointState::new(&robot);
+    for (q, qd, qdd) in [
+        ([0.3f32, 0.7f32], [1.0f32, -2.0f32], [0.5f32, 1.5f32]),
+        ([-1.1, 2.0], [0.2, 0.9], [-3.0, 0.0]),
+    ] {
+        state.set_position_vector(&q).unwrap();
+        state.set_velocity_vector(&qd).unwrap();
+        let m11 = m1 * c1 * c1 + i1 + m2 * (l1 * l1 + c2 * c2 + 2.0 * l1 * c2 * q[1].cos()) + i2;
+        let m12 = m2 * (c2 * c2 + l1 * c2 * q[1].cos()) + i2;
+        let m22 = m2 * c2 * c2 + i2;
+        let h = -m2 * l1 * c2 * q[1].sin();
+        let coriolis = [
+            h * qd[1] * qd[1] + 2.0 * h * qd[0] * qd[1],
+            -h * qd[0] * qd[0],
+        ];
+        let gravity = [
+            (m1 * c1 + m2 * l1) * g * q[0].cos() + m2 * c2 * g * (q[0] + q[1]).cos(),
+            m2 * c2 * g * (q[0] + q[1]).cos(),
+        ];
+        assert_all_close(&dynamics.coriolis(&state), &coriolis);
+        assert_all_close(&dynamics.gravity_compensation(&state), &gravity);
+        let expected = [
+            m11 * qdd[0] + m12 * qdd[1] + coriolis[0] + gravity[0],
+            m12 * qdd[0] + m22 * qdd[1] + coriolis[1] + gravity[1],
+        ];
+        assert_all_close(&dynamics.inverse_dynamics(&state, &qdd).unwrap(), &expected);
+    }
+}
+
+#[test]
+fn floating_body_is_held_against_gravity() {
+    let robot = RobotDescriptor::from_str(
+        r#"<robot name="free">
+          <link name="world"/>
+          <link name="body">
+            <inertial><mass value="3"/><inertia ixx="1" iyy="1" izz="1" ixy="0" ixz="0" iyz="0"/></inertial>
+          </link>
+          <joint name="free" type="floating"><parent link="world"/><child link="body"/></joint>
+        </robot>"#,
+    )
+    .unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let state = JointState::new(&robot);
+    assert_all_close(
+        &dynamics.gravity_compensation(&state),
+        &[0.0, 0.0, 3.0 * G, 0.0, 0.0, 0.0],
+    );
+    let tau = dynamics
+        .inverse_dynamics(&state, &[1.0, 0.0, 0.0, 0.0, 0.0, 2.0])
+        .unwrap();
+    assert_all_close(&tau, &[3.0, 0.0, 3.0 * G, 0.0, 0.0, 2.0]);
+}
+
+#[test]
+fn little_dog_bias_is_gravity_and_coriolis() {
+    let robot = RobotDescriptor::from_file("assets/LittleDog.urdf").unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let mut state = JointState::new(&robot);
+    let n = state.nv();
+    let q: Vec<f32> = (0..state.nq()).map(|i| (i as f32 * 0.37).sin()).collect();
+    let qd: Vec<f32> = (0..n).map(|i| (i as f32 * 0.91).cos()).collect();
+    state.set_position_vector(&q).unwrap();
+    state.set_velocity_vector(&qd).unwrap();
+    let sum: Vec<f32> = std::iter::zip(
+        dynamics.gravity_compensation(&state),
+        dynamics.coriolis(&state),
+    )
+    .map(|(g, c)| g + c)
+    .collect();
+    assert_all_close(&dynamics.bias(&state), &sum);
+    assert_all_close(
+        &dynamics.inverse_dynamics(&state, &vec![0.0; n]).unwrap(),
+        &sum,
+    );
+    assert!(dynamics.inverse_dynamics(&state, &[0.0]).is_err());
+}
+
+// a pendulum hanging from a slider, the rod on a damped hinge with limits
+const SWING: &str = r#"<robot name="swing">
+  <link name="base"/>
+  <link name="cart">
+    <inertial><mass value="1"/><inertia ixx="0.1" iyy="0.1" izz="0.1" ixy="0" ixz="0" iyz="0"/></inertial>
+  </link>
+  <link name="rod">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="2"/>
+      <inertia ixx="0.01" iyy="0.2" izz="0.2" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <joint name="slider" type="prismatic">
+    <parent link="base"/>
+    <child link="cart"/>
+    <axis xyz="1 0 0"/>
+    <limit effort="10" lower="-1" upper="1" velocity="1"/>
+  </joint>
+  <joint name="hinge" type="revolute">
+    <parent link="cart"/>
+    <child link="rod"/>
+    <axis xyz="0 1 0"/>
+    <limit effort="10" lower="-0.5" upper="1" velocity="1"/>
+    <dynamics damping="0.3" friction="0.1"/>
+  </joint>
+</robot>"#;
+
+fn some_state(robot: &RobotDescriptor, seed: f32) -> JointState {
+    let mut state = JointState::new(robot);
+    let mut q: Vec<f32> = (0..state.nq())
+        .map(|i| 0.5 * (i as f32 * 0.37 + seed).sin())
+        .collect();
+    // keep floating orientations unit quaternions
+    for id in state.ids().collect::<Vec<_>>() {
+        if let (Some(JointType::Floating), Some(p)) =
+            (state.joint_type(id), state.position_offset(id))
+        {
+            let rotation = glm::quat_normalize(&glm::quat(q[p + 3], q[p + 4], q[p + 5], 1.0));
+            q[p + 3..p + 7].copy_from_slice(rotation.coords.as_slice());
+        }
+    }
+    let qd: Vec<f32> = (0..state.nv())
+        .map(|i| (i as f32 * 0.91 + seed).cos())
+        .collect();
+    state.set_position_vector(&q).unwrap();
+    state.set_velocity_vector(&qd).unwrap();
+    state
+}
+
+// the mass matrix against inverse dynamics, and forward dynamics against both
+fn check_consistency(robot: &RobotDescriptor) {
+    let dynamics = RobotDynamics::new(robot);
+    let state = some_state(robot, 0.3);
+    let n = state.nv();
+    let bias = dynamics.bias(&state);
+    let m = dynamics.mass_matrix(&state);
+    let scale = m.abs().max().max(1.0);
+    for i in 0..n {
+        let mut qdd = vec![0.0; n];
+        qdd[i] = 1.0;
+        let tau = dynamics.inverse_dynamics(&state, &qdd).unwrap();
+        for r in 0..n {
+            let expected = tau[r] - bias[r];
+            assert!(
+                (m[(r, i)] - expected).abs() < 1e-3 * scale,
+                "{}: M[{}, {}] = {}, inverse dynamics gives {}",
+                robot.name.as_deref().unwrap_or_default(),
+                r,
+                i,
+                m[(r, i)],
+                expected
+            );
+        }
+    }
+    let torques: Vec<f32> = (0..n).map(|i| (i as f32 * 1.3).sin()).collect();
+    let qdd = dynamics.forward_dynamics(&state, &torques).unwrap();
+    // light legs on a heavy body make large accelerations, so round-off in
+    // the round trip grows with them
+    let size = scale * qdd.iter().fold(1.0f32, |a, x| a.max(x.abs()));
+    let back = dynamics.inverse_dynamics(&state, &qdd).unwrap();
+    for (x, y) in back.iter().zip(&torques) {
+        assert!(
+            (x - y).abs() < 1e-5 * size + 1e-3,
+            "{:?} != {:?}",
+            back,
+            torques
+        );
+    }
+}
+
+#[test]
+fn mass_matrix_and_forward_dynamics_agree_with_inverse_dynamics() {
+    check_consistency(&RobotDescriptor::from_str(ARM).unwrap());
+    check_consistency(&RobotDescriptor::from_str(SWING).unwrap());
+    check_consistency(
+        &RobotDescriptor::from_file("assets/LittleDog.urdf")
+            .unwrap()
+            .with_floating_base(),
+    );
+}
+
+#[test]
+fn mimic_joints_share_the_dynamics_of_their_leader() {
+    let robot = RobotDescriptor::from_str(&SWING.replace(
+        "<dynamics damping",
+        r#"<mimic joint="slider" multiplier="2" offset="0.1"/><dynamics damping"#,
+    ))
+    .unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let state = some_state(&robot, 1.0);
+    assert_eq!(state.nv(), 1);
+    let qdd = dynamics.forward_dynamics(&state, &[0.7]).unwrap();
+    assert_all_close(&dynamics.inverse_dynamics(&state, &qdd).unwrap(), &[0.7]);
+}
+
+#[test]
+fn floating_base_falls_freely() {
+    let robot = RobotDescriptor::from_str(PENDULUM)
+        .unwrap()
+        .with_floating_base();
+    let dynamics = RobotDynamics::new(&robot);
+    let mut state = JointState::new(&robot);
+    let dt = 1e-3;
+    for _ in 0..500 {
+        dynamics.step(&mut state, dt).unwrap();
+    }
+    // the body and the rod fall together, the hinge does not move
+    let v = state.velocities("base_floating").unwrap();
+    assert_all_close(v, &[0.0, 0.0, -G * 0.5, 0.0, 0.0, 0.0]);
+    let z = state.positions("base_floating").unwrap()[2];
+    assert!((z + 0.5 * G * 0.25).abs() < 0.01, "fell to {}", z);
+    assert!(state.position("hinge").unwrap().abs() < 1e-3);
+}
+
+#[test]
+fn damping_slows_a_spinning_rod() {
+    // turning about z, so gravity does not act on the hinge
+    let robot = RobotDescriptor::from_str(&PENDULUM.replace(
+        r#"<axis xyz="0 1 0"/>"#,
+        r#"<axis xyz="0 0 1"/><dynamics damping="0.4"/>"#,
+    ))
+    .unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let mut state = JointState::new(&robot);
+    state.set_velocity("hinge", 2.0).unwrap();
+    let inertia = 0.2 + 2.0 * 0.5 * 0.5;
+    let dt = 1e-3;
+    for _ in 0..1000 {
+        dynamics.step(&mut state, dt).unwrap();
+    }
+    let expected = 2.0 * (-0.4f32 / inertia).exp();
+    let v = state.velocity("hinge").unwrap();
+    assert!(
+        (v - expected).abs() < 0.01 * expected,
+        "{} != {}",
+        v,
+        expected
+    );
+}
+
+#[test]
+fn joints_stop_at_their_limits() {
+    let robot = RobotDescriptor::from_str(SWING).unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let mut state = JointState::new(&robot);
+    state.set_effort("slider", 5.0).unwrap();
+    for _ in 0..3000 {
+        dynamics.step(&mut state, 1e-3).unwrap();
+    }
+    // gravity swings the rod down into its upper limit, the push takes the cart to its end
+    assert_eq!(state.position("hinge"), Some(1.0));
+    assert_eq!(state.position("slider"), Some(1.0));
+    assert_eq!(state.velocity("slider"), Some(0.0));
+}
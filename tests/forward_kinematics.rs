@@ -0,0 +1,131 @@
+extern crate nalgebra_glm as glm;
+
+use std::str::FromStr;
+use wgpu_robotic_simulator::kinematics::JointState;
+use wgpu_robotic_simulator::urdf::{Origin, RobotDescriptor};
+
+// the bodies of the arm each off its link frame in their own way
+const ARM: &str = r#"<robot name="arm">
+  <link name="base"/>
+  <link name="arm">
+    <inertial>
+      <origin xyz="0 0 0.2" rpy="0 0.1 0"/>
+      <mass value="1"/>
+      <inertia ixx="0.01" ixy="0" ixz="0" iyy="0.01" iyz="0" izz="0.01"/>
+    </inertial>
+    <visual>
+      <origin xyz="0.1 0 0.3" rpy="0.4 -0.2 0.7"/>
+      <geometry><box size="0.1 0.1 0.4"/></geometry>
+    </visual>
+    <collision>
+      <origin xyz="0 0.05 0.2" rpy="-0.3 0.5 0.1"/>
+      <geometry><cylinder radius="0.05" length="0.4"/></geometry>
+    </collision>
+  </link>
+  <joint name="shoulder" type="revolute">
+    <origin xyz="0.1 0.2 0.3" rpy="0.3 0.2 0.1"/>
+    <parent link="base"/>
+    <child link="arm"/>
+    <axis xyz="0 1 0"/>
+    <limit effort="10" lower="-2" upper="2" velocity="1"/>
+  </joint>
+</robot>"#;
+
+fn assert_same_pose(a: &glm::Mat4, b: &glm::Mat4) {
+    assert!((a - b).abs().max() < 1e-5, "{} {}", a, b);
+}
+
+#[test]
+fn build_with_poses_every_frame_as_forward_kinematics_does() {
+    let mut robot = RobotDescriptor::from_str(ARM).unwrap();
+    let mut state = JointState::new(&robot);
+    state.set_position("shoulder", 0.6).unwrap();
+    robot.build_with(&state);
+    let poses = robot.forward_kinematics(&state);
+    for (link, pose) in std::iter::zip(&robot.links, poses.iter()) {
+        assert_same_pose(&link.inertial.transform.tmatrix, &pose.inertial);
+        assert_same_pose(&link.visual.transform.tmatrix, &pose.visual);
+        assert_same_pose(&link.collision.transform.tmatrix, &pose.collision);
+    }
+
+    // URDF rotations: roll about x, then pitch about y, then yaw about z
+    let rpy = |r: f32, p: f32, y: f32| {
+        glm::rotation(y, &glm::Vec3::z())
+            * glm::rotation(p, &glm::Vec3::y())
+            * glm::rotation(r, &glm::Vec3::x())
+    };
+    let link = glm::translation(&glm::vec3(0.1, 0.2, 0.3))
+        * rpy(0.3, 0.2, 0.1)
+        * glm::rotation(0.6, &glm::Vec3::y());
+    let arm = &poses[1];
+    assert_same_pose(&arm.link, &link);
+    let visual = link * glm::translation(&glm::vec3(0.1, 0.0, 0.3)) * rpy(0.4, -0.2, 0.7);
+    let collision = link * glm::translation(&glm::vec3(0.0, 0.05, 0.2)) * rpy(-0.3, 0.5, 0.1);
+    assert_same_pose(&arm.visual, &visual);
+    assert_same_pose(&arm.collision, &collision);
+    assert_same_pose(
+        &arm.inertial,
+        &(link * Origin::new(glm::vec3(0.0, 0.0, 0.2), Some(glm::vec3(0.0, 0.1, 0.0))).matrix()),
+    );
+
+    // and again from another position, not from the last one
+    state.set_position("shoulder", -1.0).unwrap();
+    robot.build_with(&state);
+    let poses = robot.forward_kinematics(&state);
+    assert_same_pose(
+        &robot.links[1].collision.transform.tmatrix,
+        &poses[1].collision,
+    );
+    assert_same_pose(
+        &robot.links[0].visual.transform.tmatrix,
+        &glm::Mat4::identity(),
+    );
+}
+
+#[test]
+fn build_keeps_the_joint_positions_already_set() {
+    let mut robot = RobotDescriptor::from_str(ARM).unwrap();
+    let mut state = JointState::new(&robot);
+    state.set_position("shoulder", 0.6).unwrap();
+    let poses = robot.forward_kinematics(&state);
+    robot.set_joint_position(&[0.6], false);
+    robot.build();
+    for (link, pose) in std::iter::zip(&robot.links, poses.iter()) {
+        assert_same_pose(&link.inertial.transform.tmatrix, &pose.inertial);
+        assert_same_pose(&link.visual.transform.tmatrix, &pose.visual);
+        assert_same_pose(&link.collision.transform.tmatrix, &pose.collision);
+    }
+    // relative positions add up
+    robot.set_joint_position(&[-0.2], true);
+    robot.build();
+    state.set_position("shoulder", 0.4).unwrap();
+    assert_same_pose(
+        &robot.links[1].visual.transform.tmatrix,
+        &robot.forward_kinematics(&state)[1].visual,
+    );
+}
+
+// the slide axis is written two long, as URDF allows
+const SLIDE: &str = r#"<robot name="slide">
+  <link name="rail"/>
+  <link name="carriage"/>
+  <joint name="slide" type="prismatic">
+    <parent link="rail"/>
+    <child link="carriage"/>
+    <axis xyz="0 0 2"/>
+    <limit effort="10" lower="-1" upper="1" velocity="1"/>
+  </joint>
+</robot>"#;
+
+#[test]
+fn prismatic_joints_move_the_distance_given() {
+    let robot = RobotDescriptor::from_str(SLIDE).unwrap();
+    let mut state = JointState::new(&robot);
+    state.set_position("slide", 0.3).unwrap();
+    let poses = robot.forward_kinematics(&state);
+    assert_same_pose(&poses[1].link, &glm::translation(&glm::vec3(0.0, 0.0, 0.3)));
+    // a unit along the axis per unit of joint velocity
+    let jacobian = robot.jacobian(&state, 1, &glm::Vec3::zeros());
+    let column: Vec<f32> = jacobian.column(0).iter().copied().collect();
+    assert_eq!(column, [0.0, 0.0, 1.0, 0.0, 0.0, 0.0]);
+}
@@ -0,0 +1,164 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::xarm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wgpu_robotic_simulator::kinematics::{
+    IkError, IkMethod, IkSolver, IkStatus, IkTarget, JointState,
+};
+use wgpu_robotic_simulator::urdf::RobotDescriptor;
+
+const ARM: [&str; 5] = [
+    "base_joint",
+    "shoulder_joint",
+    "elbow_joint",
+    "wrist_joint",
+    "wrist_rotation_joint",
+];
+
+// the arm somewhere inside its limits
+fn random_arm(robot: &RobotDescriptor, rng: &mut StdRng) -> JointState {
+    let mut state = JointState::new(robot);
+    for name in ARM {
+        let limits = robot.joints[robot.joint_id(name).unwrap().index()]
+            .limits()
+            .unwrap();
+        let q = rng.gen_range(limits.lower() * 0.8..limits.upper() * 0.8);
+        state.set_position(name, q).unwrap();
+    }
+    state
+}
+
+// pose of `tip` in the frame of `base`
+fn relative_pose(robot: &RobotDescriptor, state: &JointState, base: &str, tip: &str) -> glm::Mat4 {
+    let poses = robot.forward_kinematics(state);
+    glm::inverse(&poses.by_name(base).unwrap().link) * poses.by_name(tip).unwrap().link
+}
+
+fn assert_within_limits(robot: &RobotDescriptor, state: &JointState) {
+    for name in ARM {
+        let joint = &robot.joints[robot.joint_id(name).unwrap().index()];
+        if let Some(l) = joint.limits() {
+            let q = state.position(name).unwrap();
+            assert!(
+                (l.lower()..=l.upper()).contains(&q),
+                "{} at {} outside [{}, {}]",
+                joint.name(),
+                q,
+                l.lower(),
+                l.upper()
+            );
+        }
+    }
+}
+
+#[test]
+fn reaches_poses_of_the_arm() {
+    let robot = xarm();
+    let solver = IkSolver::new(&robot, "base_link", "virtual_grasp_link")
+        .unwrap()
+        .with_restarts(8, 7);
+    let mut rng = StdRng::seed_from_u64(1);
+    let seed = JointState::new(&robot);
+    for _ in 0..10 {
+        let goal = random_arm(&robot, &mut rng);
+        let target = IkTarget::pose(relative_pose(
+            &robot,
+            &goal,
+            "base_link",
+            "virtual_grasp_link",
+        ));
+        let solution = solver.solve(&target, &seed);
+        assert!(solution.converged(), "{:?}", solution);
+        assert!(solution.position_error <= 1e-4);
+        assert!(solution.orientation_error <= 1e-3);
+        assert_within_limits(&robot, &solution.state);
+        let reached = relative_pose(&robot, &solution.state, "base_link", "virtual_grasp_link");
+        assert!((reached - target.pose).abs().max() < 1e-3);
+    }
+}
+
+#[test]
+fn position_only_targets_with_damped_least_squares() {
+    let robot = xarm();
+    let solver = IkSolver::new(&robot, "base_link", "virtual_grasp_link")
+        .unwrap()
+        .with_method(IkMethod::DampedLeastSquares { damping: 0.01 })
+        .with_max_iterations(500)
+        .with_restarts(4, 3);
+    let mut rng = StdRng::seed_from_u64(2);
+    for _ in 0..10 {
+        let goal = random_arm(&robot, &mut rng);
+        let pose = relative_pose(&robot, &goal, "base_link", "virtual_grasp_link");
+        let target = IkTarget::position(pose.column(3).xyz());
+        let solution = solver.solve(&target, &JointState::new(&robot));
+        assert!(solution.converged(), "{:?}", solution);
+        assert_eq!(solution.orientation_error, 0.0);
+        assert_within_limits(&robot, &solution.state);
+    }
+}
+
+#[test]
+fn weights_leave_components_free() {
+    let robot = xarm();
+    let solver = IkSolver::new(&robot, "base_link", "virtual_grasp_link").unwrap();
+    let mut rng = StdRng::seed_from_u64(3);
+    let goal = random_arm(&robot, &mut rng);
+    let pose = relative_pose(&robot, &goal, "base_link", "virtual_grasp_link");
+    // any height will do
+    let target = IkTarget::position(pose.column(3).xyz() + glm::vec3(0.0, 0.0, 5.0))
+        .with_weights([1.0, 1.0, 0.0, 0.0, 0.0, 0.0]);
+    let solution = solver.solve(&target, &goal);
+    assert!(solution.converged(), "{:?}", solution);
+}
+
+#[test]
+fn reports_the_residual_of_unreachable_targets() {
+    let robot = xarm();
+    let solver = IkSolver::new(&robot, "base_link", "virtual_grasp_link")
+        .unwrap()
+        .with_restarts(2, 1);
+    let target = IkTarget::position(glm::vec3(10.0, 0.0, 0.0));
+    let solution = solver.solve(&target, &JointState::new(&robot));
+    assert_ne!(solution.status, IkStatus::Converged);
+    assert!(solution.residual > 9.0 && solution.residual < 10.0);
+    assert_eq!(solution.residual, solution.position_error);
+    assert_eq!(solution.restarts, 2);
+    assert_within_limits(&robot, &solution.state);
+}
+
+#[test]
+fn mimic_joints_move_the_joint_they_follow() {
+    let robot = xarm();
+    let solver = IkSolver::new(&robot, "hand_link", "right_finger_base_link").unwrap();
+    let mut goal = JointState::new(&robot);
+    goal.set_position("left_knuckle_joint", 0.8).unwrap();
+    let target = IkTarget::pose(relative_pose(
+        &robot,
+        &goal,
+        "hand_link",
+        "right_finger_base_link",
+    ));
+    let solution = solver.solve(&target, &JointState::new(&robot));
+    assert!(solution.converged(), "{:?}", solution);
+    let q = solution.state.position("left_knuckle_joint").unwrap();
+    assert!((q - 0.8).abs() < 1e-3);
+}
+
+#[test]
+fn base_must_be_above_tip() {
+    let robot = xarm();
+    assert_eq!(
+        IkSolver::new(&robot, "hand_link", "base_link").unwrap_err(),
+        IkError::NotAChain {
+            base: "hand_link".to_owned(),
+            tip: "base_link".to_owned()
+        }
+    );
+    assert!(matches!(
+        IkSolver::new(&robot, "base_link", "gripper").unwrap_err(),
+        IkError::UnknownLink(_)
+    ));
+}
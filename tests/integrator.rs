@@ -0,0 +1,205 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::G;
+use std::str::FromStr;
+use wgpu_robotic_simulator::dynamics::RobotDynamics;
+use wgpu_robotic_simulator::kinematics::JointState;
+use wgpu_robotic_simulator::physics::{
+    ExplicitEuler, FixedTimestep, FreeBody, Integrable, Integrator, RungeKutta4, SemiImplicitEuler,
+    VelocityVerlet,
+};
+use wgpu_robotic_simulator::urdf::RobotDescriptor;
+
+// a point mass on a massless rod of length 1, theta from hanging straight down
+#[derive(Debug, Clone, Copy)]
+struct Pendulum {
+    theta: f32,
+    omega: f32,
+}
+
+impl Pendulum {
+    fn energy(&self) -> f32 {
+        0.5 * self.omega * self.omega - G * self.theta.cos()
+    }
+}
+
+impl Integrable for Pendulum {
+    fn positions(&self) -> Vec<f32> {
+        vec![self.theta]
+    }
+    fn velocities(&self) -> Vec<f32> {
+        vec![self.omega]
+    }
+    fn set_state(&mut self, positions: &[f32], velocities: &[f32]) {
+        self.theta = positions[0];
+        self.omega = velocities[0];
+    }
+    fn accelerations(&self) -> Vec<f32> {
+        vec![-G * self.theta.sin()]
+    }
+    fn displace(&self, positions: &[f32], velocities: &[f32], dt: f32) -> Vec<f32> {
+        vec![positions[0] + velocities[0] * dt]
+    }
+}
+
+// largest relative change of the pendulum's energy over 10 s
+fn pendulum_drift(integrator: &dyn Integrator, dt: f32) -> f32 {
+    let mut pendulum = Pendulum {
+        theta: 1.0,
+        omega: 0.0,
+    };
+    let start = pendulum.energy();
+    let mut drift: f32 = 0.0;
+    for _ in 0..(10.0 / dt) as usize {
+        integrator.step(&mut pendulum, dt);
+        drift = drift.max(((pendulum.energy() - start) / start).abs());
+    }
+    drift
+}
+
+#[test]
+fn pendulum_energy_drift() {
+    // explicit Euler spirals outwards, the others stay on the orbit
+    assert!(pendulum_drift(&ExplicitEuler, 0.01) > 0.5);
+    assert!(pendulum_drift(&SemiImplicitEuler, 0.01) < 0.05);
+    assert!(pendulum_drift(&VelocityVerlet, 0.01) < 1e-3);
+    assert!(pendulum_drift(&RungeKutta4, 0.01) < 1e-4);
+    // second order for Verlet
+    let (coarse, fine) = (
+        pendulum_drift(&VelocityVerlet, 0.02),
+        pendulum_drift(&VelocityVerlet, 0.01),
+    );
+    assert!(coarse / fine > 3.0, "{} {}", coarse, fine);
+}
+
+fn projectile() -> FreeBody {
+    let mass = 2.0;
+    FreeBody {
+        posn: glm::vec3(0.0, 0.0, 1.0),
+        vel: glm::vec3(3.0, 0.0, 10.0),
+        force: glm::vec3(0.0, 0.0, -G * mass),
+        mass,
+        theta: 0.0,
+        omega: 0.5,
+    }
+}
+
+fn projectile_energy(body: &FreeBody) -> f32 {
+    0.5 * body.mass * glm::length2(&body.vel) + body.mass * G * body.posn.z
+}
+
+#[test]
+fn projectile_energy_drift() {
+    let (dt, steps) = (0.01, 200);
+    let t = dt * steps as f32;
+    let start = projectile_energy(&projectile());
+    let run = |integrator: &dyn Integrator| {
+        let mut body = projectile();
+        for _ in 0..steps {
+            integrator.step(&mut body, dt);
+        }
+        body
+    };
+    // exact under constant force
+    for integrator in [&VelocityVerlet as &dyn Integrator, &RungeKutta4] {
+        let body = run(integrator);
+        let expected = glm::vec3(3.0 * t, 0.0, 1.0 + 10.0 * t - 0.5 * G * t * t);
+        assert!(
+            glm::length(&(body.posn - expected)) < 1e-3,
+            "{:?}",
+            body.posn
+        );
+        assert!((body.theta - 0.5 * t).abs() < 1e-5);
+        assert!(((projectile_energy(&body) - start) / start).abs() < 1e-4);
+    }
+    // the Eulers are half a step off in height, one up and one down
+    let gained = projectile_energy(&run(&ExplicitEuler)) - start;
+    let lost = projectile_energy(&run(&SemiImplicitEuler)) - start;
+    let expected = 2.0 * G * G * t * dt / 2.0;
+    assert!((gained - expected).abs() < 0.05 * expected, "{}", gained);
+    assert!((lost + expected).abs() < 0.05 * expected, "{}", lost);
+}
+
+#[test]
+fn fixed_timestep_takes_whole_steps() {
+    let mut timestep = FixedTimestep::new(0.01).with_substeps(4);
+    let mut taken = Vec::new();
+    assert_eq!(timestep.advance(0.035, |dt| taken.push(dt)), 3);
+    assert_eq!(taken, vec![0.0025; 12]);
+    assert!((timestep.alpha() - 0.5).abs() < 1e-3);
+    // the remainder carries over
+    assert_eq!(timestep.advance(0.006, |_| {}), 1);
+    assert!((timestep.alpha() - 0.1).abs() < 1e-3);
+
+    // a long frame is cut short
+    let mut timestep = FixedTimestep::new(0.01).with_max_steps(5);
+    assert_eq!(timestep.advance(1.0, |_| {}), 5);
+    assert!(timestep.alpha() < 1.0);
+
+    // substeps of a free body add up to the step
+    let mut body = projectile();
+    let mut timestep = FixedTimestep::new(0.1).with_substeps(10);
+    assert_eq!(timestep.integrate(0.1, &RungeKutta4, &mut body), 1);
+    assert!((body.posn.x - 0.3).abs() < 1e-5);
+
+    // steps that would never end, or never start, are held to the shortest
+    for dt in [0.0, -0.01, f32::NAN, f32::INFINITY] {
+        let mut timestep = FixedTimestep::new(dt).with_max_steps(5);
+        assert!(timestep.dt() > 0.0 && timestep.dt() < 1e-3, "{}", dt);
+        assert_eq!(timestep.advance(0.01, |_| {}), 5);
+        assert!(timestep.alpha().is_finite());
+    }
+}
+
+// a rod swinging about y, its centre of mass 0.5 along x
+const PENDULUM: &str = r#"<robot name="pendulum">
+  <link name="base"/>
+  <link name="rod">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="2"/>
+      <inertia ixx="0.01" iyy="0.2" izz="0.2" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <joint name="hinge" type="continuous">
+    <parent link="base"/>
+    <child link="rod"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+</robot>"#;
+
+fn rod_energy(robot: &RobotDescriptor, dynamics: &RobotDynamics, state: &JointState) -> f32 {
+    let qd = state.velocity("hinge").unwrap();
+    let m = dynamics.mass_matrix(state);
+    let com = robot.forward_kinematics(state)[1].inertial.column(3).xyz();
+    0.5 * m[(0, 0)] * qd * qd + 2.0 * G * com.z
+}
+
+#[test]
+fn robots_step_with_any_integrator() {
+    let robot = RobotDescriptor::from_str(PENDULUM).unwrap();
+    let dynamics = RobotDynamics::new(&robot);
+    let drift = |integrator: &dyn Integrator| {
+        let mut state = JointState::new(&robot);
+        let start = rod_energy(&robot, &dynamics, &state);
+        for _ in 0..200 {
+            dynamics.step_with(integrator, &mut state, 0.01).unwrap();
+        }
+        (rod_energy(&robot, &dynamics, &state) - start).abs()
+    };
+    assert!(drift(&RungeKutta4) < 1e-3);
+    assert!(drift(&VelocityVerlet) < 1e-2);
+    assert!(drift(&ExplicitEuler) > 0.1);
+
+    // step is semi-implicit Euler
+    let (mut a, mut b) = (JointState::new(&robot), JointState::new(&robot));
+    for _ in 0..10 {
+        dynamics.step(&mut a, 0.01).unwrap();
+        dynamics
+            .step_with(&SemiImplicitEuler, &mut b, 0.01)
+            .unwrap();
+    }
+    assert_eq!(a.position_vector(), b.position_vector());
+}
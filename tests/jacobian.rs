@@ -0,0 +1,223 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::xarm;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use wgpu_robotic_simulator::kinematics::{Jacobian, JointState};
+use wgpu_robotic_simulator::urdf::{JointType, RobotDescriptor};
+
+// every joint type, a mimic joint and bodies off the link origins
+const MIXED: &str = r#"<robot name="mixed">
+  <link name="world"/>
+  <link name="cart"/>
+  <link name="body"/>
+  <link name="slider"/>
+  <link name="wheel"/>
+  <link name="finger"/>
+  <link name="twin"/>
+  <joint name="plane" type="planar">
+    <origin xyz="0.1 0 0.2" rpy="0.3 0 0"/>
+    <parent link="world"/>
+    <child link="cart"/>
+    <axis xyz="0 0.6 0.8"/>
+  </joint>
+  <joint name="free" type="floating">
+    <origin xyz="0 0.3 0" rpy="0 0.5 0.2"/>
+    <parent link="cart"/>
+    <child link="body"/>
+  </joint>
+  <joint name="slide" type="prismatic">
+    <origin xyz="0.2 0 0.1" rpy="0.1 0.2 0.3"/>
+    <parent link="body"/>
+    <child link="slider"/>
+    <axis xyz="0 0 2"/>
+    <limit effort="10" lower="-0.5" upper="0.5" velocity="1"/>
+  </joint>
+  <joint name="spin" type="continuous">
+    <origin xyz="0 0.1 0.3"/>
+    <parent link="slider"/>
+    <child link="wheel"/>
+    <axis xyz="1 1 0"/>
+  </joint>
+  <joint name="grip" type="revolute">
+    <origin xyz="0.05 0 0" rpy="0 0 1.2"/>
+    <parent link="wheel"/>
+    <child link="finger"/>
+    <axis xyz="0 1 0"/>
+    <limit effort="10" lower="-1" upper="1" velocity="1"/>
+  </joint>
+  <joint name="grip_twin" type="revolute">
+    <origin xyz="0.1 0.02 0"/>
+    <parent link="finger"/>
+    <child link="twin"/>
+    <axis xyz="0 0 1"/>
+    <limit effort="10" lower="-2" upper="2" velocity="1"/>
+    <mimic joint="grip" multiplier="-1.5" offset="0.2"/>
+  </joint>
+</robot>"#;
+
+const STEP: f32 = 1e-3;
+
+// a configuration away from the joint limits
+fn random_state(robot: &RobotDescriptor, rng: &mut StdRng) -> JointState {
+    let mut state = JointState::new(robot);
+    for id in state.ids().collect::<Vec<_>>() {
+        let joint = &robot.joints[id.index()];
+        let q = match (joint.joint_type(), joint.limits()) {
+            (JointType::Revolute | JointType::Prismatic, Some(l)) => {
+                let margin = 0.1 * (l.upper() - l.lower());
+                vec![rng.gen_range(l.lower() + margin..l.upper() - margin)]
+            }
+            (JointType::Floating, _) => {
+                let mut q: Vec<f32> = (0..7).map(|_| rng.gen_range(-1.0..1.0)).collect();
+                let norm = q[3..].iter().map(|x| x * x).sum::<f32>().sqrt();
+                q[3..].iter_mut().for_each(|x| *x /= norm);
+                q
+            }
+            (joint_type, _) => (0..joint_type.position_dofs())
+                .map(|_| rng.gen_range(-3.0..3.0))
+                .collect(),
+        };
+        state.set_positions(id, &q).unwrap();
+    }
+    state
+}
+
+// `state` moved by `h` along velocity coordinate `v`
+fn moved(state: &JointState, v: usize, h: f32) -> JointState {
+    let mut state = state.clone();
+    let id = state
+        .ids()
+        .find(|&id| {
+            let start = state.velocity_offset(id).unwrap();
+            let len = state.joint_type(id).unwrap().velocity_dofs();
+            (start..start + len).contains(&v)
+        })
+        .unwrap();
+    let k = v - state.velocity_offset(id).unwrap();
+    let mut q = state.positions(id).unwrap().to_vec();
+    if state.joint_type(id) == Some(JointType::Floating) && k >= 3 {
+        // angular velocity in the frame the joint is attached in
+        let mut axis = glm::Vec3::zeros();
+        axis[k - 3] = 1.0;
+        let rotation = glm::quat_angle_axis(h, &axis) * glm::quat(q[3], q[4], q[5], q[6]);
+        q[3..].copy_from_slice(rotation.coords.as_slice());
+    } else {
+        q[k] += h;
+    }
+    state.set_positions(id, &q).unwrap();
+    state
+}
+
+// central differences of the world pose of `point` on `link`
+fn finite_difference(
+    robot: &RobotDescriptor,
+    state: &JointState,
+    link: usize,
+    point: &glm::Vec3,
+) -> Jacobian {
+    let mut jacobian = Jacobian::zeros(state.nv());
+    for v in 0..state.nv() {
+        let pose = |h: f32| {
+            let poses = robot.forward_kinematics(&moved(state, v, h));
+            *poses.link(link)
+        };
+        let (plus, minus) = (pose(STEP), pose(-STEP));
+        let linear = (plus * point.push(1.0) - minus * point.push(1.0)).xyz();
+        // the small rotation from minus to plus
+        let r = glm::mat4_to_mat3(&plus) * glm::mat4_to_mat3(&minus).transpose();
+        let angular = glm::vec3(
+            r[(2, 1)] - r[(1, 2)],
+            r[(0, 2)] - r[(2, 0)],
+            r[(1, 0)] - r[(0, 1)],
+        ) / 2.0;
+        for i in 0..3 {
+            jacobian[(i, v)] = linear[i] / (2.0 * STEP);
+            jacobian[(i + 3, v)] = angular[i] / (2.0 * STEP);
+        }
+    }
+    jacobian
+}
+
+fn assert_jacobians_agree(
+    robot: &RobotDescriptor,
+    link: usize,
+    analytic: &Jacobian,
+    numeric: &Jacobian,
+) {
+    let error = (analytic - numeric).abs().max();
+    let scale = numeric.abs().max().max(1.0);
+    assert!(
+        error < 5e-3 * scale,
+        "link {}: jacobian off by {}\nanalytic {}\nnumeric {}",
+        robot.links[link].link_name,
+        error,
+        analytic,
+        numeric
+    );
+}
+
+fn check_robot(robot: &RobotDescriptor, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    for _ in 0..5 {
+        let state = random_state(robot, &mut rng);
+        for link in 0..robot.links.len() {
+            let point = glm::vec3(
+                rng.gen_range(-0.2..0.2),
+                rng.gen_range(-0.2..0.2),
+                rng.gen_range(-0.2..0.2),
+            );
+            let analytic = robot.jacobian(&state, link, &point);
+            assert_jacobians_agree(
+                robot,
+                link,
+                &analytic,
+                &finite_difference(robot, &state, link, &point),
+            );
+            // the spatial Jacobian is the geometric one of the link point at the world origin
+            let pose = *robot.forward_kinematics(&state).link(link);
+            let origin = (glm::inverse(&pose) * glm::vec4(0.0, 0.0, 0.0, 1.0)).xyz();
+            assert_jacobians_agree(
+                robot,
+                link,
+                &robot.spatial_jacobian(&state, link),
+                &robot.jacobian(&state, link, &origin),
+            );
+        }
+    }
+}
+
+#[test]
+fn xarm_jacobians_match_finite_differences() {
+    check_robot(&xarm(), 1);
+}
+
+#[test]
+fn little_dog_jacobians_match_finite_differences() {
+    check_robot(
+        &RobotDescriptor::from_file("assets/LittleDog.urdf").unwrap(),
+        2,
+    );
+}
+
+#[test]
+fn every_joint_type_matches_finite_differences() {
+    check_robot(&RobotDescriptor::from_str(MIXED).unwrap(), 3);
+}
+
+#[test]
+fn jacobian_has_a_column_per_velocity_coordinate() {
+    let robot = RobotDescriptor::from_str(MIXED).unwrap();
+    let state = JointState::new(&robot);
+    let twin = robot.link_index("twin").unwrap();
+    let jacobian = robot.jacobian(&state, twin, &glm::Vec3::zeros());
+    // planar 3 + floating 6 + prismatic + continuous + revolute, the mimic joint adds to grip
+    assert_eq!(jacobian.ncols(), 12);
+    // links before a joint do not move with it
+    let cart = robot.link_index("cart").unwrap();
+    let jacobian = robot.jacobian(&state, cart, &glm::Vec3::zeros());
+    assert!(jacobian.columns(3, 9).iter().all(|&x| x == 0.0));
+}
@@ -0,0 +1,348 @@
+use std::str::FromStr;
+use std::sync::Mutex;
+use wgpu_robotic_simulator::kinematics::{JointState, JointStateError, LimitPolicy};
+use wgpu_robotic_simulator::urdf::RobotDescriptor;
+
+// twin follows the elbow at twice the angle and has tighter limits than
+// that gives it; triplet follows twin, so the elbow too
+const ARM: &str = r#"<robot name="arm">
+  <link name="base"/>
+  <link name="upper"/>
+  <link name="lower"/>
+  <link name="hand"/>
+  <link name="finger"/>
+  <link name="tip"/>
+  <joint name="elbow" type="revolute">
+    <parent link="base"/>
+    <child link="upper"/>
+    <axis xyz="0 0 1"/>
+    <limit effort="10" lower="-1" upper="1" velocity="2"/>
+  </joint>
+  <joint name="slide" type="prismatic">
+    <parent link="upper"/>
+    <child link="lower"/>
+    <axis xyz="1 0 0"/>
+    <limit effort="10" lower="0" upper="0.5" velocity="0.1"/>
+  </joint>
+  <joint name="wheel" type="continuous">
+    <parent link="lower"/>
+    <child link="hand"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+  <joint name="twin" type="revolute">
+    <parent link="hand"/>
+    <child link="finger"/>
+    <axis xyz="0 0 1"/>
+    <limit effort="10" lower="-1.5" upper="1.5" velocity="4"/>
+    <mimic joint="elbow" multiplier="2" offset="0.5"/>
+  </joint>
+  <joint name="triplet" type="continuous">
+    <parent link="finger"/>
+    <child link="tip"/>
+    <axis xyz="0 0 1"/>
+    <mimic joint="twin" multiplier="-1"/>
+  </joint>
+</robot>"#;
+
+fn state(policy: LimitPolicy) -> JointState {
+    let robot = RobotDescriptor::from_str(ARM).unwrap();
+    JointState::new(&robot).with_limit_policy(policy)
+}
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-6
+}
+
+// the warnings logged so far
+static WARNINGS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+struct Recorder;
+
+impl log::Log for Recorder {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= log::Level::Warn
+    }
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            WARNINGS.lock().unwrap().push(record.args().to_string());
+        }
+    }
+    fn flush(&self) {}
+}
+
+#[test]
+fn clamping_moves_positions_to_the_nearest_limit() {
+    let mut state = state(LimitPolicy::Clamp);
+    assert_eq!(state.limit_policy(), LimitPolicy::Clamp);
+    state.set_position("elbow", 3.0).unwrap();
+    assert_eq!(state.position("elbow"), Some(1.0));
+    state.set_position("slide", -0.2).unwrap();
+    assert_eq!(state.position("slide"), Some(0.0));
+    // no limits to keep to
+    state.set_position("wheel", 100.0).unwrap();
+    assert_eq!(state.position("wheel"), Some(100.0));
+    state.set_position_vector(&[-2.0, 0.7, 1.0]).unwrap();
+    assert_eq!(state.position_vector(), &[-1.0, 0.5, 1.0]);
+    // mimic joints keep to their own limits
+    state.set_position("elbow", 0.6).unwrap();
+    assert!(close(state.position("twin").unwrap(), 1.5));
+    // triplet follows the elbow itself, which twin's limits do not hold
+    assert!(close(state.position("triplet").unwrap(), -1.7));
+    state.set_position("elbow", -0.2).unwrap();
+    assert!(close(state.position("twin").unwrap(), 0.1));
+}
+
+#[test]
+fn the_error_policy_leaves_the_state_as_it_was() {
+    let mut state = state(LimitPolicy::Error);
+    state.set_position("elbow", 0.2).unwrap();
+    let before = state.clone();
+    assert_eq!(
+        state.set_position("elbow", 3.0),
+        Err(JointStateError::OutOfRange {
+            joint: "elbow".into(),
+            value: 3.0,
+            lower: -1.0,
+            upper: 1.0
+        })
+    );
+    assert_eq!(state, before);
+    assert!(state.set_position_vector(&[0.0, 0.6, 0.0]).is_err());
+    assert_eq!(state, before);
+    // in range itself, but it takes twin past its limit
+    match state.set_position("elbow", 0.6) {
+        Err(JointStateError::OutOfRange { joint, value, .. }) => {
+            assert_eq!(joint, "twin");
+            assert!(close(value, 1.7));
+        }
+        other => panic!("{:?}", other),
+    }
+    assert_eq!(state, before);
+    assert!(state.command_position("elbow", 0.6, 1.0).is_err());
+    assert_eq!(state, before);
+    state.set_position_vector(&[-0.5, 0.25, 4.0]).unwrap();
+    assert!(close(state.position("twin").unwrap(), -0.5));
+}
+
+#[test]
+fn the_warn_policy_takes_positions_as_they_are() {
+    log::set_logger(&Recorder).unwrap();
+    log::set_max_level(log::LevelFilter::Warn);
+    let mut state = state(LimitPolicy::Warn);
+    state.set_position("elbow", 3.0).unwrap();
+    assert_eq!(state.position("elbow"), Some(3.0));
+    assert!(close(state.position("twin").unwrap(), 6.5));
+    let warnings = WARNINGS.lock().unwrap().clone();
+    assert_eq!(warnings.len(), 2, "{:?}", warnings);
+    assert!(warnings[0].contains("\"elbow\""), "{}", warnings[0]);
+    assert!(warnings[1].contains("\"twin\""), "{}", warnings[1]);
+}
+
+#[test]
+fn commanded_positions_move_no_faster_than_the_velocity_limit() {
+    let mut state = state(LimitPolicy::Clamp);
+    // 0.1 m/s, so 0.05 a step
+    for step in 1..=8 {
+        state.command_position("slide", 0.4, 0.5).unwrap();
+        assert!(close(state.position("slide").unwrap(), 0.05 * step as f32));
+        assert!(close(state.velocity("slide").unwrap(), 0.1));
+    }
+    state.command_position("slide", 0.4, 0.5).unwrap();
+    assert!(close(state.position("slide").unwrap(), 0.4));
+    assert_eq!(state.velocity("slide"), Some(0.0));
+    // a short way at the speed it takes
+    state.command_position("elbow", -0.05, 0.1).unwrap();
+    assert!(close(state.velocity("elbow").unwrap(), -0.5));
+    // the target clamped first, then approached
+    state.command_position("slide", 2.0, 10.0).unwrap();
+    assert!(close(state.position("slide").unwrap(), 0.5));
+    // no velocity limit, straight there
+    state.command_position("wheel", 7.0, 0.5).unwrap();
+    assert_eq!(state.position("wheel"), Some(7.0));
+    assert!(close(state.velocity("wheel").unwrap(), 14.0));
+
+    state
+        .command_position_vector(&[1.0, 0.0, 7.0], 0.1)
+        .unwrap();
+    assert!(close(state.position("elbow").unwrap(), 0.15));
+    assert!(close(state.position("slide").unwrap(), 0.49));
+    assert!(matches!(
+        state.command_position("twin", 0.0, 0.1),
+        Err(JointStateError::MimicJoint(_))
+    ));
+    let mut strict = self::state(LimitPolicy::Error);
+    assert!(strict.command_position("slide", 0.6, 0.1).is_err());
+    assert_eq!(strict.position("slide"), Some(0.0));
+}
+
+#[test]
+fn commands_over_a_bad_timestep_are_rejected() {
+    let mut state = state(LimitPolicy::Clamp);
+    let before = state.clone();
+    for dt in [-0.1, f32::NAN, f32::INFINITY] {
+        match state.command_position("slide", 0.4, dt) {
+            Err(JointStateError::InvalidTimestep(got)) => {
+                assert!(got == dt || (got.is_nan() && dt.is_nan()))
+            }
+            other => panic!("{}: {:?}", dt, other),
+        }
+        assert!(matches!(
+            state.command_position_vector(&[0.1, 0.1, 0.1], dt),
+            Err(JointStateError::InvalidTimestep(_))
+        ));
+    }
+    assert_eq!(state, before);
+    // no time at all moves nothing further and leaves no speed
+    state.command_position("slide", 0.4, 0.0).unwrap();
+    assert_eq!(state.position("slide"), Some(0.0));
+    assert_eq!(state.velocity("slide"), Some(0.0));
+}
+
+#[test]
+fn mimic_joints_follow_their_leader() {
+    let mut state = state(LimitPolicy::Clamp);
+    let elbow = state.id("elbow").unwrap();
+    assert_eq!(state.mimic("twin"), Some((elbow, 2.0, 0.5)));
+    // chains fold onto the joint at the end
+    assert_eq!(state.mimic("triplet"), Some((elbow, -2.0, -0.5)));
+    assert!(state.is_mimic("triplet") && !state.is_mimic("elbow"));
+    assert_eq!(state.mimic_ids().count(), 2);
+    // at their offset to start with
+    assert_eq!(state.position("twin"), Some(0.5));
+    assert_eq!(state.position("triplet"), Some(-0.5));
+
+    state.set_position("elbow", 0.25).unwrap();
+    state.set_velocity("elbow", 0.3).unwrap();
+    assert!(close(state.position("twin").unwrap(), 1.0));
+    assert!(close(state.position("triplet").unwrap(), -1.0));
+    assert!(close(state.velocity("twin").unwrap(), 0.6));
+    assert!(close(state.velocity("triplet").unwrap(), -0.6));
+    // and only through it
+    assert_eq!(
+        state.set_position("twin", 0.0),
+        Err(JointStateError::MimicJoint("twin".into()))
+    );
+    assert_eq!(state.position_offset("twin"), None);
+    // integrating moves them along
+    state.integrate(&[-0.5, 0.0, 0.0], 0.5).unwrap();
+    assert!(close(state.position("twin").unwrap(), 0.5));
+    assert_eq!(state.position_vector().len(), 3);
+}
+
+// a body free in space carrying a hinge, and a cart on a planar joint; the
+// bracket is fixed and so takes no coordinates
+const MOBILE: &str = r#"<robot name="mobile">
+  <link name="world"/>
+  <link name="body"/>
+  <link name="arm"/>
+  <link name="bracket"/>
+  <link name="cart"/>
+  <joint name="free" type="floating">
+    <parent link="world"/>
+    <child link="body"/>
+  </joint>
+  <joint name="hinge" type="continuous">
+    <parent link="body"/>
+    <child link="arm"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+  <joint name="bolt" type="fixed">
+    <parent link="arm"/>
+    <child link="bracket"/>
+  </joint>
+  <joint name="floor" type="planar">
+    <parent link="world"/>
+    <child link="cart"/>
+    <axis xyz="0 0 1"/>
+  </joint>
+</robot>"#;
+
+#[test]
+fn floating_and_planar_joints_take_several_coordinates() {
+    let robot = RobotDescriptor::from_str(MOBILE).unwrap();
+    let mut state = JointState::new(&robot);
+    assert_eq!(
+        state.names().collect::<Vec<_>>(),
+        ["free", "hinge", "floor"]
+    );
+    // x y z i j k w, the angle, then x y and the angle about the axis
+    assert_eq!((state.nq(), state.nv()), (11, 10));
+    let offsets = |joint| (state.position_offset(joint), state.velocity_offset(joint));
+    assert_eq!(offsets("free"), (Some(0), Some(0)));
+    assert_eq!(offsets("hinge"), (Some(7), Some(6)));
+    assert_eq!(offsets("floor"), (Some(8), Some(7)));
+    assert_eq!(offsets("bolt"), (None, None));
+    assert_eq!(
+        state.position_vector(),
+        &[0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]
+    );
+    assert_eq!(state.positions("free").unwrap().len(), 7);
+    assert_eq!(state.velocities("free").unwrap().len(), 6);
+    assert_eq!(state.efforts("floor").unwrap().len(), 3);
+    // no single coordinate to give
+    assert_eq!(state.position("free"), None);
+    assert_eq!(state.velocity("floor"), None);
+
+    let half = std::f32::consts::FRAC_1_SQRT_2;
+    state
+        .set_positions("free", &[1.0, 2.0, 3.0, 0.0, 0.0, half, half])
+        .unwrap();
+    state.set_position("hinge", 0.5).unwrap();
+    state.set_positions("floor", &[4.0, 5.0, 0.25]).unwrap();
+    assert_eq!(
+        state.position_vector(),
+        &[1.0, 2.0, 3.0, 0.0, 0.0, half, half, 0.5, 4.0, 5.0, 0.25]
+    );
+    state
+        .set_velocities("free", &[1.0, 0.0, 0.0, 0.0, 0.0, 2.0])
+        .unwrap();
+    state.set_velocities("floor", &[0.0, -1.0, 0.5]).unwrap();
+    assert_eq!(
+        state.velocity_vector(),
+        &[1.0, 0.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, -1.0, 0.5]
+    );
+    assert_eq!(
+        state.set_positions("free", &[1.0, 2.0, 3.0]),
+        Err(JointStateError::WrongLength {
+            joint: Some("free".into()),
+            expected: 7,
+            got: 3
+        })
+    );
+    assert_eq!(
+        state.set_velocity_vector(&[0.0; 11]),
+        Err(JointStateError::WrongLength {
+            joint: None,
+            expected: 10,
+            got: 11
+        })
+    );
+    assert_eq!(
+        state.set_position("bolt", 1.0),
+        Err(JointStateError::UnknownJoint("bolt".into()))
+    );
+
+    // the poses read the coordinates the same way: the body at its position,
+    // turned a quarter about z, and the cart along x and y of the floor
+    let poses = robot.forward_kinematics(&state);
+    let body = poses[1].link;
+    assert!(close(body[(0, 3)], 1.0) && close(body[(1, 3)], 2.0) && close(body[(2, 3)], 3.0));
+    assert!(close(body[(1, 0)], 1.0) && close(body[(0, 1)], -1.0));
+    let cart = poses[4].link;
+    assert!(close(cart[(0, 3)], 4.0) && close(cart[(1, 3)], 5.0));
+    assert!(close(cart[(1, 0)], 0.25f32.sin()));
+
+    // integrating a quarter second: the body moves along x and turns a further
+    // half radian about z, the cart moves along -y and turns
+    let velocities = state.velocity_vector().to_vec();
+    state.integrate(&velocities, 0.25).unwrap();
+    let free = state.positions("free").unwrap();
+    assert!(close(free[0], 1.25));
+    let angle = std::f32::consts::FRAC_PI_2 + 0.5;
+    let turned = [0.0, 0.0, (angle / 2.0).sin(), (angle / 2.0).cos()];
+    for (q, expected) in free[3..].iter().zip(turned) {
+        assert!(close(*q, expected), "{:?}", free);
+    }
+    assert!(close(state.positions("floor").unwrap()[1], 4.75));
+    assert!(close(state.positions("floor").unwrap()[2], 0.375));
+}
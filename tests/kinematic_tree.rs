@@ -0,0 +1,113 @@
+use std::str::FromStr;
+use wgpu_robotic_simulator::kinematics::TopologyError;
+use wgpu_robotic_simulator::urdf::{RobotDescriptor, UrdfError};
+
+// a robot with the links named and a fixed joint "<parent>_<child>" for
+// each pair
+fn robot(links: &[&str], joints: &[(&str, &str)]) -> Result<RobotDescriptor, UrdfError> {
+    let mut urdf = String::from("<robot name=\"test\">\n");
+    for link in links {
+        urdf += &format!("  <link name=\"{}\"/>\n", link);
+    }
+    for (parent, child) in joints {
+        urdf += &format!(
+            "  <joint name=\"{0}_{1}\" type=\"fixed\">\n    <parent link=\"{0}\"/>\n    <child link=\"{1}\"/>\n  </joint>\n",
+            parent, child
+        );
+    }
+    urdf += "</robot>";
+    RobotDescriptor::from_str(&urdf)
+}
+
+fn topology_error(result: Result<RobotDescriptor, UrdfError>) -> TopologyError {
+    match result {
+        Err(UrdfError::Topology(error)) => error,
+        other => panic!("expected a topology error, got {:?}", other),
+    }
+}
+
+#[test]
+fn trees_know_their_shape() {
+    // the base given last, the tree still hangs from it
+    let robot = robot(
+        &["arm", "hand", "leg", "base"],
+        &[("base", "arm"), ("arm", "hand"), ("base", "leg")],
+    )
+    .unwrap();
+    let tree = robot.kinematic_tree();
+    assert_eq!(tree.root(), 3);
+    assert_eq!(tree.len(), 4);
+    assert_eq!(tree.children(3), &[0, 2]);
+    assert_eq!(tree.child_joints(3), &[0, 2]);
+    assert_eq!(tree.parent(1), Some(0));
+    assert_eq!(tree.parent_joint(1), Some(1));
+    assert_eq!(tree.parent_joint(3), None);
+    assert_eq!(tree.depth(1), 2);
+    assert_eq!(tree.topological_order(), &[3, 0, 2, 1]);
+    assert_eq!(tree.joint_path(1), vec![0, 1]);
+    assert!(tree.joint_path(3).is_empty());
+}
+
+#[test]
+fn loops_are_reported_in_order() {
+    let error = topology_error(robot(
+        &["base", "a", "b", "c"],
+        &[("a", "b"), ("b", "c"), ("c", "a")],
+    ));
+    assert_eq!(
+        error,
+        TopologyError::Cycle {
+            links: vec!["b".into(), "c".into(), "a".into()]
+        }
+    );
+    assert_eq!(error.to_string(), "joints form a cycle: b -> c -> a -> b");
+}
+
+#[test]
+fn links_have_one_parent() {
+    let error = topology_error(robot(
+        &["base", "a", "b", "c"],
+        &[("base", "a"), ("base", "b"), ("a", "c"), ("b", "c")],
+    ));
+    assert_eq!(
+        error,
+        TopologyError::MultipleParents {
+            link: "c".into(),
+            joints: vec!["a_c".into(), "b_c".into()]
+        }
+    );
+}
+
+#[test]
+fn every_link_hangs_from_the_root() {
+    // the first link is taken as the root, the other tree is left over
+    let error = topology_error(robot(
+        &["base", "a", "other", "b", "c"],
+        &[("base", "a"), ("other", "b"), ("b", "c")],
+    ));
+    assert_eq!(
+        error,
+        TopologyError::Disconnected {
+            root: "base".into(),
+            links: vec!["other".into(), "b".into(), "c".into()]
+        }
+    );
+    assert!(robot(&["base", "loose"], &[]).is_err());
+}
+
+#[test]
+fn joints_refer_to_links_the_robot_has() {
+    let mut robot = robot(&["base", "a"], &[("base", "a")]).unwrap();
+    robot.links.pop();
+    assert_eq!(
+        robot.update_kinematic_tree(),
+        Err(TopologyError::InvalidLink {
+            joint: "base_a".into(),
+            link: 1
+        })
+    );
+    assert_eq!(
+        RobotDescriptor::default().update_kinematic_tree(),
+        Err(TopologyError::NoLinks)
+    );
+}
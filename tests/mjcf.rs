@@ -0,0 +1,330 @@
+extern crate nalgebra_glm as glm;
+
+use std::f32::consts::PI;
+use wgpu_robotic_simulator::urdf::{
+    GeometryShape, Joint, JointType, RobotDescriptor, SourceLocation, UrdfError,
+};
+
+// a model of the given children of <mujoco>
+fn model(children: &str) -> RobotDescriptor {
+    RobotDescriptor::from_mjcf_str(&format!("<mujoco model=\"m\">\n{}\n</mujoco>", children))
+        .unwrap()
+}
+
+fn error(children: &str) -> UrdfError {
+    RobotDescriptor::from_mjcf_str(&format!("<mujoco model=\"m\">\n{}\n</mujoco>", children))
+        .unwrap_err()
+}
+
+fn at(path: &str, line: u64, column: u64) -> SourceLocation {
+    SourceLocation {
+        path: path.into(),
+        line,
+        column,
+    }
+}
+
+fn joint<'a>(robot: &'a RobotDescriptor, name: &str) -> &'a Joint {
+    robot
+        .joints
+        .iter()
+        .find(|j| j.name() == name)
+        .unwrap_or_else(|| panic!("no joint {}", name))
+}
+
+fn assert_same_pose(a: &glm::Mat4, b: &glm::Mat4) {
+    assert!((a - b).abs().max() < 1e-5, "{} != {}", a, b);
+}
+
+#[test]
+fn default_classes_fill_in_what_elements_leave_out() {
+    let robot = model(
+        r#"<default>
+    <joint damping="0.5" range="-30 30"/>
+    <geom type="box" size="0.1 0.1 0.1"/>
+    <default class="thin">
+      <geom size="0.01 0.1 0.1"/>
+      <joint damping="2"/>
+    </default>
+  </default>
+  <worldbody>
+    <body name="plain">
+      <joint name="a"/>
+      <geom/>
+    </body>
+    <body name="thin" childclass="thin">
+      <joint name="b"/>
+      <geom name="own" size="0.2 0.2 0.2"/>
+      <body name="inside">
+        <joint name="c" damping="7"/>
+        <geom class="main"/>
+      </body>
+    </body>
+  </worldbody>"#,
+    );
+    // the main class applies everywhere, nested classes start from it
+    let damping = |name| joint(&robot, name).dynamics().unwrap().damping();
+    assert_eq!(damping("a"), 0.5);
+    assert_eq!(damping("b"), 2.0);
+    // attributes written on the element win over its class
+    assert_eq!(damping("c"), 7.0);
+    let limits = joint(&robot, "b").limits().unwrap();
+    assert!((limits.upper() - PI / 6.0).abs() < 1e-6);
+    // childclass reaches the bodies inside, class picks another
+    let size = |link: &str| match robot.links[robot.link_index(link).unwrap()].collision.shape {
+        Some(GeometryShape::Box { size }) => size,
+        ref other => panic!("{}: {:?}", link, other),
+    };
+    assert_eq!(size("plain"), glm::vec3(0.2, 0.2, 0.2));
+    assert_eq!(size("thin"), glm::vec3(0.4, 0.4, 0.4));
+    assert_eq!(size("inside"), glm::vec3(0.2, 0.2, 0.2));
+
+    let error = error(
+        r#"<worldbody>
+    <body childclass="missing">
+      <geom size="1"/>
+    </body>
+  </worldbody>"#,
+    );
+    assert_eq!(
+        error,
+        UrdfError::InvalidValue {
+            attribute: "class".into(),
+            value: "missing".into(),
+            location: at("mujoco/worldbody/body/geom", 4, 7),
+        }
+    );
+}
+
+#[test]
+fn the_compiler_says_how_angles_are_read() {
+    // degrees and x, y, z about the moving axes unless told otherwise
+    let robot = model(
+        r#"<worldbody>
+    <body name="b" euler="90 0 30">
+      <joint name="hinge" range="-90 45"/>
+    </body>
+  </worldbody>"#,
+    );
+    let hinge = joint(&robot, "hinge");
+    let limits = hinge.limits().unwrap();
+    assert!((limits.lower() + PI / 2.0).abs() < 1e-6);
+    assert!((limits.upper() - PI / 4.0).abs() < 1e-6);
+    let expected =
+        glm::rotation(PI / 2.0, &glm::Vec3::x()) * glm::rotation(PI / 6.0, &glm::Vec3::z());
+    assert_same_pose(&hinge.origin().matrix(), &expected);
+
+    let robot = model(
+        r#"<compiler angle="radian" eulerseq="zyx"/>
+  <worldbody>
+    <body name="b" euler="0.3 0.2 0.1">
+      <joint name="hinge" range="-1 0.5"/>
+    </body>
+    <body name="c" axisangle="0 1 0 0.7">
+      <joint name="other"/>
+    </body>
+  </worldbody>"#,
+    );
+    let hinge = joint(&robot, "hinge");
+    let limits = hinge.limits().unwrap();
+    assert_eq!((limits.lower(), limits.upper()), (-1.0, 0.5));
+    let expected = glm::rotation(0.3, &glm::Vec3::z())
+        * glm::rotation(0.2, &glm::Vec3::y())
+        * glm::rotation(0.1, &glm::Vec3::x());
+    assert_same_pose(&hinge.origin().matrix(), &expected);
+    assert_same_pose(
+        &joint(&robot, "other").origin().matrix(),
+        &glm::rotation(0.7, &glm::Vec3::y()),
+    );
+    // upper case axes stay fixed, so the sequence applies in reverse
+    let robot = model(
+        r#"<compiler angle="radian" eulerseq="ZYX"/>
+  <worldbody>
+    <body euler="0.3 0.2 0.1">
+      <joint name="hinge"/>
+    </body>
+  </worldbody>"#,
+    );
+    let expected = glm::rotation(0.1, &glm::Vec3::x())
+        * glm::rotation(0.2, &glm::Vec3::y())
+        * glm::rotation(0.3, &glm::Vec3::z());
+    assert_same_pose(&joint(&robot, "hinge").origin().matrix(), &expected);
+
+    assert_eq!(
+        error("  <compiler eulerseq=\"xyw\"/>\n  <worldbody/>"),
+        UrdfError::InvalidValue {
+            attribute: "eulerseq".into(),
+            value: "xyw".into(),
+            location: at("mujoco/compiler", 2, 3),
+        }
+    );
+    assert_eq!(
+        error("  <compiler angle=\"gradian\"/>\n  <worldbody/>"),
+        UrdfError::InvalidValue {
+            attribute: "angle".into(),
+            value: "gradian".into(),
+            location: at("mujoco/compiler", 2, 3),
+        }
+    );
+}
+
+#[test]
+fn capsules_from_their_ends_weigh_what_they_hold() {
+    let robot = model(
+        r#"<worldbody>
+    <body name="rod">
+      <joint name="hinge"/>
+      <geom type="capsule" fromto="-0.2 0 0.1 0.2 0 0.1" size="0.05"/>
+    </body>
+  </worldbody>"#,
+    );
+    let rod = &robot.links[robot.link_index("rod").unwrap()];
+    assert_eq!(
+        rod.collision.shape,
+        Some(GeometryShape::Capsule {
+            radius: 0.05,
+            length: 0.4
+        })
+    );
+    // centred between the ends with its z axis along them
+    let pose = rod.collision.origin.matrix();
+    assert!((pose.column(3).xyz() - glm::vec3(0.0, 0.0, 0.1)).norm() < 1e-6);
+    let along = glm::mat4_to_mat3(&pose) * glm::Vec3::z();
+    assert!((along.x.abs() - 1.0).abs() < 1e-6, "{:?}", along);
+
+    // a cylinder and the two halves of a sphere, of water
+    let (r, l) = (0.05f32, 0.4f32);
+    let cylinder = 1000.0 * PI * r * r * l;
+    let sphere = 1000.0 * 4.0 / 3.0 * PI * r.powi(3);
+    let inertial = &rod.inertial;
+    assert!((inertial.mass - (cylinder + sphere)).abs() < 1e-3 * inertial.mass);
+    assert!((inertial.origin.xyz() - glm::vec3(0.0, 0.0, 0.1)).norm() < 1e-6);
+    let axial = cylinder * r * r / 2.0 + sphere * 0.4 * r * r;
+    let across = cylinder * (l * l / 12.0 + r * r / 4.0)
+        + sphere * (0.4 * r * r + l * l / 4.0 + 3.0 * l * r / 8.0);
+    let expected = glm::Mat3::from_diagonal(&glm::vec3(axial, across, across));
+    assert!(
+        (inertial.tensor() - expected).abs().max() < 1e-4 * across,
+        "{}",
+        inertial.tensor()
+    );
+}
+
+#[test]
+fn ball_joints_become_three_hinges_and_free_joints_float() {
+    let robot = model(
+        r#"<worldbody>
+    <body name="torso">
+      <freejoint name="root"/>
+      <body name="thigh" pos="0 0 -0.1">
+        <joint name="hip" type="ball" range="0 60" pos="0 0 0.02"/>
+      </body>
+      <body name="arm">
+        <joint name="shoulder" type="ball"/>
+      </body>
+    </body>
+    <body name="loose">
+      <freejoint/>
+    </body>
+  </worldbody>"#,
+    );
+    assert_eq!(joint(&robot, "root").joint_type(), JointType::Floating);
+    assert_eq!(
+        joint(&robot, "loose_joint0").joint_type(),
+        JointType::Floating
+    );
+    // one after another through massless links, the thigh on the last
+    let hips = ["hip_x", "hip_y", "hip_z"].map(|name| joint(&robot, name));
+    for (hip, axis) in hips
+        .iter()
+        .zip([glm::Vec3::x(), glm::Vec3::y(), glm::Vec3::z()])
+    {
+        assert_eq!(hip.joint_type(), JointType::Revolute);
+        assert_eq!(hip.axis(), Some(axis));
+        let limits = hip.limits().unwrap();
+        assert!((limits.lower() + PI / 3.0).abs() < 1e-6);
+        assert!((limits.upper() - PI / 3.0).abs() < 1e-6);
+    }
+    assert_eq!(hips[0].child(), hips[1].parent());
+    assert_eq!(hips[1].child(), hips[2].parent());
+    assert_eq!(robot.links[hips[0].child()].link_name, "hip_x_link");
+    assert_eq!(robot.links[hips[2].child()].link_name, "thigh");
+    assert_eq!(robot.links[hips[0].parent()].link_name, "torso");
+    // the first hinge sits at the anchor, the others on top of it
+    assert!((hips[0].origin().xyz() - glm::vec3(0.0, 0.0, -0.08)).norm() < 1e-6);
+    assert_eq!(hips[1].origin().xyz(), glm::Vec3::zeros());
+    // unlimited without a range
+    assert_eq!(
+        joint(&robot, "shoulder_y").joint_type(),
+        JointType::Continuous
+    );
+    assert!(joint(&robot, "shoulder_y").limits().is_none());
+}
+
+#[test]
+fn errors_say_where_they_are() {
+    assert_eq!(
+        error(
+            r#"<worldbody>
+    <body>
+      <geom type="hfield"/>
+    </body>
+  </worldbody>"#
+        ),
+        UrdfError::UnsupportedElement {
+            element: "geom type=\"hfield\"".into(),
+            location: at("mujoco/worldbody/body/geom", 4, 7),
+        }
+    );
+    assert_eq!(
+        error(
+            r#"<worldbody>
+    <body name="a">
+      <geom type="mesh" mesh="nowhere"/>
+    </body>
+  </worldbody>"#
+        ),
+        UrdfError::InvalidValue {
+            attribute: "mesh".into(),
+            value: "nowhere".into(),
+            location: at("mujoco/worldbody/body[a]/geom", 4, 7),
+        }
+    );
+    // a capsule needs its half length unless given its ends
+    assert!(matches!(
+        error(
+            r#"<worldbody>
+    <body name="a">
+      <geom type="capsule" size="0.1"/>
+    </body>
+  </worldbody>"#
+        ),
+        UrdfError::InvalidValue { attribute, location, .. }
+            if attribute == "size" && location == at("mujoco/worldbody/body[a]/geom", 4, 7)
+    ));
+    assert!(matches!(
+        error(
+            r#"<worldbody>
+    <body name="a">
+      <joint type="screw"/>
+    </body>
+  </worldbody>"#
+        ),
+        UrdfError::InvalidValue { attribute, location, .. }
+            if attribute == "type" && location == at("mujoco/worldbody/body[a]/joint", 4, 7)
+    ));
+    assert!(matches!(
+        error(
+            r#"<worldbody>
+    <body name="a"/>
+    <body name="a"/>
+  </worldbody>"#
+        ),
+        UrdfError::DuplicateName { kind: "link", name, location }
+            if name == "a" && location == at("mujoco/worldbody/body[a]", 4, 5)
+    ));
+    assert!(matches!(
+        error("  <default/>"),
+        UrdfError::MissingElement { element, .. } if element == "worldbody"
+    ));
+}
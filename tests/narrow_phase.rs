@@ -0,0 +1,509 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::assert_close;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use wgpu_robotic_simulator::physics::{
+    collide, Collider, CollisionShape, Contact, PhysicsWorld, RigidBody, CONTACT_MARGIN,
+};
+use wgpu_robotic_simulator::urdf::{GeometryShape, RobotDescriptor};
+use wgpu_robotic_simulator::world::WorldShape;
+
+fn at(x: f32, y: f32, z: f32) -> glm::Mat4 {
+    glm::translation(&glm::vec3(x, y, z))
+}
+
+fn turned(pose: glm::Mat4, angle: f32, axis: glm::Vec3) -> glm::Mat4 {
+    pose * glm::rotation(angle, &axis)
+}
+
+fn sphere(radius: f32) -> CollisionShape {
+    CollisionShape::Sphere { radius }
+}
+
+fn cuboid(x: f32, y: f32, z: f32) -> CollisionShape {
+    CollisionShape::Box {
+        half_extents: glm::vec3(x, y, z),
+    }
+}
+
+fn ground() -> CollisionShape {
+    CollisionShape::Plane {
+        normal: glm::Vec3::z(),
+    }
+}
+
+// the corners of a box, as a hull for GJK and EPA
+fn corners(x: f32, y: f32, z: f32) -> CollisionShape {
+    let points = (0..8)
+        .map(|i| {
+            glm::vec3(
+                if i & 1 == 0 { -x } else { x },
+                if i & 2 == 0 { -y } else { y },
+                if i & 4 == 0 { -z } else { z },
+            )
+        })
+        .collect();
+    CollisionShape::Hull { points }
+}
+
+fn deepest(contacts: &[Contact]) -> f32 {
+    contacts.iter().map(|c| c.depth).fold(f32::MIN, f32::max)
+}
+
+#[test]
+fn spheres_touch_along_the_line_between_them() {
+    let contacts = collide(
+        &sphere(1.0),
+        &at(0.0, 0.0, 0.0),
+        &sphere(0.5),
+        &at(0.0, 1.4, 0.0),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 1);
+    let c = contacts[0];
+    assert_close(&c.normal, &glm::Vec3::y(), 1e-6);
+    assert!((c.depth - 0.1).abs() < 1e-6);
+    assert_close(&c.point, &glm::vec3(0.0, 0.95, 0.0), 1e-6);
+    // apart, then apart but within the margin
+    let apart = at(0.0, 1.6, 0.0);
+    assert!(collide(
+        &sphere(1.0),
+        &glm::Mat4::identity(),
+        &sphere(0.5),
+        &apart,
+        0.0
+    )
+    .is_empty());
+    let near = collide(
+        &sphere(1.0),
+        &glm::Mat4::identity(),
+        &sphere(0.5),
+        &apart,
+        0.2,
+    );
+    assert!((near[0].depth + 0.1).abs() < 1e-6);
+}
+
+#[test]
+fn spheres_against_boxes() {
+    let cube = turned(at(0.0, 0.0, 0.0), 0.5, glm::Vec3::z());
+    // over the top face
+    let contacts = collide(
+        &sphere(0.5),
+        &at(0.1, 0.0, 1.3),
+        &cuboid(1.0, 1.0, 1.0),
+        &cube,
+        0.0,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert_close(&contacts[0].normal, &-glm::Vec3::z(), 1e-6);
+    assert!((contacts[0].depth - 0.2).abs() < 1e-6);
+    // the box first turns the normal round
+    let contacts = collide(
+        &cuboid(1.0, 1.0, 1.0),
+        &cube,
+        &sphere(0.5),
+        &at(0.1, 0.0, 1.3),
+        0.0,
+    );
+    assert_close(&contacts[0].normal, &glm::Vec3::z(), 1e-6);
+    // by a corner
+    let corner = cube * glm::vec4(1.0, 1.0, 1.0, 1.0);
+    let out = glm::normalize(&glm::vec3(1.0, 1.0, 1.0));
+    let centre = corner.xyz() + glm::mat4_to_mat3(&cube) * out * 0.4;
+    let contacts = collide(
+        &sphere(0.5),
+        &at(centre.x, centre.y, centre.z),
+        &cuboid(1.0, 1.0, 1.0),
+        &cube,
+        0.0,
+    );
+    assert!((contacts[0].depth - 0.1).abs() < 1e-5);
+    // with the centre inside, out through the nearest face
+    let contacts = collide(
+        &sphere(0.5),
+        &at(0.0, 0.0, -0.8),
+        &cuboid(1.0, 1.0, 1.0),
+        &at(0.0, 0.0, 0.0),
+        0.0,
+    );
+    assert_close(&contacts[0].normal, &glm::Vec3::z(), 1e-6);
+    assert!((contacts[0].depth - 0.7).abs() < 1e-6);
+}
+
+#[test]
+fn boxes_rest_on_planes_at_their_corners() {
+    let contacts = collide(
+        &cuboid(0.5, 0.5, 0.5),
+        &at(0.0, 0.0, 0.49),
+        &ground(),
+        &glm::Mat4::identity(),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 4);
+    for c in &contacts {
+        assert_close(&c.normal, &-glm::Vec3::z(), 1e-6);
+        assert!((c.depth - 0.01).abs() < 1e-6);
+        assert!((c.point.z + 0.005).abs() < 1e-6);
+    }
+    // on an edge, and the plane first
+    let tipped = turned(
+        at(0.0, 0.0, 0.7),
+        std::f32::consts::FRAC_PI_4,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(
+        &ground(),
+        &glm::Mat4::identity(),
+        &cuboid(0.5, 0.5, 0.5),
+        &tipped,
+        0.0,
+    );
+    assert_eq!(contacts.len(), 2);
+    for c in &contacts {
+        assert_close(&c.normal, &glm::Vec3::z(), 1e-6);
+        assert!((c.depth - (0.5f32.sqrt() - 0.7)).abs() < 1e-5);
+    }
+}
+
+#[test]
+fn round_shapes_against_planes() {
+    let slope = turned(glm::Mat4::identity(), 0.3, glm::Vec3::y());
+    let normal = glm::mat4_to_mat3(&slope) * glm::Vec3::z();
+    let centre = normal * 0.45;
+    let contacts = collide(
+        &sphere(0.5),
+        &at(centre.x, centre.y, centre.z),
+        &ground(),
+        &slope,
+        0.0,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].depth - 0.05).abs() < 1e-5);
+    assert_close(&contacts[0].normal, &-normal, 1e-6);
+
+    // a capsule lying down touches at both ends
+    let capsule = CollisionShape::Capsule {
+        radius: 0.2,
+        half_length: 0.5,
+    };
+    let lying = turned(
+        at(0.0, 0.0, 0.19),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::y(),
+    );
+    let contacts = collide(&capsule, &lying, &ground(), &glm::Mat4::identity(), 0.0);
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts.iter().all(|c| (c.depth - 0.01).abs() < 1e-5));
+
+    // a cylinder standing on its end, and on its side
+    let cylinder = CollisionShape::Cylinder {
+        radius: 0.3,
+        half_length: 0.5,
+    };
+    let contacts = collide(
+        &cylinder,
+        &at(1.0, 0.0, 0.48),
+        &ground(),
+        &glm::Mat4::identity(),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 4);
+    assert!(contacts.iter().all(|c| (c.depth - 0.02).abs() < 1e-5));
+    assert!(contacts
+        .iter()
+        .all(|c| (glm::distance(&c.point.xy(), &glm::vec2(1.0, 0.0)) - 0.3).abs() < 1e-5));
+    let side = turned(
+        at(0.0, 0.0, 0.28),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(&cylinder, &side, &ground(), &glm::Mat4::identity(), 0.0);
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts.iter().all(|c| (c.depth - 0.02).abs() < 1e-5));
+}
+
+#[test]
+fn boxes_stack_face_to_face() {
+    let base = cuboid(1.0, 1.0, 0.5);
+    let top = cuboid(0.5, 0.5, 0.5);
+    // sitting inside the top face, turned about the vertical
+    let pose = turned(at(0.2, 0.1, 0.98), 0.4, glm::Vec3::z());
+    let contacts = collide(&base, &glm::Mat4::identity(), &top, &pose, 0.0);
+    assert_eq!(contacts.len(), 4);
+    for c in &contacts {
+        assert_close(&c.normal, &glm::Vec3::z(), 1e-5);
+        assert!((c.depth - 0.02).abs() < 1e-5);
+        assert!((c.point.z - 0.49).abs() < 1e-5);
+    }
+    // hanging over the edge only the part on the face is kept
+    let contacts = collide(
+        &base,
+        &glm::Mat4::identity(),
+        &top,
+        &at(1.2, 0.0, 0.98),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 4);
+    assert!(contacts.iter().all(|c| c.point.x <= 1.0 + 1e-5));
+    assert!(contacts.iter().any(|c| (c.point.x - 1.0).abs() < 1e-5));
+    // the other way up
+    let flipped = collide(
+        &top,
+        &at(1.2, 0.0, 0.98),
+        &base,
+        &glm::Mat4::identity(),
+        0.0,
+    );
+    assert_eq!(flipped.len(), 4);
+    assert!(flipped
+        .iter()
+        .all(|c| (c.normal + glm::Vec3::z()).norm() < 1e-5));
+    // and apart
+    assert!(collide(&base, &glm::Mat4::identity(), &top, &at(0.0, 0.0, 1.1), 0.0).is_empty());
+}
+
+#[test]
+fn boxes_crossed_edge_to_edge() {
+    // two long bars turned up onto an edge, crossing at right angles
+    let bar = cuboid(2.0, 0.5, 0.5);
+    let q = std::f32::consts::FRAC_PI_4;
+    let lower = turned(glm::Mat4::identity(), q, glm::Vec3::x());
+    let upper = turned(
+        turned(
+            at(0.0, 0.0, 1.4),
+            std::f32::consts::FRAC_PI_2,
+            glm::Vec3::z(),
+        ),
+        q,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(&bar, &lower, &bar, &upper, 0.0);
+    assert_eq!(contacts.len(), 1);
+    let reach = 0.5 * 2.0f32.sqrt();
+    assert!((contacts[0].depth - (2.0 * reach - 1.4)).abs() < 1e-5);
+    assert_close(&contacts[0].normal, &glm::Vec3::z(), 1e-5);
+    assert_close(&contacts[0].point, &glm::vec3(0.0, 0.0, 0.7), 1e-5);
+}
+
+#[test]
+fn capsules_alongside_touch_twice() {
+    let capsule = CollisionShape::Capsule {
+        radius: 0.25,
+        half_length: 1.0,
+    };
+    let along_x = turned(
+        glm::Mat4::identity(),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::y(),
+    );
+    let beside = at(0.5, 0.45, 0.0) * along_x;
+    let contacts = collide(&capsule, &along_x, &capsule, &beside, 0.0);
+    assert_eq!(contacts.len(), 2);
+    for c in &contacts {
+        assert_close(&c.normal, &glm::Vec3::y(), 1e-5);
+        assert!((c.depth - 0.05).abs() < 1e-5);
+    }
+    let xs: Vec<f32> = contacts.iter().map(|c| c.point.x).collect();
+    assert!(xs.contains(&-0.5) && xs.contains(&1.0), "{:?}", xs);
+    // crossed, once
+    let across = turned(
+        at(0.0, 0.0, 0.45),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(&capsule, &along_x, &capsule, &across, 0.0);
+    assert_eq!(contacts.len(), 1);
+    assert_close(&contacts[0].normal, &glm::Vec3::z(), 1e-5);
+    assert!((contacts[0].depth - 0.05).abs() < 1e-5);
+}
+
+#[test]
+fn capsules_lying_on_boxes() {
+    let capsule = CollisionShape::Capsule {
+        radius: 0.2,
+        half_length: 0.5,
+    };
+    let lying = turned(
+        at(0.0, 0.0, 1.18),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::y(),
+    );
+    let contacts = collide(
+        &capsule,
+        &lying,
+        &cuboid(1.0, 1.0, 1.0),
+        &glm::Mat4::identity(),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 2);
+    assert!(contacts.iter().all(|c| (c.depth - 0.02).abs() < 1e-5));
+    // across a narrow rail, by the middle of the capsule
+    let across = turned(
+        at(0.0, 0.0, 1.18),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(
+        &capsule,
+        &across,
+        &cuboid(1.0, 0.1, 1.0),
+        &glm::Mat4::identity(),
+        0.0,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].depth - 0.02).abs() < 1e-5);
+    assert_close(&contacts[0].point, &glm::vec3(0.0, 0.0, 0.99), 1e-4);
+}
+
+#[test]
+fn gjk_finds_the_distance_between_hulls() {
+    // the gap between the cube and a ball over its corner, within a wide margin
+    let cube = corners(1.0, 1.0, 1.0);
+    let out = glm::normalize(&glm::vec3(1.0, 1.0, 1.0));
+    let centre = glm::vec3(1.0, 1.0, 1.0) + out * 0.8;
+    let contacts = collide(
+        &cube,
+        &glm::Mat4::identity(),
+        &sphere(0.5),
+        &at(centre.x, centre.y, centre.z),
+        1.0,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert!(
+        (contacts[0].depth + 0.3).abs() < 1e-4,
+        "{}",
+        contacts[0].depth
+    );
+    assert_close(&contacts[0].normal, &out, 1e-3);
+    // a cylinder on its side over a hull
+    let cylinder = CollisionShape::Cylinder {
+        radius: 0.5,
+        half_length: 1.0,
+    };
+    let side = turned(
+        at(0.0, 0.0, 1.45),
+        std::f32::consts::FRAC_PI_2,
+        glm::Vec3::x(),
+    );
+    let contacts = collide(&cube, &glm::Mat4::identity(), &cylinder, &side, 0.0);
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].depth - 0.05).abs() < 1e-4);
+    assert_close(&contacts[0].normal, &glm::Vec3::z(), 1e-3);
+}
+
+// EPA over the corners of boxes agrees with the separating axis test
+#[test]
+fn epa_agrees_with_separating_axes() {
+    let mut rng = StdRng::seed_from_u64(3);
+    let mut compared = 0;
+    for _ in 0..300 {
+        let size = |rng: &mut StdRng| {
+            glm::vec3(
+                rng.gen_range(0.2..1.0),
+                rng.gen_range(0.2..1.0),
+                rng.gen_range(0.2..1.0),
+            )
+        };
+        let (ha, hb) = (size(&mut rng), size(&mut rng));
+        let pose = |rng: &mut StdRng| {
+            let axis = glm::normalize(&glm::vec3(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            ));
+            turned(
+                at(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                ),
+                rng.gen_range(0.0..6.0),
+                axis,
+            )
+        };
+        let (pa, pb) = (pose(&mut rng), pose(&mut rng));
+        let sat = collide(
+            &cuboid(ha.x, ha.y, ha.z),
+            &pa,
+            &cuboid(hb.x, hb.y, hb.z),
+            &pb,
+            0.0,
+        );
+        let epa = collide(
+            &corners(ha.x, ha.y, ha.z),
+            &pa,
+            &corners(hb.x, hb.y, hb.z),
+            &pb,
+            0.0,
+        );
+        if sat.is_empty() || epa.is_empty() {
+            // only ever disagreeing about boxes that barely touch
+            let depth = deepest(&sat).max(deepest(&epa));
+            assert!(depth < 2e-3, "{} {:?} {:?}", depth, sat, epa);
+            continue;
+        }
+        compared += 1;
+        assert!(
+            (deepest(&sat) - deepest(&epa)).abs() < 2e-3,
+            "{:?} {:?}",
+            sat,
+            epa
+        );
+        // pushed apart along the normal by the depth, they just touch
+        let push = epa[0].normal * (epa[0].depth + 2e-3);
+        let moved = glm::translation(&push) * pb;
+        assert!(collide(
+            &cuboid(ha.x, ha.y, ha.z),
+            &pa,
+            &cuboid(hb.x, hb.y, hb.z),
+            &moved,
+            0.0
+        )
+        .is_empty());
+    }
+    assert!(compared > 50, "{}", compared);
+}
+
+const BALL_ROBOT: &str = r#"<robot name="ball">
+  <link name="ball">
+    <collision><geometry><sphere radius="0.1"/></geometry></collision>
+  </link>
+</robot>"#;
+
+#[test]
+fn the_world_finds_contacts_of_its_pairs() {
+    let mut world = PhysicsWorld::default().with_gravity(glm::Vec3::zeros());
+    let plane = world.add_collider(common::ground());
+    let body = world.add_body(RigidBody::default().with_position(glm::vec3(0.0, 0.0, 0.45)));
+    let ball = world.add_collider(Collider::on(
+        body,
+        WorldShape::Solid(GeometryShape::Sphere { radius: 0.5 }),
+        glm::Mat4::identity(),
+    ));
+    // kept as a sphere from the URDF, not its mesh: the contact is exact
+    let robot = RobotDescriptor::from_str(BALL_ROBOT).unwrap();
+    assert_eq!(
+        robot.links[0].collision.shape,
+        Some(GeometryShape::Sphere { radius: 0.1 })
+    );
+    world.add_robot(robot, at(3.0, 0.0, 0.095));
+    let link = world.colliders().len() - 1;
+    world.step(world.timestep().dt());
+    let manifolds = world.contacts();
+    assert_eq!(manifolds.len(), 2);
+    assert_eq!((manifolds[0].a, manifolds[0].b), (plane, ball));
+    assert!((manifolds[0].contacts[0].depth - 0.05).abs() < 1e-5);
+    assert_close(&manifolds[0].contacts[0].normal, &glm::Vec3::z(), 1e-6);
+    assert_eq!(manifolds[1].b.index(), link);
+    assert!((manifolds[1].contacts[0].depth - 0.005).abs() < 1e-6);
+
+    // lifted clear of the margin, no contact is left
+    world.body_mut(body).position.z = 0.5 + 2.0 * CONTACT_MARGIN;
+    world.step(world.timestep().dt());
+    assert_eq!(world.contacts().len(), 1);
+}
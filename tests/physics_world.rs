@@ -0,0 +1,187 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::G;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::str::FromStr;
+use wgpu_robotic_simulator::physics::{
+    Collider, FixedTimestep, Phase, PhysicsProgram, PhysicsWorld, RigidBody, RungeKutta4,
+};
+use wgpu_robotic_simulator::urdf::{GeometryShape, RobotDescriptor};
+use wgpu_robotic_simulator::world::WorldShape;
+
+// a rod swinging about y, its centre of mass 0.5 along x
+const PENDULUM: &str = r#"<robot name="pendulum">
+  <link name="base"/>
+  <link name="rod">
+    <inertial>
+      <origin xyz="0.5 0 0"/>
+      <mass value="2"/>
+      <inertia ixx="0.01" iyy="0.2" izz="0.2" ixy="0" ixz="0" iyz="0"/>
+    </inertial>
+  </link>
+  <joint name="hinge" type="continuous">
+    <parent link="base"/>
+    <child link="rod"/>
+    <axis xyz="0 1 0"/>
+  </joint>
+</robot>"#;
+
+#[test]
+fn bodies_fall_under_gravity() {
+    let mut world = PhysicsWorld::default()
+        .with_timestep(FixedTimestep::new(0.01).with_substeps(2))
+        .with_integrator(RungeKutta4);
+    let ball = world.add_body(RigidBody::default().with_position(glm::vec3(0.0, 0.0, 10.0)));
+    let ground = world.add_body(RigidBody::fixed());
+    let mut steps = 0;
+    // frames longer than a step
+    for _ in 0..40 {
+        steps += world.step(0.025);
+    }
+    assert_eq!(steps, 100);
+    assert!((world.time() - 1.0).abs() < 1e-4);
+    let z = world.body(ball).position.z;
+    assert!((z - (10.0 - G / 2.0)).abs() < 1e-3, "{}", z);
+    assert_eq!(world.body(ground).position, glm::Vec3::zeros());
+}
+
+#[test]
+fn hooks_run_after_their_phase_in_order() {
+    let mut world =
+        PhysicsWorld::default().with_timestep(FixedTimestep::new(0.01).with_substeps(2));
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    for phase in Phase::ALL.iter().rev() {
+        let seen = seen.clone();
+        let phase = *phase;
+        world.add_hook(phase, move |_, dt| seen.borrow_mut().push((phase, dt)));
+    }
+    assert_eq!(world.step(0.01), 1);
+    let expected: Vec<_> = [Phase::ALL, Phase::ALL]
+        .concat()
+        .into_iter()
+        .map(|phase| (phase, 0.005))
+        .collect();
+    assert_eq!(*seen.borrow(), expected);
+}
+
+#[test]
+fn hooks_apply_forces() {
+    let mut world = PhysicsWorld::default();
+    let body = world.add_body(RigidBody::default().with_position(glm::vec3(0.0, 0.0, 1.0)));
+    // held up against gravity
+    world.add_hook(Phase::ApplyForces, move |world, _| {
+        let lift = -world.gravity() * world.body(body).mass();
+        world.body_mut(body).apply_force(&lift);
+    });
+    for _ in 0..60 {
+        world.step(1.0 / 60.0);
+    }
+    assert!(world.body(body).position.z > 0.999);
+    assert!(world.time() > 0.99);
+}
+
+#[test]
+fn robots_move_unless_static() {
+    let mut world = PhysicsWorld::default();
+    let robot = RobotDescriptor::from_str(PENDULUM).unwrap();
+    let swinging = world.add_robot(robot.clone(), glm::Mat4::identity());
+    let held = world.add_robot(robot, glm::Mat4::identity());
+    world.robot_mut(held).is_static = true;
+    for _ in 0..30 {
+        world.step(1.0 / 60.0);
+    }
+    // the rod falls from horizontal towards hanging down
+    assert!(world.robot(swinging).state.position("hinge").unwrap() > 0.1);
+    assert_eq!(world.robot(held).state.position("hinge"), Some(0.0));
+}
+
+#[test]
+fn colliders_follow_their_body() {
+    let mut world = PhysicsWorld::default();
+    let body = world.add_body(
+        RigidBody::default()
+            .with_position(glm::vec3(1.0, 2.0, 3.0))
+            .with_orientation(glm::quat_angle_axis(
+                std::f32::consts::FRAC_PI_2,
+                &glm::Vec3::z(),
+            )),
+    );
+    let offset = glm::translation(&glm::vec3(1.0, 0.0, 0.0));
+    let ball = world.add_collider(Collider::on(
+        body,
+        WorldShape::Solid(GeometryShape::Sphere { radius: 0.5 }),
+        offset,
+    ));
+    let pose = world.collider_pose(ball);
+    assert!(
+        (pose.column(3).xyz() - glm::vec3(1.0, 3.0, 3.0))
+            .abs()
+            .max()
+            < 1e-6
+    );
+}
+
+const SCENE: &str = r#"<?xml version="1.0"?>
+<sdf version="1.7">
+  <world name="scene">
+    <gravity>0 0 -5</gravity>
+    <model name="ground_plane">
+      <static>true</static>
+      <link name="link">
+        <collision name="collision">
+          <geometry>
+            <plane><normal>0 0 1</normal><size>10 10</size></plane>
+          </geometry>
+        </collision>
+      </link>
+    </model>
+    <model name="pendulum">
+      <link name="post"/>
+      <link name="arm">
+        <inertial>
+          <pose>0.5 0 0 0 0 0</pose>
+          <mass>1</mass>
+          <inertia><ixx>0.01</ixx><iyy>0.1</iyy><izz>0.1</izz></inertia>
+        </inertial>
+      </link>
+      <joint name="hinge" type="revolute">
+        <parent>post</parent>
+        <child>arm</child>
+        <axis><xyz>0 1 0</xyz></axis>
+      </joint>
+    </model>
+  </world>
+</sdf>"#;
+
+#[test]
+fn setup_loads_an_sdf_world() {
+    let mut world = <PhysicsWorld as PhysicsProgram>::new();
+    world.setup(SCENE);
+    assert_eq!(world.gravity(), glm::vec3(0.0, 0.0, -5.0));
+    assert_eq!(world.robots().len(), 1);
+    assert_eq!(world.colliders().len(), 1);
+    assert!(world.colliders()[0].body().is_none());
+    assert!(matches!(
+        world.colliders()[0].shape,
+        WorldShape::Plane { .. }
+    ));
+    for _ in 0..10 {
+        PhysicsProgram::step(&mut world);
+    }
+    assert!(world.robots()[0].state.position("hinge").unwrap() > 0.0);
+
+    // a scene that does not load leaves the world alone
+    let time = world.time();
+    assert!(world.load_sdf("<sdf><world><model/></world>").is_err());
+    assert!(world.load_sdf("not xml").is_err());
+    assert_eq!(world.robots().len(), 1);
+    assert_eq!(world.time(), time);
+    world
+        .load_sdf("<sdf version=\"1.9\"><world name=\"empty\"/></sdf>")
+        .unwrap();
+    assert!(world.robots().is_empty() && world.colliders().is_empty());
+    assert_eq!(world.time(), 0.0);
+}
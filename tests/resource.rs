@@ -0,0 +1,184 @@
+use std::path::{Path, PathBuf};
+use wgpu_robotic_simulator::resource::{
+    FileUriResolver, PackageResolver, RelativeResolver, ResolverChain, ResourceResolver,
+};
+use wgpu_robotic_simulator::urdf::{GeometryShape, RobotDescriptor};
+
+// one triangle, or two for the second copy, so that the file read can be told
+// apart by its vertices
+const TRIANGLE: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+const TWO_TRIANGLES: &str = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 1 1 0\nf 1 2 3\nf 2 4 3\n";
+
+fn write(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, contents).unwrap();
+}
+
+// a fresh directory for each test to lay its files out in
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn packages_are_found_by_name_then_on_the_search_path() {
+    let dir = scratch("packages_are_found_by_name_then_on_the_search_path");
+    for root in ["share", "other", "named"] {
+        write(&dir.join(root).join("arm/meshes/a.obj"), TRIANGLE);
+    }
+    let uri = "package://arm/meshes/a.obj";
+    let search = PackageResolver::new()
+        .with_search_dir(dir.join("share"))
+        .with_search_dir(dir.join("other"));
+    // the first search directory holding the package
+    assert_eq!(
+        search.resolve(uri),
+        Some(dir.join("share/arm/meshes/a.obj"))
+    );
+    // a package given by name comes before the search path
+    let named = search.clone().with_package("arm", dir.join("named/arm"));
+    assert_eq!(named.resolve(uri), Some(dir.join("named/arm/meshes/a.obj")));
+    assert_eq!(named.package_dir("arm"), Some(dir.join("named/arm")));
+    // a search directory may be the package itself
+    let itself = PackageResolver::new().with_search_dir(dir.join("other/arm"));
+    assert_eq!(
+        itself.resolve(uri),
+        Some(dir.join("other/arm/meshes/a.obj"))
+    );
+
+    assert_eq!(search.resolve("package://arm/meshes/b.obj"), None);
+    assert_eq!(search.resolve("package://leg/meshes/a.obj"), None);
+    assert_eq!(search.resolve("package://arm"), None);
+    assert_eq!(search.resolve("arm/meshes/a.obj"), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn plain_paths_are_relative_to_the_base() {
+    let dir = scratch("plain_paths_are_relative_to_the_base");
+    let mesh = dir.join("robot/meshes/a.obj");
+    write(&mesh, TRIANGLE);
+    let relative = RelativeResolver::for_file(dir.join("robot/robot.urdf"));
+    assert_eq!(relative.resolve("meshes/a.obj"), Some(mesh.clone()));
+    assert_eq!(relative.resolve(mesh.to_str().unwrap()), Some(mesh.clone()));
+    assert_eq!(relative.resolve("meshes/b.obj"), None);
+    // URIs are for the other resolvers, even when the path would be there
+    assert_eq!(relative.resolve("package://meshes/a.obj"), None);
+    assert_eq!(relative.resolve("file://meshes/a.obj"), None);
+    // the working directory by default
+    assert_eq!(
+        RelativeResolver::default().resolve("assets/LittleDog.urdf"),
+        Some(PathBuf::from("assets/LittleDog.urdf"))
+    );
+
+    let uri = format!("file://{}", mesh.display());
+    assert_eq!(FileUriResolver.resolve(&uri), Some(mesh.clone()));
+    assert_eq!(FileUriResolver.resolve(mesh.to_str().unwrap()), None);
+    assert_eq!(FileUriResolver.resolve(&format!("{}.stl", uri)), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn chains_take_the_first_resolver_that_finds_it() {
+    let dir = scratch("chains_take_the_first_resolver_that_finds_it");
+    write(&dir.join("first/arm/a.obj"), TRIANGLE);
+    write(&dir.join("second/arm/a.obj"), TRIANGLE);
+    let packages = |root: &str| PackageResolver::new().with_search_dir(dir.join(root));
+    let chain = ResolverChain::new()
+        .with(packages("first"))
+        .with(packages("second"));
+    assert_eq!(
+        chain.resolve("package://arm/a.obj"),
+        Some(dir.join("first/arm/a.obj"))
+    );
+    let mut chain = ResolverChain::new()
+        .with(packages("second"))
+        .with(packages("first"));
+    assert_eq!(
+        chain.resolve("package://arm/a.obj"),
+        Some(dir.join("second/arm/a.obj"))
+    );
+    // later resolvers fill in for the earlier ones
+    assert_eq!(chain.resolve("arm/a.obj"), None);
+    chain.push(RelativeResolver::new(dir.join("first")));
+    assert_eq!(
+        chain.resolve("arm/a.obj"),
+        Some(dir.join("first/arm/a.obj"))
+    );
+    assert_eq!(ResolverChain::new().resolve("arm/a.obj"), None);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+// a robot file with a link for each mesh, all fixed to the first
+fn write_robot(path: &Path, meshes: &[&str]) {
+    let mut urdf = String::from("<robot name=\"r\">\n");
+    for (i, mesh) in meshes.iter().enumerate() {
+        urdf += &format!(
+            "  <link name=\"l{}\"><visual><geometry><mesh filename=\"{}\"/></geometry></visual></link>\n",
+            i, mesh
+        );
+        if i > 0 {
+            urdf += &format!(
+                "  <joint name=\"j{0}\" type=\"fixed\"><parent link=\"l0\"/><child link=\"l{0}\"/></joint>\n",
+                i
+            );
+        }
+    }
+    urdf += "</robot>";
+    write(path, &urdf);
+}
+
+fn verts(robot: &RobotDescriptor) -> Vec<usize> {
+    robot
+        .links
+        .iter()
+        .map(|l| l.visual.geometry.verts.len())
+        .collect()
+}
+
+#[test]
+fn robot_files_look_next_to_themselves_first() {
+    let dir = scratch("robot_files_look_next_to_themselves_first");
+    let path = dir.join("robot/robot.urdf");
+    // a hip next to the file and under the working directory, the upper leg
+    // only under the working directory
+    write(&dir.join("robot/meshes/a.obj"), TWO_TRIANGLES);
+    write(
+        &dir.join("robot/assets/meshes/front_left_hip.obj"),
+        TRIANGLE,
+    );
+    write_robot(
+        &path,
+        &[
+            "meshes/a.obj",
+            "assets/meshes/front_left_hip.obj",
+            "assets/meshes/front_left_upper.obj",
+        ],
+    );
+    let robot = RobotDescriptor::from_file(&path).unwrap();
+    let upper = robot.links[2].visual.geometry.verts.len();
+    assert!(upper > 6);
+    assert_eq!(verts(&robot), vec![6, 3, upper]);
+    // the mesh keeps the URI it was given
+    assert_eq!(
+        robot.links[0].visual.shape,
+        Some(GeometryShape::Mesh {
+            filename: "meshes/a.obj".into(),
+            scale: None
+        })
+    );
+
+    // a resolver that is given comes before the file's directory
+    write(&dir.join("share/arm/meshes/a.obj"), TRIANGLE);
+    write_robot(&path, &["meshes/a.obj", "package://arm/meshes/a.obj"]);
+    let packages = PackageResolver::new().with_search_dir(dir.join("share"));
+    let robot = RobotDescriptor::from_file_with_resolver(&path, packages.clone()).unwrap();
+    assert_eq!(verts(&robot), vec![6, 3]);
+    let first = ResolverChain::new()
+        .with(RelativeResolver::new(dir.join("share/arm")))
+        .with(packages);
+    let robot = RobotDescriptor::from_file_with_resolver(&path, first).unwrap();
+    assert_eq!(verts(&robot), vec![3, 3]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
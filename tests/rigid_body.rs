@@ -0,0 +1,139 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::{assert_close, G};
+use wgpu_robotic_simulator::physics::{
+    RigidBody, RungeKutta4, SemiImplicitEuler, SLEEP_LINEAR_VELOCITY, SLEEP_TIME,
+};
+use wgpu_robotic_simulator::urdf::InertialBody;
+
+// a 3 x 2 x 1 box of mass 6, principal moments 5, 10 and 13
+fn brick() -> RigidBody {
+    RigidBody::new(6.0, glm::diagonal3x3(&glm::vec3(2.5, 5.0, 6.5)))
+}
+
+#[test]
+fn inertia_turns_with_the_body() {
+    let turn = glm::quat_angle_axis(0.7, &glm::normalize(&glm::vec3(1.0, 2.0, 3.0)));
+    let body = brick().with_orientation(turn);
+    let r = glm::quat_to_mat3(&turn);
+    let expected = r * glm::diagonal3x3(&glm::vec3(2.5, 5.0, 6.5)) * r.transpose();
+    assert!((body.inertia_world() - expected).abs().max() < 1e-5);
+    let identity = body.inertia_world() * body.inverse_inertia_world();
+    assert!((identity - glm::Mat3::identity()).abs().max() < 1e-5);
+}
+
+#[test]
+fn from_inertial_puts_the_body_at_the_centre_of_mass() {
+    let tensor = glm::diagonal3x3(&glm::vec3(1.0, 2.0, 3.0));
+    let inertial = InertialBody::from_tensor(2.0, glm::vec3(0.5, 0.0, 0.0), &tensor);
+    let pose = glm::translate(
+        &glm::rotate(&glm::Mat4::identity(), 1.0, &glm::Vec3::z_axis()),
+        &glm::vec3(0.0, 0.0, 1.0),
+    );
+    let body = RigidBody::from_inertial(&inertial, &pose);
+    assert_eq!(body.mass(), 2.0);
+    assert_eq!(body.com(), glm::vec3(0.5, 0.0, 0.0));
+    let com = pose * glm::vec4(0.5, 0.0, 0.0, 1.0);
+    assert_close(&body.position, &com.xyz(), 1e-6);
+    assert!((body.transform() - pose).abs().max() < 1e-6);
+    assert!((body.inertia() - tensor).abs().max() < 1e-6);
+}
+
+#[test]
+fn forces_off_the_centre_of_mass_turn_the_body() {
+    let mut body = brick().with_position(glm::vec3(1.0, 0.0, 0.0));
+    body.apply_force_at_point(&glm::vec3(0.0, 0.0, 2.0), &glm::vec3(1.0, 0.0, 0.0));
+    assert_eq!(body.torque(), glm::Vec3::zeros());
+    body.apply_force_at_point(&glm::vec3(0.0, 3.0, 0.0), &glm::vec3(2.0, 0.0, 0.0));
+    assert_eq!(body.force(), glm::vec3(0.0, 3.0, 2.0));
+    assert_eq!(body.torque(), glm::vec3(0.0, 0.0, 3.0));
+    body.step(&RungeKutta4, 0.1);
+    assert_eq!(body.force(), glm::Vec3::zeros());
+    assert_close(
+        &body.linear_velocity,
+        &glm::vec3(0.0, 0.05, 2.0 / 60.0),
+        1e-6,
+    );
+    assert_close(
+        &body.angular_velocity,
+        &glm::vec3(0.0, 0.0, 0.3 / 6.5),
+        1e-6,
+    );
+
+    // an impulse changes the velocity at once
+    let mut body = brick();
+    body.apply_impulse(&glm::vec3(0.0, 6.0, 0.0), &glm::vec3(1.0, 0.0, 0.0));
+    assert_close(&body.linear_velocity, &glm::vec3(0.0, 1.0, 0.0), 1e-6);
+    assert_close(
+        &body.angular_velocity,
+        &glm::vec3(0.0, 0.0, 6.0 / 6.5),
+        1e-6,
+    );
+}
+
+#[test]
+fn tumbling_keeps_momentum_and_energy() {
+    // spun mostly about the intermediate axis, which is unstable and flips
+    // over in the body frame
+    let mut body = brick().with_velocity(glm::vec3(1.0, 0.0, 0.0), glm::vec3(0.01, 3.0, 0.01));
+    let (momentum, energy) = (body.angular_momentum(), body.kinetic_energy());
+    let mut flipped = false;
+    for _ in 0..2000 {
+        body.step(&RungeKutta4, 0.005);
+        flipped |= (body.rotation().transpose() * body.angular_velocity).y < 0.0;
+    }
+    assert!(flipped, "the spin should flip over");
+    assert_close(
+        &body.angular_momentum(),
+        &momentum,
+        2e-3 * glm::length(&momentum),
+    );
+    assert!((body.kinetic_energy() - energy).abs() < 2e-3 * energy);
+    assert!((glm::quat_length(&body.orientation) - 1.0).abs() < 1e-5);
+    assert_close(&body.position, &glm::vec3(10.0, 0.0, 0.0), 1e-3);
+    assert!(!body.is_sleeping());
+}
+
+#[test]
+fn bodies_fall_asleep_at_rest_and_wake_when_pushed() {
+    let mut body = brick().with_velocity(
+        glm::vec3(SLEEP_LINEAR_VELOCITY / 2.0, 0.0, 0.0),
+        glm::Vec3::zeros(),
+    );
+    let dt = 0.01;
+    for _ in 0..(SLEEP_TIME / dt) as usize + 1 {
+        body.step(&SemiImplicitEuler, dt);
+    }
+    assert!(body.is_sleeping());
+    assert_eq!(body.linear_velocity, glm::Vec3::zeros());
+    // asleep it stays put
+    let position = body.position;
+    body.step(&SemiImplicitEuler, dt);
+    assert_eq!(body.position, position);
+
+    body.apply_force(&glm::vec3(0.0, 0.0, -G * body.mass()));
+    assert!(!body.is_sleeping());
+    body.step(&SemiImplicitEuler, dt);
+    assert!(body.linear_velocity.z < 0.0);
+
+    let mut awake = brick();
+    awake.can_sleep = false;
+    for _ in 0..(SLEEP_TIME / dt) as usize * 2 {
+        awake.step(&SemiImplicitEuler, dt);
+    }
+    assert!(!awake.is_sleeping());
+}
+
+#[test]
+fn fixed_bodies_do_not_move() {
+    let mut ground = RigidBody::fixed();
+    assert!(ground.is_fixed());
+    ground.apply_force(&glm::vec3(0.0, 0.0, -100.0));
+    ground.apply_impulse(&glm::vec3(1.0, 0.0, 0.0), &glm::vec3(0.0, 1.0, 0.0));
+    ground.step(&RungeKutta4, 0.1);
+    assert_eq!(ground.position, glm::Vec3::zeros());
+    assert_eq!(ground.linear_velocity, glm::Vec3::zeros());
+    assert_eq!(ground.angular_velocity, glm::Vec3::zeros());
+}
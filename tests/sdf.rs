@@ -0,0 +1,372 @@
+extern crate nalgebra_glm as glm;
+
+use std::path::PathBuf;
+use wgpu_robotic_simulator::kinematics::TopologyError;
+use wgpu_robotic_simulator::urdf::{GeometryShape, JointType, SourceLocation, UrdfError};
+use wgpu_robotic_simulator::world::{LightKind, World, WorldShape};
+
+// a world of the given children of <world>
+fn parse_world(children: &str) -> Result<World, UrdfError> {
+    World::from_sdf_str(&format!(
+        "<sdf version=\"1.9\">\n<world name=\"w\">\n{}\n</world>\n</sdf>",
+        children
+    ))
+}
+
+fn world_error(children: &str) -> UrdfError {
+    parse_world(children).unwrap_err()
+}
+
+// a directory of its own under the temporary directory, holding `files`
+fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(test);
+    for (name, contents) in files {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+    dir
+}
+
+fn at(path: &str, line: u64, column: u64) -> SourceLocation {
+    SourceLocation {
+        path: path.into(),
+        line,
+        column,
+    }
+}
+
+fn assert_near(a: &glm::Vec3, b: &glm::Vec3) {
+    assert!((a - b).norm() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+const CART: &str = r#"<sdf version="1.9">
+  <model name="cart">
+    <link name="body">
+      <collision name="hull">
+        <geometry><box><size>0.4 0.2 0.1</size></box></geometry>
+      </collision>
+    </link>
+    <link name="wheel">
+      <pose>0.2 0 0 0 0 0</pose>
+    </link>
+    <joint name="axle" type="continuous">
+      <parent>body</parent>
+      <child>wheel</child>
+      <axis><xyz>0 1 0</xyz></axis>
+    </joint>
+  </model>
+</sdf>"#;
+
+#[test]
+fn includes_find_models_through_their_model_config() {
+    // the model file is only found through model.config
+    let dir = write_files(
+        "includes_find_models_through_their_model_config",
+        &[
+            (
+                "cart/model.config",
+                "<model><name>cart</name><sdf version=\"1.9\">cart.sdf</sdf></model>",
+            ),
+            ("cart/cart.sdf", CART),
+            (
+                "world.sdf",
+                r#"<sdf version="1.9">
+  <world name="w">
+    <include>
+      <uri>model://cart</uri>
+    </include>
+    <include>
+      <uri>model://cart</uri>
+      <name>parked</name>
+      <pose>1 2 0 0 0 0</pose>
+      <static>true</static>
+    </include>
+  </world>
+</sdf>"#,
+            ),
+        ],
+    );
+    let world = World::from_sdf_file(dir.join("world.sdf")).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+    // the model's own name unless the include gives one
+    assert_eq!(world.models.len(), 1);
+    let cart = world.model("cart").unwrap();
+    assert_eq!(cart.robot.links.len(), 2);
+    assert_eq!(cart.robot.joints[0].joint_type(), JointType::Continuous);
+    // <static> on the include makes the model environment
+    let names: Vec<_> = world.environment.iter().map(|b| b.name.as_str()).collect();
+    assert_eq!(names, ["parked::body", "parked::wheel"]);
+    assert_near(
+        &world.environment[1].pose.tmatrix.column(3).xyz(),
+        &glm::vec3(1.2, 2.0, 0.0),
+    );
+    let missing = world_error("<include><uri>model://nothing_here</uri></include>");
+    assert!(matches!(
+        missing,
+        UrdfError::UnresolvedResource { uri, location }
+            if uri == "model://nothing_here" && location == at("sdf/world[w]/include", 3, 1)
+    ));
+}
+
+#[test]
+fn poses_are_relative_to_the_frames_they_name() {
+    let world = parse_world(
+        r#"<model name="arm">
+  <pose>0 0 1 0 0 0</pose>
+  <link name="upper">
+    <pose>1 0 0 0 0 1.5707963</pose>
+  </link>
+  <link name="lower">
+    <pose relative_to="upper">1 0 0 0 0 0</pose>
+  </link>
+  <joint name="elbow" type="revolute">
+    <pose relative_to="__model__">2 0 0 0 0 0</pose>
+    <parent>upper</parent>
+    <child>lower</child>
+    <axis><xyz>0 0 1</xyz><limit><lower>-1</lower><upper>1</upper></limit></axis>
+  </joint>
+</model>
+<model name="follower">
+  <pose relative_to="arm">0 3 0 0 0 0</pose>
+  <link name="only"/>
+</model>"#,
+    )
+    .unwrap();
+    let arm = world.model("arm").unwrap();
+    assert_near(&arm.pose.tmatrix.column(3).xyz(), &glm::vec3(1.0, 0.0, 1.0));
+    // lower is one along the turned x of upper, at (1, 1) in the model; the
+    // joint at (2, 0) in the model is where lower's link frame goes
+    let elbow = &arm.robot.joints[0];
+    assert_eq!(elbow.joint_type(), JointType::Revolute);
+    assert_near(&elbow.origin().xyz(), &glm::vec3(0.0, -1.0, 0.0));
+    let follower = world.model("follower").unwrap();
+    assert_near(
+        &follower.pose.tmatrix.column(3).xyz(),
+        &glm::vec3(0.0, 3.0, 1.0),
+    );
+
+    for (children, frame, location) in [
+        (
+            "<model name=\"m\">\n<link name=\"a\"><pose relative_to=\"b\">0 0 0 0 0 0</pose></link>\n</model>",
+            "b",
+            at("sdf/world[w]/model[m]/link[a]", 4, 1),
+        ),
+        (
+            "<model name=\"m\">\n<pose relative_to=\"elsewhere\">0 0 0 0 0 0</pose>\n<link name=\"a\"/>\n</model>",
+            "elsewhere",
+            at("sdf/world[w]/model[m]", 3, 1),
+        ),
+    ] {
+        assert_eq!(
+            world_error(children),
+            UrdfError::InvalidValue {
+                attribute: "relative_to".into(),
+                value: frame.into(),
+                location,
+            }
+        );
+    }
+}
+
+#[test]
+fn static_models_become_environment() {
+    let world = parse_world(
+        r#"<model name="ground">
+  <static>true</static>
+  <pose>0 0 -0.5 0 0 0</pose>
+  <link name="floor">
+    <collision name="plane">
+      <geometry><plane><normal>0 0 1</normal><size>20 20</size></plane></geometry>
+    </collision>
+    <visual name="tile">
+      <pose>0 0 0.01 0 0 0</pose>
+      <geometry><box><size>1 1 0.02</size></box></geometry>
+      <material><diffuse>0.2 0.4 0.6 1</diffuse></material>
+    </visual>
+  </link>
+  <link name="post">
+    <pose>2 0 0 0 0 0</pose>
+    <collision name="pole">
+      <geometry><cylinder><radius>0.1</radius><length>1</length></cylinder></geometry>
+    </collision>
+  </link>
+</model>"#,
+    )
+    .unwrap();
+    assert!(world.models.is_empty());
+    assert_eq!(world.environment.len(), 2);
+    let floor = &world.environment[0];
+    assert_eq!(floor.name, "ground::floor");
+    assert_near(
+        &floor.pose.tmatrix.column(3).xyz(),
+        &glm::vec3(0.0, 0.0, -0.5),
+    );
+    assert_eq!(
+        floor.collisions[0].shape,
+        WorldShape::Plane {
+            normal: glm::Vec3::z(),
+            size: glm::vec2(20.0, 20.0)
+        }
+    );
+    assert_eq!(floor.visuals[0].rgba, Some(glm::vec4(0.2, 0.4, 0.6, 1.0)));
+    assert_near(
+        &floor.visuals[0].pose.tmatrix.column(3).xyz(),
+        &glm::vec3(0.0, 0.0, 0.01),
+    );
+    let post = &world.environment[1];
+    assert_near(
+        &post.pose.tmatrix.column(3).xyz(),
+        &glm::vec3(2.0, 0.0, -0.5),
+    );
+    assert_eq!(
+        post.collisions[0].shape,
+        WorldShape::Solid(GeometryShape::Cylinder {
+            radius: 0.1,
+            length: 1.0
+        })
+    );
+}
+
+#[test]
+fn worlds_set_gravity_and_lights() {
+    let world = parse_world(
+        r#"<gravity>0 0 -3.71</gravity>
+<light name="sun" type="directional">
+  <direction>0.5 0 -1</direction>
+  <cast_shadows>true</cast_shadows>
+</light>
+<light name="lamp" type="spot">
+  <pose>0 0 3 0 0 0</pose>
+  <diffuse>1 0.9 0.8 1</diffuse>
+  <attenuation><range>25</range></attenuation>
+  <spot><inner_angle>0.3</inner_angle><outer_angle>0.6</outer_angle><falloff>1</falloff></spot>
+</light>"#,
+    )
+    .unwrap();
+    assert_eq!(world.gravity, glm::vec3(0.0, 0.0, -3.71));
+    let sun = &world.lights[0];
+    assert_eq!(
+        (sun.name.as_str(), sun.kind, sun.cast_shadows),
+        ("sun", LightKind::Directional, true)
+    );
+    assert_eq!(sun.direction, glm::vec3(0.5, 0.0, -1.0));
+    let lamp = &world.lights[1];
+    assert_eq!(
+        lamp.kind,
+        LightKind::Spot {
+            inner_angle: 0.3,
+            outer_angle: 0.6,
+            falloff: 1.0
+        }
+    );
+    assert_eq!(lamp.range, 25.0);
+    assert_eq!(lamp.diffuse, glm::vec4(1.0, 0.9, 0.8, 1.0));
+    assert_near(
+        &lamp.pose.tmatrix.column(3).xyz(),
+        &glm::vec3(0.0, 0.0, 3.0),
+    );
+    // older files give gravity under <physics>, without it is standard
+    let world_of = |children| parse_world(children).unwrap().gravity;
+    assert_eq!(
+        world_of("<physics><gravity>0 -1 0</gravity></physics>"),
+        glm::vec3(0.0, -1.0, 0.0)
+    );
+    assert_eq!(world_of(""), glm::vec3(0.0, 0.0, -9.80665));
+
+    assert_eq!(
+        world_error("<light name=\"torch\" type=\"laser\"/>"),
+        UrdfError::InvalidValue {
+            attribute: "type".into(),
+            value: "laser".into(),
+            location: at("sdf/world[w]/light[torch]", 3, 1),
+        }
+    );
+}
+
+#[test]
+fn included_models_cannot_include_again() {
+    // a model that includes itself stops at the first nested include
+    let dir = write_files(
+        "included_models_cannot_include_again",
+        &[
+            (
+                "again.sdf",
+                r#"<sdf version="1.9">
+  <model name="again">
+    <link name="a"/>
+    <include><uri>again.sdf</uri></include>
+  </model>
+</sdf>"#,
+            ),
+            (
+                "world.sdf",
+                "<sdf version=\"1.9\">\n<world name=\"w\">\n<include><uri>again.sdf</uri></include>\n</world>\n</sdf>",
+            ),
+        ],
+    );
+    let error = World::from_sdf_file(dir.join("world.sdf")).unwrap_err();
+    std::fs::remove_dir_all(dir).unwrap();
+    assert_eq!(
+        error,
+        UrdfError::UnsupportedElement {
+            element: "include inside a model".into(),
+            location: at("sdf/model[again]/include", 4, 5),
+        }
+    );
+}
+
+#[test]
+fn links_with_two_parent_joints_are_not_a_tree() {
+    let error = world_error(
+        r#"<model name="m">
+  <link name="a"/>
+  <link name="b"/>
+  <link name="c"/>
+  <joint name="first" type="fixed"><parent>a</parent><child>c</child></joint>
+  <joint name="second" type="fixed"><parent>b</parent><child>c</child></joint>
+</model>"#,
+    );
+    assert_eq!(
+        error,
+        UrdfError::Topology(TopologyError::MultipleParents {
+            link: "c".into(),
+            joints: vec!["first".into(), "second".into()],
+        })
+    );
+}
+
+#[test]
+fn errors_say_where_they_are() {
+    assert_eq!(
+        world_error(
+            "<model name=\"m\">\n<link name=\"a\"/>\n<joint name=\"j\" type=\"fixed\">\n<parent>a</parent><child>z</child>\n</joint>\n</model>"
+        ),
+        UrdfError::UnknownLink {
+            link: "z".into(),
+            location: at("sdf/world[w]/model[m]/joint[j]", 5, 1),
+        }
+    );
+    assert_eq!(
+        world_error(
+            "<model name=\"m\">\n<link name=\"a\"/>\n<joint name=\"j\" type=\"gearbox\">\n<parent>a</parent><child>a</child>\n</joint>\n</model>"
+        ),
+        UrdfError::UnsupportedElement {
+            element: "joint type=\"gearbox\"".into(),
+            location: at("sdf/world[w]/model[m]/joint[j]", 5, 1),
+        }
+    );
+    assert_eq!(
+        world_error("<model name=\"m\"><link name=\"a\"/></model>\n<model name=\"m\"><link name=\"a\"/></model>"),
+        UrdfError::DuplicateName {
+            kind: "model",
+            name: "m".into(),
+            location: at("sdf/world[w]/model[m]", 4, 1),
+        }
+    );
+    assert!(matches!(
+        World::from_sdf_str("<sdf version=\"1.4\"><world/></sdf>"),
+        Err(UrdfError::InvalidValue { attribute, value, .. })
+            if attribute == "version" && value == "1.4"
+    ));
+}
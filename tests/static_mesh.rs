@@ -0,0 +1,231 @@
+extern crate nalgebra_glm as glm;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
+use wgpu_robotic_simulator::geometry::{Aabb, BoxMesh, Polyhedron, TriMesh};
+use wgpu_robotic_simulator::physics::{
+    collide, Attachment, BodyId, Collider, CollisionShape, Material, MeshEdge, PhysicsWorld,
+    RigidBody, StaticMeshCollider, CONTACT_MARGIN,
+};
+use wgpu_robotic_simulator::urdf::GeometryShape;
+use wgpu_robotic_simulator::world::WorldShape;
+
+// a flat floor of `n` by `n` squares of side `size` round the origin, each
+// split into two triangles along alternating diagonals
+fn floor(n: usize, size: f32) -> TriMesh {
+    let mut mesh = TriMesh::default();
+    let at = |i: usize, j: usize| {
+        let half = n as f32 * size / 2.0;
+        glm::vec3(i as f32 * size - half, j as f32 * size - half, 0.0)
+    };
+    for i in 0..n {
+        for j in 0..n {
+            let [a, b, c, d] = [at(i, j), at(i + 1, j), at(i + 1, j + 1), at(i, j + 1)];
+            if (i + j) % 2 == 0 {
+                mesh.add_rectangle([a, b, c, d]);
+            } else {
+                mesh.add_rectangle([b, c, d, a]);
+            }
+        }
+    }
+    mesh
+}
+
+fn add_cube(world: &mut PhysicsWorld, at: glm::Vec3, material: Material) -> BodyId {
+    let inertia = glm::Mat3::identity() * (0.04 / 6.0);
+    let body = world.add_body(RigidBody::new(1.0, inertia).with_position(at));
+    world.add_collider(
+        Collider::on(
+            body,
+            WorldShape::Solid(GeometryShape::Box {
+                size: glm::vec3(0.2, 0.2, 0.2),
+            }),
+            glm::Mat4::identity(),
+        )
+        .with_material(material),
+    );
+    body
+}
+
+fn run(world: &mut PhysicsWorld, seconds: f32, mut each: impl FnMut(&PhysicsWorld)) {
+    for _ in 0..(seconds / world.timestep().dt()).round() as usize {
+        world.step(world.timestep().dt());
+        each(world);
+    }
+}
+
+#[test]
+fn the_bvh_finds_what_testing_every_triangle_finds() {
+    let mut rng = StdRng::seed_from_u64(23);
+    let point = |rng: &mut StdRng| {
+        glm::vec3(
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+            rng.gen_range(-10.0..10.0),
+        )
+    };
+    let mut vertices = Vec::new();
+    for _ in 0..1000 {
+        let corner = point(&mut rng);
+        vertices.push(corner);
+        for _ in 0..2 {
+            let offset = point(&mut rng) * 0.05;
+            vertices.push(corner + offset);
+        }
+    }
+    let triangles: Vec<[usize; 3]> = (0..1000).map(|t| [3 * t, 3 * t + 1, 3 * t + 2]).collect();
+    let mesh = StaticMeshCollider::new(&vertices, &triangles);
+    assert_eq!(mesh.len(), 1000);
+    // four to a leaf and split about evenly
+    assert!(mesh.depth() <= 16, "{}", mesh.depth());
+    for _ in 0..100 {
+        let (a, b) = (point(&mut rng), point(&mut rng) * 0.2);
+        let query = Aabb::new(glm::min2(&a, &(a + b)), glm::max2(&a, &(a + b)));
+        let mut found = BTreeSet::new();
+        mesh.query(&query, |t| {
+            assert!(found.insert(t), "{} twice", t);
+        });
+        let expected: BTreeSet<usize> = (0..mesh.len())
+            .filter(|&t| Aabb::from_points(&mesh.triangle(t)).overlaps(&query))
+            .collect();
+        assert_eq!(found, expected);
+    }
+}
+
+#[test]
+fn welded_edges_know_what_lies_across_them() {
+    let cube = StaticMeshCollider::from(&TriMesh::create_box(glm::vec3(1.0, 1.0, 1.0)));
+    assert_eq!(cube.len(), 12);
+    assert_eq!(cube.vertices().len(), 8);
+    let edges: Vec<MeshEdge> = (0..cube.len()).flat_map(|t| cube.edges(t)).collect();
+    // the twelve edges of the cube from both sides, and a diagonal across
+    // each face
+    let convex = edges
+        .iter()
+        .filter(|e| matches!(e, MeshEdge::Convex { .. }))
+        .count();
+    let internal = edges
+        .iter()
+        .filter(|e| matches!(e, MeshEdge::Internal { .. }))
+        .count();
+    assert_eq!((convex, internal), (24, 12));
+
+    let floor = StaticMeshCollider::from(&Polyhedron::from(floor(4, 1.0)));
+    assert_eq!(floor.vertices().len(), 25);
+    let boundary = (0..floor.len())
+        .flat_map(|t| floor.edges(t))
+        .filter(|e| *e == MeshEdge::Boundary)
+        .count();
+    assert_eq!(boundary, 16);
+    assert!((0..floor.len()).all(|t| (floor.normal(t) - glm::Vec3::z()).norm() < 1e-6));
+}
+
+#[test]
+fn fixed_mesh_colliders_collide_as_triangles() {
+    let geometry = Polyhedron::from(floor(2, 1.0));
+    let shape = WorldShape::Solid(GeometryShape::Mesh {
+        filename: "floor.stl".to_string(),
+        scale: None,
+    });
+    let fixed =
+        Collider::fixed(shape.clone(), glm::Mat4::identity()).with_geometry(geometry.clone());
+    assert_eq!(fixed.attachment, Attachment::Fixed);
+    assert!(matches!(
+        CollisionShape::from_collider(&fixed),
+        Some(CollisionShape::TriangleMesh(_))
+    ));
+    // on a body they are still their hull
+    let mut world = PhysicsWorld::default();
+    let body = world.add_body(RigidBody::default());
+    let moving = Collider::on(body, shape, glm::Mat4::identity()).with_geometry(geometry);
+    assert!(matches!(
+        CollisionShape::from_collider(&moving),
+        Some(CollisionShape::Hull { .. })
+    ));
+}
+
+#[test]
+fn contacts_on_seams_take_the_face_normal_and_on_ridges_the_edge_normal() {
+    let mesh = CollisionShape::TriangleMesh(StaticMeshCollider::from(&floor(4, 1.0)).into());
+    let sphere = CollisionShape::Sphere { radius: 0.5 };
+    // on the seam between two squares, a little sunk, and beside the floor
+    // over its rim
+    for (x, z, normal) in [
+        (0.0, 0.495, glm::Vec3::z()),
+        (2.3, 0.4, glm::vec3(0.6, 0.0, 0.8)),
+    ] {
+        let pose = glm::translation(&glm::vec3(x, 0.3, z));
+        let contacts = collide(
+            &mesh,
+            &glm::Mat4::identity(),
+            &sphere,
+            &pose,
+            CONTACT_MARGIN,
+        );
+        assert!(!contacts.is_empty());
+        for c in contacts {
+            assert!((c.normal - normal).norm() < 1e-3, "{:?}", c);
+        }
+    }
+    // on a ridge, the normal between the faces either side
+    let mut ridge = TriMesh::default();
+    let (a, b) = (glm::vec3(0.0, -1.0, 1.0), glm::vec3(0.0, 1.0, 1.0));
+    ridge.add_rectangle([glm::vec3(-1.0, -1.0, 0.0), a, b, glm::vec3(-1.0, 1.0, 0.0)]);
+    ridge.add_rectangle([a, glm::vec3(1.0, -1.0, 0.0), glm::vec3(1.0, 1.0, 0.0), b]);
+    let ridge = CollisionShape::TriangleMesh(StaticMeshCollider::from(&ridge).into());
+    let pose = glm::translation(&glm::vec3(0.0, 0.2, 1.45));
+    let contacts = collide(
+        &ridge,
+        &glm::Mat4::identity(),
+        &sphere,
+        &pose,
+        CONTACT_MARGIN,
+    );
+    assert_eq!(contacts.len(), 1);
+    assert!((contacts[0].normal - glm::Vec3::z()).norm() < 1e-3);
+    assert!((contacts[0].depth - 0.05).abs() < 1e-3);
+}
+
+#[test]
+fn boxes_come_to_rest_on_a_mesh_floor() {
+    let mut world = PhysicsWorld::default();
+    world.add_static_mesh(
+        StaticMeshCollider::from(&floor(8, 0.25)),
+        glm::translation(&glm::vec3(0.0, 0.0, -0.5)),
+    );
+    let start = glm::quat_angle_axis(0.3, &glm::normalize(&glm::vec3(1.0, 1.0, 0.0)));
+    let cube = add_cube(&mut world, glm::vec3(0.1, -0.05, 0.0), Material::default());
+    world.body_mut(cube).orientation = start;
+    run(&mut world, 2.0, |_| {});
+    let body = world.body(cube);
+    assert!((body.position.z + 0.4).abs() < 5e-3, "{:?}", body.position);
+    // flat on a face, so some quarter turn about z
+    let up = body.rotation() * glm::Vec3::z();
+    assert!(up.z.abs() > 0.999, "{:?}", up);
+    assert!(glm::length(&body.linear_velocity) < 1e-2);
+}
+
+#[test]
+fn sliding_boxes_do_not_catch_on_seams() {
+    let mut world = PhysicsWorld::default();
+    world.add_static_mesh(
+        StaticMeshCollider::from(&floor(16, 0.2)),
+        glm::Mat4::identity(),
+    );
+    let cube = add_cube(
+        &mut world,
+        glm::vec3(-1.0, 0.03, 0.1),
+        Material::new(0.0, 0.0),
+    );
+    world.body_mut(cube).linear_velocity = glm::vec3(1.5, 0.0, 0.0);
+    // across ten seams and as many diagonals
+    run(&mut world, 1.2, |world| {
+        let body = world.body(cube);
+        assert!(body.linear_velocity.z.abs() < 0.05, "{:?}", body);
+        assert!(glm::length(&body.angular_velocity) < 0.05, "{:?}", body);
+    });
+    let body = world.body(cube);
+    assert!((body.linear_velocity.x - 1.5).abs() < 0.02, "{:?}", body);
+    assert!((body.position.z - 0.1).abs() < 5e-3, "{:?}", body);
+}
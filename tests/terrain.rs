@@ -0,0 +1,280 @@
+extern crate nalgebra_glm as glm;
+
+mod common;
+
+use common::run;
+use image::{DynamicImage, GrayImage, Luma};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wgpu_robotic_simulator::physics::{
+    collide, BodyId, Collider, CollisionShape, Material, PhysicsWorld, RigidBody, CONTACT_MARGIN,
+};
+use wgpu_robotic_simulator::terrain::{Fractal, HeightField, SteppingStones, TerrainError};
+use wgpu_robotic_simulator::urdf::GeometryShape;
+use wgpu_robotic_simulator::world::WorldShape;
+
+fn add_solid(world: &mut PhysicsWorld, at: glm::Vec3, shape: GeometryShape) -> BodyId {
+    let inertia = glm::Mat3::identity() * (0.04 / 6.0);
+    let body = world.add_body(RigidBody::new(1.0, inertia).with_position(at));
+    world.add_collider(
+        Collider::on(body, WorldShape::Solid(shape), glm::Mat4::identity())
+            .with_material(Material::default()),
+    );
+    body
+}
+
+#[test]
+fn images_read_as_seen_from_above() {
+    // brighter to the right, and a white pixel at the top left
+    let mut image = GrayImage::from_fn(5, 3, |x, _| Luma([(x * 50) as u8]));
+    image.put_pixel(0, 0, Luma([255]));
+    let field =
+        HeightField::from_image(&DynamicImage::ImageLuma8(image.clone()), 0.5, 2.0).unwrap();
+    assert_eq!((field.columns(), field.rows()), (5, 3));
+    let aabb = field.aabb();
+    assert!((aabb.min - glm::vec3(-1.0, -0.5, 0.0)).norm() < 1e-6);
+    assert!((aabb.max - glm::vec3(1.0, 0.5, 2.0)).norm() < 1e-6);
+    for column in 1..5 {
+        let expected = column as f32 * 50.0 / 255.0 * 2.0;
+        assert!((field.height(column, 0) - expected).abs() < 1e-4);
+    }
+    assert_eq!(field.height(0, 2), 2.0);
+    assert_eq!(field.height(0, 0), 0.0);
+
+    let path = std::env::temp_dir().join("images_read_as_seen_from_above.png");
+    image.save(&path).unwrap();
+    assert_eq!(HeightField::load(&path, 0.5, 2.0).unwrap(), field);
+    std::fs::remove_file(path).unwrap();
+    assert!(matches!(
+        HeightField::load("no such terrain.png", 0.5, 2.0),
+        Err(TerrainError::Image(_))
+    ));
+
+    // a row or a column of pixels has no cells
+    for (width, height) in [(1, 4), (4, 1), (1, 1)] {
+        let strip = DynamicImage::ImageLuma8(GrayImage::new(width, height));
+        let path = std::env::temp_dir().join(format!("strip_{}x{}.png", width, height));
+        strip.save(&path).unwrap();
+        let error = HeightField::load(&path, 0.5, 2.0).unwrap_err();
+        std::fs::remove_file(path).unwrap();
+        assert!(
+            matches!(
+                error,
+                TerrainError::TooSmall { columns, rows }
+                    if (columns, rows) == (width as usize, height as usize)
+            ),
+            "{}",
+            error
+        );
+    }
+}
+
+#[test]
+fn generated_terrain_has_the_shape_asked_for() {
+    let stairs = HeightField::stairs(41, 5, 0.05, 0.25, 0.1);
+    // five points to a step, flat along each
+    for column in 0..41 {
+        let expected = (column / 5) as f32 * 0.1;
+        assert!(
+            (stairs.height(column, 2) - expected).abs() < 1e-5,
+            "{}",
+            column
+        );
+    }
+
+    let angle = 0.3f32;
+    let slope = HeightField::slope(11, 11, 0.2, angle);
+    let normal = glm::vec3(-angle.sin(), 0.0, angle.cos());
+    for (x, y) in [(0.1, -0.3), (-0.77, 0.52), (0.9, 0.9)] {
+        assert!((slope.height_at(x, y).unwrap() - x * angle.tan()).abs() < 1e-5);
+        assert!((slope.normal_at(x, y).unwrap() - normal).norm() < 1e-5);
+    }
+    assert_eq!(slope.height_at(1.01, 0.0), None);
+
+    let fractal = Fractal {
+        seed: 7,
+        ..Fractal::default()
+    };
+    let rough = HeightField::fractal(64, 64, 0.1, fractal);
+    assert_eq!(rough, HeightField::fractal(64, 64, 0.1, fractal));
+    assert_ne!(
+        rough,
+        HeightField::fractal(64, 64, 0.1, Fractal { seed: 8, ..fractal })
+    );
+    // within the sum of the octaves, and not flat
+    let aabb = rough.aabb();
+    assert!(aabb.min.z > -0.4 && aabb.max.z < 0.4, "{:?}", aabb);
+    assert!(aabb.max.z - aabb.min.z > 0.1, "{:?}", aabb);
+    let noise = HeightField::perlin(32, 32, 0.1, 1.0, 1.0, 3);
+    assert!(noise.heights().iter().all(|h| h.abs() <= 1.0));
+
+    let stones = SteppingStones {
+        stone: 0.3,
+        gap: 0.1,
+        depth: 0.5,
+        jitter: 0.05,
+        seed: 1,
+    };
+    let field = HeightField::stepping_stones(41, 41, 0.025, stones);
+    // the first stone from the corner, then a trench
+    assert!(field.height(0, 0) >= 0.0 && field.height(11, 11) <= 0.05);
+    assert_eq!(field.height(11, 11), field.height(0, 0));
+    assert_eq!(field.height(14, 5), -0.5);
+    assert_eq!(field.height(5, 14), -0.5);
+}
+
+#[test]
+fn rays_hit_what_testing_every_triangle_hits() {
+    let field = HeightField::fractal(33, 25, 0.125, Fractal::default());
+    let triangles = field.polyhedron();
+    let brute = |origin: &glm::Vec3, direction: &glm::Vec3| {
+        triangles
+            .indices
+            .chunks(3)
+            .filter_map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|k| triangles.verts[t[k] as usize].position);
+                // where the ray meets the plane of the triangle, if inside it
+                let normal = (b - a).cross(&(c - a));
+                let distance = normal.dot(&(a - origin)) / normal.dot(direction);
+                let p = origin + direction * distance;
+                let inside = [(a, b), (b, c), (c, a)]
+                    .iter()
+                    .all(|(u, v)| (v - u).cross(&(p - u)).dot(&normal) >= -1e-7);
+                (distance >= 0.0 && inside).then_some(distance)
+            })
+            .fold(f32::INFINITY, f32::min)
+    };
+    let mut rng = StdRng::seed_from_u64(24);
+    let mut hits = 0;
+    for _ in 0..500 {
+        let origin = glm::vec3(
+            rng.gen_range(-3.0..3.0),
+            rng.gen_range(-2.0..2.0),
+            rng.gen_range(0.5..2.0),
+        );
+        let direction = glm::normalize(&glm::vec3(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..-0.05),
+        ));
+        let expected = brute(&origin, &direction);
+        match field.ray_cast(&origin, &direction, 100.0) {
+            Some(hit) => {
+                hits += 1;
+                assert!(
+                    (hit.distance - expected).abs() < 1e-3,
+                    "{:?} {}",
+                    hit,
+                    expected
+                );
+                assert!((hit.point - (origin + direction * hit.distance)).norm() < 1e-5);
+                assert!(hit.normal.z > 0.0);
+            }
+            None => assert!(expected.is_infinite(), "missed {}", expected),
+        }
+    }
+    assert!(hits > 100, "{}", hits);
+    // straight down lands on the surface
+    let hit = field
+        .ray_cast(&glm::vec3(0.3, -0.4, 5.0), &-glm::Vec3::z(), 10.0)
+        .unwrap();
+    assert!((hit.point.z - field.height_at(0.3, -0.4).unwrap()).abs() < 1e-5);
+    // and not beyond the distance asked
+    assert!(field
+        .ray_cast(&glm::vec3(0.3, -0.4, 5.0), &-glm::Vec3::z(), 4.0)
+        .is_none());
+}
+
+#[test]
+fn drawn_normals_follow_the_slope() {
+    let angle = 0.4f32;
+    let slope = HeightField::slope(6, 4, 0.5, angle);
+    let mesh = slope.polyhedron();
+    assert_eq!(mesh.verts.len(), 24);
+    assert_eq!(mesh.indices.len(), 5 * 3 * 2 * 3);
+    let normal = glm::vec3(-angle.sin(), 0.0, angle.cos());
+    for v in &mesh.verts {
+        assert!((v.normal - normal).norm() < 1e-5, "{:?}", v);
+    }
+    // wound counter-clockwise seen from above
+    for t in mesh.indices.chunks(3) {
+        let [a, b, c] = [0, 1, 2].map(|k| mesh.verts[t[k] as usize].position);
+        assert!((b - a).cross(&(c - a)).z > 0.0);
+    }
+}
+
+#[test]
+fn contacts_on_terrain_are_on_its_surface() {
+    let field = HeightField::fractal(40, 40, 0.1, Fractal::default());
+    let terrain = CollisionShape::HeightField(field.clone().into());
+    let sphere = CollisionShape::Sphere { radius: 0.15 };
+    for (x, y) in [(0.0, 0.0), (0.42, -1.13), (-1.5, 0.7)] {
+        let ground = field.height_at(x, y).unwrap();
+        let pose = glm::translation(&glm::vec3(x, y, ground + 0.1));
+        let contacts = collide(
+            &terrain,
+            &glm::Mat4::identity(),
+            &sphere,
+            &pose,
+            CONTACT_MARGIN,
+        );
+        assert!(!contacts.is_empty());
+        for c in &contacts {
+            assert!(c.normal.z > 0.0, "{:?}", c);
+            assert!(c.depth > -CONTACT_MARGIN && c.depth < 0.15, "{:?}", c);
+        }
+    }
+    // and none well above it
+    let pose = glm::translation(&glm::vec3(0.0, 0.0, 1.0));
+    assert!(collide(
+        &terrain,
+        &glm::Mat4::identity(),
+        &sphere,
+        &pose,
+        CONTACT_MARGIN
+    )
+    .is_empty());
+}
+
+#[test]
+fn bodies_come_to_rest_on_rough_terrain() {
+    let mut world = PhysicsWorld::default();
+    let field = HeightField::fractal(
+        48,
+        48,
+        0.1,
+        Fractal {
+            amplitude: 0.05,
+            octaves: 3,
+            seed: 5,
+            ..Fractal::default()
+        },
+    );
+    let ground = field.height_at(0.3, -0.2).unwrap();
+    let stairs = HeightField::stairs(21, 11, 0.05, 0.25, 0.05);
+    world.add_height_field(field, glm::Mat4::identity());
+    world.add_height_field(stairs, glm::translation(&glm::vec3(10.0, 0.0, 0.0)));
+    let rock = add_solid(
+        &mut world,
+        glm::vec3(0.3, -0.2, ground + 0.5),
+        GeometryShape::Box {
+            size: glm::vec3(0.2, 0.2, 0.2),
+        },
+    );
+    // on the step 0.15 up, clear of its edges
+    let cube = add_solid(
+        &mut world,
+        glm::vec3(10.4, 0.0, 0.5),
+        GeometryShape::Box {
+            size: glm::vec3(0.1, 0.1, 0.1),
+        },
+    );
+    run(&mut world, 3.0);
+    let rock = world.body(rock);
+    assert!(rock.position.z > ground, "{:?}", rock.position);
+    assert!(glm::length(&rock.linear_velocity) < 1e-2, "{:?}", rock);
+    assert!(glm::length(&rock.angular_velocity) < 1e-2, "{:?}", rock);
+    let cube = world.body(cube);
+    assert!((cube.position.z - 0.2).abs() < 5e-3, "{:?}", cube.position);
+    assert!(glm::length(&cube.linear_velocity) < 1e-2, "{:?}", cube);
+}
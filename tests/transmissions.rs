@@ -0,0 +1,134 @@
+use std::str::FromStr;
+use wgpu_robotic_simulator::urdf::{
+    ExtensionElement, RobotDescriptor, Transmission, TransmissionActuator, TransmissionJoint,
+    UrdfError,
+};
+
+// a two joint arm with whatever else is given after it
+fn arm(rest: &str) -> Result<RobotDescriptor, UrdfError> {
+    RobotDescriptor::from_str(&format!(
+        r#"<robot name="arm">
+  <link name="base"/>
+  <link name="upper"/>
+  <link name="lower"/>
+  <joint name="shoulder" type="continuous">
+    <parent link="base"/>
+    <child link="upper"/>
+  </joint>
+  <joint name="elbow" type="continuous">
+    <parent link="upper"/>
+    <child link="lower"/>
+  </joint>
+{}
+</robot>"#,
+        rest
+    ))
+}
+
+#[test]
+fn transmissions_are_read_in_either_format() {
+    let robot = arm(r#"
+  <transmission name="shoulder_transmission">
+    <type>transmission_interface/SimpleTransmission</type>
+    <joint name="shoulder">
+      <hardwareInterface>hardware_interface/EffortJointInterface</hardwareInterface>
+    </joint>
+    <actuator name="shoulder_motor">
+      <hardwareInterface>EffortJointInterface</hardwareInterface>
+      <hardwareInterface>PositionJointInterface</hardwareInterface>
+      <mechanicalReduction>50</mechanicalReduction>
+    </actuator>
+  </transmission>
+  <transmission type="pr2_mechanism_model/SimpleTransmission">
+    <joint name="elbow"/>
+    <actuator name="elbow_motor"/>
+    <mechanicalReduction>12.5</mechanicalReduction>
+  </transmission>"#)
+    .unwrap();
+    assert_eq!(
+        robot.transmissions,
+        vec![
+            Transmission {
+                name: Some("shoulder_transmission".into()),
+                transmission_type: "transmission_interface/SimpleTransmission".into(),
+                joints: vec![TransmissionJoint {
+                    name: "shoulder".into(),
+                    hardware_interfaces: vec!["hardware_interface/EffortJointInterface".into()],
+                }],
+                actuators: vec![TransmissionActuator {
+                    name: "shoulder_motor".into(),
+                    hardware_interfaces: vec![
+                        "EffortJointInterface".into(),
+                        "PositionJointInterface".into()
+                    ],
+                    mechanical_reduction: Some(50.0),
+                }],
+            },
+            // the legacy reduction goes to every actuator
+            Transmission {
+                name: None,
+                transmission_type: "pr2_mechanism_model/SimpleTransmission".into(),
+                joints: vec![TransmissionJoint {
+                    name: "elbow".into(),
+                    hardware_interfaces: Vec::new(),
+                }],
+                actuators: vec![TransmissionActuator {
+                    name: "elbow_motor".into(),
+                    hardware_interfaces: Vec::new(),
+                    mechanical_reduction: Some(12.5),
+                }],
+            },
+        ]
+    );
+    let reparsed = RobotDescriptor::from_str(&robot.to_urdf_string()).unwrap();
+    assert_eq!(reparsed.transmissions, robot.transmissions);
+}
+
+#[test]
+fn transmissions_drive_joints_the_robot_has() {
+    assert!(matches!(
+        arm(r#"<transmission name="t"><type>simple</type><joint name="wrist"/></transmission>"#),
+        Err(UrdfError::UnknownJoint { joint, .. }) if joint == "wrist"
+    ));
+    assert!(matches!(
+        arm(r#"<transmission name="t"><joint name="elbow"/></transmission>"#),
+        Err(UrdfError::MissingElement { element, .. }) if element == "type"
+    ));
+    assert!(matches!(
+        arm(r#"<transmission type="simple"><mechanicalReduction>x</mechanicalReduction></transmission>"#),
+        Err(UrdfError::InvalidNumber { value, .. }) if value == "x"
+    ));
+}
+
+#[test]
+fn unknown_elements_are_kept_as_they_were() {
+    let robot = arm(r#"
+  <gazebo reference="upper">
+    <material>Gazebo/Grey</material>
+    <mu1 value="0.2"/>
+  </gazebo>
+  <sensor name="camera" type="camera"><parent link="lower"/></sensor>"#)
+    .unwrap();
+    assert_eq!(
+        robot.extensions,
+        vec![
+            ExtensionElement {
+                name: "gazebo".into(),
+                xml: "<gazebo reference=\"upper\"><material>Gazebo/Grey</material>\
+                      <mu1 value=\"0.2\" /></gazebo>"
+                    .into(),
+            },
+            ExtensionElement {
+                name: "sensor".into(),
+                xml: "<sensor name=\"camera\" type=\"camera\"><parent link=\"lower\" /></sensor>"
+                    .into(),
+            },
+        ]
+    );
+    // and written back out as they came in
+    let urdf = robot.to_urdf_string();
+    assert!(urdf.contains("<mu1 value=\"0.2\" />"), "{}", urdf);
+    let reparsed = RobotDescriptor::from_str(&urdf).unwrap();
+    assert_eq!(reparsed.extensions, robot.extensions);
+    assert_eq!(reparsed.joints.len(), 2);
+}
@@ -0,0 +1,153 @@
+use std::str::FromStr;
+use wgpu_robotic_simulator::urdf::{RobotDescriptor, SourceLocation, UrdfError};
+
+fn error(urdf: &str) -> UrdfError {
+    RobotDescriptor::from_str(urdf).unwrap_err()
+}
+
+fn at(path: &str, line: u64, column: u64) -> SourceLocation {
+    SourceLocation {
+        path: path.into(),
+        line,
+        column,
+    }
+}
+
+#[test]
+fn missing_attributes_point_at_their_element() {
+    let error = error(
+        r#"<robot name="r">
+  <link name="base"/>
+  <joint name="j" type="fixed">
+    <parent/>
+    <child link="base"/>
+  </joint>
+</robot>"#,
+    );
+    assert_eq!(
+        error,
+        UrdfError::MissingAttribute {
+            attribute: "link".into(),
+            location: at("robot[r]/joint[j]/parent", 4, 5)
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "missing attribute \"link\" at robot[r]/joint[j]/parent (line 4, column 5)"
+    );
+}
+
+#[test]
+fn bad_numbers_say_which_attribute() {
+    assert_eq!(
+        error(
+            r#"<robot name="r">
+  <link name="base">
+    <visual>
+      <geometry>
+        <box size="1 2.5x 3"/>
+      </geometry>
+    </visual>
+  </link>
+</robot>"#
+        ),
+        UrdfError::InvalidNumber {
+            attribute: "size".into(),
+            value: "1 2.5x 3".into(),
+            location: at("robot[r]/link[base]/visual/geometry/box", 5, 9)
+        }
+    );
+    assert_eq!(
+        error(
+            r#"<robot name="r">
+  <link name="base">
+    <inertial>
+      <mass value="abc"/>
+    </inertial>
+  </link>
+</robot>"#
+        ),
+        UrdfError::InvalidNumber {
+            attribute: "value".into(),
+            value: "abc".into(),
+            location: at("robot[r]/link[base]/inertial/mass", 4, 7)
+        }
+    );
+}
+
+#[test]
+fn joints_name_links_the_robot_has() {
+    assert_eq!(
+        error(
+            r#"<robot name="r">
+  <link name="base"/>
+  <link name="arm"/>
+  <joint name="j" type="fixed">
+    <parent link="base"/>
+    <child link="hand"/>
+  </joint>
+</robot>"#
+        ),
+        UrdfError::UnknownLink {
+            link: "hand".into(),
+            location: at("robot[r]/joint[j]", 4, 3)
+        }
+    );
+}
+
+#[test]
+fn names_are_given_once() {
+    assert_eq!(
+        error(
+            r#"<robot name="r">
+  <link name="base"/>
+  <link name="base"/>
+</robot>"#
+        ),
+        UrdfError::DuplicateName {
+            kind: "link",
+            name: "base".into(),
+            location: at("robot[r]/link[base]", 3, 3)
+        }
+    );
+    let error = error(
+        r#"<robot name="r">
+  <link name="base"/>
+  <link name="arm"/>
+  <joint name="j" type="fixed">
+    <parent link="base"/>
+    <child link="arm"/>
+  </joint>
+  <joint name="j" type="fixed">
+    <parent link="base"/>
+    <child link="arm"/>
+  </joint>
+</robot>"#,
+    );
+    assert!(matches!(
+        &error,
+        UrdfError::DuplicateName { kind: "joint", name, .. } if name == "j"
+    ));
+    assert_eq!(error.location().map(|l| (l.line, l.column)), Some((8, 3)));
+}
+
+#[test]
+fn unsupported_elements_are_not_skipped() {
+    assert_eq!(
+        error(
+            r#"<robot name="r">
+  <link name="base">
+    <visual>
+      <geometry>
+        <torus radius="1"/>
+      </geometry>
+    </visual>
+  </link>
+</robot>"#
+        ),
+        UrdfError::UnsupportedElement {
+            element: "torus".into(),
+            location: at("robot[r]/link[base]/visual/geometry/torus", 5, 9)
+        }
+    );
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use std::path::Path;
+use wgpu_robotic_simulator::urdf::xacro::Xacro;
+use wgpu_robotic_simulator::urdf::{RobotDescriptor, UrdfError};
+use wgpu_robotic_simulator::world::World;
+
+// assets that are not valid robots and cannot be loaded in the first place
+fn expected_failure(file: &str, err: &UrdfError) -> bool {
+    match file {
+        // the base_link referenced by the joints is not defined
+        "leg.urdf" => matches!(err, UrdfError::UnknownLink { .. }),
+        _ => false,
+    }
+}
+
+// every robot in the file, worlds may hold several
+fn load(path: &Path) -> Result<Vec<RobotDescriptor>, UrdfError> {
+    let name = path.to_string_lossy();
+    if name.ends_with("xarm.urdf") {
+        // its meshes are not checked in, common::xarm puts a cube in their place
+        Ok(vec![common::xarm()])
+    } else if name.ends_with(".xacro") {
+        RobotDescriptor::from_xacro_file(path, &Xacro::new()).map(|r| vec![r])
+    } else if name.ends_with(".xml") {
+        RobotDescriptor::from_mjcf_file(path).map(|r| vec![r])
+    } else if name.ends_with(".sdf") {
+        let world = World::from_sdf_file(path)?;
+        Ok(world.models.into_iter().map(|m| m.robot).collect())
+    } else {
+        RobotDescriptor::from_file(path).map(|r| vec![r])
+    }
+}
+
+#[test]
+fn parse_serialize_parse_gives_equal_descriptors() {
+    let mut checked = 0;
+    for entry in std::fs::read_dir("assets").unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let file = path.file_name().unwrap().to_string_lossy().into_owned();
+        let robots = match load(&path) {
+            Ok(robots) => robots,
+            Err(err) if expected_failure(&file, &err) => continue,
+            Err(err) => panic!("{}: {}", file, err),
+        };
+        for robot in robots {
+            let urdf = robot.to_urdf_string();
+            let reparsed = RobotDescriptor::from_str_with_resolver(&urdf, &|uri: &str| {
+                Some(Path::new(uri).to_path_buf()).filter(|p| p.is_file())
+            })
+            .unwrap_or_else(|err| panic!("{}: {}\n{}", file, err, urdf));
+            assert!(
+                robot == reparsed,
+                "{} changed after a round trip:\n{}",
+                file,
+                urdf
+            );
+            checked += 1;
+        }
+    }
+    assert!(checked >= 12);
+}
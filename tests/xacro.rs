@@ -0,0 +1,262 @@
+use wgpu_robotic_simulator::urdf::xacro::{Xacro, XacroError};
+use wgpu_robotic_simulator::urdf::RobotDescriptor;
+
+// the children of a robot element, expanded
+fn expand(body: &str) -> Result<String, XacroError> {
+    Xacro::new().with_arg("side", "left").expand_str(&format!(
+        "<robot xmlns:xacro=\"http://www.ros.org/wiki/xacro\">\n{}\n</robot>",
+        body
+    ))
+}
+
+fn expanded(body: &str, children: &str) {
+    let document = format!("<?xml version=\"1.0\"?>\n<robot>\n{}</robot>\n", children);
+    assert_eq!(expand(body).unwrap(), document);
+}
+
+// the message of an expression error, after checking it is one
+fn expression_error(body: &str) -> String {
+    match expand(body) {
+        Err(XacroError::Expression { message, .. }) => message,
+        other => panic!("expected an expression error, got {:?}", other),
+    }
+}
+
+#[test]
+fn little_dog_expands_to_the_urdf() {
+    let expanded =
+        RobotDescriptor::from_xacro_file("assets/LittleDog.urdf.xacro", &Xacro::new()).unwrap();
+    let written = RobotDescriptor::from_file("assets/LittleDog.urdf").unwrap();
+    assert_eq!(expanded.links.len(), 13);
+    assert!(expanded == written, "{}", expanded.to_urdf_string());
+}
+
+#[test]
+fn macros_take_parameters_and_blocks() {
+    expanded(
+        r#"<xacro:property name="size" value="2"/>
+        <xacro:macro name="box" params="name size:=^ xyz:='0 0 0' scale:=${size*2}">
+          <link name="${name}" size="${size}" xyz="${xyz}" scale="${scale}"/>
+        </xacro:macro>
+        <xacro:box name="a"/>
+        <xacro:box name="b" size="3" xyz="1 2 3"/>"#,
+        "  <link name=\"a\" size=\"2\" xyz=\"0 0 0\" scale=\"4\"/>\n  \
+         <link name=\"b\" size=\"3\" xyz=\"1 2 3\" scale=\"6\"/>\n",
+    );
+    // *block takes the element, **block what is inside it
+    expanded(
+        r#"<xacro:macro name="wrap" params="*first **rest">
+          <link name="wrapped">
+            <xacro:insert_block name="first"/>
+            <xacro:insert_block name="rest"/>
+          </link>
+        </xacro:macro>
+        <xacro:wrap>
+          <visual/>
+          <inner><collision/><inertial/></inner>
+        </xacro:wrap>"#,
+        "  <link name=\"wrapped\">\n    <visual/>\n    <collision/>\n    <inertial/>\n  </link>\n",
+    );
+
+    let location = |error: XacroError| error.location().unwrap().line;
+    let error = expand(r#"<xacro:macro name="m" params="a"/><xacro:m/>"#).unwrap_err();
+    assert!(matches!(
+        &error,
+        XacroError::MissingParameter { macro_name, param, .. } if macro_name == "m" && param == "a"
+    ));
+    assert_eq!(location(error), 2);
+    assert!(matches!(
+        expand(r#"<xacro:macro name="m" params="a"/><xacro:m a="1" b="2"/>"#),
+        Err(XacroError::UnexpectedParameter { param, .. }) if param == "b"
+    ));
+    assert!(matches!(
+        expand(r#"<xacro:macro name="m" params="*a"/><xacro:m/>"#),
+        Err(XacroError::MissingParameter { param, .. }) if param == "a"
+    ));
+    assert!(matches!(
+        expand("<xacro:nothing/>"),
+        Err(XacroError::UndefinedMacro { name, .. }) if name == "nothing"
+    ));
+    assert!(matches!(
+        expand(r#"<xacro:macro name="again" params=""><xacro:again/></xacro:macro><xacro:again/>"#),
+        Err(XacroError::RecursionLimit { macro_name, .. }) if macro_name == "again"
+    ));
+}
+
+#[test]
+fn property_blocks_are_inserted_where_asked() {
+    expanded(
+        r#"<xacro:property name="shape">
+          <geometry><sphere radius="${radius}"/></geometry>
+        </xacro:property>
+        <xacro:property name="radius" value="0.5"/>
+        <link name="ball">
+          <visual><xacro:insert_block name="shape"/></visual>
+        </link>"#,
+        "  <link name=\"ball\">\n    <visual>\n      <geometry>\n        \
+         <sphere radius=\"0.5\"/>\n      </geometry>\n    </visual>\n  </link>\n",
+    );
+    // properties set in a macro stay there unless scoped out of it
+    expanded(
+        r#"<xacro:macro name="set" params="">
+          <xacro:property name="inner" value="1"/>
+          <xacro:property name="outer" value="2" scope="parent"/>
+        </xacro:macro>
+        <xacro:set/>
+        <link name="${outer}"/>"#,
+        "  <link name=\"2\"/>\n",
+    );
+    assert!(matches!(
+        expand(r#"<xacro:macro name="set" params=""><xacro:property name="inner" value="1"/></xacro:macro><xacro:set/><link name="${inner}"/>"#),
+        Err(XacroError::UndefinedProperty { name, .. }) if name == "inner"
+    ));
+    assert!(matches!(
+        expand(r#"<xacro:property name="value" value="1"/><xacro:insert_block name="value"/>"#),
+        Err(XacroError::MissingBlock { name, .. }) if name == "value"
+    ));
+}
+
+#[test]
+fn conditionals_keep_what_they_hold() {
+    expanded(
+        r#"<xacro:property name="n" value="3"/>
+        <xacro:property name="side" value="$(arg side)"/>
+        <xacro:if value="${n > 2 and side == 'left'}"><link name="if"/></xacro:if>
+        <xacro:if value="false"><link name="not_if"/></xacro:if>
+        <xacro:unless value="${n % 2}"><link name="not_unless"/></xacro:unless>
+        <xacro:unless value="0"><link name="unless"/></xacro:unless>
+        <link name="${'odd' if n % 2 == 1 else 'even'}"/>"#,
+        "  <link name=\"if\"/>\n  <link name=\"unless\"/>\n  <link name=\"odd\"/>\n",
+    );
+    assert_eq!(
+        expression_error(r#"<xacro:if value="maybe"><link name="a"/></xacro:if>"#),
+        "not a boolean"
+    );
+    assert!(matches!(
+        expand(r#"<xacro:if><link name="a"/></xacro:if>"#),
+        Err(XacroError::MissingAttribute { attribute, .. }) if attribute == "value"
+    ));
+}
+
+#[test]
+fn expressions_are_evaluated_or_say_what_is_wrong() {
+    expanded(
+        r#"<xacro:property name="a" value="0.5"/>
+        <link name="$(arg side)" x="${a * 4 - 2 ** 2 / 2}" y="${radians(180) == pi}"
+          z="${int(7 // 2) % 2}" w="$${a}" v="$(eval a + 1)"/>"#,
+        "  <link name=\"left\" x=\"0\" y=\"true\" z=\"1\" w=\"${a}\" v=\"1.5\"/>\n",
+    );
+    let cwd = std::env::current_dir().unwrap();
+    expanded(
+        r#"<link name="$(cwd)"/>"#,
+        &format!("  <link name=\"{}\"/>\n", cwd.to_string_lossy()),
+    );
+
+    assert_eq!(
+        expression_error(r#"<link name="${1 / 0}"/>"#),
+        "division by zero"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${(1 + 2}"/>"#),
+        "expected ')'"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${1 +}"/>"#),
+        "unexpected end of expression"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${1 2}"/>"#),
+        "unexpected Number(2.0)"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${1 ; 2}"/>"#),
+        "unexpected character ';'"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${1 if 2}"/>"#),
+        "expected 'else'"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${'a' - 1}"/>"#),
+        "\"a\" is not a number"
+    );
+    assert_eq!(
+        expression_error(r#"<link name="${sin(1, 2)}"/>"#),
+        "sin() takes 1 argument(s), got 2"
+    );
+    assert_eq!(expression_error(r#"<link name="${1 + 2"/>"#), "unclosed {");
+    assert_eq!(
+        expression_error(r#"<link name="$(where is it)"/>"#),
+        "unknown substitution \"where\""
+    );
+
+    let error = expand(r#"<link name="${missing}"/>"#).unwrap_err();
+    assert!(matches!(&error, XacroError::UndefinedProperty { name, .. } if name == "missing"));
+    assert_eq!(error.location().unwrap().line, 2);
+    assert!(matches!(
+        expand(r#"<link name="$(arg nothing)"/>"#),
+        Err(XacroError::UndefinedArg { name, .. }) if name == "nothing"
+    ));
+    let unset = "XACRO_TEST_VARIABLE_THAT_IS_NEVER_SET";
+    assert!(matches!(
+        expand(&format!("<link name=\"$(env {})\"/>", unset)),
+        Err(XacroError::UndefinedEnv { name, .. }) if name == unset
+    ));
+    expanded(
+        &format!("<link name=\"$(optenv {} fallback)\"/>", unset),
+        "  <link name=\"fallback\"/>\n",
+    );
+}
+
+#[test]
+fn includes_expand_in_place_but_not_in_a_cycle() {
+    let dir = std::env::temp_dir().join("includes_expand_in_place_but_not_in_a_cycle");
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, body: &str| {
+        let document = format!(
+            "<robot xmlns:xacro=\"http://www.ros.org/wiki/xacro\">\n{}\n</robot>",
+            body
+        );
+        std::fs::write(dir.join(name), document).unwrap();
+        dir.join(name)
+    };
+    // the same file may be included twice, one after the other
+    write("leg.xacro", r#"<link name="leg"/>"#);
+    let twice = write(
+        "twice.xacro",
+        r#"<xacro:include filename="leg.xacro"/><xacro:include filename="leg.xacro"/>"#,
+    );
+    assert_eq!(
+        Xacro::new().expand_file(&twice).unwrap(),
+        "<?xml version=\"1.0\"?>\n<robot>\n  <link name=\"leg\"/>\n  <link name=\"leg\"/>\n</robot>\n"
+    );
+
+    let own = write("own.xacro", r#"<xacro:include filename="own.xacro"/>"#);
+    let first = write("first.xacro", r#"<xacro:include filename="second.xacro"/>"#);
+    write(
+        "second.xacro",
+        "<link name=\"between\"/>\n<xacro:include filename=\"first.xacro\"/>",
+    );
+    for (path, files, line) in [
+        (&own, vec!["own", "own"], 2),
+        (&first, vec!["first", "second", "first"], 3),
+    ] {
+        let error = Xacro::new().expand_file(path).unwrap_err();
+        match &error {
+            XacroError::IncludeCycle { cycle, location } => {
+                let names: Vec<_> = cycle
+                    .iter()
+                    .map(|p| p.file_stem().unwrap().to_string_lossy())
+                    .collect();
+                assert_eq!(names, files);
+                // where the file closing the cycle includes the first again
+                let last = format!("{}.xacro", files[files.len() - 2]);
+                assert!(location.path.contains(&last), "{}", location);
+                assert_eq!(location.line, line);
+            }
+            other => panic!("expected an include cycle, got {:?}", other),
+        }
+        assert!(error.to_string().starts_with("include cycle "), "{}", error);
+    }
+    std::fs::remove_dir_all(dir).unwrap();
+}